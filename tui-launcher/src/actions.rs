@@ -1,11 +1,126 @@
 // ===================================================================
 // Core Actions Module
 // ===================================================================
-use crate::app::{AppAction};
+use crate::app::App;
+use crate::components::size_field::{self, SizeField};
+use crate::config::{Config, Escalation, Setup};
+use crate::detach::{self, Multiplexer, Session};
+use crate::disk;
+use crate::doctor;
+use crate::error::{ActionError, TAIL_LINES};
+use crate::flash::{self, FlashTarget};
+use crate::grub;
+use crate::highlight::LineSplitter;
+use crate::inspect::{self, DriverSwap, Hardware, SystemReport};
+use crate::install::{self, InstallPlan, InstallStep, PartitionTable, RootFs, StepKind, BASE_PACKAGES, TARGET_ROOT};
+use crate::iso;
+use crate::mirrors::{self, Country};
+use crate::packages::{self, Recipe};
+use crate::paths::{self, BaseDir};
+use crate::services::{self, ServiceOp, Unit};
+use crate::signature::{self, Verdict};
+use crate::snapshot::{self, Compression, DeployPhase, DeployState, Localization, Manifest, Components, SnapshotFile, SnapshotKind, DEPLOY_STATE_FILE, MANIFEST_FILE};
+use crate::timeshift;
+use crate::vm;
+use crate::ui::Theme;
+use crate::workflow::{ItemUpdate, StepStatus};
 use anyhow::{anyhow, Context, Result};
-use std::io::{self, Write};
+use std::future::Future;
+use std::collections::{HashSet, VecDeque};
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
 use std::process::Stdio;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
 use tokio::process::Command;
+use tokio::task::JoinHandle;
+
+/// The future every executable menu action resolves to.
+pub type AppAction = Pin<Box<dyn Future<Output = Result<ActionResult, ActionError>> + Send>>;
+
+/// What an action produced; the UI picks a popup renderer based on the variant.
+pub enum ActionResult {
+    /// Plain text for the result popup.
+    Message(String),
+    /// A file the action created, highlighted (and copyable with 'p') above the details.
+    File { path: PathBuf, details: String },
+    /// Hardware details from the system inspector, rendered as a table.
+    Report(Box<SystemReport>),
+    /// A question the action needs answered before it can continue.
+    Prompt(Prompt),
+    /// A manual installer step that completed; it is added to the install history and
+    /// `next` (the rest of an install plan) starts right away.
+    Step { step: InstallStep, details: String, next: Option<Box<Action>> },
+    /// The systemd services, for the services list; `notice` is the outcome of the last operation.
+    Services { units: Vec<Unit>, notice: Option<String> },
+    /// The snapshots in the work directory, for the snapshot browser; `notice` as for `Services`.
+    Snapshots { snapshots: Vec<SnapshotFile>, notice: Option<String> },
+}
+
+/// Intermediate progress an action reports to the UI while it runs.
+pub enum Progress {
+    Message(String),
+    Percent(u8),
+    /// Text drawn on the gauge, e.g. a transfer rate; replaced by each new label.
+    Label(String),
+    /// The action runs until stopped (it follows a log); the popup takes this title and stops the
+    /// action on Esc or 'q'.
+    Follow(String),
+    /// The action's sub-steps, shown as a checklist in its popup.
+    Checklist(Vec<String>),
+    /// Sub-step `i` of the checklist started; the one before it is done.
+    Check(usize),
+    /// The devices or hosts the action works on at once, shown as a table in its popup.
+    Items(Vec<String>),
+    /// One of them progressed, finished or failed.
+    Item(ItemUpdate),
+    /// A line that takes the place of the last Message, as a progress bar redraws its own line.
+    Replace(String),
+}
+
+/// The sending half handed to every action. Send errors are ignored: they only
+/// mean the UI stopped listening, which must never fail the action itself.
+pub type ProgressTx = tokio::sync::mpsc::UnboundedSender<Progress>;
+
+/// Builds an action's future. A closure, so actions can capture the user's earlier answers.
+pub type ActionFn = Arc<dyn Fn(ProgressTx) -> AppAction + Send + Sync>;
+/// Builds the follow-up action from the items checked in a MultiSelect popup.
+pub type ChoicesFn = Arc<dyn Fn(Vec<String>) -> Action + Send + Sync>;
+/// Builds the follow-up action from the text typed into an Input popup or picked in a Select popup.
+pub type InputFn = Arc<dyn Fn(String) -> Action + Send + Sync>;
+/// Checks the text typed into a Checked popup before it is submitted; the error says what is wrong.
+pub type CheckFn = fn(&str) -> Result<(), String>;
+
+/// A popup that collects an answer before the next action can run.
+#[derive(Clone)]
+pub enum Prompt {
+    /// A checklist; `checked` holds the initial state of each item.
+    MultiSelect { title: String, items: Vec<String>, checked: Vec<bool>, then: ChoicesFn },
+    /// A checklist of files that differ between two versions, all ticked, where 'd' shows the
+    /// highlighted one's entry in `diffs` (unified diffs, one per file); `then` gets the ticked files.
+    Merge { title: String, files: Vec<String>, diffs: Vec<String>, then: ChoicesFn },
+    /// A single line of text, pre-filled with `default`.
+    Input { title: String, default: String, then: InputFn },
+    /// An Input that Enter only submits once `check` accepts the text; until then the popup stays
+    /// open and shows why, below the text.
+    Checked { title: String, default: String, check: CheckFn, then: InputFn },
+    /// A single line of text that is shown masked and not kept once submitted, for passphrases.
+    Secret { title: String, then: InputFn },
+    /// A partition size checked against what is left on the disk as it is typed; '+' and '-' step
+    /// it. `then` only gets a size that fits, or "" for the rest of the disk when `field.rest`.
+    Size { title: String, field: SizeField, default: String, then: InputFn },
+    /// A single choice from `items`.
+    Select { title: String, items: Vec<String>, then: InputFn },
+    /// A choice of disk, drawing the highlighted one's partitions as a bar; `then` gets its path.
+    SelectDisk { title: String, disks: Vec<disk::BlockDevice>, then: InputFn },
+    /// A yes/no question; Enter picks `default`, which destructive actions set to `false`.
+    Confirm { title: String, text: String, default: bool, then: Box<Action> },
+    /// Runs `then` only once `phrase` (the device about to be erased) is typed; never skipped,
+    /// not even in expert mode.
+    Phrase { title: String, text: String, phrase: String, then: Box<Action> },
+}
 
 /// An enum to represent the different types of actions the app can perform.
 /// This is more robust than using magic strings in the error channel.
@@ -13,82 +128,3941 @@ use tokio::process::Command;
 pub enum Action {
     Quit,
     SetView(crate::app::AppView),
-    Execute(fn() -> AppAction),
+    Execute(ActionFn),
+    /// Picks the real action from the current session state (e.g. results of earlier actions).
+    Resolve(fn(&App<'_>) -> Action),
+    Prompt(Prompt),
+    /// Runs the manual installer's remaining steps in order.
+    RunWorkflow,
+    /// Shows a message without running anything, e.g. why an action cannot start yet.
+    Message { title: String, text: String },
+    /// Writes the given popup content to the path typed into the Input popup, then reopens `popup`.
+    SaveText { title: String, text: String, popup: crate::app::Popup },
+}
+
+impl Action {
+    pub fn execute(func: impl Fn(ProgressTx) -> AppAction + Send + Sync + 'static) -> Self {
+        Action::Execute(Arc::new(func))
+    }
 }
 
 // --- Replicator Actions ---
-pub fn create_snapshot() -> AppAction {
-    Box::pin(async {
-        let user_output = Command::new("whoami").output().await?;
-        let user_name = String::from_utf8(user_output.stdout)?.trim().to_string();
-        let home_dir = format!("/home/{}", user_name);
-        let work_dir = format!("{}/arch-suite-work", home_dir);
-        let snapshot_dir = format!("{}/snapshot_tmp", work_dir);
-        let snapshot_file = format!("{}/snapshot-{}.tar.gz", work_dir, chrono::Local::now().format("%Y%m%d"));
+pub fn create_snapshot(progress: ProgressTx) -> AppAction {
+    create_snapshot_at(None, progress)
+}
+
+/// `create_snapshot`, writing the archive to `out` instead of the work directory when given.
+pub fn create_snapshot_at(out: Option<PathBuf>, progress: ProgressTx) -> AppAction {
+    build_snapshot(out, Components::full(), None, progress)
+}
+
+/// Asks what goes into the snapshot: /etc, dotfiles only or the whole home directory, and
+/// patterns to leave out. /etc with dotfiles only is ticked, the most portable recipe. Ticking
+/// encryption asks for a passphrase twice once the rest is chosen.
+pub fn choose_snapshot_components(_app: &App<'_>) -> Action {
+    const ETC: &str = "/etc (system configuration and localization)";
+    const DOTFILES: &str = "Only dotfiles from the home directory (untick for all of it)";
+    const ENCRYPT: &str = "Encrypt with a passphrase (gpg, AES-256)";
+    Action::Prompt(Prompt::MultiSelect {
+        title: "Snapshot contents (the package lists are always included)".to_string(),
+        items: vec![ETC.to_string(), DOTFILES.to_string(), ENCRYPT.to_string()],
+        checked: vec![true, true, false],
+        then: Arc::new(|chosen| {
+            let etc = chosen.iter().any(|item| item == ETC);
+            let encrypt = chosen.iter().any(|item| item == ENCRYPT);
+            let excludes = move |dotfiles: Option<Vec<String>>| {
+                Action::Prompt(Prompt::Input {
+                    title: "Also leave out of the home directory (patterns separated by spaces, e.g. *.iso Videos)".to_string(),
+                    default: String::new(),
+                    then: Arc::new(move |text| {
+                        let components = Components { etc, dotfiles: dotfiles.clone(), excludes: Components::parse_excludes(&text) };
+                        if encrypt {
+                            return snapshot_passphrase(components);
+                        }
+                        Action::execute(move |progress| build_snapshot(None, components.clone(), None, progress))
+                    }),
+                })
+            };
+            if !chosen.iter().any(|item| item == DOTFILES) {
+                return excludes(None);
+            }
+            choose_dotfiles("Dotfiles to snapshot", Arc::new(move |dotfiles| excludes(Some(dotfiles))))
+        }),
+    })
+}
+
+/// Asks twice for the passphrase to encrypt the snapshot of `components` with, then takes it.
+fn snapshot_passphrase(components: Components) -> Action {
+    Action::Prompt(Prompt::Secret {
+        title: "Snapshot passphrase (needed again to deploy it)".to_string(),
+        then: Arc::new(move |passphrase| {
+            if passphrase.is_empty() {
+                return Action::Message { title: "No Passphrase".to_string(), text: "An encrypted snapshot needs a passphrase; take the snapshot again to enter one.".to_string() };
+            }
+            let components = components.clone();
+            Action::Prompt(Prompt::Secret {
+                title: "Repeat the snapshot passphrase".to_string(),
+                then: Arc::new(move |repeated| {
+                    if repeated != passphrase {
+                        return Action::Message { title: "Passphrases Differ".to_string(), text: "The two passphrases did not match; take the snapshot again.".to_string() };
+                    }
+                    let (components, passphrase) = (components.clone(), Arc::new(repeated));
+                    Action::execute(move |progress| build_snapshot(None, components.clone(), Some(passphrase.clone()), progress))
+                }),
+            })
+        }),
+    })
+}
+
+/// Runs `arch-suite snapshot create` in a detached tmux or screen session, so it can go on after
+/// the TUI is closed; without either, the snapshot is taken here instead.
+pub fn detached_snapshot(progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let Some(multiplexer) = Multiplexer::available(command_exists) else {
+            let result = create_snapshot(progress).await?;
+            return Ok(match result {
+                ActionResult::File { path, details } => ActionResult::File { path, details: format!("{}\n\nNeither tmux nor screen is installed, so it ran here.", details) },
+                other => other,
+            });
+        };
+        let exe = std::env::current_exe()?.display().to_string();
+        let job = "snapshot create";
+        let argv: Vec<String> = [exe, "--work-dir".to_string(), paths::work_dir()?.display().to_string()].into_iter().chain(job.split(' ').map(String::from)).collect();
+        let now = chrono::Local::now();
+        let name = detach::session_name(job, now);
+        run_checked(Command::new(multiplexer.program()).args(multiplexer.start_args(&name, &detach::script(&argv)))).await?;
+        let mut sessions = detach::load();
+        sessions.push(Session { name: name.clone(), multiplexer, job: job.to_string(), started: now.format("%Y-%m-%d %H:%M").to_string() });
+        detach::save(&sessions)?;
+        Ok(ActionResult::Message(format!(
+            "✅ The snapshot is running in {} session '{}'. It goes on if you close the suite.\n\nReattach with: {}\nor list it under Utilities → Detached Sessions.",
+            multiplexer.program(), name, multiplexer.attach_command(&name),
+        )))
+    })
+}
+
+/// Lists the detached sessions still running, with how to reattach and, for tmux, their latest
+/// output. Closed sessions are forgotten.
+pub fn detached_sessions(_progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let mut running = Vec::new();
+        for session in detach::load() {
+            let alive = status_within(Command::new(session.multiplexer.program()).args(session.multiplexer.alive_args(&session.name)), Budget::Probe).await;
+            if alive.is_ok_and(|status| status.success()) {
+                running.push(session);
+            }
+        }
+        detach::save(&running)?;
+        if running.is_empty() {
+            return Ok(ActionResult::Message("No detached sessions are running.".to_string()));
+        }
+        let mut text = String::new();
+        for session in &running {
+            text.push_str(&format!("{}  ({}, started {})\n  Reattach: {}\n", session.name, session.job, session.started, session.multiplexer.attach_command(&session.name)));
+            if session.multiplexer == Multiplexer::Tmux {
+                let output = run_checked_within(Command::new("tmux").args(["capture-pane", "-p", "-t", &session.name]), Budget::Probe).await;
+                let tail: Vec<String> = output.map(|o| o.lines().filter(|l| !l.trim().is_empty()).map(|l| format!("  │ {}", l)).collect()).unwrap_or_default();
+                text.push_str(&tail[tail.len().saturating_sub(5)..].join("\n"));
+                text.push('\n');
+            }
+            text.push('\n');
+        }
+        Ok(ActionResult::Message(text.trim_end().to_string()))
+    })
+}
+
+/// Asks which dotfiles to keep, ticking the ones listed under [snapshot], then snapshots only
+/// those and the package lists.
+pub fn dotfiles_snapshot(_app: &App<'_>) -> Action {
+    choose_dotfiles(
+        "Dotfiles to snapshot (the package lists are always included)",
+        Arc::new(|dotfiles| {
+            let components = Components { etc: false, dotfiles: Some(dotfiles), excludes: Vec::new() };
+            Action::execute(move |progress| build_snapshot(None, components.clone(), None, progress))
+        }),
+    )
+}
+
+/// Asks which of the home directory's dotfiles to snapshot, those under [snapshot] ticked, and
+/// continues with them; ticking none takes no snapshot.
+fn choose_dotfiles(title: &str, then: ChoicesFn) -> Action {
+    let (home, configured) = match (paths::real_user(), Config::load()) {
+        (Ok(user), Ok(config)) => (user.home, config.snapshot.dotfiles),
+        (Err(e), _) | (_, Err(e)) => return Action::Message { title: "Dotfiles Snapshot".to_string(), text: format!("❌ {:#}", e) },
+    };
+    let items = snapshot::detect_dotfiles(&home, &configured);
+    if items.is_empty() {
+        return Action::Message { title: "Dotfiles Snapshot".to_string(), text: format!("No dotfiles were found in {}.", home.display()) };
+    }
+    Action::Prompt(Prompt::MultiSelect {
+        title: title.to_string(),
+        checked: items.iter().map(|item| configured.contains(item)).collect(),
+        items,
+        then: Arc::new(move |chosen| {
+            if chosen.is_empty() {
+                return Action::Message { title: "Dotfiles Snapshot".to_string(), text: "Nothing was ticked, so no snapshot was taken.".to_string() };
+            }
+            then(chosen)
+        }),
+    })
+}
+
+/// Snapshots the package lists and the chosen `components`, encrypting the archive with
+/// `passphrase` when given.
+fn build_snapshot(out: Option<PathBuf>, components: Components, passphrase: Option<Arc<String>>, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        // Under sudo, `whoami` is root; archive and hand the snapshot to the user who asked for it.
+        let user = paths::real_user()?;
+        let work_dir = paths::work_dir()?;
+        let config = Config::load()?;
+        let mut home_excludes = vec![".cache".to_string()];
+        for sensitive in config.snapshot.excluded() {
+            home_excludes.extend(sensitive.patterns.iter().cloned());
+        }
+        home_excludes.extend(components.excludes.iter().cloned());
+        let _ = progress.send(Progress::Message("Checking the work directory...".to_string()));
+        let mut targets: Vec<PathBuf> = match &components.dotfiles {
+            Some(dotfiles) => dotfiles.iter().map(|d| user.home.join(d)).collect(),
+            None => vec![user.home.clone()],
+        };
+        if components.etc {
+            targets.push(PathBuf::from("/etc"));
+        }
+        // The parts and the bundle are both on disk until cleanup, about twice the compressed
+        // size; at a typical 2:1 ratio that is what `du` counts.
+        paths::precheck_work_dir(&work_dir, measure(&targets, &home_excludes).await)?;
+        let snapshot_dir = work_dir.join("snapshot_tmp");
+        // Parts left by an interrupted snapshot would be bundled into this one; they are root's.
+        if snapshot_dir.exists() {
+            let _ = progress.send(Progress::Message("Removing the parts of an interrupted snapshot...".to_string()));
+            run_checked(escalated().arg("rm").arg("-rf").arg("--").arg(&snapshot_dir)).await?;
+        }
         std::fs::create_dir_all(&snapshot_dir)?;
-        let command_script = format!(
-            "pacman -Qqe > {0}/packages.x86_64.txt && \
-             pacman -Qqm > {0}/packages.foreign.txt && \
-             sudo tar -czf {0}/etc.tar.gz /etc && \
-             sudo tar -czf {0}/home.tar.gz -C {1} --exclude='.cache' . && \
-             sudo tar -czf {2} -C {0} . && \
-             sudo chown {3}:{3} '{2}' && \
-             sudo rm -rf {0}",
-            snapshot_dir, home_dir, snapshot_file, user_name
+
+        let compression = config.snapshot.compression;
+        require_tools(&["tar", compression.tool()])?;
+        if passphrase.is_some() {
+            require_tools(&["gpg"])?;
+        }
+        let prefix = if components.kind() == SnapshotKind::Dotfiles { "dotfiles" } else { "snapshot" };
+        let snapshot_file = out.unwrap_or_else(|| work_dir.join(format!("{}-{}.{}", prefix, chrono::Local::now().format("%Y%m%d"), compression.extension())));
+        let reproducible = config.snapshot.reproducible;
+        let (compressor, mut tar_args) = compressor_args(compression, config.snapshot.threads, config.snapshot.level, reproducible);
+        let mut manifest = Manifest::new();
+        if reproducible {
+            let epoch = snapshot::reproducible_epoch();
+            tar_args.extend(snapshot::reproducible_tar_args(epoch));
+            manifest.created = chrono::DateTime::from_timestamp(epoch, 0).unwrap_or_default().to_rfc3339();
+            manifest.reproducible = true;
+        }
+        manifest.compression = compression;
+        manifest.level = config.snapshot.level.map(|level| compression.clamp_level(level));
+        manifest.user = user.name.clone();
+        manifest.hardware = Some(inspect::hardware().await);
+        manifest.kind = components.kind();
+        if components.etc {
+            manifest.localization = Localization::capture(Path::new("/etc"));
+        }
+        manifest.excluded_sensitive = config.snapshot.excluded().iter().map(|sensitive| sensitive.category.clone()).collect();
+        manifest.pinned = config.snapshot.pin_versions;
+        std::fs::write(snapshot_dir.join(MANIFEST_FILE), serde_json::to_string_pretty(&manifest)?)?;
+
+        let paths = SnapshotPaths { dir: &snapshot_dir, home: &user.home, file: &snapshot_file, user: &user.name };
+        let steps = snapshot_steps(&paths, &tar_args, compression.extension(), &home_excludes, components.etc, components.dotfiles.as_deref());
+        let total = steps.len() + 1;
+        let mut labels: Vec<String> = std::iter::once("Capturing package lists").chain(steps.iter().map(|(label, _)| *label)).map(String::from).collect();
+        if passphrase.is_some() {
+            labels.push("Encrypting the snapshot".to_string());
+        }
+        if config.snapshot.sign {
+            labels.push("Signing the snapshot".to_string());
+        }
+        let _ = progress.send(Progress::Checklist(labels));
+        let _ = progress.send(Progress::Check(0));
+        std::fs::write(snapshot_dir.join("packages.x86_64.txt"), run_checked_within(Command::new("pacman").arg("-Qqe"), Budget::Probe).await?)?;
+        // `pacman -Qqm` exits non-zero when there are no foreign packages, which is not an error here.
+        let mut query = Command::new("pacman");
+        query.arg("-Qqm");
+        let foreign = within(Budget::Probe, describe(&query), async { query.kill_on_drop(true).output().await.map_err(|e| spawn_error(&query, e)) }).await?;
+        std::fs::write(snapshot_dir.join("packages.foreign.txt"), &foreign.stdout)?;
+        let mut pinned = None;
+        if manifest.pinned {
+            let versions = run_checked_within(Command::new("pacman").arg("-Q"), Budget::Probe).await?;
+            std::fs::write(snapshot_dir.join(snapshot::VERSIONS_FILE), &versions)?;
+            let versions = snapshot::parse_versions(&versions);
+            let mut copied = 0;
+            if config.snapshot.include_package_cache {
+                let _ = progress.send(Progress::Message("Copying the cached package files...".to_string()));
+                let cached: Vec<String> = std::fs::read_dir(snapshot::PACMAN_CACHE).into_iter().flatten().flatten().map(|entry| entry.file_name().to_string_lossy().to_string()).collect();
+                let pkg_dir = snapshot_dir.join(snapshot::PACKAGE_DIR);
+                std::fs::create_dir_all(&pkg_dir)?;
+                for (name, version) in &versions {
+                    if let Some(file) = snapshot::package_file(&cached, name, version) {
+                        std::fs::copy(Path::new(snapshot::PACMAN_CACHE).join(file), pkg_dir.join(file))?;
+                        copied += 1;
+                    }
+                }
+            }
+            pinned = Some((versions.len(), copied));
+        }
+        let _ = progress.send(Progress::Percent((100 / total) as u8));
+        for (i, (_, argv)) in steps.iter().enumerate() {
+            let _ = progress.send(Progress::Check(i + 1));
+            stream_checked_within(escalated().args(argv), &progress, Budget::Long).await?;
+            let _ = progress.send(Progress::Percent(((i + 2) * 100 / total) as u8));
+        }
+        let snapshot_file = match &passphrase {
+            Some(passphrase) => {
+                let _ = progress.send(Progress::Check(total));
+                let encrypted = snapshot::encrypted_path(&snapshot_file);
+                let partial = snapshot::partial_path(&encrypted);
+                let mut gpg = as_real_user("gpg")?;
+                gpg.args(snapshot::encrypt_args(&snapshot_file, &partial));
+                stream_checked_feeding(&mut gpg, Some(passphrase.as_bytes()), &progress, Budget::Long).await?;
+                std::fs::rename(&partial, &encrypted)?;
+                std::fs::remove_file(&snapshot_file)?;
+                encrypted
+            }
+            None => snapshot_file,
+        };
+
+        let mut message = match components.kind() {
+            SnapshotKind::Dotfiles => format!("✅ Dotfiles snapshot created successfully.\n\nContains {}.\nCompressed with {}.", components.describe(), compressor),
+            SnapshotKind::Full if components == Components::full() => format!("✅ Snapshot created successfully.\n\nCompressed with {}.", compressor),
+            SnapshotKind::Full => format!("✅ Snapshot created successfully.\n\nContains {}.\nCompressed with {}.", components.describe(), compressor),
+        };
+        if reproducible {
+            message.push_str(&format!("\nReproducible: the same files give the same bytes, dated {}.", manifest.created));
+        }
+        if passphrase.is_some() {
+            message.push_str("\nEncrypted with gpg (AES-256); deploying it asks for the passphrase.");
+        }
+        if config.snapshot.sign {
+            let _ = progress.send(Progress::Check(total + usize::from(passphrase.is_some())));
+            let sig = signature::sign_file(&snapshot_file, config.snapshot.signing_key.as_deref()).await?;
+            message.push_str(&format!("\nSigned: {}", sig.display()));
+        }
+        if let Some((versions, copied)) = pinned {
+            message.push_str(&format!("\nPinned {} package versions", versions));
+            message.push_str(&if config.snapshot.include_package_cache { format!("; {} package files included.", copied) } else { ".".to_string() });
+        }
+        if !manifest.excluded_sensitive.is_empty() {
+            message.push_str(&format!(
+                "\n\n⚠️ Sensitive paths were NOT included: {}.\nTo include them, set `include_sensitive = true` under [snapshot] in {}.",
+                manifest.excluded_sensitive.join(", "),
+                Config::path().map_or("config.toml".to_string(), |p| p.display().to_string()),
+            ));
+        }
+        message.push_str(&run_hook("post_snapshot", config.hooks.post_snapshot.as_deref(), &snapshot_file, &progress).await.unwrap_or_default());
+        Ok(ActionResult::File { path: snapshot_file, details: message })
+    })
+}
+
+/// How many bytes `du` counts under `targets`, leaving out `excludes`. Files the user cannot read
+/// are skipped (and make `du` exit non-zero), so this is a lower bound.
+async fn measure(targets: &[PathBuf], excludes: &[String]) -> u64 {
+    let mut cmd = Command::new("du");
+    cmd.arg("-sbc").args(excludes.iter().map(|pattern| format!("--exclude={}", pattern))).arg("--").args(targets);
+    let output = within(Budget::Standard, describe(&cmd), async { cmd.kill_on_drop(true).stderr(Stdio::null()).output().await.map_err(|e| spawn_error(&cmd, e)) }).await;
+    output.ok().and_then(|o| snapshot::du_total(&String::from_utf8_lossy(&o.stdout))).unwrap_or(0)
+}
+
+/// Asks which directory to back up, suggesting the home directory.
+pub fn quick_backup(_app: &App<'_>) -> Action {
+    let home = paths::real_user().map(|user| user.home.display().to_string()).unwrap_or_default();
+    Action::Prompt(Prompt::Input {
+        title: "Directory to back up".to_string(),
+        default: home,
+        then: Arc::new(|dir| Action::execute(move |progress| archive_directory(dir.clone(), progress))),
+    })
+}
+
+/// Archives `dir` into a timestamped file in the backup directory with the snapshot compressor,
+/// leaving out the sensitive paths; the gauge follows tar's checkpoints against `du`'s total.
+fn archive_directory(dir: String, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let dir = expand_home(dir.trim())?;
+        if !dir.is_dir() {
+            return Err(ActionError::InvalidInput(format!("{} is not a directory.", dir.display())));
+        }
+        let user = paths::real_user()?;
+        let backup_dir = paths::backup_dir()?;
+        let Config { snapshot: config, hooks, .. } = Config::load()?;
+        require_tools(&["tar", config.compression.tool()])?;
+        let file = backup_dir.join(snapshot::backup_name(&dir, chrono::Local::now(), config.compression.extension()));
+        let partial = snapshot::partial_path(&file);
+
+        let _ = progress.send(Progress::Message(format!("Measuring {}...", dir.display())));
+        let usage = run_checked_within(Command::new("du").args(["-sb", "--"]).arg(&dir), Budget::Standard).await?;
+        let total = usage.split_whitespace().next().and_then(|n| n.parse::<u64>().ok()).unwrap_or(0).max(1);
+        // Compression only makes the archive smaller, so room for the directory itself is enough.
+        paths::precheck_work_dir(&backup_dir, total)?;
+
+        let (compressor, tar_compress) = compressor_args(config.compression, config.threads, config.level, false);
+        let mut cmd = Command::new("tar");
+        cmd.args(&tar_compress).args(["--checkpoint=1000", "--checkpoint-action=echo", "-cf"]).arg(&partial).arg("-C").arg(&dir);
+        // The archive may be written inside the directory it archives.
+        if let Ok(inside) = backup_dir.strip_prefix(&dir) {
+            cmd.arg(format!("--exclude=./{}", inside.display()));
+        }
+        let mut skipped = Vec::new();
+        for sensitive in config.excluded() {
+            cmd.args(sensitive.patterns.iter().map(|p| format!("--exclude={}", p)));
+            skipped.push(sensitive.category.clone());
+        }
+        cmd.arg(".");
+        let _ = progress.send(Progress::Message(format!("Archiving {} with {}...", dir.display(), compressor)));
+        let mut child = cmd.kill_on_drop(true).stdout(Stdio::null()).stderr(Stdio::piped()).spawn().map_err(|e| spawn_error(&cmd, e))?;
+        let mut tail = VecDeque::with_capacity(TAIL_LINES);
+        let status = within(Budget::Long, describe(&cmd), async {
+            if let Some(stderr) = child.stderr.take() {
+                read_records(stderr, |line| match snapshot::checkpoint_bytes(line) {
+                    // Headers and padding make the archive a little larger than `du` counts.
+                    Some(bytes) => { let _ = progress.send(Progress::Percent((bytes * 100 / total).min(99) as u8)); }
+                    None if !line.trim().is_empty() => {
+                        if tail.len() == TAIL_LINES {
+                            tail.pop_front();
+                        }
+                        tail.push_back(line.trim().to_string());
+                        let _ = progress.send(Progress::Message(line.trim().to_string()));
+                    }
+                    None => {}
+                })
+                .await?;
+            }
+            Ok(child.wait().await?)
+        })
+        .await?;
+        if !status.success() {
+            let _ = std::fs::remove_file(&partial);
+            return Err(ActionError::command_failed(describe(&cmd), status, "", &Vec::from(tail).join("\n")));
+        }
+        std::fs::rename(&partial, &file)?;
+        if user.elevated {
+            run_checked(Command::new("chown").arg(format!("{}:", user.name)).arg(&file)).await?;
+        }
+        let size = std::fs::metadata(&file).map_or(0, |m| m.len());
+        let mut message = format!("✅ Backed up {} ({} compressed with {}).", dir.display(), disk::human_size(size), compressor);
+        if !skipped.is_empty() {
+            message.push_str(&format!("\n\n⚠️ Sensitive paths were NOT included: {}.", skipped.join(", ")));
+        }
+        message.push_str(&run_hook("post_backup", hooks.post_backup.as_deref(), &file, &progress).await.unwrap_or_default());
+        Ok(ActionResult::File { path: file, details: message })
+    })
+}
+
+/// Asks where to mirror the home directory and, since the mirror deletes what home does not
+/// have, whether that destination is the right one; then syncs it with rsync.
+pub fn sync_home(_app: &App<'_>) -> Action {
+    Action::Prompt(Prompt::Input {
+        title: "Sync home to (directory or user@host:path)".to_string(),
+        default: String::new(),
+        then: Arc::new(|dest| {
+            let text = format!(
+                "Mirror your home directory to {0}?\n\n⚠️ rsync runs with --delete: files in {0} that are not in your home directory will be deleted.",
+                dest.trim()
+            );
+            Action::Prompt(Prompt::Confirm {
+                title: "Sync home?".to_string(),
+                text,
+                default: false,
+                then: Box::new(Action::execute(move |progress| sync_home_to(dest.clone(), progress))),
+            })
+        }),
+    })
+}
+
+/// Mirrors the home directory to `dest` with `rsync -aAX --delete`, using the snapshot excludes
+/// and feeding rsync's overall progress into the gauge.
+fn sync_home_to(dest: String, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let dest = if dest.trim().starts_with("~/") { expand_home(&dest)?.display().to_string() } else { dest.trim().to_string() };
+        if dest.is_empty() {
+            return Err(ActionError::InvalidInput("No destination given.".to_string()));
+        }
+        require_tools(&["rsync"])?;
+        let home = paths::real_user()?.home.display().to_string();
+        let config = Config::load()?.snapshot;
+        // The work directory holds snapshots (and possibly the destination itself), so never sync it.
+        let mut cmd = Command::new("rsync");
+        cmd.args(["-aAX", "--delete", "--info=progress2", "--exclude=.cache"]);
+        if let Ok(inside) = paths::work_dir()?.strip_prefix(&home) {
+            cmd.arg(format!("--exclude=/{}", inside.display()));
+        }
+        let mut skipped = Vec::new();
+        for sensitive in config.excluded() {
+            cmd.args(sensitive.patterns.iter().map(|p| format!("--exclude={}", p)));
+            skipped.push(sensitive.category.clone());
+        }
+        cmd.arg(format!("{}/", home)).arg(&dest);
+        let _ = progress.send(Progress::Message(format!("Syncing {} to {}...", home, dest)));
+        run_rsync(&mut cmd, &progress).await?;
+        let mut message = format!("✅ Home directory synced to {}.", dest);
+        if !skipped.is_empty() {
+            message.push_str(&format!("\n\n⚠️ Sensitive paths were NOT synced: {}.", skipped.join(", ")));
+        }
+        Ok(ActionResult::Message(message))
+    })
+}
+
+/// Runs an `rsync --info=progress2` command, feeding its overall progress into the gauge.
+async fn run_rsync(cmd: &mut Command, progress: &ProgressTx) -> Result<(), ActionError> {
+    let mut child = cmd.kill_on_drop(true).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().map_err(|e| spawn_error(cmd, e))?;
+    let stderr = forward_lines(child.stderr.take(), progress.clone());
+    let status = within(Budget::Long, describe(cmd), async {
+        if let Some(stdout) = child.stdout.take() {
+            read_records(stdout, |line| report_rsync_line(line, progress)).await?;
+        }
+        Ok(child.wait().await?)
+    })
+    .await?;
+    let stderr = stderr.await.unwrap_or_default();
+    if status.success() {
+        Ok(())
+    } else {
+        Err(ActionError::command_failed(describe(cmd), status, "", &stderr))
+    }
+}
+
+/// Lists the Timeshift restore points, newest first, under a choice to make a new one; picking one
+/// offers to restore it.
+pub fn restore_points(_app: &App<'_>) -> Action {
+    const CREATE: &str = "+ Create a new restore point";
+    let dir = Config::load().map(|c| c.snapshot.timeshift_dir).unwrap_or_else(|_| PathBuf::from("/timeshift"));
+    let points = timeshift::list(&dir);
+    let mut items = vec![CREATE.to_string()];
+    items.extend(points.iter().map(timeshift::RestorePoint::label));
+    Action::Prompt(Prompt::Select {
+        title: format!("Restore points in {}", dir.join("snapshots").display()),
+        items,
+        then: Arc::new(move |choice| {
+            let dir = dir.clone();
+            if choice == CREATE {
+                return Action::Prompt(Prompt::Input {
+                    title: "Comment for the restore point".to_string(),
+                    default: String::new(),
+                    then: Arc::new(move |comment| {
+                        let dir = dir.clone();
+                        Action::execute(move |progress| create_restore_point(dir.clone(), comment.clone(), progress))
+                    }),
+                });
+            }
+            let Some(point) = points.iter().find(|p| p.label() == choice).cloned() else { return Action::Message { title: "Not Found".to_string(), text: choice } };
+            Action::Prompt(Prompt::Input {
+                title: format!("Restore {} into (/ for this system, or a root mounted at /mnt)", point.name),
+                default: "/".to_string(),
+                then: Arc::new(move |root| {
+                    let (point, root) = (point.clone(), PathBuf::from(root.trim()));
+                    let text = format!(
+                        "Every system file under {} is replaced by the one in {}, and files it does not have are DELETED. Home directories and the excluded paths are left alone.\n\nRestoring the running system: reboot right afterwards.",
+                        root.display(),
+                        point.path.display()
+                    );
+                    Action::Prompt(Prompt::Confirm {
+                        title: "This cannot be undone".to_string(),
+                        text,
+                        default: false,
+                        then: Box::new(Action::execute(move |progress| restore_point(point.clone(), root.clone(), progress))),
+                    })
+                }),
+            })
+        }),
+    })
+}
+
+/// Copies this system into a new restore point with rsync, hard-linking the files that have not
+/// changed since the newest existing one.
+fn create_restore_point(dir: PathBuf, comment: String, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        require_tools(&["rsync"])?;
+        let previous = timeshift::list(&dir).into_iter().next();
+        let at = chrono::Local::now();
+        let point = dir.join("snapshots").join(timeshift::snapshot_name(at));
+        run_checked_within(escalated().args(["mkdir", "-p"]).arg(point.join("localhost")), Budget::Standard).await?;
+        let mut excludes: Vec<String> = timeshift::EXCLUDES.map(String::from).to_vec();
+        excludes.push(format!("{}/*", dir.display()));
+        sudo_write(&point.join("exclude.list").display().to_string(), &(excludes.join("\n") + "\n")).await?;
+
+        let _ = progress.send(Progress::Message(format!("Copying the system to {}...", point.display())));
+        run_rsync(escalated().arg("rsync").args(timeshift::create_args(&point, previous.as_ref())), &progress).await?;
+
+        // Written last, so an interrupted copy is never listed as a restore point.
+        let uuid = run_checked(Command::new("findmnt").args(["-no", "UUID", "/"])).await.unwrap_or_default();
+        let distro = std::fs::read_to_string("/etc/os-release")
+            .ok()
+            .and_then(|text| text.lines().find_map(|line| line.strip_prefix("PRETTY_NAME=").map(|v| v.trim_matches('"').to_string())))
+            .unwrap_or_else(|| "Arch Linux".to_string());
+        sudo_write(&point.join("info.json").display().to_string(), &timeshift::info_json(at, uuid.trim(), &distro, comment.trim())).await?;
+        let mut message = format!("✅ Restore point created in {}.", point.display());
+        if let Some(previous) = previous {
+            message.push_str(&format!("\n\nUnchanged files are hard links into {}, so it only takes the space of what changed.", previous.name));
+        }
+        Ok(ActionResult::Message(message))
+    })
+}
+
+/// Brings the system under `root` back to `point` with rsync.
+fn restore_point(point: timeshift::RestorePoint, root: PathBuf, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        if !root.is_dir() {
+            return Err(ActionError::InvalidInput(format!("{} is not a directory.", root.display())));
+        }
+        require_tools(&["rsync"])?;
+        let _ = progress.send(Progress::Message(format!("Restoring {} into {}...", point.name, root.display())));
+        run_rsync(escalated().arg("rsync").args(timeshift::restore_args(&point, &root)), &progress).await?;
+        let reboot = if root == Path::new("/") { "\n\nReboot now: running programs still use the files that were replaced." } else { "" };
+        Ok(ActionResult::Message(format!("✅ {} restored into {}.{}", point.name, root.display(), reboot)))
+    })
+}
+
+/// Calls `on_record` for every line of `pipe`. Progress meters (rsync, dd) redraw their
+/// line with '\r', so both line endings end a record.
+async fn read_records<R: AsyncRead + Unpin>(pipe: R, mut on_record: impl FnMut(&str)) -> io::Result<()> {
+    read_lines(pipe, |line, _| on_record(&line)).await
+}
+
+/// Calls `on_line` for every line of `pipe` with whether it overwrites the one before, as a line
+/// after a '\r' does.
+async fn read_lines<R: AsyncRead + Unpin>(pipe: R, mut on_line: impl FnMut(String, bool)) -> io::Result<()> {
+    let mut reader = BufReader::new(pipe);
+    let mut splitter = LineSplitter::default();
+    loop {
+        let buf = reader.fill_buf().await?;
+        if buf.is_empty() {
+            break;
+        }
+        let len = buf.len();
+        for (line, replace) in splitter.feed(buf) {
+            on_line(line, replace);
+        }
+        reader.consume(len);
+    }
+    if let Some((line, replace)) = splitter.finish() {
+        on_line(line, replace);
+    }
+    Ok(())
+}
+
+/// Sends an rsync `--info=progress2` line as a gauge update, or any other line as text.
+fn report_rsync_line(line: &str, progress: &ProgressTx) {
+    let line = line.trim();
+    if line.is_empty() {
+        return;
+    }
+    match line.split_whitespace().find_map(|field| field.strip_suffix('%')?.parse::<u8>().ok()) {
+        Some(percent) => { let _ = progress.send(Progress::Percent(percent)); }
+        None => { let _ = progress.send(Progress::Message(line.to_string())); }
+    }
+}
+
+/// Snapshots every configured fleet host concurrently and copies the archives here with rsync.
+/// A failing host is reported in the summary without stopping the others.
+pub fn snapshot_fleet(progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let fleet = Config::load()?.fleet;
+        if fleet.hosts.is_empty() {
+            return Err(ActionError::InvalidInput(format!(
+                "No fleet hosts are configured. Add them as `hosts = [\"user@host\"]` under [fleet] in {}.",
+                Config::path().map_or("config.toml".to_string(), |p| p.display().to_string()),
+            )));
+        }
+        let dest = paths::work_dir()?.join("fleet");
+        paths::ensure_writable(&dest)?;
+        let _ = progress.send(Progress::Items(fleet.hosts.clone()));
+        let mut tasks = tokio::task::JoinSet::new();
+        for host in fleet.hosts.clone() {
+            let (dest, command, progress) = (dest.clone(), fleet.remote_command.clone(), progress.clone());
+            tasks.spawn(async move {
+                let result = snapshot_host(&host, &command, &dest, &progress).await;
+                let update = match &result {
+                    Ok(path) => ItemUpdate { key: host.clone(), status: StepStatus::Done, bytes: std::fs::metadata(path).ok().map(|m| m.len()), note: Some(path.display().to_string()) },
+                    Err(e) => ItemUpdate { key: host.clone(), status: StepStatus::Failed, bytes: None, note: Some(e.to_string().lines().next().unwrap_or_default().to_string()) },
+                };
+                let _ = progress.send(Progress::Item(update));
+                (host, result)
+            });
+        }
+        let mut results = Vec::new();
+        while let Some(joined) = tasks.join_next().await {
+            results.push(joined.map_err(|e| ActionError::Other(e.to_string()))?);
+            let _ = progress.send(Progress::Percent((results.len() * 100 / fleet.hosts.len()) as u8));
+        }
+        results.sort_by_key(|(host, _)| fleet.hosts.iter().position(|h| h == host));
+        let failed = results.iter().filter(|(_, r)| r.is_err()).count();
+        let mut message = format!("Fleet snapshot finished: {} of {} hosts succeeded; the archives are under {}.", results.len() - failed, results.len(), dest.display());
+        // The table keeps one line per host; the full errors go here.
+        for (host, result) in results {
+            if let Err(e) = result {
+                message.push_str(&format!("\n\n❌ {}: {}", host, e));
+            }
+        }
+        Ok(ActionResult::Message(message))
+    })
+}
+
+async fn snapshot_host(host: &str, command: &str, dest: &std::path::Path, progress: &ProgressTx) -> Result<PathBuf, ActionError> {
+    let running = |note: &str| Progress::Item(ItemUpdate { key: host.to_string(), status: StepStatus::Running, bytes: None, note: Some(note.to_string()) });
+    let _ = progress.send(running("creating the snapshot"));
+    let output = run_checked_within(Command::new("ssh").args(["-o", "BatchMode=yes", host, command]), Budget::Long).await?;
+    let remote = output.lines().rev().find(|l| !l.trim().is_empty()).map(str::trim).unwrap_or_default().to_string();
+    let name = std::path::Path::new(&remote).file_name().ok_or_else(|| ActionError::Other(format!("'{}' did not print a snapshot path.", command)))?;
+    let host_dir = dest.join(host.replace(['@', '/'], "_"));
+    std::fs::create_dir_all(&host_dir)?;
+    let _ = progress.send(running(&format!("copying {}", remote)));
+    run_checked_within(Command::new("rsync").arg("-a").arg("--partial").arg(format!("{}:{}", host, remote)).arg(&host_dir), Budget::Long).await?;
+    Ok(host_dir.join(name))
+}
+
+/// Chooses a multithreaded compressor when one is installed, falling back to the
+/// single-threaded tool. Returns a description of the compressor and the `tar` flags. A
+/// `reproducible` gzip stream leaves the name and time out of its header (`-n`).
+fn compressor_args(compression: Compression, threads: usize, level: Option<u32>, reproducible: bool) -> (String, Vec<String>) {
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let threads = if threads == 0 { cores } else { threads };
+    let level = level.map(|level| compression.clamp_level(level));
+    let (mut flag, at) = level.map_or((String::new(), String::new()), |level| (format!(" -{}", level), format!(", level {}", level)));
+    let no_name = reproducible && compression == Compression::Gzip;
+    if no_name {
+        flag.push_str(" -n");
+    }
+    let (tool, command) = match compression {
+        Compression::Gzip if command_exists("pigz") => (format!("pigz ({} threads{})", threads, at), format!("pigz -p {}", threads)),
+        Compression::Zstd | Compression::Xz => (format!("{} ({} threads{})", compression.tool(), threads, at), format!("{} -T{}", compression.tool(), threads)),
+        _ if level.is_none() && !no_name => return (format!("{} (single-threaded)", compression.tool()), vec![compression.tar_flag().to_string()]),
+        _ => (format!("{} (single-threaded{})", compression.tool(), at), compression.tool().to_string()),
+    };
+    (tool, vec!["-I".to_string(), command + &flag])
+}
+
+/// Where `snapshot_steps` reads from and writes to.
+struct SnapshotPaths<'a> {
+    /// Staging directory the archive parts are collected in.
+    dir: &'a Path,
+    home: &'a Path,
+    file: &'a Path,
+    user: &'a str,
+}
+
+/// The root commands that archive /etc (with `etc`) and the home directory and bundle them, as
+/// argument vectors for `sudo`. Paths are passed as single arguments, never through a shell. With
+/// `dotfiles`, only those home-relative paths are archived.
+fn snapshot_steps(paths: &SnapshotPaths<'_>, tar_compress: &[String], ext: &str, home_excludes: &[String], etc: bool, dotfiles: Option<&[String]>) -> Vec<(&'static str, Vec<String>)> {
+    let arg = |path: &Path| path.display().to_string();
+    let partial = arg(&snapshot::partial_path(paths.file));
+    // Verbose, so the popup lists each file as it is archived rather than sitting still for minutes.
+    let tar = |rest: Vec<String>| std::iter::once("tar".to_string()).chain(tar_compress.iter().cloned()).chain(["--verbose".to_string()]).chain(rest).collect::<Vec<_>>();
+    let mut home = vec!["-cf".to_string(), arg(&paths.dir.join(format!("home.{}", ext))), "-C".to_string(), arg(paths.home)];
+    home.extend(home_excludes.iter().map(|pattern| format!("--exclude={}", pattern)));
+    let mut steps = Vec::new();
+    match dotfiles {
+        Some(dotfiles) => {
+            // "--" so a dotfile can never be read as an option.
+            home.push("--".to_string());
+            home.extend(dotfiles.iter().cloned());
+        }
+        None => home.push(".".to_string()),
+    }
+    if etc {
+        steps.push(("Archiving /etc", tar(vec!["-cf".to_string(), arg(&paths.dir.join(format!("etc.{}", ext))), "/etc".to_string()])));
+    }
+    steps.extend([
+        ("Archiving the home directory", tar(home)),
+        ("Bundling the snapshot", tar(vec!["-cf".to_string(), partial.clone(), "-C".to_string(), arg(paths.dir), ".".to_string()])),
+        ("Handing the snapshot to its owner", vec!["chown".to_string(), format!("{}:", paths.user), partial.clone()]),
+        ("Marking the snapshot complete", vec!["mv".to_string(), "-f".to_string(), partial, arg(paths.file)]),
+        ("Cleaning up", vec!["rm".to_string(), "-rf".to_string(), arg(paths.dir)]),
+    ]);
+    steps
+}
+
+/// The snapshot is unpacked here inside the target, so chrooted commands can read it too.
+const DEPLOY_STAGING: &str = "/tmp/arch-suite-snapshot";
+
+/// The root commands that replace `staging` with the contents of `archive`, as argument vectors
+/// for `sudo` like `snapshot_steps`: the path typed for the snapshot never meets a shell.
+fn unpack_steps(staging: &str, archive: &Path) -> Vec<Vec<String>> {
+    let staging = staging.to_string();
+    vec![
+        vec!["rm".to_string(), "-rf".to_string(), staging.clone()],
+        vec!["mkdir".to_string(), "-p".to_string(), staging.clone()],
+        vec!["tar".to_string(), "-xf".to_string(), archive.display().to_string(), "-C".to_string(), staging],
+    ]
+}
+
+/// Asks which snapshot to deploy, suggesting the newest one in the work directory.
+pub fn deploy_snapshot(_app: &App<'_>) -> Action {
+    let then: InputFn = Arc::new(deploy_file);
+    let other = {
+        let then = then.clone();
+        move || Action::Prompt(Prompt::Checked { title: "Snapshot file to deploy".to_string(), default: String::new(), check: existing_file, then: then.clone() })
+    };
+    let snapshots = snapshots();
+    if snapshots.is_empty() {
+        return other();
+    }
+    const OTHER: &str = "Another file...";
+    let mut items: Vec<String> = snapshots.iter().map(|path| path.display().to_string()).collect();
+    items.push(OTHER.to_string());
+    Action::Prompt(Prompt::Select {
+        title: "Snapshot to deploy (newest first)".to_string(),
+        items,
+        then: Arc::new(move |choice| if choice == OTHER { other() } else { then(choice) }),
+    })
+}
+
+/// Deploys the snapshot at `path` onto /mnt, asking for its passphrase first when it is encrypted.
+pub fn deploy_file(path: String) -> Action {
+    if !snapshot::is_encrypted(Path::new(path.trim())) {
+        return Action::execute(move |progress| prepare_deploy(path.clone(), None, progress));
+    }
+    Action::Prompt(Prompt::Secret {
+        title: format!("Passphrase for {}", path.trim()),
+        then: Arc::new(move |passphrase| {
+            let (path, passphrase) = (path.clone(), Arc::new(passphrase));
+            Action::execute(move |progress| prepare_deploy(path.clone(), Some(passphrase.clone()), progress))
+        }),
+    })
+}
+
+/// The snapshots in the work directory with their date, size and package count. The count is
+/// read from the archive, which may take up to the probe timeout each.
+async fn snapshot_files() -> Vec<SnapshotFile> {
+    let mut found = Vec::new();
+    for path in snapshots() {
+        let metadata = std::fs::metadata(&path).ok();
+        let list = run_checked_within(Command::new("tar").arg("-xOf").arg(&path).args(["--occurrence=1", "./packages.x86_64.txt"]), Budget::Probe).await;
+        found.push(SnapshotFile {
+            modified: metadata.as_ref().and_then(|m| m.modified().ok()).map(chrono::DateTime::from),
+            bytes: metadata.map_or(0, |m| m.len()),
+            packages: list.ok().map(|list| list.lines().filter(|line| !line.trim().is_empty()).count()),
+            path,
+        });
+    }
+    found
+}
+
+/// Opens the snapshot browser; Enter there deploys the selected snapshot and 'd' deletes it.
+pub fn browse_snapshots(progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let _ = progress.send(Progress::Message("Reading the snapshots...".to_string()));
+        Ok(ActionResult::Snapshots { snapshots: snapshot_files().await, notice: None })
+    })
+}
+
+/// Deletes a snapshot and its detached signature, then lists the ones left.
+pub fn delete_snapshot(path: PathBuf, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let _ = progress.send(Progress::Message(format!("Deleting {}...", path.display())));
+        std::fs::remove_file(&path).with_context(|| format!("Could not delete {}", path.display()))?;
+        let _ = std::fs::remove_file(signature::sig_path(&path));
+        Ok(ActionResult::Snapshots { snapshots: snapshot_files().await, notice: Some(format!("Deleted {}.", path.display())) })
+    })
+}
+
+/// The finished snapshots in the work directory, newest first.
+fn snapshots() -> Vec<PathBuf> {
+    let Ok(dir) = paths::work_dir() else { return Vec::new() };
+    let mut found: Vec<(Option<SystemTime>, PathBuf)> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| ["snapshot-", "dotfiles-"].iter().any(|prefix| entry.file_name().to_string_lossy().starts_with(prefix)))
+        .filter(|entry| !snapshot::is_partial(&entry.path()) && entry.path().extension().is_none_or(|ext| ext != "sig"))
+        .map(|entry| (entry.metadata().and_then(|m| m.modified()).ok(), entry.path()))
+        .collect();
+    found.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    found.into_iter().map(|(_, path)| path).collect()
+}
+
+fn latest_snapshot() -> Option<PathBuf> {
+    snapshots().into_iter().next()
+}
+
+/// How much changed since the latest snapshot, as a note for the main menu, and whether it is
+/// enough to suggest a new one. `None` without a snapshot, without changes, or with [snapshot]
+/// `drift_reminder` at 0.
+pub fn snapshot_drift() -> Option<(String, bool)> {
+    let threshold = Config::load().map_or(20, |config| config.snapshot.drift_reminder);
+    if threshold == 0 {
+        return None;
+    }
+    let snapshot = latest_snapshot()?;
+    let taken = std::fs::metadata(&snapshot).and_then(|m| m.modified()).ok()?;
+    let since = chrono::DateTime::<chrono::Local>::from(taken);
+    let log = std::fs::read_to_string(PACMAN_LOG).unwrap_or_default();
+    let drift = snapshot::Drift { etc_changed: changed_since(Path::new("/etc"), taken), ..snapshot::package_drift(&log, since.fixed_offset()) };
+    if drift.total() == 0 && drift.upgraded == 0 {
+        return None;
+    }
+    let age = match (chrono::Local::now() - since).num_days() {
+        0 => "today".to_string(),
+        1 => "yesterday".to_string(),
+        days => format!("{} days ago", days),
+    };
+    let large = drift.total() >= threshold;
+    let nudge = if large { " | time for a new one (Replicator → Create Snapshot)" } else { "" };
+    Some((format!("Since the last snapshot ({}): {}{}", age, drift.summary(), nudge), large))
+}
+
+/// The files under `dir` modified after `since`; symlinks are not followed and unreadable
+/// directories are skipped.
+fn changed_since(dir: &Path, since: SystemTime) -> usize {
+    let Ok(entries) = std::fs::read_dir(dir) else { return 0 };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| match entry.file_type() {
+            Ok(kind) if kind.is_dir() => changed_since(&entry.path(), since),
+            Ok(kind) if kind.is_file() => usize::from(entry.metadata().and_then(|m| m.modified()).is_ok_and(|at| at > since)),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Asks which snapshot to compare with this system, suggesting the newest one.
+pub fn compare_snapshot(_app: &App<'_>) -> Action {
+    let default = latest_snapshot().map(|p| p.display().to_string()).unwrap_or_default();
+    Action::Prompt(Prompt::Checked {
+        title: "Snapshot file to compare with this system".to_string(),
+        default,
+        check: existing_file,
+        then: Arc::new(|path| Action::execute(move |progress| compare_snapshot_with_system(path.clone(), progress))),
+    })
+}
+
+/// Reports the packages the snapshot and this system do not share and the /etc files that differ.
+/// Only the manifest, package list and /etc archive are unpacked, into the work directory; nothing
+/// on the system changes.
+fn compare_snapshot_with_system(path: String, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let snapshot = expand_home(&path)?;
+        if !snapshot.is_file() {
+            return Err(ActionError::InvalidInput(format!("Snapshot '{}' does not exist.", snapshot.display())));
+        }
+        let cache_dir = paths::base_dir(BaseDir::Cache)?;
+        paths::ensure_writable(&cache_dir)?;
+        let staging = cache_dir.join("compare_tmp");
+        let _ = std::fs::remove_dir_all(&staging);
+        std::fs::create_dir_all(&staging)?;
+        let _ = progress.send(Progress::Message(format!("Reading {}...", snapshot.display())));
+        run_checked(Command::new("tar").arg("-xf").arg(&snapshot).arg("-C").arg(&staging).args([format!("./{}", MANIFEST_FILE), "./packages.x86_64.txt".to_string()])).await?;
+        let manifest: Manifest = std::fs::read_to_string(staging.join(MANIFEST_FILE))
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .ok_or_else(|| ActionError::InvalidInput("The snapshot manifest is missing or invalid.".to_string()))?;
+        let _ = progress.send(Progress::Percent(20));
+
+        let _ = progress.send(Progress::Message("Comparing package lists...".to_string()));
+        let lines = |text: &str| text.lines().map(str::trim).filter(|l| !l.is_empty()).map(String::from).collect::<Vec<_>>();
+        let installed = lines(&run_checked_within(Command::new("pacman").arg("-Qqe"), Budget::Probe).await?);
+        let packages = snapshot::package_diff(&lines(&std::fs::read_to_string(staging.join("packages.x86_64.txt"))?), &installed);
+        let _ = progress.send(Progress::Percent(40));
+
+        let list = |items: &[String]| if items.is_empty() { "  (none)".to_string() } else { items.iter().map(|i| format!("  {}", i)).collect::<Vec<_>>().join("\n") };
+        let mut report = format!(
+            "Snapshot of '{}' ({}) compared with this system.\n\nPackages: {} in both, {} only in the snapshot, {} only here.\n\nOnly in the snapshot (deploying would install them):\n{}\n\nOnly on this system:\n{}",
+            manifest.hostname, manifest.created, packages.common, packages.only_snapshot.len(), packages.only_here.len(), list(&packages.only_snapshot), list(&packages.only_here),
         );
-        let output = Command::new("sudo").arg("sh").arg("-c").arg(command_script).output().await?;
-        if output.status.success() {
-            Ok(format!("✅ Snapshot created successfully:\n{}", snapshot_file))
+
+        if manifest.kind.has_system() {
+            let _ = progress.send(Progress::Message("Unpacking the snapshot's /etc...".to_string()));
+            let etc_archive = format!("etc.{}", manifest.compression.extension());
+            run_checked(Command::new("tar").arg("-xf").arg(&snapshot).arg("-C").arg(&staging).arg(format!("./{}", etc_archive))).await?;
+            // As root, so files only root can read (shadow, sudoers) are compared too.
+            run_checked_within(escalated().arg("tar").arg("-xpf").arg(staging.join(&etc_archive)).arg("-C").arg(&staging), Budget::Long).await?;
+            let _ = progress.send(Progress::Percent(70));
+
+            let _ = progress.send(Progress::Message("Comparing /etc...".to_string()));
+            let snapshot_etc = staging.join("etc").display().to_string();
+            let mut diff_cmd = escalated();
+            diff_cmd.args(["diff", "-rq", "--no-dereference", &snapshot_etc, "/etc"]);
+            let output = within(Budget::Long, describe(&diff_cmd), async { diff_cmd.kill_on_drop(true).output().await.map_err(|e| spawn_error(&diff_cmd, e)) }).await?;
+            // diff exits 1 when the trees differ; only 2 means it could not compare something.
+            let etc = snapshot::parse_etc_diff(&String::from_utf8_lossy(&output.stdout), &snapshot_etc, "/etc");
+            report.push_str(&format!(
+                "\n\n/etc: {} files differ, {} only in the snapshot, {} only here.\n\nDiffering:\n{}\n\nOnly in the snapshot:\n{}\n\nOnly on this system:\n{}",
+                etc.differ.len(), etc.only_snapshot.len(), etc.only_here.len(), list(&etc.differ), list(&etc.only_snapshot), list(&etc.only_here),
+            ));
+            if output.status.code() == Some(2) {
+                report.push_str(&format!("\n\n⚠️ Some entries could not be compared:\n{}", String::from_utf8_lossy(&output.stderr).trim()));
+            }
         } else {
-            Err(anyhow!("Failed to create snapshot:\n{}", String::from_utf8_lossy(&output.stderr)))
+            report.push_str("\n\nThis is a dotfiles snapshot; it has no /etc to compare.");
         }
+        let _ = progress.send(Progress::Percent(90));
+        run_checked(escalated().arg("rm").arg("-rf").arg(&staging)).await?;
+        let _ = progress.send(Progress::Percent(100));
+        Ok(ActionResult::Message(report))
     })
 }
 
-pub fn deploy_snapshot() -> AppAction { Box::pin(async { Ok("Deploy Snapshot not yet implemented.".to_string()) }) }
+/// Asks which snapshot a system was deployed from, suggesting the newest one, then audits it.
+pub fn audit_deploy(_app: &App<'_>) -> Action {
+    let default = latest_snapshot().map(|p| p.display().to_string()).unwrap_or_default();
+    Action::Prompt(Prompt::Checked {
+        title: "Snapshot the system was deployed from".to_string(),
+        default,
+        check: existing_file,
+        then: Arc::new(|path| Action::execute(move |progress| audit_deployed_system(path.clone(), progress))),
+    })
+}
 
-// --- Cloner Actions ---
-pub fn create_iso() -> AppAction { Box::pin(async { Ok("Create ISO not yet implemented.".to_string()) }) }
+/// Checks a deployed system against its snapshot: every package installed, every unit the source
+/// enabled enabled, and the localization restored. Audits the root mounted at /mnt, or without one
+/// this system (after booting into it). Nothing is changed.
+fn audit_deployed_system(path: String, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let snapshot = expand_home(&path)?;
+        if !snapshot.is_file() {
+            return Err(ActionError::InvalidInput(format!("Snapshot '{}' does not exist.", snapshot.display())));
+        }
+        let mounted = status_within(Command::new("mountpoint").args(["-q", TARGET_ROOT]), Budget::Probe).await.is_ok_and(|s| s.success());
+        let root = Path::new(if mounted { TARGET_ROOT } else { "/" });
+        let cache_dir = paths::base_dir(BaseDir::Cache)?;
+        paths::ensure_writable(&cache_dir)?;
+        let staging = cache_dir.join("audit_tmp");
+        let _ = std::fs::remove_dir_all(&staging);
+        std::fs::create_dir_all(&staging)?;
+        let _ = progress.send(Progress::Message(format!("Reading {}...", snapshot.display())));
+        run_checked(Command::new("tar").arg("-xf").arg(&snapshot).arg("-C").arg(&staging).args([format!("./{}", MANIFEST_FILE), "./packages.x86_64.txt".to_string()])).await?;
+        let manifest: Manifest = std::fs::read_to_string(staging.join(MANIFEST_FILE))
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .ok_or_else(|| ActionError::InvalidInput("The snapshot manifest is missing or invalid.".to_string()))?;
+        let mut checks = Vec::new();
+        let mut details = Vec::new();
 
-// --- Utilities Actions ---
-pub fn inspect_system() -> AppAction { Box::pin(async { Ok("System Inspector not yet implemented.".to_string()) }) }
-pub fn flash_iso() -> AppAction { Box::pin(async { Ok("Flash ISO not yet implemented.".to_string()) }) }
+        let _ = progress.send(Progress::Message(format!("Comparing the packages installed in {}...", root.display())));
+        let lines = |text: &str| text.lines().map(str::trim).filter(|l| !l.is_empty()).map(String::from).collect::<Vec<_>>();
+        // Every installed package, not only explicit ones: a snapshot's package may have come in as a dependency.
+        let installed = lines(&run_checked_within(Command::new("pacman").arg("--root").arg(root).arg("-Qq"), Budget::Probe).await?);
+        let wanted = lines(&std::fs::read_to_string(staging.join("packages.x86_64.txt"))?);
+        let missing = snapshot::package_diff(&wanted, &installed).only_snapshot;
+        checks.push(if missing.is_empty() {
+            doctor::check(doctor::Status::Pass, "Packages", format!("all {} installed", wanted.len()))
+        } else {
+            details.push(format!("Packages not installed (driver packages swapped during the deploy are expected here):\n  {}", missing.join("\n  ")));
+            doctor::check(doctor::Status::Fail, "Packages", format!("{} of {} not installed", missing.len(), wanted.len()))
+        });
+        let _ = progress.send(Progress::Percent(40));
 
-// --- Manual Installer Actions ---
-pub fn manual_wipe_disk() -> AppAction { Box::pin(async { Ok("Wipe Disk not yet implemented.".to_string()) }) }
-pub fn manual_partition_disk() -> AppAction { Box::pin(async { Ok("Partition Disk not yet implemented.".to_string()) }) }
-pub fn manual_format_partitions() -> AppAction { Box::pin(async { Ok("Format Partitions not yet implemented.".to_string()) }) }
-pub fn manual_mount_partitions() -> AppAction { Box::pin(async { Ok("Mount Partitions not yet implemented.".to_string()) }) }
-pub fn manual_pacstrap() -> AppAction { Box::pin(async { Ok("Pacstrap not yet implemented.".to_string()) }) }
-pub fn manual_chroot_grub() -> AppAction { Box::pin(async { Ok("Chroot & GRUB not yet implemented.".to_string()) }) }
+        if manifest.kind.has_system() {
+            let _ = progress.send(Progress::Message("Comparing enabled services...".to_string()));
+            let etc_archive = format!("etc.{}", manifest.compression.extension());
+            run_checked(Command::new("tar").arg("-xf").arg(&snapshot).arg("-C").arg(&staging).arg(format!("./{}", etc_archive))).await?;
+            let listing = run_checked_within(Command::new("tar").arg("-tf").arg(staging.join(&etc_archive)), Budget::Standard).await?;
+            let expected = snapshot::enabled_units(listing.lines());
+            let system = root.join("etc/systemd/system");
+            let links: Vec<String> = std::fs::read_dir(&system)
+                .into_iter()
+                .flatten()
+                .flatten()
+                .flat_map(|wants| std::fs::read_dir(wants.path()).into_iter().flatten().flatten().map(move |unit| format!("etc/systemd/system/{}/{}", wants.file_name().to_string_lossy(), unit.file_name().to_string_lossy())))
+                .collect();
+            let enabled = snapshot::enabled_units(links.iter().map(String::as_str));
+            let not_enabled: Vec<&String> = expected.iter().filter(|unit| !enabled.contains(unit)).collect();
+            checks.push(if not_enabled.is_empty() {
+                doctor::check(doctor::Status::Pass, "Services", format!("all {} enabled", expected.len()))
+            } else {
+                details.push(format!("Enabled in the snapshot but not here:\n  {}", not_enabled.iter().map(|u| u.as_str()).collect::<Vec<_>>().join("\n  ")));
+                doctor::check(doctor::Status::Fail, "Services", format!("{} of {} not enabled", not_enabled.len(), expected.len()))
+            });
+            let _ = progress.send(Progress::Percent(80));
 
+            let differ = snapshot::localization_mismatches(&manifest.localization, &Localization::capture(&root.join("etc")));
+            checks.push(if differ.is_empty() {
+                doctor::check(doctor::Status::Pass, "Localization", "locale, time zone and keymap as captured")
+            } else {
+                doctor::check(doctor::Status::Fail, "Localization", format!("differs: {}", differ.join(", ")))
+            });
+        }
+        let _ = std::fs::remove_dir_all(&staging);
+        let _ = progress.send(Progress::Percent(100));
 
-// --- Dependency Management ---
-pub async fn check_and_install_dependencies() -> Result<bool> {
-    let deps = ["gum", "arch-install-scripts", "pacman-contrib", "gptfdisk", "dosfstools", "e2fsprogs", "archiso", "rsync", "pciutils"];
-    let mut missing_deps = Vec::new();
-    println!("Checking dependencies...");
-    for dep in &deps {
-        let status = Command::new("pacman").arg("-Q").arg(dep).stdout(Stdio::null()).stderr(Stdio::null()).status().await?;
-        if !status.success() { missing_deps.push(*dep); }
-    }
-    if missing_deps.is_empty() {
-        println!("✅ All dependencies are satisfied.");
-        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-        return Ok(true);
-    }
-    println!("\n⚠️ The following required packages are missing: {}", missing_deps.join(", "));
-    print!("Would you like to install them now with sudo? (y/N) ");
-    io::stdout().flush()?;
-    let mut input = String::new();
-    io::stdin().read_line(&mut input)?;
-    if input.trim().eq_ignore_ascii_case("y") {
-        println!("Attempting to install missing packages...");
-        let mut args = vec!["-Syu", "--noconfirm", "--needed"];
-        args.extend_from_slice(&missing_deps);
-        let mut child = Command::new("sudo").args(&args).spawn().context("Failed to run sudo pacman. Do you have sudo privileges?")?;
-        let status = child.wait().await?;
-        if status.success() { println!("✅ Dependencies installed successfully."); Ok(true) } 
-        else { println!("❌ Failed to install dependencies. Please try installing them manually."); Ok(false) }
-    } else { Ok(false) }
+        let verdict = if checks.iter().all(|c| c.status == doctor::Status::Pass) { "✅ The system matches the snapshot." } else { "❌ The system does not fully match the snapshot." };
+        let mut report = format!("Audit of {} against the snapshot of '{}' ({})\n\n{}\n\n{}", root.display(), manifest.hostname, manifest.created, verdict, doctor::render(&checks));
+        for detail in details {
+            report.push_str(&format!("\n\n{}", detail));
+        }
+        Ok(ActionResult::Message(report))
+    })
+}
+
+/// Offers to resume an earlier deploy of the same snapshot that did not finish; otherwise (or to
+/// start over) checks and unpacks it. `passphrase` decrypts an encrypted snapshot.
+fn prepare_deploy(path: String, passphrase: Option<Arc<String>>, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let snapshot = expand_home(&path)?;
+        if !snapshot.is_file() {
+            return Err(ActionError::InvalidInput(format!("Snapshot '{}' does not exist.", snapshot.display())));
+        }
+        if !status_within(Command::new("mountpoint").args(["-q", TARGET_ROOT]), Budget::Probe).await?.success() {
+            return Err(ActionError::InvalidInput(format!("Nothing is mounted at {}. Prepare and mount the new system first (Manual Installer).", TARGET_ROOT)));
+        }
+        let Some(state) = DeployState::load(Path::new(&format!("{}{}", TARGET_ROOT, DEPLOY_STAGING)), &snapshot) else {
+            return verify_for_deploy(snapshot, passphrase, progress).await;
+        };
+        let resume = "Resume where it stopped".to_string();
+        Ok(ActionResult::Prompt(Prompt::Select {
+            title: format!("A deploy of this snapshot did not finish (done: {})", state.describe_done()),
+            items: vec![resume.clone(), "Start over and unpack it again".to_string()],
+            then: Arc::new(move |choice| {
+                let (state, snapshot, passphrase) = (state.clone(), snapshot.clone(), passphrase.clone());
+                if choice == resume {
+                    Action::execute(move |progress| resume_deploy(state.clone(), progress))
+                } else {
+                    Action::execute(move |progress| verify_for_deploy(snapshot.clone(), passphrase.clone(), progress))
+                }
+            }),
+        }))
+    })
+}
+
+/// Checks the snapshot's signature when it has one (or [snapshot] signs them), asking before going on
+/// without a good, trusted one, then unpacks it. An encrypted snapshot's signature is of the
+/// encrypted file.
+fn verify_for_deploy(snapshot: PathBuf, passphrase: Option<Arc<String>>, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let sig = signature::sig_path(&snapshot);
+        if !sig.is_file() && !Config::load()?.snapshot.sign {
+            return unpack_for_deploy(snapshot, String::new(), passphrase, progress).await;
+        }
+        let _ = progress.send(Progress::Message("Checking the snapshot's GPG signature...".to_string()));
+        let verified = signature::verify_signature(&snapshot, &sig, None).await?;
+        let signed = verified.describe();
+        match verified.warning(&snapshot, &sig) {
+            None => unpack_for_deploy(snapshot, signed, passphrase, progress).await,
+            Some(warning) => Ok(ActionResult::Prompt(Prompt::Confirm {
+                title: "Snapshot signature not verified".to_string(),
+                text: format!("⚠️ {}\n\nDeploy it anyway?", warning),
+                default: false,
+                then: Box::new(Action::execute(move |progress| unpack_for_deploy(snapshot.clone(), signed.clone(), passphrase.clone(), progress))),
+            })),
+        }
+    })
+}
+
+/// Unpacks the snapshot into the target and asks which top-level /etc entries to restore. `signed`
+/// describes its signature check, for the final confirmation. An encrypted snapshot is decrypted
+/// with `passphrase` next to it first, and the decrypted copy removed once unpacked.
+fn unpack_for_deploy(snapshot: PathBuf, signed: String, passphrase: Option<Arc<String>>, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let staging = format!("{}{}", TARGET_ROOT, DEPLOY_STAGING);
+        let archive = match &passphrase {
+            Some(passphrase) if snapshot::is_encrypted(&snapshot) => {
+                require_tools(&["gpg"])?;
+                let _ = progress.send(Progress::Message(format!("Decrypting {}...", snapshot.display())));
+                let decrypted = snapshot::partial_path(&snapshot.with_extension(""));
+                let mut gpg = Command::new("gpg");
+                gpg.args(snapshot::decrypt_args(&snapshot, &decrypted));
+                if let Err(e) = stream_checked_feeding(&mut gpg, Some(passphrase.as_bytes()), &progress, Budget::Long).await {
+                    let _ = std::fs::remove_file(&decrypted);
+                    return Err(e);
+                }
+                Some(decrypted)
+            }
+            _ => None,
+        };
+        let _ = progress.send(Progress::Message(format!("Unpacking {}...", snapshot.display())));
+        let unpacked = sudo_steps(&unpack_steps(&staging, archive.as_ref().unwrap_or(&snapshot)), Budget::Long).await;
+        if let Some(decrypted) = &archive {
+            let _ = std::fs::remove_file(decrypted);
+        }
+        unpacked?;
+        let manifest: Manifest = std::fs::read_to_string(format!("{}/{}", staging, MANIFEST_FILE))
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .ok_or_else(|| ActionError::InvalidInput("The snapshot manifest is missing or invalid.".to_string()))?;
+
+        let entries = if manifest.kind.has_system() {
+            let _ = progress.send(Progress::Message("Reading the /etc archive...".to_string()));
+            let etc_archive = format!("{}/etc.{}", staging, manifest.compression.extension());
+            let listing = run_checked(Command::new("tar").arg("-tf").arg(&etc_archive)).await?;
+            snapshot::top_level_etc_entries(&listing)
+        } else {
+            Vec::new()
+        };
+
+        // This machine is the target: compare it with the one the snapshot was taken on.
+        let _ = progress.send(Progress::Message("Comparing the snapshot's hardware with this machine...".to_string()));
+        let packages: Vec<String> = std::fs::read_to_string(format!("{}/packages.x86_64.txt", staging))?.lines().map(String::from).collect();
+        let target = inspect::hardware().await;
+        let swap = inspect::driver_swap(manifest.hardware.as_ref(), &target, &packages);
+        let state = DeployState { snapshot, restore: Vec::new(), entries, keep: Vec::new(), swap: DriverSwap::default(), done: Vec::new() };
+        if swap.is_empty() {
+            // A dotfiles snapshot has no /etc, so it goes straight to the confirmation.
+            if !manifest.kind.has_system() {
+                return confirm_deploy(manifest, state, signed, progress).await;
+            }
+            return Ok(ActionResult::Prompt(choose_etc_entries(manifest, state, signed)?));
+        }
+        let source = manifest.hardware.as_ref().map_or("unrecorded hardware".to_string(), Hardware::describe);
+        let list = |items: &[String]| if items.is_empty() { "nothing".to_string() } else { items.join(" ") };
+        let swap_choice = format!("Swap drivers: drop {}; add {}", list(&swap.remove), list(&swap.add));
+        let verbatim = "Install every package verbatim".to_string();
+        let swap = Arc::new(swap);
+        Ok(ActionResult::Prompt(Prompt::Select {
+            title: format!("Snapshot from {}; this machine has {}", source, target.describe()),
+            items: vec![swap_choice.clone(), verbatim],
+            then: Arc::new(move |choice| {
+                let swap = if choice == swap_choice { (*swap).clone() } else { DriverSwap::default() };
+                let (manifest, state, signed) = (manifest.clone(), DeployState { swap, ..state.clone() }, signed.clone());
+                if !manifest.kind.has_system() {
+                    return Action::execute(move |progress| confirm_deploy(manifest.clone(), state.clone(), signed.clone(), progress));
+                }
+                match choose_etc_entries(manifest, state, signed) {
+                    Ok(prompt) => Action::Prompt(prompt),
+                    Err(e) => step_required(&e.to_string()),
+                }
+            }),
+        }))
+    })
+}
+
+/// Asks which top-level /etc entries of a system snapshot to restore, then confirms the deploy.
+fn choose_etc_entries(manifest: Manifest, state: DeployState, signed: String) -> Result<Prompt, ActionError> {
+    let skip = Config::load()?.deploy.etc_skip;
+    Ok(Prompt::MultiSelect {
+        title: "Select /etc entries to restore (unticked ones keep the new system's version)".to_string(),
+        checked: state.entries.iter().map(|e| !skip.contains(e)).collect(),
+        items: state.entries.clone(),
+        then: Arc::new(move |restore| {
+            let (manifest, state, signed) = (manifest.clone(), DeployState { restore, ..state.clone() }, signed.clone());
+            Action::execute(move |progress| merge_etc_files(manifest.clone(), state.clone(), signed.clone(), progress))
+        }),
+    })
+}
+
+/// Unpacks the snapshot's /etc next to the target's and lists the files in the chosen entries that
+/// differ between them, pacnew-style: ticked ones take the snapshot's version, the others are kept.
+/// Files only in the snapshot are always restored.
+fn merge_etc_files(manifest: Manifest, state: DeployState, signed: String, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        if state.restore.is_empty() {
+            return confirm_deploy(manifest, state, signed, progress).await;
+        }
+        let staging = format!("{}{}", TARGET_ROOT, DEPLOY_STAGING);
+        let (snapshot_etc, target_etc) = (format!("{}/etc", staging), format!("{}/etc", TARGET_ROOT));
+        let _ = progress.send(Progress::Message("Unpacking the snapshot's /etc to compare it with the target's...".to_string()));
+        run_checked_within(escalated().args(["rm", "-rf", &snapshot_etc]), Budget::Long).await?;
+        run_checked_within(escalated().args(["tar", "-xpf", &format!("{}/etc.{}", staging, manifest.compression.extension()), "-C", &staging]), Budget::Long).await?;
+        let mut diff_cmd = escalated();
+        diff_cmd.args(["diff", "-rq", "--no-dereference", &snapshot_etc, &target_etc]);
+        // diff exits 1 when the trees differ; what it could not compare is simply restored.
+        let output = within(Budget::Long, describe(&diff_cmd), async { diff_cmd.kill_on_drop(true).output().await.map_err(|e| spawn_error(&diff_cmd, e)) }).await?;
+        let files: Vec<String> = snapshot::parse_etc_diff(&String::from_utf8_lossy(&output.stdout), &snapshot_etc, &target_etc)
+            .differ
+            .into_iter()
+            .filter(|file| state.restore.iter().any(|entry| snapshot::is_under(file, entry)))
+            .collect();
+        if files.is_empty() {
+            return confirm_deploy(manifest, state, signed, progress).await;
+        }
+        let mut diffs = Vec::new();
+        for (i, file) in files.iter().enumerate() {
+            let _ = progress.send(Progress::Message(format!("Comparing /etc/{}...", file)));
+            let _ = progress.send(Progress::Percent((i * 100 / files.len()) as u8));
+            let (target, snapshot) = (format!("{}/{}", target_etc, file), format!("{}/{}", snapshot_etc, file));
+            let mut cmd = escalated();
+            cmd.args(["diff", "-u", "--no-dereference", "--label", &format!("target /etc/{}", file), "--label", &format!("snapshot /etc/{}", file), &target, &snapshot]);
+            let output = within(Budget::Probe, describe(&cmd), async { cmd.kill_on_drop(true).output().await.map_err(|e| spawn_error(&cmd, e)) }).await?;
+            diffs.push(String::from_utf8_lossy(if output.stdout.is_empty() { &output.stderr } else { &output.stdout }).to_string());
+        }
+        let conflicts = files.clone();
+        Ok(ActionResult::Prompt(Prompt::Merge {
+            title: format!("{} /etc files differ from the target's: ticked ones take the snapshot's version", files.len()),
+            files,
+            diffs,
+            then: Arc::new(move |take| {
+                let keep = conflicts.iter().filter(|file| !take.contains(file)).cloned().collect();
+                let state = DeployState { keep, ..state.clone() };
+                let (manifest, signed) = (manifest.clone(), signed.clone());
+                Action::execute(move |progress| confirm_deploy(manifest.clone(), state.clone(), signed.clone(), progress))
+            }),
+        }))
+    })
+}
+
+/// Asks before deploying, with what will be overwritten and what installing the snapshot's
+/// packages downloads. `signed` describes its signature check.
+fn confirm_deploy(manifest: Manifest, state: DeployState, signed: String, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let list = std::fs::read_to_string(format!("{}{}/packages.x86_64.txt", TARGET_ROOT, DEPLOY_STAGING))?;
+        let mut packages: Vec<String> = list.lines().filter(|package| !state.swap.remove.iter().any(|p| p == package)).map(String::from).collect();
+        packages.extend(state.swap.add.iter().cloned());
+        let download = download_note(&packages, TARGET_ROOT, &progress).await;
+        Ok(ActionResult::Prompt(deploy_prompt(manifest, state, &signed, &download)))
+    })
+}
+
+/// Where the snapshot's home directory is restored in the target, or the note on why it is not. The
+/// user comes from the snapshot's manifest, so a name that could point outside /home is refused.
+fn restored_home(user: &str) -> Result<String, String> {
+    match user {
+        "" => Err("The snapshot does not name its user, so the home directory was not restored.".to_string()),
+        user if !install::is_valid_user_name(user) => Err(format!("The snapshot names its user '{}', which is not a user name, so the home directory was not restored.", user)),
+        user => Ok(format!("{}/home/{}", TARGET_ROOT, user)),
+    }
+}
+
+fn deploy_prompt(manifest: Manifest, state: DeployState, signed: &str, download: &str) -> Prompt {
+    let (restore, swap) = (&state.restore, &state.swap);
+    let mut text = if manifest.kind.has_system() {
+        let overwritten = if restore.is_empty() { "nothing in /etc".to_string() } else { format!("/etc/{{{}}}", restore.join(",")) };
+        format!("This installs the snapshot's packages into {} and overwrites {} there.", TARGET_ROOT, overwritten)
+    } else {
+        format!("This installs the snapshot's packages into {} and restores its dotfiles into /home/{}. /etc is not touched.", TARGET_ROOT, manifest.user)
+    };
+    if !state.keep.is_empty() {
+        text.push_str(&format!("\n\nKept at the target's version: {}", state.keep.iter().map(|file| format!("/etc/{}", file)).collect::<Vec<_>>().join(", ")));
+    }
+    if !swap.is_empty() {
+        text.push_str(&format!("\n\nDriver packages left out: {}\nAdded for this machine: {}", swap.remove.join(" "), swap.add.join(" ")));
+    }
+    if !signed.is_empty() {
+        text.push_str(&format!("\n\n{}", signed));
+    }
+    text.push_str(&format!("\n\n{}", download));
+    Prompt::Confirm {
+        title: "Deploy snapshot?".to_string(),
+        text,
+        default: false,
+        then: Box::new(Action::execute(move |progress| restore_snapshot(manifest.clone(), state.clone(), progress))),
+    }
+}
+
+/// Continues a deploy from its state file, with the snapshot already unpacked in the target.
+fn resume_deploy(state: DeployState, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let staging = format!("{}{}", TARGET_ROOT, DEPLOY_STAGING);
+        let manifest: Manifest = std::fs::read_to_string(format!("{}/{}", staging, MANIFEST_FILE))
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .ok_or_else(|| ActionError::InvalidInput("The unpacked snapshot is incomplete. Start the deploy over.".to_string()))?;
+        restore_snapshot(manifest, state, progress).await
+    })
+}
+
+/// Records how far the deploy got, next to the unpacked snapshot.
+async fn save_deploy_state(staging: &str, state: &DeployState) -> Result<(), ActionError> {
+    sudo_write(&format!("{}/{}", staging, DEPLOY_STATE_FILE), &serde_json::to_string_pretty(state)?).await
+}
+
+/// The chroot's pacman command that installs the snapshot's package `list`, and what to feed it on
+/// stdin: the list adjusted by `swap` and without the `foreign` packages no repository has. The
+/// names come from the snapshot, so they are never handed to a shell.
+fn package_install(list: &str, swap: &DriverSwap, foreign: &[String], config: Option<&str>) -> (Vec<String>, String) {
+    let mut args = vec!["pacman".to_string()];
+    if let Some(config) = config {
+        args.extend(["--config".to_string(), config.to_string()]);
+    }
+    args.extend(["-S", "--needed", "--noconfirm", "-"].map(String::from));
+    let skipped = |package: &&str| swap.remove.iter().chain(foreign).any(|p| p == package);
+    let packages: Vec<&str> = list.lines().map(str::trim).filter(|p| !p.is_empty()).filter(|p| !skipped(p)).chain(swap.add.iter().map(String::as_str)).collect();
+    (args, packages.iter().map(|p| format!("{}\n", p)).collect())
+}
+
+/// Brings the packages just installed into the target to the snapshot's versions, from the
+/// snapshot's package files or the target's cache; those without a file stay as installed.
+async fn pin_versions(staging: &str, notes: &mut Vec<String>, progress: &ProgressTx) -> Result<(), ActionError> {
+    let _ = progress.send(Progress::Message("Installing the snapshot's package versions...".to_string()));
+    let pinned = snapshot::parse_versions(&std::fs::read_to_string(format!("{}/{}", staging, snapshot::VERSIONS_FILE))?);
+    let installed = snapshot::parse_versions(&run_checked(Command::new("pacman").args(["--root", TARGET_ROOT, "-Q"])).await?);
+    let mut files = Vec::new();
+    for dir in [format!("{}/{}", staging, snapshot::PACKAGE_DIR), format!("{}{}", TARGET_ROOT, snapshot::PACMAN_CACHE)] {
+        let entries = std::fs::read_dir(&dir).into_iter().flatten().flatten();
+        files.extend(entries.map(|entry| format!("{}/{}", dir, entry.file_name().to_string_lossy())));
+    }
+    let plan = snapshot::pin_plan(&pinned, &installed, &files);
+    if !plan.files.is_empty() {
+        // `pacstrap -U` installs files from the host's paths into the target.
+        stream_checked_within(escalated().args(["pacstrap", "-U", TARGET_ROOT]).args(&plan.files), progress, Budget::Long).await?;
+        notes.push(format!("Installed the snapshot's versions of {} packages.", plan.files.len()));
+    }
+    if !plan.unavailable.is_empty() {
+        notes.push(format!("⚠️ No package file of the snapshot's version was found for {} packages, so they keep the repository version: {}.", plan.unavailable.len(), plan.unavailable.join(", ")));
+    }
+    Ok(())
+}
+
+/// Installs the snapshot's packages into the target, then restores the chosen /etc entries and the
+/// home directory. Each phase is recorded in the state file once done and skipped when resuming.
+fn restore_snapshot(manifest: Manifest, state: DeployState, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let staging = format!("{}{}", TARGET_ROOT, DEPLOY_STAGING);
+        let ext = manifest.compression.extension();
+        ensure_arch()?;
+        require_tools(&["tar", manifest.compression.tool(), "arch-chroot"])?;
+        let mut state = state;
+        let resumed = (!state.done.is_empty()).then(|| state.describe_done());
+        save_deploy_state(&staging, &state).await?;
+        let mut notes = Vec::new();
+        if let Some(done) = &resumed {
+            notes.push(format!("Resumed an earlier deploy; already done were: {}.", done));
+        }
+        let read_list = |name: &str| std::fs::read_to_string(format!("{}/{}", staging, name)).unwrap_or_default().lines().map(str::trim).filter(|l| !l.is_empty()).map(String::from).collect::<Vec<_>>();
+        // Snapshots from before the foreign list was written have none; pacman reports those itself.
+        let foreign = read_list("packages.foreign.txt");
+
+        if !state.is_done(DeployPhase::Packages) {
+            check_connectivity().await?;
+            let _ = progress.send(Progress::Message("Installing the snapshot's packages...".to_string()));
+            let list = std::fs::read_to_string(format!("{}/packages.x86_64.txt", staging))?;
+            let (pacman, packages) = package_install(&list, &state.swap, &foreign, pacman_conf_override(TARGET_ROOT).await?.as_deref());
+            // A whole system's worth of packages; like pacstrap, this depends on the mirror.
+            stream_checked_feeding(escalated().args(["arch-chroot", TARGET_ROOT]).args(&pacman), Some(packages.as_bytes()), &progress, Budget::Long).await?;
+            if manifest.pinned {
+                pin_versions(&staging, &mut notes, &progress).await?;
+            }
+            state.done.push(DeployPhase::Packages);
+            save_deploy_state(&staging, &state).await?;
+        }
+        let mut wanted: Vec<String> = read_list("packages.x86_64.txt").into_iter().filter(|p| !state.swap.remove.contains(p)).collect();
+        wanted.extend(state.swap.add.iter().cloned());
+        let installed = run_checked(Command::new("pacman").args(["--root", TARGET_ROOT, "-Qq"])).await?;
+        let packages = snapshot::install_summary(&wanted, &foreign, &installed.lines().map(String::from).collect::<Vec<_>>());
+        let _ = progress.send(Progress::Percent(40));
+
+        if !state.is_done(DeployPhase::Etc) {
+            if !state.restore.is_empty() {
+                let _ = progress.send(Progress::Message("Restoring /etc...".to_string()));
+                run_checked(escalated().arg("tar").arg("-xpf").arg(format!("{}/etc.{}", staging, ext)).arg("-C").arg(TARGET_ROOT).args(snapshot::etc_extract_args(&state.restore, &state.keep))).await?;
+            }
+            state.done.push(DeployPhase::Etc);
+            save_deploy_state(&staging, &state).await?;
+        }
+        if manifest.kind.has_system() && !state.is_done(DeployPhase::Localization) {
+            let _ = progress.send(Progress::Message("Restoring the locale, time zone and keymap...".to_string()));
+            restore_localization(&manifest.localization, &mut notes).await?;
+            state.done.push(DeployPhase::Localization);
+            save_deploy_state(&staging, &state).await?;
+        }
+        let _ = progress.send(Progress::Percent(70));
+
+        match restored_home(&manifest.user) {
+            Err(note) => notes.push(note),
+            Ok(_) if state.is_done(DeployPhase::Home) => {}
+            Ok(home) => {
+                let _ = progress.send(Progress::Message(format!("Restoring /home/{}...", manifest.user)));
+                run_checked_within(escalated().args(["mkdir", "-p", &home]), Budget::Long).await?;
+                run_checked_within(escalated().args(["tar", "-xpf", &format!("{}/home.{}", staging, ext), "-C", &home]), Budget::Long).await?;
+                let owner = format!("{0}:{0}", manifest.user);
+                let chown = run_checked(escalated().args(["arch-chroot", TARGET_ROOT, "chown", "-R", &owner, &format!("/home/{}", manifest.user)])).await;
+                if chown.is_err() {
+                    notes.push(format!("User '{}' does not exist on the new system yet; fix ownership of /home/{0} after creating it.", manifest.user));
+                }
+                state.done.push(DeployPhase::Home);
+                save_deploy_state(&staging, &state).await?;
+            }
+        }
+        let _ = progress.send(Progress::Percent(90));
+
+        // Removes the state file with the rest: the deploy is complete.
+        let _ = progress.send(Progress::Message("Cleaning up...".to_string()));
+        run_checked_within(escalated().args(["rm", "-rf", &staging]), Budget::Standard).await?;
+        let _ = progress.send(Progress::Percent(100));
+
+        let (restore, entries) = (&state.restore, &state.entries);
+        let skipped: Vec<&String> = entries.iter().filter(|e| !restore.contains(e)).collect();
+        let list = |items: Vec<&String>| if items.is_empty() { "(none)".to_string() } else { items.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(", ") };
+        let mut message = if manifest.kind.has_system() {
+            format!(
+                "✅ Snapshot of '{}' ({}) deployed to {}.\n\nRestored from /etc: {}\nSkipped (kept the new system's version): {}",
+                manifest.hostname, manifest.created, TARGET_ROOT, list(restore.iter().collect()), list(skipped),
+            )
+        } else {
+            format!("✅ Dotfiles snapshot of '{}' ({}) deployed to {}.\n\nPackages and dotfiles were restored; /etc and the localization were left as they are.", manifest.hostname, manifest.created, TARGET_ROOT)
+        };
+        message.push_str(&format!("\n\n{}", packages.join("\n")));
+        for note in notes {
+            message.push_str(&format!("\n\n⚠️ {}", note));
+        }
+        let hook = Config::load().ok().and_then(|config| config.hooks.post_deploy);
+        message.push_str(&run_hook("post_deploy", hook.as_deref(), Path::new(TARGET_ROOT), &progress).await.unwrap_or_default());
+        let source_hostname = manifest.hostname.clone();
+        Ok(ActionResult::Prompt(Prompt::Checked {
+            title: "New hostname (regenerates machine-id; leave empty to keep the snapshot's identity)".to_string(),
+            default: String::new(),
+            check: hostname_or_empty,
+            then: Arc::new(move |hostname| {
+                let (old, summary) = (source_hostname.clone(), message.clone());
+                if hostname.trim().is_empty() {
+                    let text = format!("{}\n\n⚠️ Hostname and machine-id were copied from '{}'; change them before both machines share a network.", summary, old);
+                    return Action::Message { title: "Success".to_string(), text };
+                }
+                Action::execute(move |progress| regenerate_identity(hostname.trim().to_string(), old.clone(), summary.clone(), progress))
+            }),
+        }))
+    })
+}
+
+/// Writes the captured locale, time zone and keymap into the target and regenerates its locales.
+/// Parts the source did not have are noted and left at the new system's defaults.
+async fn restore_localization(localization: &Localization, notes: &mut Vec<String>) -> Result<(), ActionError> {
+    let etc = format!("{}/etc", TARGET_ROOT);
+    let mut missing = Vec::new();
+    for (name, contents) in [("locale.conf", &localization.locale_conf), ("vconsole.conf", &localization.vconsole_conf)] {
+        match contents {
+            Some(contents) => sudo_write(&format!("{}/{}", etc, name), contents).await?,
+            None => missing.push(format!("/etc/{}", name)),
+        }
+    }
+    match &localization.timezone {
+        Some(zone) if Path::new(&format!("{}/usr/share/zoneinfo/{}", TARGET_ROOT, zone)).is_file() => {
+            run_checked(escalated().args(["ln", "-sf", &format!("/usr/share/zoneinfo/{}", zone), &format!("{}/localtime", etc)])).await?;
+        }
+        Some(zone) => notes.push(format!("Time zone '{}' is not available on the new system; /etc/localtime was left as it was.", zone)),
+        None => missing.push("/etc/localtime".to_string()),
+    }
+    if localization.locales.is_empty() {
+        missing.push("locales in /etc/locale.gen".to_string());
+    } else {
+        let current = std::fs::read_to_string(format!("{}/locale.gen", etc)).unwrap_or_default();
+        sudo_write(&format!("{}/locale.gen", etc), &snapshot::enable_locales(&current, &localization.locales)).await?;
+        run_checked(escalated().args(["arch-chroot", TARGET_ROOT, "locale-gen"])).await?;
+    }
+    if !missing.is_empty() {
+        notes.push(format!("The snapshot has no {}; the new system keeps its defaults there.", missing.join(", ")));
+    }
+    Ok(())
+}
+
+/// The deploy's final stage: gives the new system its own hostname and machine-id.
+fn regenerate_identity(hostname: String, old: String, summary: String, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        if !install::is_valid_hostname(&hostname) {
+            return Err(ActionError::InvalidInput(format!("'{}' is not a valid hostname: use up to 63 letters, digits and inner hyphens.", hostname)));
+        }
+        let etc = format!("{}/etc", TARGET_ROOT);
+        let _ = progress.send(Progress::Message(format!("Setting the hostname to '{}'...", hostname)));
+        // Edited here rather than with sed, so the snapshot's hostname is only ever compared.
+        let hosts = match std::fs::read_to_string(format!("{}/hosts", etc)) {
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            hosts => hosts?,
+        };
+        sudo_write(&format!("{}/hostname", etc), &format!("{}\n", hostname)).await?;
+        sudo_write(&format!("{}/hosts", etc), &install::renamed_hosts(&hosts, &old, &hostname)).await?;
+        let _ = progress.send(Progress::Message("Regenerating /etc/machine-id...".to_string()));
+        run_checked_within(escalated().args(["rm", "-f", &format!("{}/machine-id", etc)]), Budget::Standard).await?;
+        run_checked_within(escalated().args(["arch-chroot", TARGET_ROOT, "systemd-machine-id-setup"]), Budget::Standard).await?;
+        Ok(ActionResult::Message(format!("{}\n\n✅ Hostname set to '{}' and a new machine-id generated.", summary, hostname)))
+    })
+}
+
+const HOOK_ENABLE: &str = "Enable (or change the trigger packages)";
+const HOOK_DISABLE: &str = "Disable (remove the hook)";
+
+/// Installs or removes the pacman hook that snapshots the system before upgrades.
+pub fn upgrade_hook(_app: &App<'_>) -> Action {
+    let installed = std::fs::read_to_string(snapshot::PRESNAPSHOT_HOOK).ok();
+    let state = if installed.is_some() { "enabled" } else { "disabled" };
+    let targets = installed.as_deref().map(snapshot::hook_targets).unwrap_or_else(|| snapshot::DEFAULT_HOOK_TARGETS.map(String::from).to_vec());
+    Action::Prompt(Prompt::Select {
+        title: format!("Snapshot before upgrades (currently {})", state),
+        items: vec![HOOK_ENABLE.to_string(), HOOK_DISABLE.to_string()],
+        then: Arc::new(move |choice| {
+            if choice == HOOK_DISABLE {
+                return Action::execute(remove_upgrade_hook);
+            }
+            Action::Prompt(Prompt::Input {
+                title: "Snapshot before upgrading these packages (space-separated; empty = any upgrade)".to_string(),
+                default: targets.join(" "),
+                then: Arc::new(|targets| {
+                    let targets: Vec<String> = targets.split_whitespace().map(String::from).collect();
+                    Action::execute(move |progress| install_upgrade_hook(targets.clone(), progress))
+                }),
+            })
+        }),
+    })
+}
+
+fn install_upgrade_hook(targets: Vec<String>, _progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        if let Some(bad) = targets.iter().find(|t| !t.chars().all(|c| c.is_ascii_alphanumeric() || "@._+-*".contains(c))) {
+            return Err(ActionError::InvalidInput(format!("'{}' is not a package name or pattern.", bad)));
+        }
+        let user = paths::real_user()?;
+        let exe = std::env::current_exe()?;
+        let hook = snapshot::presnapshot_hook(&exe, &user.name, &targets);
+        let dir = Path::new(snapshot::PRESNAPSHOT_HOOK).parent().map(|d| d.display().to_string()).unwrap_or_default();
+        run_checked(escalated().args(["mkdir", "-p", &dir])).await?;
+        sudo_write(snapshot::PRESNAPSHOT_HOOK, &hook).await?;
+        let trigger = if targets.is_empty() { "any package".to_string() } else { targets.join(", ") };
+        Ok(ActionResult::Message(format!(
+            "✅ Installed {}.\n\nBefore upgrading {}, pacman now runs `{} snapshot create` for {}. A failed snapshot does not stop the upgrade.\n\nSnapshots are full ones and go to the work directory; remove old ones now and then.",
+            snapshot::PRESNAPSHOT_HOOK, trigger, exe.display(), user.name,
+        )))
+    })
+}
+
+fn remove_upgrade_hook(_progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        run_checked(escalated().args(["rm", "-f", snapshot::PRESNAPSHOT_HOOK])).await?;
+        Ok(ActionResult::Message(format!("✅ Removed {}; upgrades no longer take snapshots.", snapshot::PRESNAPSHOT_HOOK)))
+    })
+}
+
+// --- Cloner Actions ---
+/// Asks whether the live system gets this system's packages too ([iso] `include_installed`
+/// first) and which packages to add (those under [iso] by default), then prepares the profile.
+pub fn create_iso(_app: &App<'_>) -> Action {
+    const INSTALLED: &str = "releng's packages and every package explicitly installed here";
+    const RELENG_ONLY: &str = "releng's packages only (the official installation image)";
+    let config = Config::load().map(|config| config.iso).unwrap_or_default();
+    let items = if config.include_installed { [INSTALLED, RELENG_ONLY] } else { [RELENG_ONLY, INSTALLED] };
+    let extra = config.extra_packages.join(" ");
+    Action::Prompt(Prompt::Select {
+        title: "Packages of the live system".to_string(),
+        items: items.map(String::from).to_vec(),
+        then: Arc::new(move |choice| {
+            let installed = choice == INSTALLED;
+            Action::Prompt(Prompt::Input {
+                title: "Extra packages for the ISO (space-separated, may be empty)".to_string(),
+                default: extra.clone(),
+                then: Arc::new(move |packages| {
+                    let packages: Vec<String> = packages.split_whitespace().map(String::from).collect();
+                    Action::execute(move |progress| customize_iso(packages.clone(), installed, progress))
+                }),
+            })
+        }),
+    })
+}
+
+/// The newest ISO in the work directory, e.g. one just built, to suggest for flashing or a test
+/// boot; otherwise where a downloaded one usually is.
+fn iso_default() -> String {
+    let built = paths::work_dir().ok().and_then(|dir| {
+        std::fs::read_dir(dir).ok()?.flatten().map(|entry| entry.path()).filter(|path| path.extension().is_some_and(|ext| ext == "iso")).max_by_key(|path| path.metadata().and_then(|m| m.modified()).ok())
+    });
+    built.map_or("~/Downloads/archlinux-x86_64.iso".to_string(), |iso| iso.display().to_string())
+}
+
+/// Copies releng into `iso-profile` in the work directory and applies the customizations, then
+/// shows what the ISO will hold before building. The profile can be edited by hand meanwhile.
+/// With `installed`, the packages explicitly installed here from the repositories are added; AUR
+/// and other foreign ones cannot be, as mkarchiso installs from the repositories.
+fn customize_iso(extra: Vec<String>, installed: bool, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        ensure_arch()?;
+        require_tools(&["mkarchiso"])?;
+        if let Some(bad) = iso::invalid_package(&extra) {
+            return Err(ActionError::InvalidInput(format!("'{}' is not a package name.", bad)));
+        }
+        let config = Config::load()?.iso;
+        let user = paths::real_user()?;
+        let work_dir = paths::work_dir()?;
+        paths::ensure_writable(&work_dir)?;
+        let profile = work_dir.join("iso-profile");
+        let _ = progress.send(Progress::Message(format!("Copying the releng profile to {}...", profile.display())));
+        let _ = std::fs::remove_dir_all(&profile);
+        run_checked(Command::new("cp").arg("-r").arg(iso::RELENG).arg(&profile)).await?;
+        let mut summary = vec![format!("Profile: {} (from releng)", profile.display())];
+
+        if installed {
+            let _ = progress.send(Progress::Message("Listing the packages explicitly installed here...".to_string()));
+            let native: Vec<String> = run_checked_within(Command::new("pacman").arg("-Qqen"), Budget::Probe).await?.lines().map(String::from).collect();
+            let list = profile.join("packages.x86_64");
+            let releng = std::fs::read_to_string(&list)?;
+            let added = native.iter().filter(|package| !iso::listed(&releng).contains(&package.as_str())).count();
+            std::fs::write(&list, iso::with_packages(&releng, &native))?;
+            let mut query = Command::new("pacman");
+            query.arg("-Qqem");
+            // `pacman -Qqem` exits non-zero when there are no foreign packages.
+            let foreign = within(Budget::Probe, describe(&query), async { query.kill_on_drop(true).output().await.map_err(|e| spawn_error(&query, e)) }).await?;
+            let foreign = String::from_utf8_lossy(&foreign.stdout).lines().count();
+            let left_out = if foreign == 0 { String::new() } else { format!("; {} foreign (AUR) packages left out", foreign) };
+            summary.push(format!("Installed packages: {} added from this system{}", added, left_out));
+        }
+        if !extra.is_empty() {
+            let _ = progress.send(Progress::Message("Checking that the extra packages exist...".to_string()));
+            run_checked_within(Command::new("pacman").args(["-Sddp", "--print-format", "%n"]).args(&extra), Budget::Probe).await?;
+            let list = profile.join("packages.x86_64");
+            std::fs::write(&list, iso::with_packages(&std::fs::read_to_string(&list)?, &extra))?;
+            summary.push(format!("Extra packages: {}", extra.join(" ")));
+        }
+        if let Some(overlay) = &config.overlay {
+            let overlay = paths::under_home(overlay.clone(), &user);
+            if !overlay.is_dir() {
+                return Err(ActionError::InvalidInput(format!("The [iso] overlay {} is not a directory.", overlay.display())));
+            }
+            run_checked(Command::new("cp").arg("-rT").arg(&overlay).arg(profile.join("airootfs"))).await?;
+            summary.push(format!("Overlay: {} copied into airootfs", overlay.display()));
+        }
+        if config.bundle_snapshot {
+            let snapshot = latest_snapshot().ok_or_else(|| ActionError::InvalidInput("[iso] bundle_snapshot is set, but the work directory holds no snapshot. Create one first.".to_string()))?;
+            let airootfs = profile.join("airootfs");
+            let live_dir = airootfs.join(iso::LIVE_WORK_DIR.trim_start_matches('/'));
+            std::fs::create_dir_all(&live_dir)?;
+            for file in [snapshot.clone(), signature::sig_path(&snapshot)].iter().filter(|file| file.is_file()) {
+                run_checked(Command::new("cp").arg(file).arg(&live_dir)).await?;
+            }
+            let exe = airootfs.join(iso::LIVE_EXE.trim_start_matches('/'));
+            std::fs::create_dir_all(exe.parent().unwrap_or(&airootfs))?;
+            std::fs::copy(std::env::current_exe()?, &exe)?;
+            let profiledef = profile.join("profiledef.sh");
+            let permissions = iso::with_file_permission(&std::fs::read_to_string(&profiledef)?, iso::LIVE_EXE, "0:0:755")
+                .ok_or_else(|| ActionError::InvalidInput(format!("{} has no file_permissions to make the suite executable.", profiledef.display())))?;
+            std::fs::write(&profiledef, permissions)?;
+            let zlogin = airootfs.join("root/.zlogin");
+            let login = std::fs::read_to_string(&zlogin).unwrap_or_default();
+            std::fs::write(&zlogin, login + &iso::autostart())?;
+            let size = std::fs::metadata(&snapshot).map(|m| disk::human_size(m.len())).unwrap_or_default();
+            summary.push(format!("Bundled: {} ({}), with the suite started on tty1 to deploy it", snapshot.display(), size));
+        }
+        let missing = iso::missing_files(&profile);
+        if !missing.is_empty() {
+            return Err(ActionError::InvalidInput(format!("The profile in {} is incomplete; missing: {}.", profile.display(), missing.join(", "))));
+        }
+        run_checked(Command::new("bash").arg("-n").arg(profile.join("profiledef.sh"))).await?;
+        Ok(ActionResult::Prompt(Prompt::Confirm {
+            title: "Build the ISO?".to_string(),
+            text: format!(
+                "{}\n\nmkarchiso builds the image as root into the work directory; this downloads every package and takes a while. Packages that conflict with releng's make it fail. The profile can still be edited by hand before you continue.",
+                summary.join("\n")
+            ),
+            default: true,
+            then: Box::new(Action::execute(move |progress| build_iso(profile.clone(), progress))),
+        }))
+    })
+}
+
+/// Builds the ISO from `profile` once there is room for it: mkarchiso's work directory in the
+/// cache needs several times what the live system's packages download as (see `iso::build_space`).
+fn build_iso(profile: PathBuf, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        check_connectivity().await?;
+        let user = paths::real_user()?;
+        let work_dir = paths::work_dir()?;
+        let cache_dir = paths::base_dir(BaseDir::Cache)?;
+        std::fs::create_dir_all(&cache_dir)?;
+        let scratch = cache_dir.join("iso_work");
+        let _ = progress.send(Progress::Message("Estimating the space the build needs...".to_string()));
+        let packages: Vec<String> = iso::listed(&std::fs::read_to_string(profile.join("packages.x86_64"))?).into_iter().map(String::from).collect();
+        // A root without a local database: every package and dependency counts.
+        let download = estimate_download(&packages, "/nonexistent").await.map_or(0, |pending| pending.iter().map(|p| p.size).sum());
+        let space = iso::build_space(download);
+        let device = |dir: &Path| dir.ancestors().find_map(|p| std::fs::metadata(p).ok()).map(|m| std::os::unix::fs::MetadataExt::dev(&m));
+        if device(&cache_dir) == device(&work_dir) {
+            paths::precheck_work_dir(&work_dir, space.scratch + space.iso)?;
+        } else {
+            paths::precheck_work_dir(&cache_dir, space.scratch)?;
+            paths::precheck_work_dir(&work_dir, space.iso)?;
+        }
+        let before = SystemTime::now();
+        let mut mkarchiso = escalated();
+        mkarchiso.args(["mkarchiso", "-v", "-w"]).arg(&scratch).arg("-o").arg(&work_dir).arg(&profile);
+        let built = stream_checked_within(&mut mkarchiso, &progress, Budget::Long).await;
+        // Its work directory holds a whole root filesystem; never keep it.
+        run_checked(escalated().arg("rm").arg("-rf").arg("--").arg(&scratch)).await?;
+        built?;
+        let iso = std::fs::read_dir(&work_dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "iso") && path.metadata().and_then(|m| m.modified()).is_ok_and(|at| at >= before))
+            .max()
+            .ok_or_else(|| ActionError::Other(format!("mkarchiso finished, but no new ISO is in {}.", work_dir.display())))?;
+        run_checked(escalated().arg("chown").arg(format!("{}:", user.name)).arg(&iso)).await?;
+        let size = std::fs::metadata(&iso).map(|m| disk::human_size(m.len())).unwrap_or_default();
+        Ok(ActionResult::File { path: iso, details: format!("✅ Bootable ISO built ({}). Write it to a USB drive with Utilities → Flash ISO to USB.", size) })
+    })
+}
+
+// --- Utilities Actions ---
+pub fn inspect_system(progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let _ = progress.send(Progress::Message("Probing CPU, GPU, memory and storage...".to_string()));
+        Ok(ActionResult::Report(Box::new(inspect::gather().await)))
+    })
+}
+
+/// Runs the environment self-check and shows it as a checklist.
+pub fn run_doctor(progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let _ = progress.send(Progress::Message("Checking the environment...".to_string()));
+        Ok(ActionResult::Message(format!("Environment health check\n\n{}", doctor::render(&doctor::run().await))))
+    })
+}
+
+/// Offers the inspector's recommended packages as a pre-checked list and installs the chosen ones.
+pub fn install_recommended_drivers(app: &App<'_>) -> Action {
+    let Some(report) = &app.system_report else {
+        return Action::Message {
+            title: "Inspection Required".to_string(),
+            text: INSPECT_FIRST.to_string(),
+        };
+    };
+    Action::Prompt(Prompt::MultiSelect {
+        title: "Select packages to install (Space toggles, Enter installs)".to_string(),
+        checked: vec![true; report.recommended.len()],
+        items: report.recommended.clone(),
+        then: Arc::new(|packages| Action::execute(move |progress| install_packages(packages.clone(), progress))),
+    })
+}
+
+fn install_packages(packages: Vec<String>, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        if packages.is_empty() {
+            return Ok(ActionResult::Message("No packages selected; nothing was installed.".to_string()));
+        }
+        ensure_arch()?;
+        check_connectivity().await?;
+        let _ = progress.send(Progress::Message(format!("Installing: {}", packages.join(" "))));
+        stream_checked(pacman().await?.args(["-S", "--needed", "--noconfirm"]).args(&packages), &progress).await?;
+        Ok(ActionResult::Message(format!("✅ Installed: {}", packages.join(" "))))
+    })
+}
+
+/// Offers the orphaned packages (installed as dependencies, no longer needed) for removal.
+pub fn remove_orphans(progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        ensure_arch()?;
+        let _ = progress.send(Progress::Message("Looking for orphaned packages...".to_string()));
+        // `pacman -Qdtq` exits with 1 when there is nothing to list.
+        let orphans = match run_checked_within(Command::new("pacman").arg("-Qdtq"), Budget::Probe).await {
+            Ok(list) => list.lines().map(String::from).collect::<Vec<_>>(),
+            Err(ActionError::CommandFailed { code: Some(1), .. }) => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        if orphans.is_empty() {
+            return Ok(ActionResult::Message("✅ No orphaned packages found.".to_string()));
+        }
+        Ok(ActionResult::Prompt(Prompt::MultiSelect {
+            title: "Select orphaned packages to remove (Space toggles, Enter continues)".to_string(),
+            checked: vec![true; orphans.len()],
+            items: orphans,
+            then: Arc::new(|packages| Action::execute(move |progress| confirm_removal(packages.clone(), progress))),
+        }))
+    })
+}
+
+/// Looks up what still depends on `packages` and asks before removing them, listing any dependents.
+fn confirm_removal(packages: Vec<String>, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        if packages.is_empty() {
+            return Ok(ActionResult::Message("No packages selected; nothing was removed.".to_string()));
+        }
+        let _ = progress.send(Progress::Message("Checking reverse dependencies...".to_string()));
+        let info = run_checked_within(Command::new("pacman").arg("-Qi").args(&packages), Budget::Probe).await?;
+        let dependents = packages::dependents(&packages::parse_qi(&info), &packages);
+        let mut text = format!("Remove {} package(s) with 'pacman -Rns'?\n\n{}", packages.len(), packages.join(" "));
+        if !dependents.is_empty() {
+            text.push_str("\n\n⚠️ These are still used by other packages:");
+            for (package, users) in &dependents {
+                text.push_str(&format!("\n  {}: {}", package, users));
+            }
+        }
+        Ok(ActionResult::Prompt(Prompt::Confirm {
+            title: "Remove packages?".to_string(),
+            text,
+            default: dependents.is_empty(),
+            then: Box::new(Action::execute(move |progress| remove_packages(packages.clone(), progress))),
+        }))
+    })
+}
+
+fn remove_packages(packages: Vec<String>, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let _ = progress.send(Progress::Message(format!("Removing: {}", packages.join(" "))));
+        stream_checked(escalated().args(["pacman", "-Rns", "--noconfirm"]).args(&packages), &progress).await?;
+        Ok(ActionResult::Message(format!("✅ Removed: {}", packages.join(" "))))
+    })
+}
+
+/// Lists the installed and the common kernels, marking the running one, and installs or removes
+/// kernels to match the ticked ones.
+pub fn manage_kernels(progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        ensure_arch()?;
+        let _ = progress.send(Progress::Message("Looking for installed kernels...".to_string()));
+        let running = run_checked_within(Command::new("uname").arg("-r"), Budget::Probe).await?.trim().to_string();
+        let kernels = packages::installed_kernels(Path::new("/usr/lib/modules"), &running);
+        let installed: Vec<String> = kernels.iter().map(|k| k.name.clone()).collect();
+        let mut names: Vec<String> = packages::KERNELS.iter().map(|k| k.to_string()).filter(|k| !installed.contains(k)).chain(installed.iter().cloned()).collect();
+        names.sort();
+        let label = |name: &String| match kernels.iter().find(|k| &k.name == name) {
+            Some(kernel) if kernel.running => format!("{}  (installed {}, running)", name, kernel.release),
+            Some(kernel) => format!("{}  (installed {})", name, kernel.release),
+            None => name.clone(),
+        };
+        let running_kernel = kernels.iter().find(|k| k.running).map(|k| k.name.clone());
+        Ok(ActionResult::Prompt(Prompt::MultiSelect {
+            title: format!("Kernels to have installed (running: {})", running),
+            checked: names.iter().map(|n| installed.contains(n)).collect(),
+            items: names.iter().map(label).collect(),
+            then: Arc::new(move |ticked| {
+                let chosen: Vec<String> = ticked.iter().filter_map(|label| label.split_whitespace().next()).map(String::from).collect();
+                let (install, remove) = packages::kernel_changes(&installed, &chosen);
+                if install.is_empty() && remove.is_empty() {
+                    return Action::Message { title: "Kernels".to_string(), text: "The ticked kernels are the installed ones; nothing changes.".to_string() };
+                }
+                if chosen.is_empty() {
+                    return Action::Message { title: "Kernels".to_string(), text: "Keep at least one kernel installed, or the system cannot boot.".to_string() };
+                }
+                let list = |items: &[String]| if items.is_empty() { "nothing".to_string() } else { items.join(" ") };
+                let mut text = format!("Install: {}\nRemove:  {}\n\nThe bootloader configuration is regenerated afterwards.", list(&install), list(&remove));
+                let removes_running = running_kernel.as_ref().is_some_and(|k| remove.contains(k));
+                if removes_running {
+                    text.push_str("\n\n⚠️ This removes the kernel that is running now. Its modules disappear with it, so reboot into another kernel right after.");
+                }
+                Action::Prompt(Prompt::Confirm {
+                    title: "Change the installed kernels?".to_string(),
+                    text,
+                    default: !removes_running,
+                    then: Box::new(Action::execute(move |progress| change_kernels(install.clone(), remove.clone(), progress))),
+                })
+            }),
+        }))
+    })
+}
+
+/// Installs and removes kernels, together with their headers when headers are in use, then
+/// regenerates grub.cfg.
+fn change_kernels(install: Vec<String>, remove: Vec<String>, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        // `pacman -Qq` prints the installed ones and exits 1 if any are missing, which is expected.
+        let headers: Vec<String> = packages::KERNELS.iter().copied().chain(remove.iter().map(String::as_str)).map(|k| format!("{}-headers", k)).collect();
+        let mut query = Command::new("pacman");
+        query.arg("-Qq").args(&headers);
+        let output = within(Budget::Probe, describe(&query), async { query.kill_on_drop(true).output().await.map_err(|e| spawn_error(&query, e)) }).await?;
+        let installed_headers: Vec<String> = String::from_utf8_lossy(&output.stdout).lines().map(String::from).collect();
+        let mut install_args = install.clone();
+        if !installed_headers.is_empty() {
+            install_args.extend(install.iter().map(|k| format!("{}-headers", k)));
+        }
+        let mut remove_args = remove.clone();
+        remove_args.extend(remove.iter().map(|k| format!("{}-headers", k)).filter(|h| installed_headers.contains(h)));
+
+        if !install_args.is_empty() {
+            check_connectivity().await?;
+            let _ = progress.send(Progress::Message(format!("Installing: {}", install_args.join(" "))));
+            stream_checked_within(pacman().await?.args(["-S", "--needed", "--noconfirm"]).args(&install_args), &progress, Budget::Long).await?;
+        }
+        let _ = progress.send(Progress::Percent(50));
+        if !remove_args.is_empty() {
+            let _ = progress.send(Progress::Message(format!("Removing: {}", remove_args.join(" "))));
+            stream_checked(escalated().args(["pacman", "-Rns", "--noconfirm"]).args(&remove_args), &progress).await?;
+        }
+        let _ = progress.send(Progress::Percent(80));
+        let mut message = format!("✅ Installed: {}\nRemoved: {}", if install_args.is_empty() { "nothing".to_string() } else { install_args.join(" ") }, if remove_args.is_empty() { "nothing".to_string() } else { remove_args.join(" ") });
+        if Path::new(grub::GRUB_CFG).exists() {
+            let _ = progress.send(Progress::Message("Regenerating the GRUB configuration...".to_string()));
+            stream_checked(escalated().args(["grub-mkconfig", "-o", grub::GRUB_CFG]), &progress).await?;
+            message.push_str(&format!("\n\n{} was regenerated.", grub::GRUB_CFG));
+        } else {
+            message.push_str(&format!("\n\n⚠️ {} was not found; update your bootloader's entries by hand.", grub::GRUB_CFG));
+        }
+        Ok(ActionResult::Message(message))
+    })
+}
+
+/// Opens GRUB_CMDLINE_LINUX_DEFAULT from /etc/default/grub for editing, then shows the change
+/// before applying it.
+pub fn edit_kernel_parameters(_app: &App<'_>) -> Action {
+    let text = match std::fs::read_to_string(grub::DEFAULT_GRUB) {
+        Ok(text) => text,
+        Err(e) => return Action::Message { title: "Kernel Parameters".to_string(), text: format!("❌ Could not read {} ({}). Is GRUB installed?", grub::DEFAULT_GRUB, e) },
+    };
+    let current = grub::value(&text, grub::CMDLINE_DEFAULT).unwrap_or_default();
+    Action::Prompt(Prompt::Input {
+        title: format!("{} (kernel parameters for every normal boot)", grub::CMDLINE_DEFAULT),
+        default: current.clone(),
+        then: Arc::new(move |edited| {
+            let edited = edited.trim().to_string();
+            if let Some(reason) = grub::invalid_value(&edited) {
+                return Action::Message { title: "Invalid Parameters".to_string(), text: reason.to_string() };
+            }
+            if edited == current {
+                return Action::Message { title: "Kernel Parameters".to_string(), text: "The parameters are unchanged; nothing was written.".to_string() };
+            }
+            let mut diff = format!("{}\n\n- {}=\"{}\"\n+ {}=\"{}\"", grub::DEFAULT_GRUB, grub::CMDLINE_DEFAULT, current, grub::CMDLINE_DEFAULT, edited);
+            let clearing = edited.is_empty() && !current.is_empty();
+            if clearing {
+                diff.push_str("\n\n⚠️ This removes every default kernel parameter.");
+            }
+            diff.push_str(&format!("\n\nThe original is backed up next to it and {} is regenerated.", grub::GRUB_CFG));
+            let updated = grub::set_value(&text, grub::CMDLINE_DEFAULT, &edited);
+            Action::Prompt(Prompt::Confirm {
+                title: "Apply the new kernel parameters?".to_string(),
+                text: diff,
+                default: !clearing,
+                then: Box::new(Action::execute(move |progress| write_grub_defaults(updated.clone(), progress))),
+            })
+        }),
+    })
+}
+
+/// Backs up /etc/default/grub, writes `contents` to it and regenerates grub.cfg.
+fn write_grub_defaults(contents: String, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        require_tools(&["grub-mkconfig"])?;
+        let backup = format!("{}.{}.bak", grub::DEFAULT_GRUB, chrono::Local::now().format("%Y%m%d-%H%M%S"));
+        let _ = progress.send(Progress::Message(format!("Backing up {} to {}...", grub::DEFAULT_GRUB, backup)));
+        run_checked(escalated().args(["cp", "-a", grub::DEFAULT_GRUB, &backup])).await?;
+        sudo_write(grub::DEFAULT_GRUB, &contents).await?;
+        let _ = progress.send(Progress::Percent(30));
+        let _ = progress.send(Progress::Message("Regenerating the GRUB configuration...".to_string()));
+        stream_checked(escalated().args(["grub-mkconfig", "-o", grub::GRUB_CFG]), &progress).await?;
+        Ok(ActionResult::Message(format!("✅ Kernel parameters updated and {} regenerated.\n\nThe original is saved as {}.\nReboot to boot with the new parameters.", grub::GRUB_CFG, backup)))
+    })
+}
+
+async fn list_services() -> Result<Vec<Unit>, ActionError> {
+    let units = run_checked_within(Command::new("systemctl").args(services::LIST_UNITS), Budget::Probe).await?;
+    let unit_files = run_checked_within(Command::new("systemctl").args(services::LIST_UNIT_FILES), Budget::Probe).await?;
+    Ok(services::parse_units(&units, &unit_files))
+}
+
+/// Opens the services list; its keys start, stop, restart, enable or disable the selected unit.
+pub fn manage_services(progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let _ = progress.send(Progress::Message("Listing services...".to_string()));
+        Ok(ActionResult::Services { units: list_services().await?, notice: None })
+    })
+}
+
+/// Runs `systemctl <op> <unit>` and reopens the list with its outcome. A failure is reported in the
+/// list too, so the next operation is a key away.
+pub fn service_op(unit: String, op: ServiceOp, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let _ = progress.send(Progress::Message(format!("systemctl {} {}...", op.command(), unit)));
+        let outcome = run_checked_within(escalated().args(["systemctl", op.command(), &unit]), Budget::Standard).await;
+        let units = list_services().await?;
+        let notice = match outcome {
+            Ok(_) => match units.iter().find(|u| u.name == unit) {
+                Some(now) => format!("✅ {} {}: now {}, {}/{}", op.done(), unit, now.enabled, now.active, now.sub),
+                None => format!("✅ {} {}", op.done(), unit),
+            },
+            Err(ActionError::CommandFailed { stderr, .. }) => format!("❌ {} {} failed: {}", op.command(), unit, stderr.lines().last().unwrap_or("no output")),
+            Err(e) => format!("❌ {} {} failed: {}", op.command(), unit, e),
+        };
+        Ok(ActionResult::Services { units, notice: Some(notice) })
+    })
+}
+
+/// Shows `unit`'s last journal entries at `priority` or more severe; with `follow`, keeps adding
+/// new ones until stopped.
+pub fn service_journal(unit: String, priority: &'static str, follow: bool, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        // As root, so system services' entries are readable without the systemd-journal group.
+        let mut cmd = escalated();
+        cmd.arg("journalctl").args(services::journal_args(&unit, priority, follow));
+        if !follow {
+            let journal = run_checked_within(&mut cmd, Budget::Probe).await?;
+            return Ok(ActionResult::Message(if journal.trim().is_empty() { format!("No journal entries for {} at priority {} or above.", unit, priority) } else { journal }));
+        }
+        let _ = progress.send(Progress::Follow(format!("Journal of {} (priority {}+) | Esc stops", unit, priority)));
+        let mut child = cmd.kill_on_drop(true).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().map_err(|e| spawn_error(&cmd, e))?;
+        let stdout = forward_lines(child.stdout.take(), progress.clone());
+        let stderr = forward_lines(child.stderr.take(), progress.clone());
+        // No budget: following only ends when the user stops it, which drops (and kills) journalctl.
+        let status = child.wait().await?;
+        let (_, stderr) = tokio::join!(stdout, stderr);
+        if !status.success() {
+            return Err(ActionError::command_failed(describe(&cmd), status, "", &stderr.unwrap_or_default()));
+        }
+        Ok(ActionResult::Message(format!("journalctl stopped following {}.", unit)))
+    })
+}
+
+/// Asks which ISO to write and how to check it, then which USB drive to write it to.
+pub fn flash_iso(_app: &App<'_>) -> Action {
+    Action::Prompt(Prompt::Checked {
+        title: "ISO image to flash".to_string(),
+        default: iso_default(),
+        check: existing_file,
+        then: Arc::new(|path| {
+            let iso = expand_home(path.trim()).unwrap_or_else(|_| PathBuf::from(path.trim()));
+            let name = iso.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            let sums = iso.with_file_name("sha256sums.txt");
+            // An ISO built here is named like a release but is not in the official sums.
+            let built_here = paths::work_dir().is_ok_and(|dir| iso.starts_with(dir));
+            let default = if sums.is_file() {
+                sums.display().to_string()
+            } else if flash::arch_iso_version(&name).is_some() && !built_here {
+                "auto".to_string()
+            } else {
+                String::new()
+            };
+            Action::Prompt(Prompt::Input {
+                title: "Expected SHA256: a hash, a sha256sums.txt path, 'auto' for the official sums, or empty to skip".to_string(),
+                default,
+                then: Arc::new(move |checksum| {
+                    let path = path.clone();
+                    Action::execute(move |progress| choose_flash_target(path.clone(), checksum.clone(), progress))
+                }),
+            })
+        }),
+    })
+}
+
+/// Checks `iso` against the SHA256 `checksum` describes (see `flash_iso`) and fails on a mismatch.
+/// Returns the line to show with the result.
+async fn verify_iso_checksum(iso: &Path, checksum: &str, progress: &ProgressTx) -> Result<String, ActionError> {
+    let name = iso.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let not_listed = |source: &str| ActionError::InvalidInput(format!("{} has no SHA256 for {}.", source, name));
+    let (expected, source) = if flash::is_sha256(checksum) {
+        (checksum.to_lowercase(), "the hash you entered".to_string())
+    } else if checksum.eq_ignore_ascii_case("auto") {
+        let version = flash::arch_iso_version(&name).ok_or_else(|| {
+            ActionError::InvalidInput(format!("'{}' is not an official Arch ISO name (archlinux-YYYY.MM.DD-x86_64.iso); give the hash or a sums file instead.", name))
+        })?;
+        require_tools(&["curl"])?;
+        let url = format!("{}/sha256sums.txt", flash::ARCH_RELEASE_URL.replace("{version}", version));
+        let _ = progress.send(Progress::Message(format!("Fetching {}...", url)));
+        let sums = run_checked_within(Command::new("curl").args(["-fsSL", &url]), Budget::Probe).await?;
+        (flash::hash_from_sums(&sums, &name).ok_or_else(|| not_listed(&url))?, url)
+    } else {
+        let sums_file = expand_home(checksum)?;
+        let sums = std::fs::read_to_string(&sums_file).map_err(|e| ActionError::InvalidInput(format!("Cannot read {} ({}).", sums_file.display(), e)))?;
+        let source = sums_file.display().to_string();
+        (flash::hash_from_sums(&sums, &name).ok_or_else(|| not_listed(&source))?, source)
+    };
+    let _ = progress.send(Progress::Message(format!("Hashing {}...", iso.display())));
+    let len = std::fs::metadata(iso)?.len();
+    let computed = flash::sha256_prefix(tokio::fs::File::open(iso).await?, len, |percent| { let _ = progress.send(Progress::Percent(percent)); }).await?;
+    if computed != expected {
+        return Err(ActionError::InvalidInput(format!(
+            "❌ {} does not match its checksum; it is corrupt or incomplete. Download it again.\n\nComputed: {}\nExpected: {}\n(from {})",
+            name, computed, expected, source
+        )));
+    }
+    Ok(format!("✅ SHA256 matches {}:\n{}", source, computed))
+}
+
+fn choose_flash_target(path: String, checksum: String, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let iso = expand_home(path.trim())?;
+        if !iso.is_file() {
+            return Err(ActionError::InvalidInput(format!("ISO '{}' does not exist.", iso.display())));
+        }
+        let checked = match checksum.trim() {
+            "" => "SHA256 not checked.".to_string(),
+            checksum => verify_iso_checksum(&iso, checksum, &progress).await?,
+        };
+        let mut warnings = Vec::new();
+        let signed = if Config::load()?.flash.verify_signature {
+            let _ = progress.send(Progress::Message("Checking the GPG signature...".to_string()));
+            let (signature, warning) = check_iso_signature(&iso).await?;
+            warnings.extend(warning);
+            signature.describe()
+        } else {
+            "Signature not checked.".to_string()
+        };
+        warnings.extend(flash::classify_image(&flash::read_image_head(&iso)?).warning(&iso));
+        let _ = progress.send(Progress::Message("Looking for USB drives...".to_string()));
+        let targets = flash::candidates(&disk::list().await?);
+        if targets.is_empty() {
+            return Err(ActionError::InvalidInput("No USB or removable drives were found (the one the system runs from is never offered). Plug one in and try again.".to_string()));
+        }
+        let pick = pick_flash_target(iso.clone(), targets, format!("{}\n{}", checked, signed));
+        if warnings.is_empty() {
+            return Ok(ActionResult::Prompt(pick));
+        }
+        Ok(ActionResult::Prompt(Prompt::Confirm {
+            title: "Check this image before writing it".to_string(),
+            text: format!("{}\n\nChoose a drive for it anyway?", warnings.iter().map(|w| format!("⚠️ {}", w)).collect::<Vec<_>>().join("\n\n")),
+            default: false,
+            then: Box::new(Action::Prompt(pick)),
+        }))
+    })
+}
+
+/// Checks `iso` against `<iso>.sig`, looking the key up in the Arch keyring too. The warning, if
+/// any, says where an official image's signature can be downloaded.
+async fn check_iso_signature(iso: &Path) -> Result<(signature::Signature, Option<String>), ActionError> {
+    let sig = signature::sig_path(iso);
+    let signature = signature::verify_signature(iso, &sig, Some(Path::new(signature::ARCH_KEYRING))).await?;
+    let mut warning = signature.warning(iso, &sig);
+    let name = iso.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    if let (Some(warning), Verdict::Missing, Some(version)) = (&mut warning, signature.verdict, flash::arch_iso_version(&name)) {
+        warning.push_str(&format!(" Download it from {}/{}.sig", flash::ARCH_RELEASE_URL.replace("{version}", version), name));
+    }
+    Ok((signature, warning))
+}
+
+/// Asks which of `targets` to overwrite with `iso`, then confirms with the drive's details and
+/// the outcome of the checksum and signature checks.
+fn pick_flash_target(iso: PathBuf, targets: Vec<FlashTarget>, checked: String) -> Prompt {
+    Prompt::Select {
+        title: "Select the USB drive to overwrite".to_string(),
+        items: targets.iter().map(FlashTarget::label).collect(),
+        then: Arc::new(move |choice| {
+            let Some(target) = targets.iter().find(|t| t.label() == choice).cloned() else { return step_required("The selected drive is no longer listed.") };
+            let text = format!(
+                "Write {} to {}?\n\nModel:  {}\nSerial: {}\nSize:   {}\n\n{}\n\nEverything on the drive will be erased. The drive is checked again before writing.",
+                iso.display(), target.dev, target.model, target.serial.as_deref().unwrap_or("unknown"), disk::human_size(target.size), checked
+            );
+            let (iso, phrase) = (iso.clone(), target.dev.clone());
+            let run = Action::execute(move |progress| write_iso(iso.clone(), target.clone(), progress));
+            Action::Prompt(Prompt::Phrase { title: "This cannot be undone".to_string(), text, phrase, then: Box::new(run) })
+        }),
+    }
+}
+
+/// Writes `iso` to `device` without asking anything, then verifies it unless `verify` is unset.
+/// The device must be a USB or removable drive, and the file an Arch ISO unless `any_image`. When
+/// [flash] verifies signatures, the ISO needs a good, trusted one unless `allow_unsigned`.
+pub fn flash_unattended(iso: PathBuf, device: String, verify: bool, any_image: bool, allow_unsigned: bool, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        if !any_image {
+            if let Some(warning) = flash::classify_image(&flash::read_image_head(&iso)?).warning(&iso) {
+                return Err(ActionError::InvalidInput(format!("{} Pass --any-image to write it anyway.", warning)));
+            }
+        }
+        if !allow_unsigned && Config::load()?.flash.verify_signature {
+            if let (_, Some(warning)) = check_iso_signature(&iso).await? {
+                return Err(ActionError::InvalidInput(format!("{}\nPass --allow-unsigned to write it anyway.", warning)));
+            }
+        }
+        let target = flash::candidates(&disk::list().await?)
+            .into_iter()
+            .find(|t| t.dev == device)
+            .ok_or_else(|| ActionError::InvalidInput(format!("{} is not a USB or removable drive.", device)))?;
+        write_image(&iso, &target, &progress).await?;
+        if verify {
+            verify_flash(iso, target, progress).await
+        } else {
+            Ok(ActionResult::Message(format!("✅ Wrote {} to {} (not verified).", iso.display(), target.dev)))
+        }
+    })
+}
+
+/// Writes the image, then offers to verify it.
+fn write_iso(iso: PathBuf, target: FlashTarget, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        write_image(&iso, &target, &progress).await?;
+        let text = format!(
+            "✅ Wrote {} to {} ({}, serial {}).\n\nRead the image back and compare its SHA256 with the ISO? This takes about as long as the write.",
+            iso.display(), target.dev, target.model, target.serial.as_deref().unwrap_or("unknown")
+        );
+        let verify = Action::execute(move |progress| verify_flash(iso.clone(), target.clone(), progress));
+        Ok(ActionResult::Prompt(Prompt::Confirm { title: "Verify the drive?".to_string(), text, default: true, then: Box::new(verify) }))
+    })
+}
+
+/// Re-checks that the confirmed drive is still the one at its device path, then writes the image.
+async fn write_image(iso: &Path, target: &FlashTarget, progress: &ProgressTx) -> Result<(), ActionError> {
+    let _ = progress.send(Progress::Message(format!("Checking that {} is still the selected drive...", target.dev)));
+    target.recheck().await?;
+    ensure_not_busy(&target.dev).await?;
+    let config = Config::load()?.flash;
+    let total = std::fs::metadata(iso)?.len();
+    let bs = format!("bs={}", config.block_size);
+    let mut cmd = escalated();
+    if config.use_pv && command_exists("pv") {
+        let _ = progress.send(Progress::Message(format!("Writing {} to {} (pv, {})...", iso.display(), target.dev, bs)));
+        cmd.args(["sh", "-c", r#"pv -n -t -b -i 0.5 "$1" | dd of="$2" "$3" iflag=fullblock oflag=direct conv=fsync status=none"#, "sh"]);
+        cmd.arg(iso).arg(&target.dev).arg(&bs);
+    } else {
+        let _ = progress.send(Progress::Message(format!("Writing {} to {} (dd, {})...", iso.display(), target.dev, bs)));
+        cmd.arg("dd").arg(format!("if={}", iso.display())).arg(format!("of={}", target.dev)).args([bs.as_str(), "conv=fsync", "oflag=direct", "status=progress"]);
+    }
+    let mut child = cmd.kill_on_drop(true).stdout(Stdio::null()).stderr(Stdio::piped()).spawn().map_err(|e| spawn_error(&cmd, e))?;
+    // Progress readings become gauge updates; anything else is shown and kept for the error report.
+    let mut tail = VecDeque::with_capacity(TAIL_LINES);
+    let status = within(Budget::Long, describe(&cmd), async {
+        if let Some(stderr) = child.stderr.take() {
+            read_records(stderr, |line| match flash::Copied::parse(line) {
+                Some(copied) => {
+                    let _ = progress.send(Progress::Percent(copied.percent(total)));
+                    let _ = progress.send(Progress::Label(copied.label(total)));
+                }
+                None if !line.trim().is_empty() => {
+                    if tail.len() == TAIL_LINES {
+                        tail.pop_front();
+                    }
+                    tail.push_back(line.to_string());
+                    let _ = progress.send(Progress::Message(line.to_string()));
+                }
+                None => {}
+            })
+            .await?;
+        }
+        Ok(child.wait().await?)
+    })
+    .await?;
+    if !status.success() {
+        return Err(ActionError::command_failed(describe(&cmd), status, "", &Vec::from(tail).join("\n")));
+    }
+    Ok(())
+}
+
+/// Compares the SHA256 of the ISO with that of the same number of bytes read back from the drive.
+fn verify_flash(iso: PathBuf, target: FlashTarget, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        target.recheck().await?;
+        let len = std::fs::metadata(&iso)?.len();
+        let report = |percent| { let _ = progress.send(Progress::Percent(percent)); };
+
+        let _ = progress.send(Progress::Message(format!("Hashing {}...", iso.display())));
+        let expected = flash::sha256_prefix(tokio::fs::File::open(&iso).await?, len, report).await?;
+
+        let _ = progress.send(Progress::Message(format!("Reading {} back from {}...", disk::human_size(len), target.dev)));
+        let mut cmd = escalated();
+        cmd.args(["head", "-c", &len.to_string(), &target.dev]);
+        let mut child = cmd.kill_on_drop(true).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().map_err(|e| spawn_error(&cmd, e))?;
+        let stderr = forward_lines(child.stderr.take(), progress.clone());
+        let stdout = child.stdout.take().ok_or_else(|| ActionError::Other("head produced no output stream".to_string()))?;
+        let (actual, status) = within(Budget::Long, describe(&cmd), async {
+            let actual = flash::sha256_prefix(stdout, len, report).await;
+            Ok((actual, child.wait().await?))
+        })
+        .await?;
+        let stderr = stderr.await.unwrap_or_default();
+        if !status.success() {
+            return Err(ActionError::command_failed(describe(&cmd), status, "", &stderr));
+        }
+        let actual = actual?;
+
+        if actual == expected {
+            Ok(ActionResult::Message(format!("✅ Verified: {} matches the ISO.\n\nSHA256 {}", target.dev, expected)))
+        } else {
+            Err(ActionError::Other(format!(
+                "❌ Verification failed: {} does not match the ISO. The drive may be faulty or counterfeit; try another one.\n\nISO:   {}\nDrive: {}",
+                target.dev, expected, actual
+            )))
+        }
+    })
+}
+
+// --- Test Boot ---
+
+/// Asks for the ISO to boot in QEMU, suggesting the newest one built into the work directory.
+pub fn test_boot_iso(_app: &App<'_>) -> Action {
+    Action::Prompt(Prompt::Checked {
+        title: "ISO image to test-boot".to_string(),
+        default: iso_default(),
+        check: existing_file,
+        then: Arc::new(|path| {
+            let iso = expand_home(path.trim()).unwrap_or_else(|_| PathBuf::from(path.trim()));
+            Action::execute(move |progress| start_test_boot(iso.clone(), progress))
+        }),
+    })
+}
+
+/// Boots `iso` in QEMU in a window, or without a desktop in a tmux or screen session, and leaves
+/// it running. Without QEMU, offers to install it and then boots.
+fn start_test_boot(iso: PathBuf, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        if !iso.is_file() {
+            return Err(ActionError::InvalidInput(format!("{} is not a file.", iso.display())));
+        }
+        if !command_exists(vm::QEMU) {
+            let retry = Action::execute(move |progress| start_test_boot(iso.clone(), progress));
+            let _ = progress.send(Progress::Message(format!("{} is not installed.", vm::QEMU)));
+            return preview_dependency_install(vm::PACKAGES.map(String::from).to_vec(), Some(retry), false, progress).await;
+        }
+        let firmware = vm::ovmf(Path::exists);
+        let kvm = std::fs::OpenOptions::new().read(true).write(true).open("/dev/kvm").is_ok();
+        let boot = match &firmware {
+            Some(firmware) => format!("UEFI ({})", firmware.display()),
+            None => "BIOS; install edk2-ovmf to test UEFI".to_string(),
+        };
+        let accel = if kvm { "KVM" } else { "no KVM, so slowly" };
+        if vm::has_desktop() {
+            let log = paths::base_dir(BaseDir::State)?.join("logs").join("qemu-test-boot.log");
+            std::fs::create_dir_all(log.parent().unwrap_or(Path::new(".")))?;
+            let mut cmd = Command::new(vm::QEMU);
+            cmd.args(vm::qemu_args(&iso, firmware.as_deref(), kvm, vm::Display::Window)).stdin(Stdio::null()).stdout(Stdio::null()).stderr(std::fs::File::create(&log)?);
+            let _ = progress.send(Progress::Message(format!("Starting {} ...", describe(&cmd))));
+            let mut child = cmd.spawn().map_err(|e| spawn_error(&cmd, e))?;
+            // A bad image or firmware makes QEMU quit at once; one that lasts this long is booting.
+            if let Ok(status) = tokio::time::timeout(Duration::from_secs(2), child.wait()).await {
+                let output = std::fs::read_to_string(&log).unwrap_or_default();
+                return Err(ActionError::Other(format!("QEMU quit right away ({}):\n{}", status?, output.trim())));
+            }
+            return Ok(ActionResult::Message(format!("✅ {} is booting in a QEMU window: {}, {}.\n\nClose the window to stop it; QEMU's messages go to {}.", iso.display(), boot, accel, log.display())));
+        }
+        let Some(multiplexer) = Multiplexer::available(command_exists) else {
+            return Err(ActionError::Other("No desktop session to open a QEMU window in, and neither tmux nor screen is installed to show its console.".to_string()));
+        };
+        let argv: Vec<String> = std::iter::once(vm::QEMU.to_string()).chain(vm::qemu_args(&iso, firmware.as_deref(), kvm, vm::Display::Curses)).collect();
+        let now = chrono::Local::now();
+        let name = detach::session_name("test boot", now);
+        run_checked(Command::new(multiplexer.program()).args(multiplexer.start_args(&name, &detach::script(&argv)))).await?;
+        let mut sessions = detach::load();
+        sessions.push(Session { name: name.clone(), multiplexer, job: format!("test boot {}", iso.display()), started: now.format("%Y-%m-%d %H:%M").to_string() });
+        detach::save(&sessions)?;
+        Ok(ActionResult::Message(format!(
+            "✅ {} is booting in {} session '{}': {}, {}.\n\nThere is no desktop here, so its console is drawn in the terminal. Watch it with: {}\nPower the live system off, or close that session, to stop it.",
+            iso.display(), multiplexer.program(), name, boot, accel, multiplexer.attach_command(&name),
+        )))
+    })
+}
+
+// --- Pacman Keyring ---
+const PACMAN_LOG: &str = "/var/log/pacman.log";
+const PACMAN_GNUPG: &str = "/etc/pacman.d/gnupg";
+
+/// Looks for keyring trouble in pacman's log, then asks before refreshing and repopulating it.
+pub fn repair_keyring(progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        ensure_arch()?;
+        let _ = progress.send(Progress::Message("Checking the pacman keyring...".to_string()));
+        let initialized = Path::new(PACMAN_GNUPG).join("trustdb.gpg").exists();
+        let errors = std::fs::read_to_string(PACMAN_LOG).map(|log| packages::keyring_errors(&log, 5)).unwrap_or_default();
+        let mut text = match (initialized, errors.is_empty()) {
+            (false, _) => format!("⚠️ The keyring in {} has not been set up; it is initialized first.", PACMAN_GNUPG),
+            (true, true) => "✅ No keyring errors in pacman's log since archlinux-keyring was last updated. Refreshing it is still safe.".to_string(),
+            (true, false) => format!("⚠️ Recent keyring errors in {}:\n  {}", PACMAN_LOG, errors.join("\n  ")),
+        };
+        text.push_str("\n\nThis runs `pacman -Sy archlinux-keyring`, then `pacman-key --populate archlinux`.");
+        Ok(ActionResult::Prompt(Prompt::Confirm {
+            title: "Repair the pacman keyring?".to_string(),
+            text,
+            default: !initialized || !errors.is_empty(),
+            then: Box::new(Action::execute(move |progress| fix_keyring(initialized, progress))),
+        }))
+    })
+}
+
+fn fix_keyring(initialized: bool, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        check_connectivity().await?;
+        if !initialized {
+            stream_checked(escalated().args(["pacman-key", "--init"]), &progress).await?;
+        }
+        let mut cmd = pacman().await?;
+        stream_checked_within(cmd.args(["-Sy", "--needed", "--noconfirm", "archlinux-keyring"]), &progress, Budget::Long).await?;
+        stream_checked(escalated().args(["pacman-key", "--populate", "archlinux"]), &progress).await?;
+        let version = run_checked_within(Command::new("pacman").args(["-Q", "archlinux-keyring"]), Budget::Probe).await.unwrap_or_default();
+        Ok(ActionResult::Message(format!("✅ The pacman keyring is up to date ({}). Retry what failed with a signature error.", version.trim())))
+    })
+}
+
+// --- Mirrors ---
+/// reflector's countries, listed once per session: the list comes from the mirror status service.
+static COUNTRIES: OnceLock<Vec<Country>> = OnceLock::new();
+
+/// Asks which countries' mirrors to rank, from reflector's list; without reflector, offers to
+/// install it first.
+pub fn rank_mirrors(progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        ensure_arch()?;
+        if !command_exists("reflector") {
+            let _ = progress.send(Progress::Message("reflector is not installed.".to_string()));
+            return preview_dependency_install(vec!["reflector".to_string()], Some(Action::execute(rank_mirrors)), false, progress).await;
+        }
+        let countries = match COUNTRIES.get() {
+            Some(countries) => countries.clone(),
+            None => {
+                check_connectivity().await?;
+                let _ = progress.send(Progress::Message("Fetching the countries reflector knows mirrors in...".to_string()));
+                let countries = mirrors::parse_countries(&run_checked_within(Command::new("reflector").arg("--list-countries"), Budget::Probe).await?);
+                if countries.is_empty() {
+                    return Err(ActionError::Other("reflector listed no countries.".to_string()));
+                }
+                COUNTRIES.get_or_init(|| countries).clone()
+            }
+        };
+        Ok(ActionResult::Prompt(Prompt::MultiSelect {
+            title: "Countries to rank mirrors in".to_string(),
+            items: countries.iter().map(Country::label).collect(),
+            checked: vec![false; countries.len()],
+            then: Arc::new(move |chosen: Vec<String>| {
+                let codes: Vec<String> = countries.iter().filter(|c| chosen.contains(&c.label())).map(|c| c.code.clone()).collect();
+                Action::execute(move |progress| write_mirrorlist(codes.clone(), progress))
+            }),
+        }))
+    })
+}
+
+/// Ranks the mirrors in the `codes` countries with reflector and replaces the mirrorlist with
+/// them, keeping the old one as a backup.
+fn write_mirrorlist(codes: Vec<String>, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        if codes.is_empty() {
+            return Err(ActionError::InvalidInput("Tick at least one country.".to_string()));
+        }
+        check_connectivity().await?;
+        let _ = progress.send(Progress::Message(format!("Ranking the mirrors in {} by download rate...", codes.join(", "))));
+        // Rating downloads from every mirror, which takes a while on a slow link.
+        let list = run_checked_within(Command::new("reflector").args(mirrors::rank_args(&codes)), Budget::Long).await?;
+        let servers = mirrors::servers(&list);
+        if servers.is_empty() {
+            return Err(ActionError::Other(format!("reflector found no recently synced HTTPS mirrors in {}; {} is unchanged.", codes.join(", "), mirrors::MIRRORLIST)));
+        }
+        let backup = format!("{}.{}.bak", mirrors::MIRRORLIST, chrono::Local::now().format("%Y%m%d-%H%M%S"));
+        run_checked(escalated().args(["cp", "-a", mirrors::MIRRORLIST, &backup])).await?;
+        sudo_write(mirrors::MIRRORLIST, &list).await?;
+        let fastest: Vec<String> = servers.iter().take(5).map(|server| format!("  {}", server)).collect();
+        Ok(ActionResult::Message(format!(
+            "✅ {} now lists {} mirrors; the fastest:\n{}\n\nThe previous one is saved as {}.",
+            mirrors::MIRRORLIST,
+            servers.len(),
+            fastest.join("\n"),
+            backup
+        )))
+    })
+}
+
+// --- Package Recipes ---
+const RECIPE_EXPLICIT: &str = "Explicitly installed packages";
+const RECIPE_WITH_DEPS: &str = "Explicitly installed packages and, listed apart, their dependencies";
+
+/// Asks whether to list dependencies too, then writes this system's package recipe.
+pub fn export_recipe(_app: &App<'_>) -> Action {
+    Action::Prompt(Prompt::Select {
+        title: "What should the recipe list?".to_string(),
+        items: vec![RECIPE_EXPLICIT.to_string(), RECIPE_WITH_DEPS.to_string()],
+        then: Arc::new(|choice| Action::execute(move |progress| write_recipe(choice == RECIPE_WITH_DEPS, progress))),
+    })
+}
+
+/// `pacman -Qq<flags>`; pacman exits non-zero when nothing matches, which is not an error here.
+async fn query_packages(flags: &str) -> Result<Vec<String>, ActionError> {
+    let mut query = Command::new("pacman");
+    query.arg(format!("-Qq{}", flags));
+    let output = within(Budget::Probe, describe(&query), async { query.kill_on_drop(true).output().await.map_err(|e| spawn_error(&query, e)) }).await?;
+    Ok(String::from_utf8_lossy(&output.stdout).lines().map(str::trim).filter(|l| !l.is_empty()).map(String::from).collect())
+}
+
+fn write_recipe(with_deps: bool, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let _ = progress.send(Progress::Message("Listing the installed packages...".to_string()));
+        let mut recipe = Recipe { native: query_packages("en").await?, foreign: query_packages("em").await?, ..Recipe::default() };
+        if with_deps {
+            recipe.native_deps = query_packages("dn").await?;
+            recipe.foreign_deps = query_packages("dm").await?;
+        }
+        let host = std::fs::read_to_string("/etc/hostname").map_or("unknown".to_string(), |h| h.trim().to_string());
+        let now = chrono::Local::now();
+        let work_dir = paths::work_dir()?;
+        paths::ensure_writable(&work_dir)?;
+        let file = work_dir.join(format!("packages-{}-{}.txt", host, now.format("%Y%m%d")));
+        let text = recipe.render(&format!("arch-suite package recipe of {}, {}. Apply it with Utilities → Apply Package Recipe.", host, now.format("%Y-%m-%d %H:%M")));
+        let path = save_text(&file.display().to_string(), &text)?;
+        let details = format!("✅ Package recipe written: {} native and {} foreign packages{}.", recipe.native.len(), recipe.foreign.len(), if with_deps { format!(", plus {} dependencies", recipe.native_deps.len() + recipe.foreign_deps.len()) } else { String::new() });
+        Ok(ActionResult::File { path, details })
+    })
+}
+
+/// The newest `packages-*.txt` recipe in the work directory.
+fn latest_recipe() -> Option<PathBuf> {
+    std::fs::read_dir(paths::work_dir().ok()?)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("packages-"))
+        .max_by_key(|entry| entry.metadata().and_then(|m| m.modified()).ok())
+        .map(|entry| entry.path())
+}
+
+/// Asks which recipe to apply, suggesting the newest one in the work directory.
+pub fn apply_recipe(_app: &App<'_>) -> Action {
+    Action::Prompt(Prompt::Checked {
+        title: "Package recipe to apply".to_string(),
+        default: latest_recipe().map(|p| p.display().to_string()).unwrap_or_default(),
+        check: existing_file,
+        then: Arc::new(|path| Action::execute(move |progress| preview_recipe(path.clone(), progress))),
+    })
+}
+
+fn preview_recipe(path: String, _progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let path = expand_home(&path)?;
+        let text = std::fs::read_to_string(&path).map_err(|e| ActionError::InvalidInput(format!("Cannot read {}: {}", path.display(), e)))?;
+        let recipe = Recipe::parse(&text);
+        if recipe.is_empty() {
+            return Err(ActionError::InvalidInput(format!("{} lists no packages.", path.display())));
+        }
+        let helper = packages::aur_helper(command_exists);
+        let foreign = recipe.foreign.len() + recipe.foreign_deps.len();
+        let foreign_note = match (foreign, helper) {
+            (0, _) => String::new(),
+            (n, Some(helper)) => format!("\n{} foreign packages are built with {} as {}.", n, helper, paths::real_user()?.name),
+            (n, None) => format!("\n⚠️ {} foreign packages are skipped: install an AUR helper ({}) to build them.", n, packages::AUR_HELPERS.join(" or ")),
+        };
+        let text = format!(
+            "{} explicit and {} dependency packages from the repositories are installed with `pacman -S --needed`; those already here are left alone. Dependencies are marked as such.{}\n\nConfigs and home directories are not touched.",
+            recipe.native.len(), recipe.native_deps.len(), foreign_note,
+        );
+        Ok(ActionResult::Prompt(Prompt::Confirm {
+            title: "Apply package recipe?".to_string(),
+            text,
+            default: true,
+            then: Box::new(Action::execute(move |progress| install_recipe(recipe.clone(), helper, progress))),
+        }))
+    })
+}
+
+fn install_recipe(recipe: Recipe, helper: Option<&'static str>, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        check_connectivity().await?;
+        for (packages, asdeps) in [(&recipe.native, false), (&recipe.native_deps, true)] {
+            if packages.is_empty() {
+                continue;
+            }
+            let mut cmd = pacman().await?;
+            cmd.args(["-S", "--needed", "--noconfirm"]);
+            if asdeps {
+                cmd.arg("--asdeps");
+            }
+            stream_checked_within(cmd.args(packages), &progress, Budget::Long).await?;
+        }
+        let mut foreign = 0;
+        if let Some(helper) = helper {
+            for (packages, asdeps) in [(&recipe.foreign, false), (&recipe.foreign_deps, true)] {
+                if packages.is_empty() {
+                    continue;
+                }
+                let mut cmd = as_real_user(helper)?;
+                cmd.args(["-S", "--needed", "--noconfirm"]);
+                if asdeps {
+                    cmd.arg("--asdeps");
+                }
+                stream_checked_within(cmd.args(packages), &progress, Budget::Long).await?;
+                foreign += packages.len();
+            }
+        }
+        let skipped = recipe.foreign.len() + recipe.foreign_deps.len() - foreign;
+        let mut message = format!("✅ Applied the recipe: {} repository packages and {} foreign packages are installed.", recipe.native.len() + recipe.native_deps.len(), foreign);
+        if skipped > 0 {
+            message.push_str(&format!("\n⚠️ {} foreign packages were skipped: no AUR helper is installed.", skipped));
+        }
+        Ok(ActionResult::Message(message))
+    })
+}
+
+// --- Manual Installer Actions ---
+/// Lists the whole disks and asks which one to erase; the one the system runs from is left out.
+pub fn manual_wipe_disk(progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let _ = progress.send(Progress::Message("Looking for disks...".to_string()));
+        let disks: Vec<_> = disk::list().await?.into_iter().filter(|d| d.kind == "disk" && !d.runs_the_system()).collect();
+        if disks.is_empty() {
+            return Err(ActionError::Other("No disks were found apart from the one the system runs from.".to_string()));
+        }
+        Ok(ActionResult::Prompt(Prompt::SelectDisk {
+            title: "Select the disk to wipe".to_string(),
+            disks: disks.clone(),
+            then: Arc::new(move |disk| {
+                // Show what is on the disk right in the confirmation, mounted partitions marked.
+                let layout = disks.iter().find(|d| d.name == disk).map(|d| d.layout_lines().join("\n")).unwrap_or_default();
+                step_action_noting(StepKind::Wipe { disk: disk.clone() }, Vec::new(), true, format!("Currently on {}:\n{}", disk, layout))
+            }),
+        }))
+    })
+}
+
+// Menu item availability checks (`MenuItem::requires`); each says what to do first.
+const WIPE_FIRST: &str = "Wipe a disk first (step 1) so there is a target for the new layout.";
+const PARTITION_FIRST: &str = "Partition the disk first (step 2).";
+const HISTORY_FIRST: &str = "Perform at least one manual installer step first; the script replays the recorded history.";
+const INSPECT_FIRST: &str = "Run 'System Inspector & Prep' first so there is a list of recommended packages to install.";
+
+fn require(ok: bool, reason: &str) -> Result<(), String> {
+    if ok { Ok(()) } else { Err(reason.to_string()) }
+}
+
+fn step_done(app: &App<'_>, id: &str) -> bool {
+    app.install.history.iter().any(|step| step.kind.id() == id)
+}
+
+pub fn needs_disk(app: &App<'_>) -> Result<(), String> {
+    require(app.install.disk.is_some(), WIPE_FIRST)
+}
+
+pub fn needs_partitions(app: &App<'_>) -> Result<(), String> {
+    require(app.install.efi_partition.is_some() && app.install.root_partition.is_some(), PARTITION_FIRST)
+}
+
+pub fn needs_mounted(app: &App<'_>) -> Result<(), String> {
+    require(step_done(app, "mount"), &format!("Mount the partitions first (step 4); the base system is installed into {}.", TARGET_ROOT))
+}
+
+pub fn needs_base_system(app: &App<'_>) -> Result<(), String> {
+    require(step_done(app, "pacstrap"), "Install the base system first (step 5); the rest is set up from inside it.")
+}
+
+pub fn needs_history(app: &App<'_>) -> Result<(), String> {
+    require(!app.install.history.is_empty(), HISTORY_FIRST)
+}
+
+pub fn needs_report(app: &App<'_>) -> Result<(), String> {
+    require(app.system_report.is_some(), INSPECT_FIRST)
+}
+
+pub fn needs_snapshot(_app: &App<'_>) -> Result<(), String> {
+    let dir = paths::work_dir().map_or("the work directory".to_string(), |dir| dir.display().to_string());
+    require(latest_snapshot().is_some(), &format!("Create a snapshot first (or copy a snapshot-* file into {}).", dir))
+}
+
+/// Asks for the partition table type and the boot and root sizes, then partitions; a layout that
+/// does not match the way this machine booted is allowed, with a warning in the confirmation.
+pub fn manual_partition_disk(app: &App<'_>) -> Action {
+    let Some(disk) = app.install.disk.clone() else { return step_required(WIPE_FIRST) };
+    let uefi = install::booted_uefi();
+    let label = |table: PartitionTable| {
+        let matches = (table == PartitionTable::Gpt) == uefi;
+        format!("{} ({}){}", table, table.boot_mode(), if matches { " - matches how this machine booted" } else { "" })
+    };
+    let labels: Vec<String> = PartitionTable::ALL.into_iter().map(label).collect();
+    Action::Prompt(Prompt::Select {
+        title: "Partition table".to_string(),
+        items: labels.clone(),
+        then: Arc::new(move |choice| {
+            let table = PartitionTable::ALL.into_iter().zip(&labels).find(|(_, l)| **l == choice).map(|(t, _)| t).unwrap_or_default();
+            let (efi, root) = (install::partition_path(&disk, 1), install::partition_path(&disk, 2));
+            let note = match (table, uefi) {
+                (PartitionTable::Mbr, true) => "⚠ This machine booted in UEFI mode. An MBR disk with BIOS GRUB only boots if the firmware's legacy (CSM) mode is enabled.",
+                (PartitionTable::Gpt, false) => "⚠ This machine booted in BIOS mode. The GPT layout installs GRUB for UEFI, which this firmware may not be able to boot.",
+                _ => "",
+            };
+            let boot = if table == PartitionTable::Gpt { "EFI" } else { "/boot" };
+            let field = SizeField { label: boot.to_string(), total: disk_space(&disk), used: Vec::new(), rest: false };
+            let (disk, note) = (disk.clone(), note.to_string());
+            Action::Prompt(Prompt::Size {
+                title: format!("{} partition size", boot),
+                field: field.clone(),
+                default: "512M".to_string(),
+                then: Arc::new(move |efi_size| {
+                    let efi_size = efi_size.trim().to_uppercase();
+                    let used = vec![(boot.to_string(), install::size_bytes(&efi_size).unwrap_or(0))];
+                    let (disk, efi, root, note) = (disk.clone(), efi.clone(), root.clone(), note.clone());
+                    Action::Prompt(Prompt::Size {
+                        title: "Root partition size (empty for the rest of the disk)".to_string(),
+                        field: SizeField { label: "Root".to_string(), used, rest: true, ..field.clone() },
+                        default: String::new(),
+                        then: Arc::new(move |root_size| {
+                            let root_size = Some(root_size.trim().to_uppercase()).filter(|size| !size.is_empty());
+                            let kind = StepKind::Partition { disk: disk.clone(), efi: efi.clone(), root: root.clone(), efi_size: efi_size.clone(), root_size, table };
+                            step_action_noting(kind, Vec::new(), true, note.clone())
+                        }),
+                    })
+                }),
+            })
+        }),
+    })
+}
+
+/// What partitions can use of `disk`: its size less the 2 MiB the partition table and the first
+/// partition's alignment take.
+fn disk_space(disk: &str) -> u64 {
+    disk::size_of(disk).map_or(size_field::UNKNOWN, |bytes| bytes.saturating_sub(2 << 20))
+}
+
+/// Asks whether to encrypt the root partition; with LUKS, asks for the passphrase twice.
+pub fn manual_format_partitions(app: &App<'_>) -> Action {
+    let (Some(efi), Some(root)) = (app.install.efi_partition.clone(), app.install.root_partition.clone()) else {
+        return step_required(PARTITION_FIRST);
+    };
+    let table = app.install.table;
+    const PLAIN: &str = "No, plain ext4 root";
+    Action::Prompt(Prompt::Select {
+        title: "Encrypt the root partition?".to_string(),
+        items: vec![PLAIN.to_string(), "Yes, LUKS2 (asks for a passphrase at boot)".to_string()],
+        then: Arc::new(move |choice| {
+            let kind = |encrypted| StepKind::Format { efi: efi.clone(), root: root.clone(), fs: RootFs::Ext4, table, encrypted };
+            if choice == PLAIN {
+                return step_action(kind(false), Vec::new(), true);
+            }
+            let kind = kind(true);
+            Action::Prompt(Prompt::Secret {
+                title: "LUKS passphrase".to_string(),
+                then: Arc::new(move |passphrase| {
+                    if passphrase.is_empty() {
+                        return Action::Message { title: "No Passphrase".to_string(), text: "An encrypted root needs a passphrase; run the step again to enter one.".to_string() };
+                    }
+                    let kind = kind.clone();
+                    Action::Prompt(Prompt::Secret {
+                        title: "Repeat the LUKS passphrase".to_string(),
+                        then: Arc::new(move |repeated| {
+                            if repeated != passphrase {
+                                return Action::Message { title: "Passphrases Differ".to_string(), text: "The two passphrases did not match; run the step again.".to_string() };
+                            }
+                            step_action_keyed(kind.clone(), Vec::new(), true, String::new(), Some(Arc::new(repeated)))
+                        }),
+                    })
+                }),
+            })
+        }),
+    })
+}
+
+pub fn manual_mount_partitions(app: &App<'_>) -> Action {
+    let (Some(efi), Some(root)) = (app.install.efi_partition.clone(), app.install.root_partition.clone()) else {
+        return step_required("Partition and format the disk first (steps 2 and 3).");
+    };
+    let ram_kb = std::fs::read_to_string("/proc/meminfo").ok().and_then(|m| inspect::parse_meminfo(&m).ok()).map_or(0, |m| m.total_kb);
+    let root = if app.install.encrypted { install::luks_device() } else { root };
+    let table = app.install.table;
+    Action::Prompt(Prompt::Input {
+        title: "Swap file size (e.g. 4G; empty for no swap)".to_string(),
+        default: install::default_swap_size(ram_kb),
+        then: Arc::new(move |size| {
+            let size = size.trim().to_uppercase();
+            if !size.is_empty() && !install::is_valid_size(&size) {
+                return Action::Message { title: "Invalid Size".to_string(), text: format!("'{}' is not a size; use a number followed by K, M or G, e.g. 4G.", size) };
+            }
+            let swap = Some(size).filter(|s| !s.is_empty());
+            step_action(StepKind::Mount { root: root.clone(), efi: efi.clone(), table, swap }, Vec::new(), true)
+        }),
+    })
+}
+
+pub fn manual_pacstrap(app: &App<'_>) -> Action {
+    let mut packages: Vec<String> = BASE_PACKAGES.map(String::from).to_vec();
+    for package in app.system_report.iter().flat_map(|r| &r.recommended) {
+        if !packages.contains(package) {
+            packages.push(package.clone());
+        }
+    }
+    step_action(StepKind::Pacstrap { packages }, Vec::new(), true)
+}
+
+pub fn manual_chroot_grub(app: &App<'_>) -> Action {
+    let bios_disk = app.install.disk.clone().filter(|_| app.install.table == PartitionTable::Mbr);
+    let luks = app.install.root_partition.clone().filter(|_| app.install.encrypted);
+    step_action(StepKind::Bootloader { bios_disk, luks }, Vec::new(), true)
+}
+
+/// Generates the target's fstab for review.
+pub fn manual_fstab(progress: ProgressTx) -> AppAction {
+    generate_fstab(Vec::new(), true, progress)
+}
+
+/// Runs genfstab for the filesystems mounted under the target. With `confirm`, the entries are
+/// shown for review and editing; otherwise a valid fstab is written right away.
+fn generate_fstab(rest: Vec<StepKind>, confirm: bool, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        require_tools(&["genfstab"])?;
+        let _ = progress.send(Progress::Message(format!("$ genfstab -U {}", TARGET_ROOT)));
+        let content = run_checked(escalated().args(["genfstab", "-U", TARGET_ROOT])).await?;
+        if confirm {
+            return Ok(ActionResult::Prompt(review_fstab(content, rest, confirm)));
+        }
+        if let Some(why) = install::invalid_fstab(&content) {
+            return Err(ActionError::InvalidInput(format!("genfstab's output was not written: {}", why)));
+        }
+        run_step(StepKind::Fstab { content }, rest, confirm, None, progress).await
+    })
+}
+
+/// Lists the fstab's entries under a choice to write it; picking an entry edits its mount options
+/// and comes back here.
+fn review_fstab(content: String, rest: Vec<StepKind>, confirm: bool) -> Prompt {
+    const WRITE: &str = "✓ Write this to /mnt/etc/fstab";
+    let mut items = vec![WRITE.to_string()];
+    items.extend(install::fstab_entries(&content).into_iter().map(String::from));
+    Prompt::Select {
+        title: "Review fstab (Enter on an entry edits its options)".to_string(),
+        items,
+        then: Arc::new(move |choice| {
+            if choice == WRITE {
+                return match install::invalid_fstab(&content) {
+                    Some(why) => Action::Prompt(Prompt::Confirm {
+                        title: "Invalid fstab".to_string(),
+                        text: format!("{}\n\nGo back to the entries?", why),
+                        default: true,
+                        then: Box::new(Action::Prompt(review_fstab(content.clone(), rest.clone(), confirm))),
+                    }),
+                    None => step_action(StepKind::Fstab { content: content.clone() }, rest.clone(), confirm),
+                };
+            }
+            let (content, rest) = (content.clone(), rest.clone());
+            let fields: Vec<&str> = choice.split_whitespace().collect();
+            Action::Prompt(Prompt::Input {
+                title: format!("Mount options for {}", fields.get(1).unwrap_or(&"the entry")),
+                default: fields.get(3).unwrap_or(&"defaults").to_string(),
+                then: Arc::new(move |options| {
+                    let options = options.trim();
+                    let content = if options.is_empty() || options.contains(char::is_whitespace) { content.clone() } else { install::set_fstab_options(&content, &choice, options) };
+                    Action::Prompt(review_fstab(content, rest.clone(), confirm))
+                }),
+            })
+        }),
+    }
+}
+
+/// Asks for a search term, then offers the `items` that contain it, ignoring case (all of them
+/// for an empty one), and runs `then` with the choice.
+fn search_select(what: &str, example: &str, items: Vec<String>, then: InputFn) -> Action {
+    let what = what.to_string();
+    Action::Prompt(Prompt::Input {
+        title: format!("Search {} (e.g. {}; empty for all)", what, example),
+        default: String::new(),
+        then: Arc::new(move |query| {
+            let query = query.trim().to_lowercase();
+            let found: Vec<String> = items.iter().filter(|item| item.to_lowercase().contains(&query)).cloned().collect();
+            if found.is_empty() {
+                return Action::Message { title: "No Match".to_string(), text: format!("No {} contains '{}'; run the step again with another search.", what, query) };
+            }
+            Action::Prompt(Prompt::Select { title: format!("Select the {} ({} found)", what, found.len()), items: found, then: then.clone() })
+        }),
+    })
+}
+
+/// The target's copy of a system path, e.g. /mnt/etc/locale.gen for /etc/locale.gen.
+fn in_target(path: &str) -> PathBuf {
+    Path::new(TARGET_ROOT).join(path.trim_start_matches('/'))
+}
+
+/// Offers the time zones installed in the target, searchable by city or region.
+pub fn manual_timezone(_app: &App<'_>) -> Action {
+    let zones = install::zone_names(&in_target("/usr/share/zoneinfo"));
+    if zones.is_empty() {
+        return step_required("No time zones were found under /mnt/usr/share/zoneinfo; install the base system first (step 5).");
+    }
+    search_select("time zone", "berlin", zones, Arc::new(|zone| step_action(StepKind::Timezone { zone }, Vec::new(), true)))
+}
+
+/// Offers the target's locale.gen entries, the enabled ones ticked; the first ticked is the language.
+pub fn manual_locale(_app: &App<'_>) -> Action {
+    let locales = match std::fs::read_to_string(in_target("/etc/locale.gen")) {
+        Ok(text) => install::parse_locale_gen(&text),
+        Err(_) => return step_required("/mnt/etc/locale.gen was not found; install the base system first (step 5)."),
+    };
+    Action::Prompt(Prompt::MultiSelect {
+        title: "Locales to generate (Space ticks; the first ticked sets LANG)".to_string(),
+        checked: locales.iter().map(|(_, enabled)| *enabled).collect(),
+        items: locales.into_iter().map(|(locale, _)| locale).collect(),
+        then: Arc::new(|locales| {
+            if locales.is_empty() {
+                return Action::Message { title: "No Locale".to_string(), text: "Tick at least one locale, e.g. en_US.UTF-8 UTF-8; run the step again.".to_string() };
+            }
+            step_action(StepKind::Locale { locales }, Vec::new(), true)
+        }),
+    })
+}
+
+/// Offers the console keymaps installed in the target, searchable by name.
+pub fn manual_keymap(_app: &App<'_>) -> Action {
+    let keymaps = install::keymap_names(&in_target("/usr/share/kbd/keymaps"));
+    if keymaps.is_empty() {
+        return step_required("No keymaps were found under /mnt/usr/share/kbd/keymaps; install the base system first (step 5).");
+    }
+    search_select("keymap", "de-latin1", keymaps, Arc::new(|keymap| step_action(StepKind::Keymap { keymap }, Vec::new(), true)))
+}
+
+/// Asks for the user name, then for its groups.
+pub fn manual_user(_app: &App<'_>) -> Action {
+    Action::Prompt(Prompt::Checked {
+        title: "User name".to_string(),
+        default: String::new(),
+        check: user_name,
+        then: Arc::new(|name| {
+            let name = name.trim().to_string();
+            Action::Prompt(Prompt::Input {
+                title: format!("Groups for {} (wheel may use sudo)", name),
+                default: "wheel".to_string(),
+                then: Arc::new(move |groups| {
+                    let groups: Vec<String> = groups.split([',', ' ']).filter(|g| !g.is_empty()).map(String::from).collect();
+                    if let Some(group) = groups.iter().find(|g| !install::is_valid_user_name(g)) {
+                        return Action::Message { title: "Invalid Group".to_string(), text: format!("'{}' is not a group name.", group) };
+                    }
+                    step_action(StepKind::User { name: name.clone(), groups }, Vec::new(), true)
+                }),
+            })
+        }),
+    })
+}
+
+/// Asks twice for the password of root and of every user the installer created.
+pub fn manual_passwords(app: &App<'_>) -> Action {
+    let mut users = vec!["root".to_string()];
+    for step in &app.install.history {
+        if let StepKind::User { name: user, .. } | StepKind::Configure { user: Some(user), .. } = &step.kind {
+            if !users.contains(user) {
+                users.push(user.clone());
+            }
+        }
+    }
+    ask_passwords(users, 0, String::new())
+}
+
+/// Asks for the password of `users[next]`, adding a `user:password` line to `lines`, until there
+/// are lines for all of them.
+fn ask_passwords(users: Vec<String>, next: usize, lines: String) -> Action {
+    let Some(user) = users.get(next).cloned() else {
+        return step_action_keyed(StepKind::Passwords { users }, Vec::new(), true, String::new(), Some(Arc::new(lines)));
+    };
+    Action::Prompt(Prompt::Secret {
+        title: format!("Password for {}", user),
+        then: Arc::new(move |password| {
+            if password.is_empty() {
+                return Action::Message { title: "No Password".to_string(), text: format!("{} needs a password; run the step again to enter one.", user) };
+            }
+            let (users, user, lines) = (users.clone(), user.clone(), lines.clone());
+            Action::Prompt(Prompt::Secret {
+                title: format!("Repeat the password for {}", user),
+                then: Arc::new(move |repeated| {
+                    if repeated != password {
+                        return Action::Message { title: "Passwords Differ".to_string(), text: "The two passwords did not match; run the step again.".to_string() };
+                    }
+                    ask_passwords(users.clone(), next + 1, format!("{}{}:{}\n", lines, user, repeated))
+                }),
+            })
+        }),
+    })
+}
+
+/// Shows every step performed so far and the commands it ran.
+pub fn show_install_history(app: &App<'_>) -> Action {
+    Action::Message { title: "Install History".to_string(), text: app.install.history_text() }
+}
+
+/// Asks where to save a bash script that replays the recorded steps, then writes it.
+pub fn export_install_script(app: &App<'_>) -> Action {
+    if app.install.history.is_empty() {
+        return step_required(HISTORY_FIRST);
+    }
+    let script = app.install.script();
+    Action::Prompt(Prompt::Input {
+        title: "Save install script to".to_string(),
+        default: format!("~/arch-install-{}.sh", chrono::Local::now().format("%Y%m%d-%H%M%S")),
+        then: Arc::new(move |path| {
+            let script = script.clone();
+            Action::execute(move |_progress| {
+                let (path, script) = (path.clone(), script.clone());
+                Box::pin(async move {
+                    use std::os::unix::fs::PermissionsExt;
+                    let path = save_text(&path, &script)?;
+                    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+                    Ok(ActionResult::File { path, details: "✅ Install script written. Lines marked DESTRUCTIVE erase data; check the device names before running it on another machine.".to_string() })
+                })
+            })
+        }),
+    })
+}
+
+/// Asks for an install plan file, validates it and offers to run every step in sequence.
+pub fn import_install_plan(_app: &App<'_>) -> Action {
+    Action::Prompt(Prompt::Checked {
+        title: "Install plan (TOML) to run".to_string(),
+        default: "~/install-plan.toml".to_string(),
+        check: existing_file,
+        then: Arc::new(|path| {
+            Action::execute(move |progress| {
+                let path = path.clone();
+                Box::pin(async move {
+                    let plan = InstallPlan::load(&expand_home(&path)?)?;
+                    let _ = progress.send(Progress::Message(format!("Validating the plan for {}...", plan.disk)));
+                    plan.validate().await?;
+                    let steps: Vec<String> = plan.steps().iter().enumerate().map(|(i, step)| format!("{}. {}", i + 1, step)).collect();
+                    Ok(ActionResult::Prompt(Prompt::Confirm {
+                        title: "Run install plan?".to_string(),
+                        text: format!("{}\n\nEach destructive step asks again before it runs.", steps.join("\n")),
+                        default: false,
+                        then: Box::new(plan_action(&plan, true)),
+                    }))
+                })
+            })
+        }),
+    })
+}
+
+/// Chains every step of `plan`; with `confirm` unset (`--yes`), destructive steps run without asking.
+pub fn plan_action(plan: &InstallPlan, confirm: bool) -> Action {
+    let mut steps = plan.steps();
+    let first = steps.remove(0);
+    step_action(first, steps, confirm)
+}
+
+fn step_required(text: &str) -> Action {
+    Action::Message { title: "Step Required".to_string(), text: text.to_string() }
+}
+
+/// Runs `kind`, then each of `rest` in turn once the previous one succeeds. With `confirm`,
+/// destructive steps first ask for confirmation, defaulting to No.
+fn step_action(kind: StepKind, rest: Vec<StepKind>, confirm: bool) -> Action {
+    step_action_noting(kind, rest, confirm, String::new())
+}
+
+/// `step_action` with `note` shown in the confirmation above the commands.
+fn step_action_noting(kind: StepKind, rest: Vec<StepKind>, confirm: bool, note: String) -> Action {
+    step_action_keyed(kind, rest, confirm, note, None)
+}
+
+/// `step_action_noting` with `key` (the LUKS passphrase or the passwords) fed to the step's
+/// commands that read a secret; see `install::reads_secret`.
+fn step_action_keyed(kind: StepKind, rest: Vec<StepKind>, confirm: bool, note: String, key: Option<Arc<String>>) -> Action {
+    if matches!(&kind, StepKind::Fstab { content } if content.is_empty()) {
+        return Action::execute(move |progress| generate_fstab(rest.clone(), confirm, progress));
+    }
+    let commands = kind.commands();
+    let note = if note.is_empty() { note } else { format!("\n\n{}", note) };
+    let text = format!("{}.{}\n\nCommands:\n{}", kind, note, commands.join("\n"));
+    let erases = kind.erases().map(String::from);
+    let packages = match &kind { StepKind::Pacstrap { packages } => Some(packages.clone()), _ => None };
+    let run = Action::execute(move |progress| run_step(kind.clone(), rest.clone(), confirm, key.clone(), progress));
+    // On a metered connection, what pacstrap downloads is worth a look before it starts.
+    if let Some(packages) = packages.filter(|_| confirm) {
+        return Action::execute(move |progress| confirm_download(packages.clone(), text.clone(), run.clone(), progress));
+    }
+    match erases.filter(|_| confirm) {
+        Some(phrase) => Action::Prompt(Prompt::Phrase { title: "This cannot be undone".to_string(), text, phrase, then: Box::new(run) }),
+        None => run,
+    }
+}
+
+/// Asks before installing the base system, with what pacstrap will download.
+fn confirm_download(packages: Vec<String>, text: String, run: Action, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let note = download_note(&packages, TARGET_ROOT, &progress).await;
+        Ok(ActionResult::Prompt(Prompt::Confirm { title: "Install the base system?".to_string(), text: format!("{}\n\n{}", note, text), default: true, then: Box::new(run) }))
+    })
+}
+
+/// Runs a manual installer step's commands as root, streaming their output, and reports the step for the history.
+fn run_step(kind: StepKind, rest: Vec<StepKind>, confirm: bool, key: Option<Arc<String>>, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        ensure_arch()?;
+        match &kind {
+            StepKind::Wipe { disk } | StepKind::Partition { disk, .. } => ensure_not_busy(disk).await?,
+            StepKind::Format { efi, root, .. } => {
+                ensure_not_busy(efi).await?;
+                ensure_not_busy(root).await?;
+            }
+            StepKind::Pacstrap { .. } => check_connectivity().await?,
+            _ => {}
+        }
+        require_tools(&kind.tools())?;
+        // pacstrap downloads and installs a whole system; its length depends on the mirror.
+        let budget = if matches!(kind, StepKind::Pacstrap { .. }) { Budget::Long } else { Budget::Standard };
+        let commands = kind.commands();
+        // The history keeps the plain command; only this run reads the override.
+        let pacman_conf = if matches!(kind, StepKind::Pacstrap { .. }) { pacman_conf_override("/").await? } else { None };
+        // What pacstrap installs, so the gauge follows its downloads and installs; without an
+        // estimate the gauge moves per command only.
+        let progress = match &kind {
+            StepKind::Pacstrap { packages } => match estimate_download(packages, TARGET_ROOT).await {
+                Ok(pending) if !pending.is_empty() => with_transaction_gauge(progress, pending),
+                _ => progress,
+            },
+            _ => progress,
+        };
+        for (i, command) in commands.iter().enumerate() {
+            let command = &match &pacman_conf {
+                Some(conf) => command.replacen("pacstrap ", &format!("pacstrap -C {} ", conf), 1),
+                None => command.clone(),
+            };
+            let _ = progress.send(Progress::Message(format!("$ {}", command)));
+            let stdin = key.as_deref().filter(|_| install::reads_secret(command)).map(|k| k.as_bytes());
+            let mut cmd = escalated();
+            match kind.argv() {
+                Some(mut argv) => {
+                    if let Some(conf) = &pacman_conf {
+                        argv.splice(1..1, ["-C".to_string(), conf.clone()]);
+                    }
+                    cmd.args(argv)
+                }
+                None => cmd.args(["sh", "-c", command]),
+            };
+            stream_checked_feeding(&mut cmd, stdin, &progress, budget).await?;
+            let _ = progress.send(Progress::Percent(((i + 1) * 100 / commands.len().max(1)) as u8));
+        }
+        let mut details = format!("✅ {}.", kind);
+        if let StepKind::Configure { user: Some(user), .. } = &kind {
+            details.push_str(&format!("\n\nSet a password for '{}' with: arch-chroot {} passwd {}", user, TARGET_ROOT, user));
+        }
+        let next = rest.split_first().map(|(next, rest)| Box::new(step_action(next.clone(), rest.to_vec(), confirm)));
+        Ok(ActionResult::Step { step: InstallStep { kind, commands, finished: chrono::Local::now().to_rfc3339() }, details, next })
+    })
+}
+
+// --- Preflight ---
+/// Verifies the configured probe host is reachable, telling DNS failures apart from no route.
+pub async fn check_connectivity() -> Result<(), ActionError> {
+    let network = Config::load()?.network;
+    let target = format!("{}:{}", network.probe_host, network.probe_port);
+    let addrs: Vec<_> = match tokio::net::lookup_host(&target).await {
+        Ok(addrs) => addrs.collect(),
+        Err(e) => return Err(ActionError::NoNetwork { reason: format!("DNS lookup for '{}' failed ({}).", network.probe_host, e) }),
+    };
+    for addr in &addrs {
+        let connect = tokio::net::TcpStream::connect(addr);
+        if let Ok(Ok(_)) = tokio::time::timeout(Duration::from_secs(5), connect).await {
+            return Ok(());
+        }
+    }
+    Err(ActionError::NoNetwork { reason: format!("'{}' resolved but could not be reached (no route to host).", target) })
+}
+
+/// Fails with `NotOnArch` unless this is an Arch Linux system (or its live ISO).
+pub fn ensure_arch() -> Result<(), ActionError> {
+    if std::path::Path::new("/etc/arch-release").exists() { Ok(()) } else { Err(ActionError::NotOnArch) }
+}
+
+/// Fails with `DeviceBusy` if `dev` or anything on it is mounted.
+async fn ensure_not_busy(dev: &str) -> Result<(), ActionError> {
+    let devices = disk::list().await?;
+    let busy = devices.iter().flat_map(|d| d.flatten()).filter(|d| d.name == dev).flat_map(|d| d.flatten()).any(|d| d.mountpoint.is_some());
+    if busy { Err(ActionError::DeviceBusy { dev: dev.to_string() }) } else { Ok(()) }
+}
+
+// --- Helpers ---
+/// A command that runs its arguments as root, through the tool set under [privileges].
+pub fn escalated() -> Command {
+    let escalation = Config::load().map(|c| c.privileges.escalation).unwrap_or_default();
+    let mut cmd = Command::new(escalation.program());
+    if NO_PASSWORD_PROMPTS.load(std::sync::atomic::Ordering::SeqCst) {
+        cmd.arg(escalation.non_interactive_flag());
+    }
+    cmd
+}
+
+/// Set while the TUI holds the terminal, where a password prompt could be neither seen nor answered.
+static NO_PASSWORD_PROMPTS: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Makes `escalated` commands fail at once when they would ask for a password, instead of waiting
+/// for their timeout; see `ActionError::command_failed`.
+pub fn never_ask_passwords() {
+    NO_PASSWORD_PROMPTS.store(true, std::sync::atomic::Ordering::SeqCst);
+}
+
+/// How long a cancelled action's commands get to exit after SIGTERM before they are killed.
+pub const STOP_GRACE: Duration = Duration::from_secs(3);
+
+/// The processes started by `parent` that still run, from each thread's list in /proc.
+fn child_pids(parent: u32) -> Vec<u32> {
+    let Ok(threads) = std::fs::read_dir(format!("/proc/{}/task", parent)) else { return Vec::new() };
+    let lists: Vec<String> = threads.flatten().filter_map(|thread| std::fs::read_to_string(thread.path().join("children")).ok()).collect();
+    parse_child_pids(&lists)
+}
+
+fn parse_child_pids(lists: &[String]) -> Vec<u32> {
+    let mut pids: Vec<u32> = lists.iter().flat_map(|list| list.split_whitespace().filter_map(|pid| pid.parse().ok())).collect();
+    pids.sort_unstable();
+    pids.dedup();
+    pids
+}
+
+/// Asks the commands this process started to exit with SIGTERM and returns how many there were.
+/// sudo and doas pass SIGTERM on to the command they run as root, where the SIGKILL of a dropped
+/// task stops only the wrapper. `kill` runs in a process group of its own: sudo ignores signals
+/// from its parent's group, taking them for a terminal's ^C the command already got.
+pub async fn terminate_children() -> usize {
+    let pids = child_pids(std::process::id());
+    if !pids.is_empty() {
+        let mut kill = Command::new("kill");
+        kill.arg("-TERM").args(pids.iter().map(u32::to_string)).process_group(0);
+        let _ = status_within(&mut kill, Budget::Probe).await;
+    }
+    pids.len()
+}
+
+/// Runs each argument vector as root in turn, stopping at the first that fails.
+async fn sudo_steps(steps: &[Vec<String>], budget: Budget) -> Result<(), ActionError> {
+    for argv in steps {
+        run_checked_within(escalated().args(argv), budget).await?;
+    }
+    Ok(())
+}
+
+/// Inside the root it is for; under /var/tmp, since arch-chroot mounts a fresh /tmp.
+const PACMAN_CONF_OVERRIDE: &str = "/var/tmp/arch-suite-pacman.conf";
+
+/// A copy of `root`'s pacman.conf with [pacman] `parallel_downloads` set, for `--config` (or
+/// pacstrap's `-C`); its path as seen from inside `root`. `None` when the setting is 0.
+async fn pacman_conf_override(root: &str) -> Result<Option<String>, ActionError> {
+    let downloads = Config::load()?.pacman.parallel_downloads;
+    if downloads == 0 {
+        return Ok(None);
+    }
+    let root = root.trim_end_matches('/');
+    let conf = std::fs::read_to_string(format!("{}/etc/pacman.conf", root))?;
+    sudo_write(&format!("{}{}", root, PACMAN_CONF_OVERRIDE), &packages::with_parallel_downloads(&conf, downloads)).await?;
+    Ok(Some(PACMAN_CONF_OVERRIDE.to_string()))
+}
+
+/// `pacman` with the `pacman_conf_override` for this system, if there is one.
+async fn pacman() -> Result<Command, ActionError> {
+    let mut cmd = escalated();
+    cmd.arg("pacman");
+    if let Some(conf) = pacman_conf_override("/").await? {
+        cmd.args(["--config", &conf]);
+    }
+    Ok(cmd)
+}
+
+/// What installing `packages` into `root` would download: `pacman -Sp` with the host's sync
+/// databases, resolved against what `root` has installed (nothing on a fresh target), so every
+/// missing dependency counts. Packages the repositories do not have are left out.
+async fn estimate_download(packages: &[String], root: &str) -> Result<Vec<packages::PendingPackage>, ActionError> {
+    let known: HashSet<String> = run_checked_within(Command::new("pacman").arg("-Slq"), Budget::Probe).await?.lines().map(String::from).collect();
+    let targets: Vec<&String> = packages.iter().filter(|package| known.contains(*package)).collect();
+    // A database directory of links: the host's sync databases and the target's local one.
+    let dbpath = std::env::temp_dir().join(format!("arch-suite-dbpath-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dbpath);
+    std::fs::create_dir_all(&dbpath)?;
+    std::os::unix::fs::symlink("/var/lib/pacman/sync", dbpath.join("sync"))?;
+    let local = Path::new(root).join("var/lib/pacman/local");
+    if local.is_dir() {
+        std::os::unix::fs::symlink(&local, dbpath.join("local"))?;
+    } else {
+        std::fs::create_dir(dbpath.join("local"))?;
+    }
+    let mut query = Command::new("pacman");
+    query.args(["-Sp", "--needed", "--print-format", packages::PRINT_FORMAT]).arg("--dbpath").arg(&dbpath).args(targets);
+    let output = run_checked_within(&mut query, Budget::Probe).await;
+    let _ = std::fs::remove_dir_all(&dbpath);
+    Ok(packages::parse_pending(&output?))
+}
+
+/// `estimate_download` as a line for a confirmation; a failed estimate is noted, not fatal.
+async fn download_note(packages: &[String], root: &str, progress: &ProgressTx) -> String {
+    let _ = progress.send(Progress::Message("Estimating the download size...".to_string()));
+    match estimate_download(packages, root).await {
+        Ok(pending) => packages::download_summary(&pending),
+        Err(e) => format!("📥 The download size could not be estimated: {}", e),
+    }
+}
+
+/// Writes `contents` to `path` as root, through `tee`.
+async fn sudo_write(path: &str, contents: &str) -> Result<(), ActionError> {
+    use tokio::io::AsyncWriteExt;
+    let mut cmd = escalated();
+    cmd.args(["tee", path]).stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::piped()).kill_on_drop(true);
+    let mut child = cmd.spawn().map_err(|e| spawn_error(&cmd, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(contents.as_bytes()).await?;
+    }
+    let output = within(Budget::Standard, describe(&cmd), async { Ok(child.wait_with_output().await?) }).await?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(ActionError::command_failed(describe(&cmd), output.status, "", &String::from_utf8_lossy(&output.stderr)))
+    }
+}
+
+/// How long a command may run before it is killed, by kind of work. The limits come from
+/// [timeouts] in the config file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Budget {
+    Probe,
+    Standard,
+    Long,
+}
+
+impl Budget {
+    /// The limit (`None` when disabled) and the config key it comes from.
+    fn limit(self) -> (Option<Duration>, &'static str) {
+        let timeouts = Config::load().map(|c| c.timeouts).unwrap_or_default();
+        let (secs, setting) = match self {
+            Budget::Probe => (timeouts.probe, "probe"),
+            Budget::Standard => (timeouts.standard, "standard"),
+            Budget::Long => (timeouts.long, "long"),
+        };
+        ((secs > 0).then(|| Duration::from_secs(secs)), setting)
+    }
+}
+
+/// Awaits `work`, failing with `Timeout` once `budget` is exceeded. Children spawned by `work` must
+/// use `kill_on_drop`, so abandoning the future kills them.
+async fn within<T>(budget: Budget, cmd: String, work: impl Future<Output = Result<T, ActionError>>) -> Result<T, ActionError> {
+    tracing::info!("$ {}", cmd);
+    let started = std::time::Instant::now();
+    let result = match budget.limit() {
+        (Some(after), setting) => tokio::time::timeout(after, work).await.unwrap_or_else(|_| Err(ActionError::Timeout { cmd: cmd.clone(), after, setting })),
+        (None, _) => work.await,
+    };
+    match &result {
+        Ok(_) => tracing::debug!("  ran for {:.1}s: {}", started.elapsed().as_secs_f32(), cmd),
+        Err(e) => tracing::error!("  {}", e),
+    }
+    result
+}
+
+/// Runs `cmd` with its output discarded and returns how it exited, a failure being an answer
+/// rather than an error here.
+async fn status_within(cmd: &mut Command, budget: Budget) -> Result<std::process::ExitStatus, ActionError> {
+    within(budget, describe(cmd), async { cmd.kill_on_drop(true).stdout(Stdio::null()).stderr(Stdio::null()).status().await.map_err(|e| spawn_error(cmd, e)) }).await
+}
+
+/// Runs `cmd` to completion and returns its stdout. A non-zero exit becomes `CommandFailed`
+/// carrying the command line, the exit code and the tails of stderr and stdout.
+pub async fn run_checked(cmd: &mut Command) -> Result<String, ActionError> {
+    run_checked_within(cmd, Budget::Standard).await
+}
+
+/// `run_checked` with an explicit time budget.
+pub async fn run_checked_within(cmd: &mut Command, budget: Budget) -> Result<String, ActionError> {
+    let description = describe(cmd);
+    let output = within(budget, description, async { cmd.kill_on_drop(true).output().await.map_err(|e| spawn_error(cmd, e)) }).await?;
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    if output.status.success() {
+        Ok(stdout)
+    } else {
+        Err(ActionError::command_failed(describe(cmd), output.status, &stdout, &String::from_utf8_lossy(&output.stderr)))
+    }
+}
+
+/// Like `run_checked`, but forwards every stdout/stderr line to the progress channel as it arrives.
+pub async fn stream_checked(cmd: &mut Command, progress: &ProgressTx) -> Result<(), ActionError> {
+    stream_checked_within(cmd, progress, Budget::Standard).await
+}
+
+/// `stream_checked` with an explicit time budget.
+pub async fn stream_checked_within(cmd: &mut Command, progress: &ProgressTx, budget: Budget) -> Result<(), ActionError> {
+    stream_checked_feeding(cmd, None, progress, budget).await
+}
+
+/// `stream_checked_within` that writes `stdin` to the command's standard input and closes it.
+pub async fn stream_checked_feeding(cmd: &mut Command, stdin: Option<&[u8]>, progress: &ProgressTx, budget: Budget) -> Result<(), ActionError> {
+    use tokio::io::AsyncWriteExt;
+    if stdin.is_some() {
+        cmd.stdin(Stdio::piped());
+    }
+    let mut child = cmd.kill_on_drop(true).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().map_err(|e| spawn_error(cmd, e))?;
+    if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        pipe.write_all(input).await?;
+    }
+    let stdout = forward_lines(child.stdout.take(), progress.clone());
+    let stderr = forward_lines(child.stderr.take(), progress.clone());
+    let status = within(budget, describe(cmd), async { Ok(child.wait().await?) }).await?;
+    let (stdout, stderr) = tokio::join!(stdout, stderr);
+    if status.success() {
+        Ok(())
+    } else {
+        Err(ActionError::command_failed(describe(cmd), status, &stdout.unwrap_or_default(), &stderr.unwrap_or_default()))
+    }
+}
+
+/// Runs the `[hooks]` command `name`, if one is set, with `result` as `$1` and `$ARCH_SUITE_RESULT`,
+/// streaming its output. The action has already succeeded, so the outcome is only a note for its report.
+pub async fn run_hook(name: &str, hook: Option<&str>, result: &Path, progress: &ProgressTx) -> Option<String> {
+    let hook = hook.filter(|hook| !hook.trim().is_empty())?;
+    let _ = progress.send(Progress::Message(format!("Running the {} hook...", name)));
+    let mut cmd = Command::new("sh");
+    cmd.args(["-c", hook, name]).arg(result).env("ARCH_SUITE_RESULT", result);
+    Some(match stream_checked_within(&mut cmd, progress, Budget::Long).await {
+        Ok(()) => format!("\n\nThe {} hook ran successfully.", name),
+        Err(e) => format!("\n\n⚠️ The {} hook failed; the result above is kept.\n{}", name, e),
+    })
+}
+
+/// `progress`, with the output lines of a pacman transaction installing `pending` also moving the
+/// gauge; see `packages::TransactionProgress`.
+fn with_transaction_gauge(progress: ProgressTx, pending: Vec<packages::PendingPackage>) -> ProgressTx {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let mut transaction = packages::TransactionProgress::default();
+        while let Some(update) = rx.recv().await {
+            if let Progress::Message(line) = &update {
+                if let Some(percent) = transaction.feed(line, &pending) {
+                    let _ = progress.send(Progress::Percent(percent));
+                }
+            }
+            let _ = progress.send(update);
+        }
+    });
+    tx
+}
+
+/// Forwards each line of `pipe` as progress and returns the last `TAIL_LINES` of them. Lines a
+/// progress bar overwrites are replaced, in the popup and in the tail.
+fn forward_lines<R: AsyncRead + Unpin + Send + 'static>(pipe: Option<R>, progress: ProgressTx) -> JoinHandle<String> {
+    tokio::spawn(async move {
+        let mut tail = VecDeque::with_capacity(TAIL_LINES);
+        let Some(pipe) = pipe else { return String::new() };
+        let _ = read_lines(pipe, |line, replace| {
+            if replace {
+                tail.pop_back();
+            } else if tail.len() == TAIL_LINES {
+                tail.pop_front();
+            }
+            tail.push_back(line.clone());
+            let _ = progress.send(if replace { Progress::Replace(line) } else { Progress::Message(line) });
+        })
+        .await;
+        Vec::from(tail).join("\n")
+    })
+}
+
+/// The command line of `cmd`, for error messages.
+fn describe(cmd: &Command) -> String {
+    let cmd = cmd.as_std();
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|arg| {
+            let arg = arg.to_string_lossy();
+            if arg.contains(' ') { format!("'{}'", arg) } else { arg.into_owned() }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn spawn_error(cmd: &Command, e: std::io::Error) -> ActionError {
+    if e.kind() == ErrorKind::NotFound {
+        let tool = cmd.as_std().get_program().to_string_lossy().to_string();
+        ActionError::MissingDependency { package: package_for(&tool).to_string(), tool }
+    } else {
+        e.into()
+    }
+}
+
+/// The package that provides `tool`, where it is not named after it.
+pub fn package_for(tool: &str) -> &str {
+    match tool {
+        "sgdisk" => "gptfdisk",
+        "mkfs.fat" => "dosfstools",
+        "mkfs.ext4" => "e2fsprogs",
+        "mkfs.btrfs" => "btrfs-progs",
+        "mkfs.xfs" => "xfsprogs",
+        "partprobe" => "parted",
+        "wipefs" | "sfdisk" | "mkswap" => "util-linux",
+        "pacstrap" | "genfstab" | "arch-chroot" => "arch-install-scripts",
+        "mkarchiso" => "archiso",
+        "paccache" => "pacman-contrib",
+        "lspci" => "pciutils",
+        "gpg" => "gnupg",
+        "grub-mkconfig" => "grub",
+        "ssh" => "openssh",
+        vm::QEMU => "qemu-desktop",
+        tool => tool,
+    }
+}
+
+/// Checks that all of `tools` are installed before an action runs any of them, so a package
+/// removed since start-up is reported by name rather than by a failed spawn halfway through.
+pub fn require_tools(tools: &[&str]) -> Result<(), ActionError> {
+    match tools.iter().find(|tool| !command_exists(tool)) {
+        Some(tool) => Err(ActionError::MissingDependency { tool: tool.to_string(), package: package_for(tool).to_string() }),
+        None => Ok(()),
+    }
+}
+
+/// Whether `name` resolves to an executable file somewhere on `PATH`.
+pub fn command_exists(name: &str) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::env::var_os("PATH").is_some_and(|path| {
+        std::env::split_paths(&path).any(|dir| {
+            std::fs::metadata(dir.join(name)).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        })
+    })
+}
+
+// --- Export ---
+/// Trims `path` and expands a leading `~/` to the real user's home directory.
+pub fn expand_home(path: &str) -> Result<PathBuf> {
+    Ok(match path.trim().strip_prefix("~/") {
+        Some(rest) => paths::real_user()?.home.join(rest),
+        None => PathBuf::from(path.trim()),
+    })
+}
+
+/// `CheckFn` for the path of a file that exists, a leading `~/` being the real user's home.
+pub fn existing_file(text: &str) -> Result<(), String> {
+    if text.trim().is_empty() {
+        return Err("Enter the path of a file.".to_string());
+    }
+    let path = expand_home(text).map_err(|e| format!("{:#}", e))?;
+    match std::fs::metadata(&path) {
+        Ok(metadata) if metadata.is_dir() => Err(format!("{} is a directory.", path.display())),
+        Ok(_) => Ok(()),
+        Err(_) => Err(format!("{} does not exist.", path.display())),
+    }
+}
+
+/// `CheckFn` for a hostname, or nothing.
+pub fn hostname_or_empty(text: &str) -> Result<(), String> {
+    let hostname = text.trim();
+    if hostname.is_empty() || install::is_valid_hostname(hostname) {
+        return Ok(());
+    }
+    Err(format!("'{}' is not a valid hostname: use up to 63 letters, digits and inner hyphens.", hostname))
+}
+
+/// `CheckFn` for a user name.
+pub fn user_name(text: &str) -> Result<(), String> {
+    if install::is_valid_user_name(text.trim()) {
+        return Ok(());
+    }
+    Err(format!("'{}' is not a user name; use lowercase letters, digits, '_' and '-'.", text.trim()))
+}
+
+/// Writes `text` to `path` atomically via a sibling temp file, expanding a leading `~/`.
+pub fn save_text(path: &str, text: &str) -> Result<PathBuf> {
+    let path = expand_home(path)?;
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let file_name = path.file_name().ok_or_else(|| anyhow!("'{}' is not a file path", path.display()))?;
+    let tmp = path.with_file_name(format!(".{}.tmp-{}", file_name.to_string_lossy(), std::process::id()));
+    std::fs::write(&tmp, text).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    if let Err(e) = std::fs::rename(&tmp, &path) {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e).with_context(|| format!("Failed to move output into {}", path.display()));
+    }
+    Ok(path)
+}
+
+// --- Setup Wizard ---
+const SETUP_NOW: &str = "Set up now (theme, work directory, privileges, dependencies)";
+const SETUP_SKIP: &str = "Skip and use the defaults";
+
+/// Shown on first launch (no config file yet) and from Utilities: asks for the basic settings,
+/// one popup each, and writes them as the config file.
+pub fn setup_wizard() -> Action {
+    Action::Prompt(Prompt::Select {
+        title: "Welcome to Arch System Suite! Esc decides later.".to_string(),
+        items: vec![SETUP_NOW.to_string(), SETUP_SKIP.to_string()],
+        then: Arc::new(|choice| {
+            if choice == SETUP_NOW {
+                choose_theme(Setup::default())
+            } else {
+                // Saving the defaults marks the wizard as done, so it does not come back on every launch.
+                Action::execute(|progress| save_setup(Setup::default(), progress))
+            }
+        }),
+    })
+}
+
+fn choose_theme(setup: Setup) -> Action {
+    Action::Prompt(Prompt::Select {
+        title: "Theme (step 1 of 4)".to_string(),
+        items: Theme::ALL.iter().map(|t| t.name().to_string()).collect(),
+        then: Arc::new(move |choice| {
+            let theme = Theme::ALL.into_iter().find(|t| t.name() == choice).unwrap_or_default();
+            // Preview the choice in the remaining steps.
+            theme.set();
+            choose_setup_work_dir(Setup { theme, ..setup.clone() })
+        }),
+    })
+}
+
+fn choose_setup_work_dir(setup: Setup) -> Action {
+    let default = paths::work_dir().map(|dir| dir.display().to_string()).unwrap_or_default();
+    Action::Prompt(Prompt::Input {
+        title: "Work directory for snapshots and archives (step 2 of 4)".to_string(),
+        default: default.clone(),
+        then: Arc::new(move |dir| {
+            let dir = dir.trim();
+            let work_dir = (!dir.is_empty() && dir != default).then(|| PathBuf::from(dir));
+            choose_escalation(Setup { work_dir, ..setup.clone() })
+        }),
+    })
+}
+
+fn choose_escalation(setup: Setup) -> Action {
+    // Installed tools first; the default stays first among them.
+    let mut tools: Vec<Escalation> = Escalation::ALL.to_vec();
+    tools.sort_by_key(|tool| !command_exists(tool.program()));
+    Action::Prompt(Prompt::Select {
+        title: "Run commands as root with (step 3 of 4)".to_string(),
+        items: tools.iter().map(|tool| format!("{}{}", tool.program(), if command_exists(tool.program()) { "" } else { " (not installed)" })).collect(),
+        then: Arc::new(move |choice| {
+            let escalation = Escalation::ALL.into_iter().find(|tool| choice.split_whitespace().next() == Some(tool.program())).unwrap_or_default();
+            choose_auto_install(Setup { escalation, ..setup.clone() })
+        }),
+    })
+}
+
+fn choose_auto_install(setup: Setup) -> Action {
+    const ASK: &str = "Ask before installing missing dependencies";
+    Action::Prompt(Prompt::Select {
+        title: "Missing dependencies at start-up (step 4 of 4)".to_string(),
+        items: vec![ASK.to_string(), "Install them automatically".to_string()],
+        then: Arc::new(move |choice| {
+            let setup = Setup { auto_install: choice != ASK, ..setup.clone() };
+            Action::execute(move |progress| save_setup(setup.clone(), progress))
+        }),
+    })
+}
+
+fn save_setup(setup: Setup, _progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        if let Some(dir) = &setup.work_dir {
+            paths::ensure_writable(&expand_home(&dir.display().to_string())?)?;
+        }
+        let path = Config::save_setup(&setup)?;
+        setup.theme.set();
+        Ok(ActionResult::Message(format!(
+            "✅ Settings saved to {}.\n\nTheme: {}\nWork directory: {}\nRoot commands via: {}\nMissing dependencies: {}\n\nEdit the file for more options, or run the wizard again from Utilities.",
+            path.display(),
+            setup.theme.name(),
+            setup.work_dir.as_ref().map_or("default".to_string(), |d| d.display().to_string()),
+            setup.escalation.program(),
+            if setup.auto_install { "installed automatically" } else { "asked about" },
+        )))
+    })
+}
+
+// --- Dependency Management ---
+/// Packages the suite relies on, checked at start-up and by the doctor.
+pub const DEPENDENCIES: [&str; 9] = ["gum", "arch-install-scripts", "pacman-contrib", "gptfdisk", "dosfstools", "e2fsprogs", "archiso", "rsync", "pciutils"];
+
+/// The `DEPENDENCIES` that are not installed.
+pub async fn missing_dependencies() -> Result<Vec<String>, ActionError> {
+    // `pacman -Q` lists what it finds even when some packages are missing.
+    let mut query = Command::new("pacman");
+    query.arg("-Q").args(DEPENDENCIES);
+    let output = within(Budget::Probe, describe(&query), async { query.kill_on_drop(true).output().await.map_err(|e| spawn_error(&query, e)) }).await?;
+    let installed = String::from_utf8_lossy(&output.stdout).to_string();
+    let found: Vec<&str> = installed.lines().filter_map(|line| line.split_whitespace().next()).collect();
+    Ok(DEPENDENCIES.iter().filter(|dep| !found.contains(dep)).map(|dep| dep.to_string()).collect())
+}
+
+/// What interrupted snapshots, backups, comparisons and audits left behind, with the space each uses.
+pub async fn interrupted_leftovers() -> Vec<(PathBuf, u64)> {
+    let dirs: Vec<PathBuf> = [paths::work_dir(), paths::backup_dir(), paths::base_dir(BaseDir::Cache)].into_iter().flatten().collect();
+    let mut found = Vec::new();
+    for path in snapshot::leftovers(&dirs) {
+        let size = measure(std::slice::from_ref(&path), &[]).await;
+        found.push((path, size));
+    }
+    found
+}
+
+/// Offers to remove `leftovers`; none of them is a usable snapshot or backup.
+pub fn clean_up_leftovers(leftovers: Vec<(PathBuf, u64)>) -> Action {
+    let list: Vec<String> = leftovers.iter().map(|(path, size)| format!("  {:>10}  {}", disk::human_size(*size), path.display())).collect();
+    let text = format!(
+        "An earlier operation did not finish and left these behind:\n{}\n\nRemove them to free {}? Partial archives are incomplete and cannot be restored; if another arch-suite is still writing them, choose No.",
+        list.join("\n"), disk::human_size(leftovers.iter().map(|(_, size)| size).sum()),
+    );
+    let paths: Vec<PathBuf> = leftovers.into_iter().map(|(path, _)| path).collect();
+    Action::Prompt(Prompt::Confirm {
+        title: "Clean up an interrupted operation?".to_string(),
+        text,
+        default: false,
+        then: Box::new(Action::execute(move |_| {
+            let paths = paths.clone();
+            Box::pin(async move {
+                // Snapshot parts are written as root.
+                run_checked(escalated().arg("rm").arg("-rf").arg("--").args(&paths)).await?;
+                Ok(ActionResult::Message(format!("✅ Removed {} leftover item(s).", paths.len())))
+            })
+        })),
+    })
+}
+
+/// Installs the `missing` dependencies, first showing what the transaction downloads and asking,
+/// unless `yes` (`--yes`) or `auto_install` under [dependencies] is set. Those the repositories do
+/// not have are built with an AUR helper. `then` is offered once they are installed.
+pub fn install_dependencies(missing: Vec<String>, then: Option<Action>, yes: bool) -> Action {
+    Action::execute(move |progress| preview_dependency_install(missing.clone(), then.clone(), yes, progress))
+}
+
+fn preview_dependency_install(missing: Vec<String>, then: Option<Action>, yes: bool, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        check_connectivity().await?;
+        let _ = progress.send(Progress::Message("Resolving the packages to install...".to_string()));
+        // Without sync databases yet nothing is known, and pacman -Syu fetches them first.
+        let known: HashSet<String> = run_checked_within(Command::new("pacman").arg("-Slq"), Budget::Probe).await?.lines().map(String::from).collect();
+        let (repo, foreign): (Vec<String>, Vec<String>) = missing.iter().cloned().partition(|package| known.is_empty() || known.contains(package));
+        let helper = packages::aur_helper(command_exists);
+        if !foreign.is_empty() && helper.is_none() {
+            return Err(ActionError::Other(format!(
+                "{} {} not in the official repositories. Install an AUR helper ({}) to build them from the AUR, or build them with makepkg.",
+                foreign.join(", "), if foreign.len() == 1 { "is" } else { "are" }, packages::AUR_HELPERS.join(" or "),
+            )));
+        }
+        let mut pending = Vec::new();
+        if !repo.is_empty() {
+            // -p only prints the transaction, so it needs no privileges.
+            let mut query = Command::new("pacman");
+            query.args(["-Sup", "--needed", "--print-format", packages::PRINT_FORMAT]).args(&repo);
+            pending = packages::parse_pending(&run_checked_within(&mut query, Budget::Probe).await?);
+        }
+        if yes || Config::load()?.dependencies.auto_install {
+            return run_dependency_install(repo, foreign, helper, pending, then, progress).await;
+        }
+        let width = pending.iter().map(|p| p.name.len() + p.version.len()).max().unwrap_or(0) + 1;
+        let list: Vec<String> = pending.iter().map(|p| format!("  {:width$}  {:>10}", format!("{} {}", p.name, p.version), disk::human_size(p.size), width = width)).collect();
+        let mut text = format!("Missing: {}", missing.join(", "));
+        if !repo.is_empty() {
+            text.push_str(&format!(
+                "\n\n`pacman -Syu` installs {} and upgrades the rest of the system ({} packages):\n{}\n\nTotal download: up to {}, per the local package databases (refreshed first).",
+                repo.join(", "), pending.len(), list.join("\n"), disk::human_size(pending.iter().map(|p| p.size).sum()),
+            ));
+        }
+        if let (false, Some(helper)) = (foreign.is_empty(), helper) {
+            text.push_str(&format!("\n\nNot in the official repositories, so built from the AUR with {} as {}: {}.", helper, paths::real_user()?.name, foreign.join(", ")));
+        }
+        text.push_str("\nWithout these packages some actions will fail.");
+        let install = Action::execute(move |progress| run_dependency_install(repo.clone(), foreign.clone(), helper, pending.clone(), then.clone(), progress));
+        Ok(ActionResult::Prompt(Prompt::Confirm { title: "Install missing dependencies?".to_string(), text, default: false, then: Box::new(install) }))
+    })
+}
+
+/// `program` run as the real user: AUR helpers refuse to build as root and escalate for the
+/// install themselves, and the files gpg writes belong to the user.
+fn as_real_user(program: &str) -> Result<Command, ActionError> {
+    let user = paths::real_user()?;
+    Ok(if user.elevated {
+        let mut cmd = Command::new("sudo");
+        cmd.args(["-u", &user.name, program]);
+        cmd
+    } else {
+        Command::new(program)
+    })
+}
+
+/// Installs the `repo` dependencies with pacman, then builds the `foreign` ones with `helper`.
+/// Mirrors that cannot be reached are reported as no network rather than as pacman's error.
+fn run_dependency_install(repo: Vec<String>, foreign: Vec<String>, helper: Option<&'static str>, pending: Vec<packages::PendingPackage>, then: Option<Action>, progress: ProgressTx) -> AppAction {
+    Box::pin(async move {
+        let missing: Vec<String> = repo.iter().chain(&foreign).cloned().collect();
+        let _ = progress.send(Progress::Message(format!("Installing {}...", missing.join(" "))));
+        let _ = progress.send(Progress::Percent(0));
+        if !repo.is_empty() {
+            install_repo_dependencies(&repo, &pending, &progress).await?;
+        }
+        if let (false, Some(helper)) = (foreign.is_empty(), helper) {
+            let mut cmd = as_real_user(helper)?;
+            cmd.args(["-S", "--needed", "--noconfirm"]).args(&foreign);
+            stream_checked_within(&mut cmd, &progress, Budget::Long).await?;
+        }
+        let _ = progress.send(Progress::Percent(100));
+        let done = format!("✅ Dependencies installed: {}.", missing.join(", "));
+        Ok(match then {
+            Some(then) => ActionResult::Prompt(Prompt::Confirm { title: "Dependencies installed".to_string(), text: format!("{}\n\nContinue?", done), default: true, then: Box::new(then) }),
+            None => ActionResult::Message(done),
+        })
+    })
+}
+
+/// `pacman -Syu` for the `repo` dependencies, moving the gauge as the `pending` packages go.
+async fn install_repo_dependencies(repo: &[String], pending: &[packages::PendingPackage], progress: &ProgressTx) -> Result<(), ActionError> {
+    let mut cmd = pacman().await?;
+    cmd.args(["-Syu", "--noconfirm", "--needed"]).args(repo);
+    let mut child = cmd.kill_on_drop(true).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().map_err(|e| spawn_error(&cmd, e))?;
+    let stderr = forward_lines(child.stderr.take(), progress.clone());
+    let mut transaction = packages::TransactionProgress::default();
+    let status = within(Budget::Long, describe(&cmd), async {
+        if let Some(stdout) = child.stdout.take() {
+            read_records(stdout, |line| {
+                if let Some(percent) = transaction.feed(line, pending) {
+                    let _ = progress.send(Progress::Percent(percent));
+                }
+                if !line.trim().is_empty() {
+                    let _ = progress.send(Progress::Message(line.trim_end().to_string()));
+                }
+            })
+            .await?;
+        }
+        Ok(child.wait().await?)
+    })
+    .await?;
+    let stderr = stderr.await.unwrap_or_default();
+    if !status.success() {
+        if let Some(reason) = packages::unreachable_mirrors(&stderr) {
+            return Err(ActionError::NoNetwork { reason });
+        }
+        return Err(ActionError::command_failed(describe(&cmd), status, "", &stderr));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compression_levels_are_passed_to_the_compressor() {
+        assert_eq!(compressor_args(Compression::Zstd, 4, Some(25), false), ("zstd (4 threads, level 19)".to_string(), vec!["-I".to_string(), "zstd -T4 -19".to_string()]));
+        assert_eq!(compressor_args(Compression::Xz, 2, None, true), ("xz (2 threads)".to_string(), vec!["-I".to_string(), "xz -T2".to_string()]));
+        let (_, gzip) = compressor_args(Compression::Gzip, 2, None, true);
+        assert!(gzip[0] == "-I" && gzip[1].ends_with(" -n"));
+    }
+
+    #[test]
+    fn snapshot_paths_with_spaces_stay_single_arguments() {
+        let (dir, home, file) = (PathBuf::from("/data/my work/snapshot_tmp"), PathBuf::from("/home/o'brien x"), PathBuf::from("/tmp/snap shot; rm -rf ~.tar.gz"));
+        let paths = SnapshotPaths { dir: &dir, home: &home, file: &file, user: "o'brien" };
+        let steps = snapshot_steps(&paths, &["-I".to_string(), "zstd -T4".to_string()], "tar.zst", &["Login Data".to_string()], true, None);
+        let argv = |label: &str| steps.iter().find(|(l, _)| *l == label).map(|(_, argv)| argv.clone()).unwrap();
+
+        let home_argv = argv("Archiving the home directory");
+        assert_eq!(home_argv[..3], ["tar", "-I", "zstd -T4"]);
+        assert!(home_argv.contains(&"/home/o'brien x".to_string()));
+        assert!(home_argv.contains(&"/data/my work/snapshot_tmp/home.tar.zst".to_string()));
+        assert!(home_argv.contains(&"--exclude=Login Data".to_string()));
+        assert!(argv("Bundling the snapshot").contains(&"/tmp/snap shot; rm -rf ~.tar.gz.part".to_string()));
+        assert_eq!(argv("Handing the snapshot to its owner"), ["chown", "o'brien:", "/tmp/snap shot; rm -rf ~.tar.gz.part"]);
+        assert_eq!(argv("Marking the snapshot complete"), ["mv", "-f", "/tmp/snap shot; rm -rf ~.tar.gz.part", "/tmp/snap shot; rm -rf ~.tar.gz"]);
+        assert_eq!(argv("Cleaning up"), ["rm", "-rf", "/data/my work/snapshot_tmp"]);
+    }
+
+    #[test]
+    fn a_manifest_user_that_leaves_home_is_not_restored() {
+        assert_eq!(restored_home("ada"), Ok(format!("{}/home/ada", TARGET_ROOT)));
+        for user in ["../../etc", "../..", "ada/../../etc", "-R"] {
+            assert!(restored_home(user).is_err_and(|note| note.contains("is not a user name")), "{}", user);
+        }
+        assert!(restored_home("").is_err_and(|note| note.contains("does not name its user")));
+    }
+
+    #[test]
+    fn children_are_gathered_from_every_thread_once() {
+        assert_eq!(parse_child_pids(&["4182 4190 ".to_string(), String::new(), "4190\n".to_string()]), [4182, 4190]);
+        assert!(parse_child_pids(&[]).is_empty());
+    }
+
+    #[test]
+    fn a_quote_in_the_snapshot_path_stays_inside_its_argument() {
+        let snapshot = PathBuf::from("/data/o'brien's snap'; rm -rf '/.tar.gz");
+        let steps = unpack_steps("/mnt/tmp/arch-suite-snapshot", &snapshot);
+        assert_eq!(steps[0], ["rm", "-rf", "/mnt/tmp/arch-suite-snapshot"]);
+        assert_eq!(steps[2], ["tar", "-xf", "/data/o'brien's snap'; rm -rf '/.tar.gz", "-C", "/mnt/tmp/arch-suite-snapshot"]);
+        assert!(steps.iter().all(|argv| argv[0] != "sh"));
+    }
+
+    #[test]
+    fn a_dotfiles_snapshot_archives_only_the_chosen_paths() {
+        let (dir, home, file) = (PathBuf::from("/tmp/snapshot_tmp"), PathBuf::from("/home/alice"), PathBuf::from("/tmp/dotfiles.tar.gz"));
+        let paths = SnapshotPaths { dir: &dir, home: &home, file: &file, user: "alice" };
+        let dotfiles = [".config".to_string(), ".bashrc".to_string()];
+        let steps = snapshot_steps(&paths, &["-z".to_string()], "tar.gz", &[".ssh".to_string()], false, Some(&dotfiles));
+        assert!(steps.iter().all(|(label, _)| *label != "Archiving /etc"));
+        let home_argv = &steps.iter().find(|(label, _)| *label == "Archiving the home directory").unwrap().1;
+        assert!(home_argv.ends_with(&["--exclude=.ssh".to_string(), "--".to_string(), ".config".to_string(), ".bashrc".to_string()]));
+        let with_etc = snapshot_steps(&paths, &["-z".to_string()], "tar.gz", &[], true, Some(&dotfiles));
+        assert_eq!(with_etc[0].0, "Archiving /etc");
+    }
+
+    #[test]
+    fn a_refused_password_prompt_is_reported_with_how_to_authenticate() {
+        let status = std::process::Command::new("false").status().unwrap();
+        let refused = ActionError::command_failed("sudo -n pacman -Syu", status, "", "sudo: a password is required\n");
+        assert!(matches!(&refused, ActionError::PermissionDenied { what } if what.contains("sudo -v")));
+        assert!(matches!(ActionError::command_failed("pacman -Syu", status, "", "error: failed to synchronize all databases\n"), ActionError::CommandFailed { .. }));
+        assert!(crate::error::needs_password("Failed to start unit: Interactive authentication required."));
+    }
+
+    #[test]
+    fn package_install_applies_the_driver_swap() {
+        let list = "base\nnvidia\nfirefox\nnvidia-utils\nyay\nx'; reboot; '\n";
+        assert_eq!(package_install(list, &DriverSwap::default(), &[], None), (["pacman", "-S", "--needed", "--noconfirm", "-"].map(String::from).to_vec(), list.to_string()));
+        let swap = DriverSwap { remove: vec!["nvidia".to_string(), "nvidia-utils".to_string()], add: vec!["vulkan-radeon".to_string()] };
+        let (args, packages) = package_install(list, &swap, &["yay".to_string()], Some(PACMAN_CONF_OVERRIDE));
+        assert_eq!(args[..3], ["pacman", "--config", "/var/tmp/arch-suite-pacman.conf"]);
+        // A name no repository has makes pacman fail, but it is not run.
+        assert_eq!(packages, "base\nfirefox\nx'; reboot; '\nvulkan-radeon\n");
+    }
+
+    #[tokio::test]
+    async fn pacstraps_output_moves_the_gauge_and_is_still_shown() {
+        let (progress, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let package = |name: &str| packages::PendingPackage { name: name.to_string(), version: "1.0-1".to_string(), size: 1000 };
+        let gauge = with_transaction_gauge(progress, vec![package("base"), package("linux")]);
+        for line in ["==> Installing packages to /mnt", " base-1.0-1-any.pkg.tar.zst downloading...", "installing base...", "installing linux..."] {
+            gauge.send(Progress::Message(line.to_string())).unwrap();
+        }
+        drop(gauge);
+        let (mut percents, mut lines) = (Vec::new(), 0);
+        while let Some(update) = rx.recv().await {
+            match update {
+                Progress::Percent(percent) => percents.push(percent),
+                Progress::Message(_) => lines += 1,
+                _ => {}
+            }
+        }
+        assert_eq!((percents, lines), (vec![35, 85, 100], 4));
+    }
+
+    #[tokio::test]
+    async fn hooks_get_the_result_and_their_failures_are_only_noted() {
+        let (progress, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let result = Path::new("/tmp/snapshot 1.tar.gz");
+        assert_eq!(run_hook("post_snapshot", None, result, &progress).await, None);
+        let ran = run_hook("post_snapshot", Some("echo \"$1|$ARCH_SUITE_RESULT\""), result, &progress).await.unwrap();
+        assert!(ran.contains("ran successfully"));
+        let mut lines = Vec::new();
+        while let Ok(Progress::Message(line)) = rx.try_recv() {
+            lines.push(line);
+        }
+        assert_eq!(lines.last().map(String::as_str), Some("/tmp/snapshot 1.tar.gz|/tmp/snapshot 1.tar.gz"));
+        assert!(run_hook("post_snapshot", Some("exit 3"), result, &progress).await.unwrap().contains("hook failed"));
+    }
 }
@@ -6,31 +6,221 @@
 // to provide a clean public API for the rest of the application.
 
 // Re-export types from sub-modules to make them accessible from here.
-pub use crate::actions::{Action, AppAction};
+pub use crate::actions::{Action, ActionResult, ChoicesFn, InputFn, Progress, Prompt};
+pub use crate::components::key_prefix::KeyPrefix;
 pub use crate::components::stateful_list::StatefulList;
 
 use crate::actions;
+use crate::components::path_completion::PathCompletion;
+use crate::components::size_field::SizeField;
+use crate::config::{Config, HighlightConfig};
+use crate::disk::BlockDevice;
+use crate::error::ActionError;
+use crate::history::Recall;
+use crate::inspect::SystemReport;
+use crate::install::InstallState;
+use crate::paths::{self, BaseDir};
+use crate::registry;
+use crate::services::Unit;
+use crate::snapshot::SnapshotFile;
+use crate::workflow::{Checklist, ItemTable, Workflow};
+use anyhow::Result;
+use clap::ValueEnum;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::task::JoinHandle;
 use tui_input::Input;
 
 // --- Enums for State Management ---
+/// The CLI names them in kebab case for `--view`, e.g. `manual-installer`.
+#[derive(Clone, Copy, PartialEq, Debug, ValueEnum)]
+pub enum AppView {
+    MainMenu,
+    HelpManual,
+    Replicator,
+    Cloner,
+    Utilities,
+    ManualInstaller,
+    Logs,
+    /// Opened from the Replicator's "Browse Snapshots" once the snapshots are read, so not by name.
+    #[value(skip)]
+    Snapshots,
+}
+
+impl AppView {
+    /// The views with a menu, in the order the main menu leads to them.
+    pub const MENUS: [AppView; 5] = [AppView::MainMenu, AppView::Replicator, AppView::Cloner, AppView::Utilities, AppView::ManualInstaller];
+    /// The views along the tab bar, switched with Tab/Shift-Tab or Alt+1..5.
+    pub const TABS: [AppView; 5] = [AppView::Replicator, AppView::Cloner, AppView::Utilities, AppView::ManualInstaller, AppView::Logs];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            AppView::MainMenu => "Main Menu",
+            AppView::HelpManual => "Help Manual",
+            AppView::Replicator => "Replicator Menu",
+            AppView::Cloner => "Cloner Menu",
+            AppView::Utilities => "Utilities Menu",
+            AppView::ManualInstaller => "Manual Installer",
+            AppView::Logs => "Session Log",
+            AppView::Snapshots => "Snapshots",
+        }
+    }
+
+    /// The name on the tab bar.
+    pub fn tab_label(self) -> &'static str {
+        match self {
+            AppView::ManualInstaller => "Installer",
+            AppView::Logs => "Logs",
+            _ => self.title().trim_end_matches(" Menu"),
+        }
+    }
+
+    /// The tab `step` places from this one, wrapping around; from a view without a tab, the first
+    /// tab forwards or the last backwards.
+    pub fn next_tab(self, step: isize) -> AppView {
+        let n = Self::TABS.len() as isize;
+        let i = match Self::TABS.iter().position(|tab| *tab == self) {
+            Some(i) => i as isize + step,
+            None if step > 0 => 0,
+            None => n - 1,
+        };
+        Self::TABS[i.rem_euclid(n) as usize]
+    }
+
+    fn last_path() -> Result<PathBuf> {
+        Ok(paths::base_dir(BaseDir::State)?.join("last-view"))
+    }
+
+    /// The view the suite was on when it was last closed, for `--resume`.
+    pub fn last() -> Option<AppView> {
+        let name = std::fs::read_to_string(Self::last_path().ok()?).ok()?;
+        AppView::from_str(name.trim(), false).ok()
+    }
+
+    pub fn save_last(self) -> Result<()> {
+        let path = Self::last_path()?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let name = self.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default();
+        std::fs::write(path, name)?;
+        Ok(())
+    }
+}
 #[derive(Clone, Copy, PartialEq, Debug)]
-pub enum AppView { MainMenu, HelpManual, Replicator, Cloner, Utilities, ManualInstaller }
-#[derive(Clone, Copy, PartialEq, Debug)]
-pub enum Popup { None, Help, Action, Confirm, Input, Select }
+pub enum Popup { None, Help, Action, Report, Confirm, Phrase, Input, Select, MultiSelect, Diff, Results, Services }
 
 // --- Core Application Structs ---
+/// `Ok` when a menu item can run in the current session state, otherwise what to do first.
+pub type AvailabilityFn = fn(&App<'_>) -> Result<(), String>;
+
 pub struct MenuItem<'a> {
     pub icon: &'a str,
+    /// Runs the item from its menu, in either case; the letter in `icon`. Digit icons have none,
+    /// since digits are counts for the motions.
+    pub hotkey: Option<char>,
     pub text: &'a str,
     pub help: &'a str,
-    pub action: Action,
+    /// The name of its action in the registry; see `registry::ACTIONS`.
+    pub id: &'a str,
+    /// Erases or overwrites data (disks, drives, a deployed or restored system), so safe mode
+    /// blocks it.
+    pub destructive: bool,
+    /// Checks the session state the action depends on; see `is_available`.
+    pub requires: Option<AvailabilityFn>,
+}
+
+impl MenuItem<'_> {
+    /// `Err` says what to do first, e.g. "Partition the disk first (step 2)."
+    pub fn is_available(&self, app: &App<'_>) -> Result<(), String> {
+        if self.destructive && app.safe_mode {
+            return Err(SAFE_MODE.to_string());
+        }
+        self.requires.map_or(Ok(()), |check| check(app))
+    }
+
+    /// Why the item cannot run, as the help and details panes put it, if it cannot.
+    pub fn unavailable_note(&self, app: &App<'_>) -> Option<String> {
+        let reason = self.is_available(app).err()?;
+        Some(if self.destructive && app.safe_mode { format!("🔒 {}", reason) } else { format!("⛔ Not available yet: {}", reason) })
+    }
+
+    /// The action the item runs, from the registry.
+    pub fn action(&self) -> Action {
+        registry::action(self.id).unwrap_or_else(|| Action::Message { title: "Unknown Action".to_string(), text: format!("No action is registered as '{}'.", self.id) })
+    }
+
+    /// The item's action, or a message explaining why it cannot run yet.
+    pub fn action_for(&self, app: &App<'_>) -> Action {
+        let title = if self.destructive && app.safe_mode { "Disabled in Safe Mode" } else { "Not Available Yet" };
+        match self.is_available(app) {
+            Ok(()) => self.action(),
+            Err(reason) => Action::Message { title: title.to_string(), text: reason },
+        }
+    }
+}
+
+/// Why a destructive item does not run in safe mode.
+/// How long a notification stays at the bottom of the screen.
+pub const NOTIFICATION_TIME: Duration = Duration::from_secs(4);
+/// The longest success that goes by as a notification; longer ones stay in the popup.
+const NOTIFICATION_MAX: usize = 100;
+
+pub const SAFE_MODE: &str = "Safe mode is on: actions that erase or overwrite data are blocked. Start without --safe, with safe_mode off under [ui], to use it.";
+
+/// A finished action, kept for the session's results panel.
+pub struct SessionResult {
+    /// The menu item (or start-up task) the action was started from.
+    pub action: String,
+    pub at: chrono::DateTime<chrono::Local>,
+    pub ok: bool,
+    pub title: String,
+    pub text: String,
+}
+
+impl SessionResult {
+    /// One line for the results panel: status, time, action and the first line of the output.
+    pub fn summary(&self) -> String {
+        let first = self.text.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or(&self.title);
+        let first: String = if first.chars().count() > 70 { first.chars().take(69).chain(['…']).collect() } else { first.to_string() };
+        format!("{} {}  {}: {}", if self.ok { "✓" } else { "✗" }, self.at.format("%H:%M:%S"), self.action, first)
+    }
+}
+
+/// An `Action::Execute` future running in the background, plus its progress feed.
+pub struct RunningTask {
+    pub handle: JoinHandle<Result<ActionResult, ActionError>>,
+    pub progress: UnboundedReceiver<Progress>,
+    /// What 'r' re-runs if the task fails: the task itself, or the confirmation that started it.
+    pub retry: Action,
+    /// Set by `Progress::Follow`: the task runs until the user stops it.
+    pub follow: bool,
+    /// When it started, for the spinner in its popup's title.
+    pub started: Instant,
 }
 
 pub struct App<'a> {
     // Core State
     pub current_view: AppView,
+    /// The views opened on the way to this one, for Esc to return through.
+    pub view_history: Vec<AppView>,
     pub active_popup: Popup,
+    /// The F1 keybinding cheatsheet, drawn over whatever view or popup is active.
+    pub show_keys: bool,
+    /// A vim-style count or `g` typed ahead of a list motion.
+    pub key_prefix: KeyPrefix,
+    /// The rows the list of the current view shows, as PgUp/PgDn move; set when it is drawn.
+    pub list_page: u16,
     pub should_quit: bool,
+    pub task: Option<RunningTask>,
+    /// Run as soon as the event loop starts, e.g. an install plan passed with `--plan`.
+    pub pending_action: Option<Action>,
+    /// Set when something visible changed; the event loop only redraws while it is set.
+    pub dirty: bool,
+    /// Set by a resize: the next frame is drawn at once onto a cleared screen.
+    pub resized: bool,
     
     // Menus
     pub main_menu: StatefulList<MenuItem<'a>>,
@@ -42,49 +232,459 @@ pub struct App<'a> {
     // Popup Data
     pub popup_title: String,
     pub popup_text: String,
+    pub popup_scroll: u16,
+    pub popup_hscroll: u16,
+    pub popup_page: u16,
+    pub popup_wrap: bool,
+    pub popup_notice: Option<String>,
+    pub popup_progress: Option<u8>,
+    pub popup_progress_label: Option<String>,
+    /// The running action's sub-steps, if it reports them; added to its result when it ends.
+    pub popup_checklist: Option<Checklist>,
+    /// The running action's per-device or per-host results, from `Progress::Items`.
+    pub popup_items: Option<ItemTable>,
+    /// The file an action produced, if any; highlighted in the result popup.
+    pub popup_path: Option<PathBuf>,
     pub popup_list: StatefulList<String>,
+    /// The disks a disk Select lists, in `popup_list` order; empty for other Selects.
+    pub popup_disks: Vec<BlockDevice>,
+    /// Indices of `popup_list` that are ticked in a MultiSelect popup.
+    pub popup_checked: HashSet<usize>,
+    /// The diff of each `popup_list` file in a Merge, shown over it with 'd'; empty for other checklists.
+    pub popup_diffs: Vec<String>,
+    /// What '/' searches a Select or MultiSelect list for: a Select list shows only the items
+    /// containing it, a MultiSelect list highlights the matches in turn.
+    pub popup_search: Input,
+    /// Every item of a Select list, of which `popup_list` holds those matching `popup_search`.
+    pub popup_unfiltered: Vec<String>,
+    /// Whether typed keys go to `popup_search` rather than the list.
+    pub popup_searching: bool,
     pub popup_input: Input,
+    /// The earlier answers to the open Input prompt, stepped through with Up and Down.
+    pub popup_recall: Recall,
+    pub popup_completion: PathCompletion,
+    /// Whether the Input popup hides what is typed (a passphrase).
+    pub popup_masked: bool,
+    /// The disk an Input popup is sizing a partition on, if it is one.
+    pub popup_size: Option<SizeField>,
+    /// What a Checked popup's text must pass before Enter submits it, and why it last did not.
+    pub popup_check: Option<crate::actions::CheckFn>,
+    pub popup_invalid: Option<String>,
     pub popup_action: Option<Action>,
+    /// The answer a Confirm popup defaults to, and the button focused when it opens.
+    pub popup_confirm_default: bool,
+    /// Whether the Yes button (rather than No) has focus in a Confirm popup.
+    pub popup_confirm_focus: bool,
+    /// What a Phrase popup wants typed before it runs `popup_action`.
+    pub popup_phrase: String,
+    /// Receives the ticked items when a MultiSelect popup is submitted.
+    pub popup_on_choices: Option<ChoicesFn>,
+    /// The confirmation that is about to start a task, so a retry asks again.
+    pub popup_confirm_origin: Option<Action>,
+    /// Set on the error popup of a failed task; 'r' runs it.
+    pub popup_retry: Option<Action>,
+    /// Receives the typed text or picked item when a prompt's Input or Select popup is submitted.
+    pub popup_on_input: Option<InputFn>,
+
+    // Help Manual
+    pub manual_scroll: u16,
+    /// Rows per page of the manual, and its rows as last wrapped, for the key handlers.
+    pub manual_page: u16,
+    pub manual_rows: Vec<String>,
+    pub manual_search: Input,
+    /// Whether typed keys go to `manual_search` rather than scrolling.
+    pub manual_searching: bool,
+    /// The row of the match last jumped to.
+    pub manual_match: Option<usize>,
+
+    // Services List
+    /// Every service unit from the last listing; `popup_list` shows the ones matching the filter.
+    pub services: Vec<Unit>,
+    pub service_filter: Input,
+    /// Whether typed keys go to `service_filter` rather than the list.
+    pub service_filtering: bool,
+    /// Index into `services::PRIORITIES` of the lowest journal priority shown; cycled with 'p'.
+    pub journal_priority: usize,
+    // Snapshot Browser
+    /// The snapshots in the work directory when the browser last read them, newest first.
+    pub snapshot_files: Vec<SnapshotFile>,
+    pub snapshot_list: StatefulList<String>,
+    /// The outcome of the last deletion, shown under the list.
+    pub snapshot_notice: Option<String>,
+    /// Whether a running action keeps its newest line in view; scrolling up pauses it, End resumes.
+    pub popup_follow: bool,
+
+    // Session Results
+    pub system_report: Option<SystemReport>,
+    /// What the manual installer has done so far, restored from the previous session.
+    pub install: InstallState,
+    /// The manual installer's steps and their status; step `i` is `manual_install_menu` item `i`.
+    pub workflow: Workflow,
+    /// What the running chain of prompts and actions was started from, for `results`.
+    pub activity: String,
+    /// Every action finished this session, oldest first.
+    pub results: Vec<SessionResult>,
+    /// The Logs tab: `results` newest first, keeping its selection while other tabs are shown.
+    pub log_list: StatefulList<String>,
+    /// Terminal width from which menus get a details pane; 0 disables it. From [ui] `two_pane_width`.
+    pub two_pane_width: u16,
+    /// Whether menus show the selected item's help below them; from [ui] `help_footer`, toggled with 'i'.
+    pub help_footer: bool,
+    /// Whether the yes/no confirmations that default to yes are skipped; from [ui] `expert_mode`, toggled with 'X'.
+    pub expert_mode: bool,
+    /// Whether destructive items are blocked; from [ui] `safe_mode` or `--safe`.
+    pub safe_mode: bool,
+    /// The art above the main menu; from [ui] `show_banner` and `banner_path`.
+    pub banner: Option<String>,
+    /// What changed since the latest snapshot, for the main menu's status bar, and whether it is
+    /// enough to suggest a new one; checked on start and shown until the main menu is left.
+    pub drift: Option<(String, bool)>,
+    /// A one-line success shown at the bottom of the screen instead of in the popup, and since
+    /// when; cleared once `NOTIFICATION_TIME` has passed.
+    pub notification: Option<(String, Instant)>,
+    /// How often a running action is checked on; from [ui] `tick_ms`.
+    pub tick: Duration,
+    /// The shortest time between redraws; from [ui] `max_fps`.
+    pub frame: Duration,
+    /// How command output is coloured in the Action popup; from [highlight].
+    pub highlight: HighlightConfig,
 }
 
 impl<'a> App<'a> {
     pub fn new() -> Self {
+        let install = InstallState::load();
+        let Config { ui, highlight, .. } = Config::load().unwrap_or_default();
+        // A custom banner that cannot be used is replaced by the built-in one, saying why once.
+        let (banner, pending_action) = match ui.banner() {
+            Ok(banner) => (banner, None),
+            Err(e) => (Some(crate::ui::ASCII_ART.to_string()), Some(Action::Message { title: "Custom Banner Not Used".to_string(), text: format!("{:#}\n\nThe built-in banner is shown instead.", e) })),
+        };
         App {
             current_view: AppView::MainMenu,
+            view_history: Vec::new(),
             active_popup: Popup::None,
+            show_keys: false,
+            key_prefix: KeyPrefix::default(),
+            list_page: 1,
             should_quit: false,
+            task: None,
+            pending_action,
+            dirty: true,
+            resized: false,
             main_menu: StatefulList::with_items(vec![
-                MenuItem { icon: "[R]", text: "Replicator (Recommended)", help: "Captures the 'recipe' of your system to perform a clean, fresh installation on new hardware.", action: Action::SetView(AppView::Replicator) },
-                MenuItem { icon: "[C]", text: "Cloner (Advanced)", help: "Creates a direct, 1:1 bootable ISO image of your current system. Best for backups or identical hardware.", action: Action::SetView(AppView::Cloner) },
-                MenuItem { icon: "[U]", text: "Utilities & Manual Tools", help: "Essential tools for system maintenance, including a hardware inspector, USB flasher, and manual installation steps.", action: Action::SetView(AppView::Utilities) },
-                MenuItem { icon: "[H]", text: "Main Help", help: "Displays the main, scrollable help manual for the entire application.", action: Action.SetView(AppView::HelpManual) },
-                MenuItem { icon: "[Q]", text: "Quit", help: "Exits the Arch System Suite application.", action: Action::Quit },
+                MenuItem { icon: "[R]", hotkey: Some('R'), text: "Replicator (Recommended)", help: "Captures the 'recipe' of your system to perform a clean, fresh installation on new hardware.", id: "open-replicator", destructive: false, requires: None },
+                MenuItem { icon: "[C]", hotkey: Some('C'), text: "Cloner (Advanced)", help: "Creates a direct, 1:1 bootable ISO image of your current system. Best for backups or identical hardware.", id: "open-cloner", destructive: false, requires: None },
+                MenuItem { icon: "[U]", hotkey: Some('U'), text: "Utilities & Manual Tools", help: "Essential tools for system maintenance, including a hardware inspector, USB flasher, quick backups, and manual installation steps.", id: "open-utilities", destructive: false, requires: None },
+                MenuItem { icon: "[H]", hotkey: Some('H'), text: "Main Help", help: "Displays the main, scrollable help manual for the entire application.", id: "open-help", destructive: false, requires: None },
+                MenuItem { icon: "[Q]", hotkey: Some('Q'), text: "Quit", help: "Exits the Arch System Suite application.", id: "quit", destructive: false, requires: None },
             ]),
             replicator_menu: StatefulList::with_items(vec![
-                MenuItem { icon: "[S]", text: "Create System Snapshot", help: "Gathers package lists, /etc configs, and dotfiles into a single snapshot file.", action: Action::Execute(actions::create_snapshot) },
-                MenuItem { icon: "[D]", text: "Deploy from Snapshot", help: "Performs a fresh Arch install and applies a snapshot file to replicate a system.", action: Action::Execute(actions::deploy_snapshot) },
+                MenuItem { icon: "[S]", hotkey: Some('S'), text: "Create System Snapshot", help: "Gathers package lists, /etc configs, and dotfiles into a single snapshot file. Asks first whether to include /etc, only dotfiles or the whole home directory (dotfiles and /etc are ticked), and which further patterns to leave out of the home directory. Ticking encryption seals the archive with gpg and a passphrase, which deploying it asks for.", id: "create-snapshot", destructive: false, requires: None },
+                MenuItem { icon: "[T]", hotkey: Some('T'), text: "Snapshot in tmux/screen", help: "Takes a system snapshot inside a detached tmux (or screen) session, so it goes on after the suite is closed; reattach with the command shown or through Utilities → Detached Sessions. Without either installed, the snapshot is taken here.", id: "detached-snapshot", destructive: false, requires: None },
+                MenuItem { icon: "[O]", hotkey: Some('O'), text: "Dotfiles Snapshot", help: "Snapshots only the dotfiles you tick (those under 'dotfiles' in [snapshot] are preselected) and the package lists, for a much smaller archive.", id: "dotfiles-snapshot", destructive: false, requires: None },
+                MenuItem { icon: "[B]", hotkey: Some('B'), text: "Browse Snapshots", help: "Lists the snapshots in the work directory with their date, size and package count; Enter deploys the highlighted one and 'd' deletes it after asking.", id: "browse-snapshots", destructive: false, requires: None },
+                MenuItem { icon: "[C]", hotkey: Some('C'), text: "Compare Snapshot", help: "Read-only: lists the packages a snapshot and this system do not share and the /etc files that differ between them.", id: "compare-snapshot", destructive: false, requires: Some(actions::needs_snapshot) },
+                MenuItem { icon: "[D]", hotkey: Some('D'), text: "Deploy from Snapshot", help: "Applies a snapshot file to the new system mounted at /mnt, letting you choose which /etc entries to restore and, for files that differ from the target's, which version to keep (with their diffs). Dotfiles snapshots restore only packages and dotfiles. A deploy that stopped partway can be resumed.", id: "deploy-snapshot", destructive: true, requires: Some(actions::needs_snapshot) },
+                MenuItem { icon: "[A]", hotkey: Some('A'), text: "Audit Deployed System", help: "Read-only: checks a system deployed from a snapshot (at /mnt, or this one once booted) for packages that did not install, services the source enabled that are not enabled, and a localization that differs.", id: "audit-deploy", destructive: false, requires: Some(actions::needs_snapshot) },
+                MenuItem { icon: "[Y]", hotkey: Some('Y'), text: "Sync Home to Target", help: "Mirrors your home directory to a directory or remote with 'rsync -aAX --delete', skipping the same sensitive paths as snapshots.", id: "sync-home", destructive: true, requires: None },
+                MenuItem { icon: "[R]", hotkey: Some('R'), text: "Restore Points (Timeshift)", help: "Lists the rsync restore points in /timeshift, which Timeshift shares, and creates a new one (unchanged files hard-linked to the last) or restores one onto / or a root mounted at /mnt.", id: "restore-points", destructive: true, requires: None },
+                MenuItem { icon: "[U]", hotkey: Some('U'), text: "Snapshot Before Upgrades", help: "Installs (or removes) a pacman hook that runs 'arch-suite snapshot create' before the packages you choose are upgraded.", id: "upgrade-hook", destructive: false, requires: None },
+                MenuItem { icon: "[F]", hotkey: Some('F'), text: "Snapshot Fleet over SSH", help: "Snapshots every host listed under [fleet] in the config file at once and collects the archives with rsync.", id: "snapshot-fleet", destructive: false, requires: None },
             ]),
             cloner_menu: StatefulList::with_items(vec![
-                MenuItem { icon: "[I]", text: "Create Bootable ISO", help: "Creates a bootable .iso file from the current system state using 'archiso'.", action: Action::Execute(actions::create_iso) },
+                MenuItem { icon: "[I]", hotkey: Some('I'), text: "Create Bootable ISO", help: "Builds a bootable .iso with 'mkarchiso' from archiso's releng profile, customized by [iso] in the config file: optionally every package explicitly installed here, extra packages (both asked for first), an overlay copied into the live system and, optionally, the latest snapshot with the suite started on tty1 to deploy it. The prepared profile is shown before the build, which checks first that the disk has room for it. Flash ISO to USB then suggests the new image.", id: "create-iso", destructive: false, requires: None },
             ]),
             utilities_menu: StatefulList::with_items(vec![
-                MenuItem { icon: "[H]", text: "System Inspector & Prep", help: "Detects CPU/GPU and prepares a list of recommended drivers for installation.", action: Action::Execute(actions::inspect_system) },
-                MenuItem { icon: "[F]", text: "Flash ISO to USB", help: "A safe wrapper around 'dd' to burn any .iso file to a USB drive.", action: Action::Execute(actions::flash_iso) },
-                MenuItem { icon: "[M]", text: "Manual Install Tools", help: "A step-by-step interface for advanced users to partition, format, and install.", action: Action::SetView(AppView::ManualInstaller) },
+                MenuItem { icon: "[C]", hotkey: Some('C'), text: "Doctor (Self-Check)", help: "Checks the environment: Arch, dependencies and versions, sudo, network, free space in the work directory, and terminal support.", id: "doctor", destructive: false, requires: None },
+                MenuItem { icon: "[H]", hotkey: Some('H'), text: "System Inspector & Prep", help: "Detects CPU/GPU and prepares a list of recommended drivers for installation.", id: "inspect-system", destructive: false, requires: None },
+                MenuItem { icon: "[D]", hotkey: Some('D'), text: "Install Recommended Drivers", help: "Installs the driver and microcode packages suggested by the System Inspector. Run the inspector first.", id: "install-drivers", destructive: false, requires: Some(actions::needs_report) },
+                MenuItem { icon: "[O]", hotkey: Some('O'), text: "Remove Orphaned Packages", help: "Lists packages no longer needed by anything and removes the ones you pick, warning about anything that still uses them.", id: "remove-orphans", destructive: false, requires: None },
+                MenuItem { icon: "[B]", hotkey: Some('B'), text: "Quick Backup", help: "Archives one directory (your home by default) to a timestamped file in the backup directory, with the snapshot compressor and without the sensitive paths. Set backup_dir under [snapshot] to keep them elsewhere.", id: "quick-backup", destructive: false, requires: None },
+                MenuItem { icon: "[F]", hotkey: Some('F'), text: "Flash ISO to USB", help: "A safe wrapper around 'dd' to burn any .iso file to a USB drive. The ISO's SHA256 and GPG signature are checked first; the drive is identified by serial and re-checked right before writing.", id: "flash-iso", destructive: true, requires: None },
+                MenuItem { icon: "[V]", hotkey: Some('V'), text: "Test-Boot ISO in QEMU", help: "Boots an ISO (the newest one built here by default) in a QEMU virtual machine, with UEFI when edk2-ovmf is installed and KVM when available, to check it starts before flashing it. Opens a window, or without a desktop shows the console in a tmux or screen session. Offers to install QEMU if it is missing.", id: "test-boot-iso", destructive: false, requires: None },
+                MenuItem { icon: "[N]", hotkey: Some('N'), text: "Manage Kernels", help: "Lists installed kernels (marking the running one) and installs or removes linux, linux-lts, linux-zen or linux-hardened, then regenerates grub.cfg.", id: "manage-kernels", destructive: false, requires: None },
+                MenuItem { icon: "[P]", hotkey: Some('P'), text: "Edit Kernel Parameters", help: "Edits GRUB_CMDLINE_LINUX_DEFAULT in /etc/default/grub, shows the change, backs up the original and regenerates grub.cfg.", id: "edit-kernel-parameters", destructive: false, requires: None },
+                MenuItem { icon: "[S]", hotkey: Some('S'), text: "Manage Services", help: "Lists the systemd services with their boot and run state; filter with '/', then start, stop, restart, enable or disable the selected one, or read and follow its journal.", id: "manage-services", destructive: false, requires: None },
+                MenuItem { icon: "[R]", hotkey: Some('R'), text: "Repair Pacman Keyring", help: "Looks for signature errors in pacman's log, then refreshes archlinux-keyring and runs 'pacman-key --populate', the standard fix when installs fail with 'invalid or corrupted package (PGP signature)'.", id: "repair-keyring", destructive: false, requires: None },
+                MenuItem { icon: "[L]", hotkey: Some('L'), text: "Rank Mirrors", help: "Picks countries from reflector's list (search it with '/'), ranks their recently synced HTTPS mirrors by download rate and writes them to /etc/pacman.d/mirrorlist, keeping a backup. Offers to install reflector if it is missing.", id: "rank-mirrors", destructive: false, requires: None },
+                MenuItem { icon: "[E]", hotkey: Some('E'), text: "Export Package Recipe", help: "Writes the installed packages, native and foreign and optionally their dependencies, as a plain text recipe in the work directory. Nothing else is recorded.", id: "export-recipe", destructive: false, requires: None },
+                MenuItem { icon: "[A]", hotkey: Some('A'), text: "Apply Package Recipe", help: "Installs the packages of a recipe (or any package list) on this system with pacman, and the foreign ones with paru or yay if installed. Configs and home directories are left alone.", id: "apply-recipe", destructive: false, requires: None },
+                MenuItem { icon: "[T]", hotkey: Some('T'), text: "Detached Sessions", help: "Lists the jobs still running in tmux or screen sessions started by the suite, with the command that reattaches each and, for tmux, its latest output.", id: "detached-sessions", destructive: false, requires: None },
+                MenuItem { icon: "[W]", hotkey: Some('W'), text: "Setup Wizard", help: "Asks for the theme, work directory, privilege tool and dependency handling again, and rewrites the config file with the answers.", id: "setup-wizard", destructive: false, requires: None },
+                MenuItem { icon: "[M]", hotkey: Some('M'), text: "Manual Install Tools", help: "A step-by-step interface for advanced users to partition, format, and install.", id: "open-manual-installer", destructive: false, requires: None },
             ]),
             manual_install_menu: StatefulList::with_items(vec![
-                MenuItem { icon: "[1]", text: "Wipe Disk", help: "Completely erases all data and partition tables from a selected disk.", action: Action::Execute(actions::manual_wipe_disk) },
-                MenuItem { icon: "[2]", text: "Partition Disk", help: "Creates a simple EFI + Root partition layout on a selected disk.", action: Action::Execute(actions::manual_partition_disk) },
-                MenuItem { icon: "[3]", text: "Format Partitions", help: "Formats the partitions created in the previous step (fat32 for EFI, ext4 for Root).", action: Action::Execute(actions::manual_format_partitions) },
-                MenuItem { icon: "[4]", text: "Mount Partitions", help: "Mounts the root and EFI partitions to /mnt and /mnt/boot/efi.", action: Action::Execute(actions::manual_mount_partitions) },
-                MenuItem { icon: "[5]", text: "Install Base System", help: "Runs 'pacstrap' to install the base Arch Linux system to /mnt.", action: Action::Execute(actions::manual_pacstrap) },
-                MenuItem { icon: "[6]", text: "Setup Bootloader", help: "Runs 'arch-chroot' to install and configure the GRUB bootloader.", action: Action::Execute(actions::manual_chroot_grub) },
+                MenuItem { icon: "[1]", hotkey: None, text: "Wipe Disk", help: "Completely erases all data and partition tables from a selected disk.", id: "wipe-disk", destructive: true, requires: None },
+                MenuItem { icon: "[2]", hotkey: None, text: "Partition Disk", help: "Creates a boot + root partition layout on the wiped disk: GPT with an EFI partition for UEFI, or MBR with /boot for BIOS.", id: "partition-disk", destructive: true, requires: Some(actions::needs_disk) },
+                MenuItem { icon: "[3]", hotkey: None, text: "Format Partitions", help: "Formats the partitions created in the previous step (fat32 for EFI or ext4 for /boot, ext4 for Root), optionally encrypting Root with LUKS.", id: "format-partitions", destructive: true, requires: Some(actions::needs_partitions) },
+                MenuItem { icon: "[4]", hotkey: None, text: "Mount Partitions", help: "Mounts the root and boot partitions under /mnt and optionally creates and enables a swap file there.", id: "mount-partitions", destructive: false, requires: Some(actions::needs_partitions) },
+                MenuItem { icon: "[5]", hotkey: None, text: "Install Base System", help: "Runs 'pacstrap' to install the base Arch Linux system to /mnt.", id: "install-base-system", destructive: false, requires: Some(actions::needs_mounted) },
+                MenuItem { icon: "[6]", hotkey: None, text: "Generate fstab", help: "Runs 'genfstab -U /mnt' and lists the entries; pick one to change its mount options (e.g. noatime, compress=zstd on btrfs), then write it to /mnt/etc/fstab once it has a root entry.", id: "generate-fstab", destructive: false, requires: Some(actions::needs_base_system) },
+                MenuItem { icon: "[7]", hotkey: None, text: "Setup Bootloader", help: "Runs 'arch-chroot' to install and configure the GRUB bootloader, set up to unlock an encrypted root.", id: "setup-bootloader", destructive: false, requires: Some(actions::needs_base_system) },
+                MenuItem { icon: "[8]", hotkey: None, text: "Set Time Zone", help: "Searches the time zones in /mnt/usr/share/zoneinfo, links the chosen one as /etc/localtime and sets the hardware clock.", id: "set-timezone", destructive: false, requires: Some(actions::needs_base_system) },
+                MenuItem { icon: "[9]", hotkey: None, text: "Generate Locales", help: "Ticks the locales to enable in /etc/locale.gen, runs locale-gen and sets LANG to the first one.", id: "generate-locales", destructive: false, requires: Some(actions::needs_base_system) },
+                MenuItem { icon: "[10]", hotkey: None, text: "Set Console Keymap", help: "Searches the installed console keymaps and writes the chosen one to /etc/vconsole.conf.", id: "set-keymap", destructive: false, requires: Some(actions::needs_base_system) },
+                MenuItem { icon: "[11]", hotkey: None, text: "Create User", help: "Creates a user in the groups you list (wheel may use sudo), or adds an existing one to them.", id: "create-user", destructive: false, requires: Some(actions::needs_base_system) },
+                MenuItem { icon: "[12]", hotkey: None, text: "Set Passwords", help: "Asks twice for the password of root and of each user created, and sets them with chpasswd. They are never recorded.", id: "set-passwords", destructive: false, requires: Some(actions::needs_base_system) },
+                MenuItem { icon: "[A]", hotkey: Some('A'), text: "Run Remaining Steps", help: "Runs the steps that are not done yet in order, starting with the highlighted one, and stops at the first failure or cancelled prompt.", id: "run-remaining-steps", destructive: true, requires: None },
+                MenuItem { icon: "[L]", hotkey: Some('L'), text: "Install History", help: "Lists the steps performed so far and the exact commands each one ran. Kept across sessions.", id: "install-history", destructive: false, requires: None },
+                MenuItem { icon: "[P]", hotkey: Some('P'), text: "Run Install Plan", help: "Loads a TOML plan (disk, partition sizes, filesystem, packages, hostname, user) and runs every step in sequence.", id: "run-install-plan", destructive: true, requires: None },
+                MenuItem { icon: "[E]", hotkey: Some('E'), text: "Export Install Script", help: "Writes the recorded steps to a standalone bash script (set -euo pipefail) for repeatable installs.", id: "export-install-script", destructive: false, requires: Some(actions::needs_history) },
             ]),
             popup_title: String::new(),
             popup_text: String::new(),
+            popup_scroll: 0,
+            popup_hscroll: 0,
+            popup_page: 1,
+            popup_wrap: true,
+            popup_notice: None,
+            popup_progress: None,
+            popup_progress_label: None,
+            popup_checklist: None,
+            popup_items: None,
+            popup_path: None,
             popup_list: StatefulList::with_items(vec![]),
+            popup_disks: Vec::new(),
+            popup_checked: HashSet::new(),
+            popup_diffs: Vec::new(),
+            popup_search: Input::default(),
+            popup_unfiltered: Vec::new(),
+            popup_searching: false,
             popup_input: Input::default(),
+            popup_recall: Recall::default(),
+            popup_completion: PathCompletion::default(),
+            popup_masked: false,
+            popup_size: None,
+            popup_check: None,
+            popup_invalid: None,
             popup_action: None,
+            popup_confirm_default: false,
+            popup_confirm_focus: false,
+            popup_phrase: String::new(),
+            popup_on_choices: None,
+            popup_on_input: None,
+            manual_scroll: 0,
+            manual_page: 1,
+            manual_rows: Vec::new(),
+            manual_search: Input::default(),
+            manual_searching: false,
+            manual_match: None,
+            services: Vec::new(),
+            service_filter: Input::default(),
+            service_filtering: false,
+            journal_priority: 0,
+            snapshot_files: Vec::new(),
+            snapshot_list: StatefulList::with_items(Vec::new()),
+            snapshot_notice: None,
+            popup_follow: false,
+            popup_confirm_origin: None,
+            popup_retry: None,
+            system_report: None,
+            workflow: Workflow::manual_installer(&install.history),
+            install,
+            activity: String::new(),
+            results: Vec::new(),
+            log_list: StatefulList::with_items(Vec::new()),
+            two_pane_width: ui.two_pane_width,
+            help_footer: ui.help_footer,
+            expert_mode: ui.expert_mode,
+            safe_mode: ui.safe_mode,
+            banner,
+            drift: None,
+            notification: None,
+            tick: ui.tick(),
+            frame: ui.frame(),
+            highlight,
+        }
+    }
+
+    /// Keeps the result now shown in the popup for the results panel.
+    pub fn record_result(&mut self, ok: bool) {
+        let action = if self.activity.is_empty() { self.popup_title.clone() } else { self.activity.clone() };
+        if ok {
+            tracing::info!("Finished: {} ({})", action, self.popup_title);
+        } else {
+            tracing::error!("Failed: {} ({})\n{}", action, self.popup_title, self.popup_text);
+        }
+        self.results.push(SessionResult { action, at: chrono::Local::now(), ok, title: self.popup_title.clone(), text: self.popup_text.clone() });
+        self.log_list.items = self.results.iter().rev().map(SessionResult::summary).collect();
+        // The newest entry is added on top; keep the one that was selected.
+        let selected = self.log_list.state.selected().map_or(0, |i| i + 1);
+        self.log_list.state.select(Some(selected));
+    }
+
+    /// Shows the full output of the `i`th newest result.
+    pub fn reopen_result(&mut self, i: usize) {
+        if let Some(entry) = self.results.iter().rev().nth(i) {
+            (self.popup_title, self.popup_text) = (entry.title.clone(), entry.text.clone());
+            self.popup_scroll = 0;
+            self.popup_hscroll = 0;
+            self.popup_notice = None;
+            self.popup_path = None;
+            self.active_popup = Popup::Action;
+        }
+    }
+
+    /// Opens the results panel, newest first.
+    pub fn show_results(&mut self) {
+        self.popup_title = format!("Session Results ({})", self.results.len());
+        self.popup_list = StatefulList::with_items(self.results.iter().rev().map(SessionResult::summary).collect());
+        self.active_popup = Popup::Results;
+    }
+
+    /// Opens the services list with `units`, or refreshes the open one keeping its filter and
+    /// selected unit.
+    pub fn show_services(&mut self, units: Vec<Unit>, notice: Option<String>) {
+        if self.services.is_empty() {
+            self.popup_list = StatefulList::with_items(Vec::new());
+            self.service_filter.reset();
+            self.service_filtering = false;
+        }
+        let selected = self.selected_service().map(|unit| unit.name.clone());
+        self.services = units;
+        self.filter_services(selected.as_deref());
+        self.popup_notice = notice;
+        self.active_popup = Popup::Services;
+    }
+
+    /// The services matching the filter, in list order.
+    pub fn visible_services(&self) -> Vec<&Unit> {
+        self.services.iter().filter(|unit| unit.matches(self.service_filter.value())).collect()
+    }
+
+    pub fn selected_service(&self) -> Option<&Unit> {
+        self.popup_list.state.selected().and_then(|i| self.visible_services().get(i).copied())
+    }
+
+    /// Rebuilds the rows after the filter or the units changed, staying on `selected` if it is shown.
+    pub fn filter_services(&mut self, selected: Option<&str>) {
+        let visible = self.visible_services();
+        let width = visible.iter().map(|unit| unit.name.len()).max().unwrap_or(0);
+        let rows: Vec<String> = visible.iter().map(|unit| unit.label(width)).collect();
+        self.popup_title = format!("Services ({} of {})", rows.len(), self.services.len());
+        let selected = selected.map(|name| format!("{:width$}  ", name, width = width));
+        self.popup_list.set_items(rows, |row| selected.as_ref().is_some_and(|prefix| row.starts_with(prefix.as_str())));
+    }
+
+    /// Back to the services list from the output of one of its keys.
+    pub fn reopen_services(&mut self) {
+        let selected = self.selected_service().map(|unit| unit.name.clone());
+        self.filter_services(selected.as_deref());
+        self.popup_notice = None;
+        self.active_popup = Popup::Services;
+    }
+
+    pub fn close_services(&mut self) {
+        self.services.clear();
+        self.service_filter.reset();
+        self.service_filtering = false;
+        self.popup_notice = None;
+        self.active_popup = Popup::None;
+    }
+
+    /// Opens the snapshot browser on `snapshots`, or refreshes it staying on the selected row.
+    pub fn show_snapshots(&mut self, snapshots: Vec<SnapshotFile>, notice: Option<String>) {
+        let width = snapshots.iter().map(|snapshot| snapshot.name().len()).max().unwrap_or(0);
+        let i = self.snapshot_list.state.selected().unwrap_or(0).min(snapshots.len().saturating_sub(1));
+        self.snapshot_list = StatefulList::with_items(snapshots.iter().map(|snapshot| snapshot.label(width)).collect());
+        self.snapshot_list.state.select((!snapshots.is_empty()).then_some(i));
+        self.snapshot_files = snapshots;
+        self.snapshot_notice = notice;
+        self.active_popup = Popup::None;
+        self.open_view(AppView::Snapshots);
+    }
+
+    pub fn selected_snapshot(&self) -> Option<&SnapshotFile> {
+        self.snapshot_list.state.selected().and_then(|i| self.snapshot_files.get(i))
+    }
+
+    /// The menu shown in the current view, if it has one.
+    pub fn current_menu(&self) -> Option<&StatefulList<MenuItem<'a>>> {
+        self.menu(self.current_view)
+    }
+
+    pub fn menu(&self, view: AppView) -> Option<&StatefulList<MenuItem<'a>>> {
+        match view {
+            AppView::MainMenu => Some(&self.main_menu),
+            AppView::Replicator => Some(&self.replicator_menu),
+            AppView::Cloner => Some(&self.cloner_menu),
+            AppView::Utilities => Some(&self.utilities_menu),
+            AppView::ManualInstaller => Some(&self.manual_install_menu),
+            AppView::HelpManual | AppView::Logs | AppView::Snapshots => None,
+        }
+    }
+
+    fn menu_mut(&mut self, view: AppView) -> Option<&mut StatefulList<MenuItem<'a>>> {
+        match view {
+            AppView::MainMenu => Some(&mut self.main_menu),
+            AppView::Replicator => Some(&mut self.replicator_menu),
+            AppView::Cloner => Some(&mut self.cloner_menu),
+            AppView::Utilities => Some(&mut self.utilities_menu),
+            AppView::ManualInstaller => Some(&mut self.manual_install_menu),
+            AppView::HelpManual | AppView::Logs | AppView::Snapshots => None,
+        }
+    }
+
+    /// Opens `view` from the current one, which Esc then returns to.
+    pub fn open_view(&mut self, view: AppView) {
+        if view != self.current_view {
+            self.view_history.push(self.current_view);
+            self.current_view = view;
+        }
+    }
+
+    /// Returns to the view this one was opened from, or to the main menu.
+    pub fn back(&mut self) {
+        self.current_view = self.view_history.pop().unwrap_or(AppView::MainMenu);
+    }
+
+    /// Goes to the menu item running the registered action `name` and returns what choosing it
+    /// does there: safe mode and the item's requirements apply as they would to a key press.
+    pub fn choose_registered(&mut self, name: &str) -> Option<Action> {
+        let (view, i) = AppView::MENUS.into_iter().find_map(|view| Some((view, self.menu(view)?.items.iter().position(|item| item.id == name)?)))?;
+        self.current_view = view;
+        let menu = self.menu_mut(view)?;
+        menu.state.select(Some(i));
+        let item = &menu.items[i];
+        self.activity = item.text.to_string();
+        if view == AppView::ManualInstaller {
+            self.workflow.start(i);
+        }
+        Some(self.menu(view)?.items[i].action_for(self))
+    }
+
+    pub fn journal_priority(&self) -> &'static str {
+        crate::services::PRIORITIES[self.journal_priority % crate::services::PRIORITIES.len()]
+    }
+
+    /// Whether the screen can change without input, so the event loop must keep ticking.
+    pub fn needs_tick(&self) -> bool {
+        self.task.is_some()
+    }
+
+    /// Closes the popup and shows its text as a notification when it is a quick success: one
+    /// line, short, and without a file to open. Returns whether it did.
+    pub fn notify_if_quick(&mut self) -> bool {
+        let text = self.popup_text.trim();
+        if self.active_popup != Popup::Action || self.popup_path.is_some() || text.is_empty() || text.contains('\n') || text.chars().count() > NOTIFICATION_MAX {
+            return false;
+        }
+        self.notification = Some((text.to_string(), Instant::now()));
+        self.active_popup = Popup::None;
+        true
+    }
+
+    /// How long the notification has left on screen, if there is one.
+    pub fn notification_left(&self) -> Option<Duration> {
+        self.notification.as_ref().map(|(_, at)| NOTIFICATION_TIME.saturating_sub(at.elapsed()))
+    }
+
+    /// Clears the notification once its time is up. Returns whether it did.
+    pub fn expire_notification(&mut self) -> bool {
+        if self.notification_left().is_some_and(|left| left.is_zero()) {
+            self.notification = None;
+            return true;
         }
+        false
     }
 }
@@ -0,0 +1,40 @@
+// ===================================================================
+// Clipboard Module
+// ===================================================================
+// Copies text to the system clipboard by shelling out to the utility
+// that matches the running display server.
+
+use anyhow::{anyhow, Result};
+use std::io::ErrorKind;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Picks the clipboard tools to try, in order, for the current session.
+fn candidates() -> Result<&'static [(&'static str, &'static [&'static str])]> {
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        Ok(&[("wl-copy", &[])])
+    } else if std::env::var_os("DISPLAY").is_some() {
+        Ok(&[("xclip", &["-selection", "clipboard"]), ("xsel", &["--clipboard", "--input"])])
+    } else {
+        Err(anyhow!("No graphical display detected; the clipboard is unavailable."))
+    }
+}
+
+/// Copies `text` to the clipboard and returns the name of the tool that was used.
+pub async fn copy(text: &str) -> Result<&'static str> {
+    for (tool, args) in candidates()? {
+        let spawned = Command::new(tool).args(*args).stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null()).spawn();
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes()).await?;
+        }
+        let status = child.wait().await?;
+        return if status.success() { Ok(tool) } else { Err(anyhow!("'{}' exited with {}", tool, status)) };
+    }
+    Err(anyhow!("No clipboard utility found. Install 'wl-clipboard' (Wayland) or 'xclip' (X11)."))
+}
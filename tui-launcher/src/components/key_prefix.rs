@@ -0,0 +1,72 @@
+// ===================================================================
+// Key Prefix Component
+// ===================================================================
+// Accumulates vim-style counts and multi-key motions (`5j`, `G`, `gg`)
+// for list navigation.
+
+use crossterm::event::KeyCode;
+use std::time::{Duration, Instant};
+
+/// A half-typed prefix is dropped after this long without another key.
+const TIMEOUT: Duration = Duration::from_millis(1500);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Motion {
+    Down(usize),
+    Up(usize),
+    First,
+    Last,
+    /// `NG`: the N-th item, counting from 1.
+    Item(usize),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Feed {
+    /// The key completed a motion.
+    Motion(Motion),
+    /// The key was taken as part of a prefix.
+    Pending,
+    /// Not a motion key; handle it normally.
+    Pass,
+}
+
+#[derive(Default)]
+pub struct KeyPrefix {
+    count: Option<usize>,
+    pending_g: bool,
+    last: Option<Instant>,
+}
+
+impl KeyPrefix {
+    pub fn feed(&mut self, code: KeyCode) -> Feed {
+        if self.last.is_some_and(|t| t.elapsed() > TIMEOUT) {
+            self.reset();
+        }
+        self.last = Some(Instant::now());
+        let count = self.count;
+        let feed = match code {
+            KeyCode::Char(c @ '0'..='9') if c != '0' || count.is_some() => {
+                let digit = c.to_digit(10).unwrap_or(0) as usize;
+                self.count = Some(count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+                self.pending_g = false;
+                return Feed::Pending;
+            }
+            KeyCode::Char('j') | KeyCode::Down => Feed::Motion(Motion::Down(count.unwrap_or(1))),
+            KeyCode::Char('k') | KeyCode::Up => Feed::Motion(Motion::Up(count.unwrap_or(1))),
+            KeyCode::Char('G') => Feed::Motion(count.map_or(Motion::Last, Motion::Item)),
+            KeyCode::Char('g') if self.pending_g => Feed::Motion(count.map_or(Motion::First, Motion::Item)),
+            KeyCode::Char('g') => {
+                self.pending_g = true;
+                return Feed::Pending;
+            }
+            _ => Feed::Pass,
+        };
+        self.reset();
+        feed
+    }
+
+    fn reset(&mut self) {
+        self.count = None;
+        self.pending_g = false;
+    }
+}
@@ -1 +1,4 @@
 pub mod stateful_list;
+pub mod key_prefix;
+pub mod path_completion;
+pub mod size_field;
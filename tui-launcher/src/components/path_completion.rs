@@ -0,0 +1,110 @@
+// ===================================================================
+// Path Completion Component
+// ===================================================================
+// Tab completion of the path being typed in an Input popup: the last
+// word of the input is completed against its directory, and repeated
+// Tabs cycle through the matches. Directories get a trailing '/'.
+
+use std::path::{Path, PathBuf};
+
+#[derive(Default, Debug)]
+pub struct PathCompletion {
+    /// The input before the word being completed.
+    head: String,
+    matches: Vec<String>,
+    at: Option<usize>,
+    /// The input as this completion last left it; anything else typed starts a new one.
+    shown: String,
+}
+
+impl PathCompletion {
+    /// The input after pressing Tab on `text`, or `None` when nothing matches. A leading `~/` is
+    /// read from `home` and kept as typed.
+    pub fn complete(&mut self, text: &str, home: &Path) -> Option<String> {
+        // A lone match (e.g. a directory just completed) completes on into what it contains.
+        if self.matches.len() <= 1 || text != self.shown {
+            let split = text.rfind(|c: char| c.is_whitespace() || c == ',').map_or(0, |i| i + 1);
+            let (head, word) = text.split_at(split);
+            self.head = head.to_string();
+            self.matches = candidates(word, home);
+            self.at = None;
+            // Several matches sharing more than was typed: add that first, as shells do.
+            let common = common_prefix(&self.matches);
+            if self.matches.len() > 1 && common.len() > word.len() {
+                self.shown = format!("{}{}", self.head, common);
+                return Some(self.shown.clone());
+            }
+        }
+        if self.matches.is_empty() {
+            return None;
+        }
+        let i = self.at.map_or(0, |i| (i + 1) % self.matches.len());
+        self.at = Some(i);
+        self.shown = format!("{}{}", self.head, self.matches[i]);
+        Some(self.shown.clone())
+    }
+}
+
+/// The entries of `word`'s directory that start with its last component, sorted, as `word` would
+/// be written with them. Hidden entries only match a component starting with '.'.
+pub fn candidates(word: &str, home: &Path) -> Vec<String> {
+    let (dir, prefix) = word.rsplit_once('/').map_or(("", word), |(dir, prefix)| (dir, prefix));
+    let written = if word.contains('/') { format!("{}/", dir) } else { String::new() };
+    let read: PathBuf = match written.strip_prefix("~/") {
+        Some(rest) => home.join(rest),
+        None if written.is_empty() => PathBuf::from("."),
+        None => PathBuf::from(&written),
+    };
+    let mut found: Vec<String> = std::fs::read_dir(&read)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", written, name, slash))
+        })
+        .collect();
+    found.sort();
+    found
+}
+
+fn common_prefix(words: &[String]) -> String {
+    let Some(first) = words.first() else { return String::new() };
+    let mut common = first.as_str();
+    for word in &words[1..] {
+        let end = common.char_indices().zip(word.chars()).take_while(|((_, a), b)| a == b).last().map_or(0, |((i, a), _)| i + a.len_utf8());
+        common = &common[..end];
+    }
+    common.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tab_completes_shared_prefixes_cycles_and_descends() {
+        let home = std::env::temp_dir().join(format!("arch-suite-completion-{}", std::process::id()));
+        for dir in ["isos", ".hidden"] {
+            std::fs::create_dir_all(home.join(dir)).unwrap();
+        }
+        for file in ["isos/archlinux-2024.05.01-x86_64.iso", "isos/archlinux-2024.06.01-x86_64.iso", "notes.txt"] {
+            std::fs::write(home.join(file), "").unwrap();
+        }
+        assert_eq!(candidates("~/", &home), ["~/isos/", "~/notes.txt"]);
+        assert_eq!(candidates("~/.h", &home), ["~/.hidden/"]);
+
+        let mut completion = PathCompletion::default();
+        assert_eq!(completion.complete("-x ~/i", &home).as_deref(), Some("-x ~/isos/"));
+        assert_eq!(completion.complete("-x ~/isos/", &home).as_deref(), Some("-x ~/isos/archlinux-2024.0"));
+        assert_eq!(completion.complete("-x ~/isos/archlinux-2024.0", &home).as_deref(), Some("-x ~/isos/archlinux-2024.05.01-x86_64.iso"));
+        assert_eq!(completion.complete("-x ~/isos/archlinux-2024.05.01-x86_64.iso", &home).as_deref(), Some("-x ~/isos/archlinux-2024.06.01-x86_64.iso"));
+        assert_eq!(completion.complete("-x ~/isos/archlinux-2024.06.01-x86_64.iso", &home).as_deref(), Some("-x ~/isos/archlinux-2024.05.01-x86_64.iso"));
+        assert_eq!(completion.complete("~/nothing", &home), None);
+        std::fs::remove_dir_all(&home).unwrap();
+    }
+}
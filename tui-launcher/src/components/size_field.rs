@@ -0,0 +1,104 @@
+// ===================================================================
+// Size Field Component
+// ===================================================================
+// A partition size typed into an Input popup, checked against the disk
+// as it is typed: the popup shows the disk, what is already allocated
+// and what would be left, and '+'/'-' step the size up and down.
+
+use crate::disk::human_size;
+use crate::install::size_bytes;
+
+const MIB: u64 = 1 << 20;
+const GIB: u64 = 1 << 30;
+/// A disk whose size could not be read: any size fits, and partitioning itself finds out.
+pub const UNKNOWN: u64 = u64::MAX;
+
+#[derive(Clone, Debug)]
+pub struct SizeField {
+    /// The partition being sized, e.g. "Root".
+    pub label: String,
+    /// The whole disk, in bytes; `UNKNOWN` when it could not be read.
+    pub total: u64,
+    /// The partitions already sized, in disk order.
+    pub used: Vec<(String, u64)>,
+    /// Whether an empty size takes the rest of the disk.
+    pub rest: bool,
+}
+
+impl SizeField {
+    pub fn free(&self) -> u64 {
+        self.total.saturating_sub(self.used.iter().map(|(_, bytes)| bytes).sum())
+    }
+
+    /// The bytes `text` asks for; `None` for the rest of the disk.
+    pub fn parse(&self, text: &str) -> Result<Option<u64>, String> {
+        let text = text.trim().to_uppercase();
+        if text.is_empty() && self.rest {
+            return Ok(None);
+        }
+        let bytes = size_bytes(&text).ok_or_else(|| format!("'{}' is not a size; use a number followed by K, M or G, e.g. 512M.", text))?;
+        match bytes {
+            0 => Err("A partition cannot be empty.".to_string()),
+            bytes if bytes > self.free() => Err(format!("{} is more than the {} left on the disk.", human_size(bytes), human_size(self.free()))),
+            bytes => Ok(Some(bytes)),
+        }
+    }
+
+    /// The size after '+' (`up`) or '-': 256M steps up to 2G, whole GiB above, kept on the disk
+    /// and off zero.
+    pub fn step(&self, text: &str, up: bool) -> String {
+        let current = match self.parse(text) {
+            Ok(Some(bytes)) => bytes,
+            Ok(None) => self.free(),
+            Err(_) => size_bytes(&text.trim().to_uppercase()).unwrap_or(0).min(self.free()),
+        };
+        let step = if current < 2 * GIB || (!up && current == 2 * GIB) { 256 * MIB } else { GIB };
+        let next = if up { (current / step + 1) * step } else { current.saturating_sub(1).div_euclid(step) * step };
+        let next = next.clamp(step.min(self.free()), self.free().max(step));
+        if next.is_multiple_of(GIB) { format!("{}G", next / GIB) } else { format!("{}M", next.div_ceil(MIB)) }
+    }
+
+    /// The lines under the input: the allocation so far with this partition, then what is left or
+    /// why the size does not fit.
+    pub fn feedback(&self, text: &str) -> (String, Result<String, String>) {
+        let mut parts: Vec<String> = self.used.iter().map(|(label, bytes)| format!("{} {}", label, human_size(*bytes))).collect();
+        let outcome = self.parse(text).map(|bytes| {
+            let bytes = bytes.unwrap_or(self.free());
+            parts.push(format!("{} {}", self.label, human_size(bytes)));
+            match self.free() - bytes {
+                _ if self.total == UNKNOWN => "The disk's size could not be read; it is checked when partitioning.".to_string(),
+                0 => "The disk is fully allocated.".to_string(),
+                left => format!("{} left unallocated.", human_size(left)),
+            }
+        });
+        let disk = if self.total == UNKNOWN { "Disk".to_string() } else { format!("Disk {}", human_size(self.total)) };
+        (format!("{}: {}", disk, parts.join(" + ")), outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_are_checked_against_what_is_left_and_stepped() {
+        let root = SizeField { label: "Root".to_string(), total: 100 * GIB, used: vec![("EFI".to_string(), 512 * MIB)], rest: true };
+        assert_eq!(root.parse(""), Ok(None));
+        assert_eq!(root.parse("20g"), Ok(Some(20 * GIB)));
+        assert!(root.parse("100G").is_err_and(|why| why == "100.0G is more than the 99.5G left on the disk."));
+        assert!(root.parse("20GB").is_err());
+        assert_eq!(root.feedback("20G"), ("Disk 100.0G: EFI 512.0M + Root 20.0G".to_string(), Ok("79.5G left unallocated.".to_string())));
+        assert_eq!(root.feedback("").1, Ok("The disk is fully allocated.".to_string()));
+
+        assert_eq!(root.step("20G", true), "21G");
+        assert_eq!(root.step("20G", false), "19G");
+        assert_eq!(root.step("2G", false), "1792M");
+        assert_eq!(root.step("512M", true), "768M");
+        assert_eq!(root.step("256M", false), "256M");
+        assert_eq!(root.step("", false), "99G");
+        assert_eq!(root.step("", true), "101888M");
+
+        let efi = SizeField { label: "EFI".to_string(), total: 100 * GIB, used: Vec::new(), rest: false };
+        assert!(efi.parse("").is_err());
+    }
+}
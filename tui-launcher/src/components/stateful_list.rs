@@ -3,6 +3,7 @@
 // ===================================================================
 // A generic struct to manage the state of any selectable list in the UI.
 
+use super::key_prefix::Motion;
 use ratatui::widgets::ListState;
 
 pub struct StatefulList<T> {
@@ -21,6 +22,15 @@ impl<T> StatefulList<T> {
         list
     }
 
+    /// Replaces the items (e.g. when a filter changes), selecting `selected` if it is still there,
+    /// otherwise the nearest remaining row.
+    pub fn set_items(&mut self, items: Vec<T>, selected: impl Fn(&T) -> bool) {
+        let nearest = self.state.selected().unwrap_or(0).min(items.len().saturating_sub(1));
+        let index = items.iter().position(selected).unwrap_or(nearest);
+        self.items = items;
+        self.state.select(Some(index).filter(|_| !self.items.is_empty()));
+    }
+
     pub fn next(&mut self) {
         if self.items.is_empty() { return; }
         let i = self.state.selected().map_or(0, |i| {
@@ -37,6 +47,46 @@ impl<T> StatefulList<T> {
         self.state.select(Some(i));
     }
 
+    pub fn first(&mut self) {
+        if !self.items.is_empty() {
+            self.state.select(Some(0));
+        }
+    }
+
+    pub fn last(&mut self) {
+        if !self.items.is_empty() {
+            self.state.select(Some(self.items.len() - 1));
+        }
+    }
+
+    /// Moves `n` items down, stopping at the last rather than wrapping around.
+    pub fn next_page(&mut self, n: usize) {
+        if self.items.is_empty() { return; }
+        let i = self.state.selected().map_or(0, |i| i.saturating_add(n).min(self.items.len() - 1));
+        self.state.select(Some(i));
+    }
+
+    /// Moves `n` items up, stopping at the first rather than wrapping around.
+    pub fn previous_page(&mut self, n: usize) {
+        if self.items.is_empty() { return; }
+        let i = self.state.selected().map_or(0, |i| i.saturating_sub(n));
+        self.state.select(Some(i));
+    }
+
+    /// Applies a counted motion. Single steps wrap around like `next`/`previous`; longer jumps stop at the ends.
+    pub fn apply(&mut self, motion: Motion) {
+        if self.items.is_empty() { return; }
+        match motion {
+            Motion::Down(1) => self.next(),
+            Motion::Up(1) => self.previous(),
+            Motion::Down(n) => self.next_page(n),
+            Motion::Up(n) => self.previous_page(n),
+            Motion::First => self.first(),
+            Motion::Last => self.last(),
+            Motion::Item(n) => self.state.select(Some(n.saturating_sub(1).min(self.items.len() - 1))),
+        }
+    }
+
     pub fn selected_item(&self) -> Option<&T> {
         match self.state.selected() {
             Some(i) => self.items.get(i),
@@ -44,3 +94,105 @@ impl<T> StatefulList<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_list_has_no_selection() {
+        let mut list: StatefulList<u8> = StatefulList::with_items(vec![]);
+        list.next();
+        list.previous();
+        assert_eq!(list.state.selected(), None);
+        assert!(list.selected_item().is_none());
+    }
+
+    #[test]
+    fn single_item_stays_selected() {
+        let mut list = StatefulList::with_items(vec!["only"]);
+        list.next();
+        assert_eq!(list.state.selected(), Some(0));
+        list.previous();
+        assert_eq!(list.state.selected(), Some(0));
+    }
+
+    #[test]
+    fn next_wraps_from_last_to_first() {
+        let mut list = StatefulList::with_items(vec!["a", "b", "c"]);
+        list.next();
+        list.next();
+        assert_eq!(list.state.selected(), Some(2));
+        list.next();
+        assert_eq!(list.state.selected(), Some(0));
+    }
+
+    #[test]
+    fn previous_wraps_from_first_to_last() {
+        let mut list = StatefulList::with_items(vec!["a", "b", "c"]);
+        list.previous();
+        assert_eq!(list.state.selected(), Some(2));
+    }
+
+    #[test]
+    fn selected_item_follows_navigation() {
+        let mut list = StatefulList::with_items(vec!["a", "b", "c"]);
+        assert_eq!(list.selected_item(), Some(&"a"));
+        list.next();
+        assert_eq!(list.selected_item(), Some(&"b"));
+        list.previous();
+        list.previous();
+        assert_eq!(list.selected_item(), Some(&"c"));
+    }
+
+    #[test]
+    fn replacing_items_keeps_the_selected_one_or_the_nearest_row() {
+        let mut list = StatefulList::with_items(vec!["a", "b", "c", "d"]);
+        list.apply(Motion::Last);
+        list.set_items(vec!["b", "d"], |item| *item == "d");
+        assert_eq!(list.selected_item(), Some(&"d"));
+        list.set_items(vec!["a"], |item| *item == "d");
+        assert_eq!(list.selected_item(), Some(&"a"));
+        list.set_items(vec![], |_| false);
+        assert_eq!(list.state.selected(), None);
+        list.set_items(vec!["x", "y"], |_| false);
+        assert_eq!(list.state.selected(), Some(0));
+    }
+
+    #[test]
+    fn counted_motions_clamp_at_the_ends() {
+        let mut list = StatefulList::with_items(vec!["a", "b", "c", "d"]);
+        list.apply(Motion::Down(10));
+        assert_eq!(list.state.selected(), Some(3));
+        list.apply(Motion::Up(2));
+        assert_eq!(list.state.selected(), Some(1));
+        list.apply(Motion::Item(3));
+        assert_eq!(list.state.selected(), Some(2));
+        list.apply(Motion::First);
+        assert_eq!(list.state.selected(), Some(0));
+    }
+
+    #[test]
+    fn pages_stop_at_the_ends_and_empty_lists_stay_unselected() {
+        let mut list = StatefulList::with_items((1..=10).collect());
+        list.next_page(4);
+        assert_eq!(list.selected_item(), Some(&5));
+        list.next_page(8);
+        assert_eq!(list.selected_item(), Some(&10));
+        list.previous_page(3);
+        assert_eq!(list.selected_item(), Some(&7));
+        list.previous_page(100);
+        assert_eq!(list.selected_item(), Some(&1));
+        list.last();
+        assert_eq!(list.selected_item(), Some(&10));
+        list.first();
+        assert_eq!(list.selected_item(), Some(&1));
+
+        let mut empty: StatefulList<u8> = StatefulList::with_items(vec![]);
+        empty.first();
+        empty.last();
+        empty.next_page(5);
+        empty.previous_page(5);
+        assert_eq!(empty.state.selected(), None);
+    }
+}
@@ -0,0 +1,536 @@
+// ===================================================================
+// Configuration Module
+// ===================================================================
+// Loads the optional user configuration from
+// `$XDG_CONFIG_HOME/arch-suite/config.toml`. Every field has a default,
+// so a missing file or a missing key simply means "use the defaults".
+// `theme.toml` beside it can recolour the theme's roles.
+
+use crate::paths::{self, BaseDir};
+use crate::snapshot::Compression;
+use crate::ui::{Theme, ASCII_ART};
+use anyhow::{anyhow, Context, Result};
+use ratatui::style::Color;
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct Config {
+    pub snapshot: SnapshotConfig,
+    pub network: NetworkConfig,
+    pub deploy: DeployConfig,
+    pub fleet: FleetConfig,
+    pub flash: FlashConfig,
+    pub timeouts: TimeoutConfig,
+    pub ui: UiConfig,
+    pub privileges: PrivilegesConfig,
+    pub dependencies: DependencyConfig,
+    pub hooks: HooksConfig,
+    pub pacman: PacmanConfig,
+    pub iso: IsoConfig,
+    pub highlight: HighlightConfig,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct UiConfig {
+    /// `dark` (default), `light` or `high-contrast`.
+    pub theme: Theme,
+    /// From this many columns on, the selected item's help is shown beside the menu; 0 keeps one column.
+    pub two_pane_width: u16,
+    /// Show the selected item's help below the menu (toggled with 'i').
+    pub help_footer: bool,
+    /// Skip the yes/no confirmations that default to yes (toggled with 'X'). Warnings and
+    /// destructive confirmations still ask, and erasing a disk still wants the device typed.
+    pub expert_mode: bool,
+    /// Block everything that erases or overwrites data (wiping, partitioning, formatting,
+    /// flashing, deploying and restoring), for demos and exploring; `--safe` turns it on too.
+    pub safe_mode: bool,
+    /// Draw the banner above the main menu; off leaves its rows to the menu on small terminals.
+    pub show_banner: bool,
+    /// A text file with ASCII art to draw instead of the built-in banner.
+    pub banner_path: Option<PathBuf>,
+    /// How often, in milliseconds, a running action's progress is read; lower is livelier, higher
+    /// saves battery. Keys are read as they come either way.
+    pub tick_ms: u64,
+    /// The most redraws per second; animations step on this frame clock.
+    pub max_fps: u32,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        UiConfig { theme: Theme::default(), two_pane_width: 120, help_footer: true, expert_mode: false, safe_mode: false, show_banner: true, banner_path: None, tick_ms: 50, max_fps: 30 }
+    }
+}
+
+/// The most columns and lines a custom banner may take up.
+pub const MAX_BANNER_WIDTH: usize = 100;
+pub const MAX_BANNER_LINES: usize = 12;
+
+impl UiConfig {
+    pub fn tick(&self) -> Duration {
+        Duration::from_millis(self.tick_ms.clamp(5, 1000))
+    }
+
+    /// The shortest time between two redraws.
+    pub fn frame(&self) -> Duration {
+        Duration::from_secs(1) / self.max_fps.clamp(1, 240)
+    }
+
+    /// The banner drawn above the main menu, if it is shown: the art in `banner_path`, or the
+    /// built-in one without it.
+    pub fn banner(&self) -> Result<Option<String>> {
+        if !self.show_banner {
+            return Ok(None);
+        }
+        let Some(path) = &self.banner_path else { return Ok(Some(ASCII_ART.to_string())) };
+        let path = paths::under_home(path.clone(), &paths::real_user()?);
+        let art = std::fs::read_to_string(&path).with_context(|| format!("Could not read the banner {}", path.display()))?;
+        match invalid_banner(&art) {
+            Some(why) => Err(anyhow!("The banner {} {}", path.display(), why)),
+            None => Ok(Some(art.trim_end().to_string())),
+        }
+    }
+}
+
+/// Why `art` cannot be drawn as the banner, if it cannot.
+pub fn invalid_banner(art: &str) -> Option<String> {
+    let art = art.trim_end();
+    let widest = art.lines().map(|line| line.trim_end().chars().count()).max().unwrap_or(0);
+    if art.trim().is_empty() {
+        Some("is empty.".to_string())
+    } else if art.lines().count() > MAX_BANNER_LINES {
+        Some(format!("has {} lines; at most {} fit above the menu.", art.lines().count(), MAX_BANNER_LINES))
+    } else if widest > MAX_BANNER_WIDTH {
+        Some(format!("is {} columns wide; at most {} fit.", widest, MAX_BANNER_WIDTH))
+    } else {
+        None
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct PrivilegesConfig {
+    /// The tool that runs commands as root: `sudo` (default), `doas` or `run0`.
+    pub escalation: Escalation,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Escalation {
+    #[default]
+    Sudo,
+    Doas,
+    Run0,
+}
+
+impl Escalation {
+    pub const ALL: [Escalation; 3] = [Escalation::Sudo, Escalation::Doas, Escalation::Run0];
+
+    pub fn program(self) -> &'static str {
+        match self { Escalation::Sudo => "sudo", Escalation::Doas => "doas", Escalation::Run0 => "run0" }
+    }
+
+    /// The flag that makes the tool fail instead of asking for a password.
+    pub fn non_interactive_flag(self) -> &'static str {
+        match self { Escalation::Run0 => "--no-ask-password", _ => "-n" }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct DependencyConfig {
+    /// Install missing dependencies at start-up without asking.
+    pub auto_install: bool,
+}
+
+/// The answers of the first-run setup wizard.
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct Setup {
+    pub theme: Theme,
+    /// `None` keeps the default work directory.
+    pub work_dir: Option<PathBuf>,
+    pub escalation: Escalation,
+    pub auto_install: bool,
+}
+
+impl Setup {
+    /// The answers as a config file; every setting not asked about keeps its default.
+    pub fn to_toml(&self) -> String {
+        let table = |entries: Vec<(&str, toml::Value)>| toml::Value::Table(entries.into_iter().map(|(k, v)| (k.to_string(), v)).collect());
+        let mut config = toml::Table::new();
+        config.insert("ui".to_string(), table(vec![("theme", self.theme.name().into())]));
+        if let Some(dir) = &self.work_dir {
+            config.insert("snapshot".to_string(), table(vec![("work_dir", dir.display().to_string().into())]));
+        }
+        config.insert("privileges".to_string(), table(vec![("escalation", self.escalation.program().into())]));
+        config.insert("dependencies".to_string(), table(vec![("auto_install", self.auto_install.into())]));
+        format!("# Written by the arch-suite setup wizard. Every other setting has a default.\n\n{}", toml::to_string(&config).unwrap_or_default())
+    }
+}
+
+/// Shell commands run after an action succeeds, with the file or directory it made as `$1` and
+/// `$ARCH_SUITE_RESULT`, e.g. `post_snapshot = "rclone copy \"$1\" nas:snapshots"`. They run as
+/// whoever runs the suite; a failing hook is reported but does not undo the action.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct HooksConfig {
+    /// After a full or dotfiles snapshot, with the snapshot file.
+    pub post_snapshot: Option<String>,
+    /// After a quick backup, with the archive.
+    pub post_backup: Option<String>,
+    /// After a deploy, with the target root (/mnt).
+    pub post_deploy: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct PacmanConfig {
+    /// `ParallelDownloads` for the pacman and pacstrap runs the suite starts (deploys, pacstrap,
+    /// drivers, kernels, dependencies); 0 keeps pacman.conf's own setting. /etc/pacman.conf itself
+    /// is not changed, so pacman run by hand is unaffected.
+    pub parallel_downloads: u32,
+}
+
+impl Default for PacmanConfig {
+    fn default() -> Self {
+        PacmanConfig { parallel_downloads: 5 }
+    }
+}
+
+/// Seconds a command may run before it is stopped, by kind of work; 0 means no limit.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct TimeoutConfig {
+    /// Quick queries such as `pacman -Q` or listing an archive.
+    pub probe: u64,
+    /// Most commands, including package installs.
+    pub standard: u64,
+    /// Work that scales with the data: pacstrap, archiving, flashing, syncing, fleet snapshots.
+    pub long: u64,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        TimeoutConfig { probe: 30, standard: 1800, long: 0 }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct FlashConfig {
+    /// dd block size, e.g. `4M`; larger blocks are often faster on USB 3 drives.
+    pub block_size: String,
+    /// Pipe the image through `pv` for precise progress when it is installed.
+    pub use_pv: bool,
+    /// Check the ISO against its `.sig` file before writing; a missing or untrusted signature asks first.
+    pub verify_signature: bool,
+}
+
+impl Default for FlashConfig {
+    fn default() -> Self {
+        FlashConfig { block_size: "4M".to_string(), use_pv: true, verify_signature: true }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct FleetConfig {
+    /// SSH destinations (`host` or `user@host`) snapshotted by "Snapshot Fleet over SSH".
+    /// Each needs arch-suite installed, key-based login and passwordless sudo.
+    pub hosts: Vec<String>,
+    /// Run on every host; must print the snapshot path as the last line of its output.
+    pub remote_command: String,
+}
+
+impl Default for FleetConfig {
+    fn default() -> Self {
+        FleetConfig { hosts: Vec::new(), remote_command: "arch-suite --snapshot".to_string() }
+    }
+}
+
+/// Customizations "Create Bootable ISO" applies to archiso's releng profile.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct IsoConfig {
+    /// Packages added to the live system, offered again before each build.
+    pub extra_packages: Vec<String>,
+    /// Preselect adding every package explicitly installed here (from the repositories) to it.
+    pub include_installed: bool,
+    /// A directory copied over the profile's airootfs, e.g. holding `etc/...` or `root/...`.
+    pub overlay: Option<PathBuf>,
+    /// Put the latest snapshot and this suite on the ISO, started on tty1 so it can be deployed.
+    pub bundle_snapshot: bool,
+}
+
+/// The words that colour command output in the Action popup; a leading '^' only matches at the
+/// start of a line.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct HighlightConfig {
+    pub enabled: bool,
+    /// Red.
+    pub errors: Vec<String>,
+    /// Yellow.
+    pub warnings: Vec<String>,
+    /// Cyan.
+    pub headings: Vec<String>,
+}
+
+impl Default for HighlightConfig {
+    fn default() -> Self {
+        let words = |words: &[&str]| words.iter().map(|w| w.to_string()).collect();
+        HighlightConfig {
+            enabled: true,
+            errors: words(&["error", "failed", "fatal", "could not", "cannot"]),
+            warnings: words(&["warning", "^warn:", "skipping"]),
+            headings: words(&["^==>", "^::"]),
+        }
+    }
+}
+
+/// Colours for the theme's roles, read from `theme.toml` beside the config file. Each one set is
+/// drawn instead of the theme's own, e.g. `popup_bg = "#f0f0f0"` or `accent = "blue"`.
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct ThemeColors {
+    pub accent: Option<Color>,
+    /// The highlighted menu item or list row.
+    pub highlight_bg: Option<Color>,
+    pub popup_bg: Option<Color>,
+    pub text: Option<Color>,
+}
+
+impl ThemeColors {
+    pub fn path() -> Option<PathBuf> {
+        paths::base_dir(BaseDir::Config).ok().map(|dir| dir.join("theme.toml"))
+    }
+
+    /// The colours in `text`; what cannot be read keeps the theme's colour and is named in the
+    /// warnings instead of failing.
+    pub fn parse(text: &str) -> (ThemeColors, Vec<String>) {
+        let mut colors = ThemeColors::default();
+        let table: toml::Table = match toml::from_str(text) {
+            Ok(table) => table,
+            Err(e) => return (colors, vec![format!("theme.toml is not valid TOML, so it was ignored: {}", e.message())]),
+        };
+        let mut warnings = Vec::new();
+        for (key, value) in table {
+            let slot = match key.as_str() {
+                "accent" => &mut colors.accent,
+                "highlight_bg" => &mut colors.highlight_bg,
+                "popup_bg" => &mut colors.popup_bg,
+                "text" => &mut colors.text,
+                _ => {
+                    warnings.push(format!("Unknown colour '{}' in theme.toml; the roles are accent, highlight_bg, popup_bg and text.", key));
+                    continue;
+                }
+            };
+            match value.as_str().map(str::parse::<Color>) {
+                Some(Ok(color)) => *slot = Some(color),
+                _ => warnings.push(format!("'{}' for {} in theme.toml is not a colour (e.g. \"#3c3c5a\" or \"blue\"); the theme's is used.", value, key)),
+            }
+        }
+        (colors, warnings)
+    }
+
+    /// Reads `theme.toml`; without one, the theme's own colours are used.
+    pub fn load() -> (ThemeColors, Vec<String>) {
+        match Self::path().map(std::fs::read_to_string) {
+            Some(Ok(text)) => Self::parse(&text),
+            Some(Err(e)) if e.kind() != std::io::ErrorKind::NotFound => (ThemeColors::default(), vec![format!("theme.toml could not be read: {}", e)]),
+            _ => (ThemeColors::default(), Vec::new()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct DeployConfig {
+    /// Top-level /etc entries left unticked by default when restoring, as they describe the source machine.
+    pub etc_skip: Vec<String>,
+}
+
+impl Default for DeployConfig {
+    fn default() -> Self {
+        DeployConfig { etc_skip: ["fstab", "machine-id", "hostname", "hosts"].map(String::from).to_vec() }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct NetworkConfig {
+    /// Host probed before online operations; point it at your mirror on restricted networks.
+    pub probe_host: String,
+    pub probe_port: u16,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig { probe_host: "archlinux.org".to_string(), probe_port: 443 }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct SnapshotConfig {
+    /// `gzip` (default), `zstd` or `xz`.
+    pub compression: Compression,
+    /// The compression level, trading speed for size: 1-9 for gzip, 1-19 for zstd, 0-9 for xz.
+    /// Unset uses the tool's default; a level out of range is clamped.
+    pub level: Option<u32>,
+    /// Threads for the parallel compressors; 0 uses every available core.
+    pub threads: usize,
+    /// Paths in the home directory that are left out of snapshots unless opted in.
+    pub sensitive: Vec<SensitivePath>,
+    /// Explicit opt-in to archive the `sensitive` paths anyway.
+    pub include_sensitive: bool,
+    /// Where snapshots and fleet archives are kept (`~/` allowed); defaults to `$XDG_DATA_HOME/arch-suite`.
+    pub work_dir: Option<PathBuf>,
+    /// Home-relative paths ticked by default for a dotfiles snapshot.
+    pub dotfiles: Vec<String>,
+    /// Record every package's exact version, so deploys install those instead of the repos'
+    /// current ones where a package file of them is available.
+    pub pin_versions: bool,
+    /// With `pin_versions`, also copy the cached package files of those versions into the
+    /// snapshot, so the deploy does not depend on this machine's pacman cache. Can be large.
+    pub include_package_cache: bool,
+    /// Write a detached GPG signature (`.sig`) next to every snapshot; deploys then check it.
+    pub sign: bool,
+    /// The key to sign with, e.g. an email or fingerprint; gpg's default key when unset.
+    pub signing_key: Option<String>,
+    /// Where quick backups are written (`~/` allowed); the work directory when unset.
+    pub backup_dir: Option<PathBuf>,
+    /// The Timeshift directory restore points are kept in, with them in its `snapshots` folder.
+    pub timeshift_dir: PathBuf,
+    /// From this many packages installed or removed plus /etc files changed since the latest
+    /// snapshot, the main menu suggests taking a new one; 0 turns the startup check off.
+    pub drift_reminder: usize,
+    /// Make snapshots byte-reproducible: entries sorted by name, every date fixed at
+    /// `$SOURCE_DATE_EPOCH` (1970 when unset) and no timestamps in gzip headers, so snapshots of
+    /// the same files hash the same. Files come back with that date on deploy.
+    pub reproducible: bool,
+}
+
+/// A category of secrets (e.g. "SSH keys") and the home-relative tar patterns that match it.
+#[derive(Deserialize, Clone)]
+pub struct SensitivePath {
+    pub category: String,
+    pub patterns: Vec<String>,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        let sensitive = |category: &str, patterns: &[&str]| SensitivePath {
+            category: category.to_string(),
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+        };
+        SnapshotConfig {
+            compression: Compression::default(),
+            level: None,
+            threads: 0,
+            sensitive: vec![
+                sensitive("SSH keys", &[".ssh"]),
+                sensitive("GnuPG keyrings", &[".gnupg"]),
+                sensitive("AWS credentials", &[".aws"]),
+                sensitive("Browser credential stores", &[
+                    ".mozilla/firefox/*/logins.json",
+                    ".mozilla/firefox/*/key4.db",
+                    ".config/google-chrome/*/Login Data",
+                    ".config/chromium/*/Login Data",
+                ]),
+            ],
+            include_sensitive: false,
+            work_dir: None,
+            dotfiles: [".config", ".bashrc", ".zshrc", ".vimrc"].map(String::from).to_vec(),
+            pin_versions: false,
+            include_package_cache: false,
+            sign: false,
+            signing_key: None,
+            backup_dir: None,
+            timeshift_dir: PathBuf::from("/timeshift"),
+            drift_reminder: 20,
+            reproducible: false,
+        }
+    }
+}
+
+impl SnapshotConfig {
+    /// The sensitive paths to leave out of snapshots and home syncs.
+    pub fn excluded(&self) -> &[SensitivePath] {
+        if self.include_sensitive { &[] } else { &self.sensitive }
+    }
+}
+
+impl Config {
+    /// `config.toml` in the real user's config directory, also under sudo.
+    pub fn path() -> Option<PathBuf> {
+        paths::base_dir(BaseDir::Config).ok().map(|dir| dir.join("config.toml"))
+    }
+
+    /// Whether no config file has been written yet, i.e. this is the first run.
+    pub fn is_first_run() -> bool {
+        Self::path().is_some_and(|path| !path.exists())
+    }
+
+    /// Writes the wizard's answers as the config file and returns its path.
+    pub fn save_setup(setup: &Setup) -> Result<PathBuf> {
+        let path = Self::path().context("The config directory could not be resolved")?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        std::fs::write(&path, setup.to_toml()).with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(path)
+    }
+
+    /// Reads the config file, falling back to defaults when it does not exist.
+    pub fn load() -> Result<Config> {
+        let Some(path) = Self::path() else { return Ok(Config::default()) };
+        match std::fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text).with_context(|| format!("Invalid configuration in {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn theme_colours_override_only_what_parses() {
+        let (colors, warnings) = ThemeColors::parse("popup_bg = \"#f0f0f0\"\naccent = \"blue\"\ntext = \"not-a-colour\"\nborder = \"red\"\n");
+        assert_eq!(colors, ThemeColors { popup_bg: Some(Color::Rgb(240, 240, 240)), accent: Some(Color::Blue), ..ThemeColors::default() });
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].starts_with("Unknown colour 'border'") && warnings[1].contains("for text"));
+        assert_eq!(ThemeColors::parse("accent = ").0, ThemeColors::default());
+    }
+
+    #[test]
+    fn setup_answers_load_back_as_a_config() {
+        let setup = Setup { theme: Theme::HighContrast, work_dir: Some(PathBuf::from("/data/arch suite")), escalation: Escalation::Doas, auto_install: true };
+        let config: Config = toml::from_str(&setup.to_toml()).unwrap();
+        assert_eq!(config.ui.theme, Theme::HighContrast);
+        assert_eq!(config.snapshot.work_dir, Some(PathBuf::from("/data/arch suite")));
+        assert_eq!(config.privileges.escalation, Escalation::Doas);
+        assert!(config.dependencies.auto_install);
+        // Settings the wizard does not ask about keep their defaults.
+        assert_eq!(config.flash.block_size, "4M");
+
+        let config: Config = toml::from_str(&Setup::default().to_toml()).unwrap();
+        assert_eq!((config.snapshot.work_dir, config.privileges.escalation), (None, Escalation::Sudo));
+    }
+
+    #[test]
+    fn custom_banners_must_fit_above_the_menu() {
+        assert!(invalid_banner(" _  _\n| || |\n\n").is_none());
+        assert_eq!(invalid_banner(" \n\n").as_deref(), Some("is empty."));
+        assert!(invalid_banner(&"#\n".repeat(13)).is_some_and(|why| why.starts_with("has 13 lines")));
+        assert!(invalid_banner(&"#".repeat(101)).is_some_and(|why| why.starts_with("is 101 columns wide")));
+        let hidden = UiConfig { show_banner: false, banner_path: Some(PathBuf::from("/nonexistent")), ..UiConfig::default() };
+        assert_eq!(hidden.banner().unwrap(), None);
+        assert_eq!(UiConfig::default().banner().unwrap().as_deref(), Some(ASCII_ART));
+    }
+}
@@ -0,0 +1,117 @@
+// ===================================================================
+// Detached Sessions Module
+// ===================================================================
+// Long jobs run as `arch-suite <subcommand>` inside a tmux or screen
+// session, so the TUI can be closed and the job reattached to later.
+// The sessions started are remembered in the state directory:
+//
+//   $XDG_STATE_HOME/arch-suite/sessions.json
+
+use crate::paths::{self, BaseDir};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Multiplexer {
+    Tmux,
+    Screen,
+}
+
+impl Multiplexer {
+    pub fn program(self) -> &'static str {
+        match self { Multiplexer::Tmux => "tmux", Multiplexer::Screen => "screen" }
+    }
+
+    /// The first one installed, tmux preferred.
+    pub fn available(exists: impl Fn(&str) -> bool) -> Option<Multiplexer> {
+        [Multiplexer::Tmux, Multiplexer::Screen].into_iter().find(|m| exists(m.program()))
+    }
+
+    /// The arguments that start `script` in a new detached session called `name`.
+    pub fn start_args(self, name: &str, script: &str) -> Vec<String> {
+        let start: &[&str] = match self { Multiplexer::Tmux => &["new-session", "-d", "-s"], Multiplexer::Screen => &["-dmS"] };
+        start.iter().map(|arg| arg.to_string()).chain([name.to_string(), "sh".to_string(), "-c".to_string(), script.to_string()]).collect()
+    }
+
+    /// The arguments that exit 0 only while session `name` exists.
+    pub fn alive_args(self, name: &str) -> Vec<String> {
+        match self {
+            Multiplexer::Tmux => vec!["has-session".to_string(), "-t".to_string(), name.to_string()],
+            Multiplexer::Screen => vec!["-S".to_string(), name.to_string(), "-Q".to_string(), "select".to_string(), ".".to_string()],
+        }
+    }
+
+    /// What to type in a terminal to reattach.
+    pub fn attach_command(self, name: &str) -> String {
+        match self { Multiplexer::Tmux => format!("tmux attach -t {}", name), Multiplexer::Screen => format!("screen -r {}", name) }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Session {
+    pub name: String,
+    pub multiplexer: Multiplexer,
+    /// What it runs, e.g. `snapshot create`.
+    pub job: String,
+    pub started: String,
+}
+
+/// A session name unique to the job and second, e.g. `arch-suite-snapshot-20240501-153000`.
+pub fn session_name(job: &str, at: chrono::DateTime<chrono::Local>) -> String {
+    format!("arch-suite-{}-{}", job.split_whitespace().next().unwrap_or("job"), at.format("%Y%m%d-%H%M%S"))
+}
+
+/// `arg` quoted for `sh`.
+fn quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// The session's shell script: runs `argv`, then waits for Enter, so the result is still there
+/// when someone reattaches after it finished.
+pub fn script(argv: &[String]) -> String {
+    let command: Vec<String> = argv.iter().map(|arg| quote(arg)).collect();
+    format!("{}; status=$?; echo; echo \"Finished (exit status $status). Press Enter to close this session.\"; read _", command.join(" "))
+}
+
+fn sessions_path() -> Result<PathBuf> {
+    Ok(paths::base_dir(BaseDir::State)?.join("sessions.json"))
+}
+
+/// The sessions started earlier, oldest first; some may have been closed since.
+pub fn load() -> Vec<Session> {
+    sessions_path().ok().and_then(|path| std::fs::read_to_string(path).ok()).and_then(|text| serde_json::from_str(&text).ok()).unwrap_or_default()
+}
+
+pub fn save(sessions: &[Session]) -> Result<()> {
+    let path = sessions_path()?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(sessions)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn sessions_start_detached_and_wait_after_the_job() {
+        assert_eq!(Multiplexer::available(|tool| tool == "screen"), Some(Multiplexer::Screen));
+        assert_eq!(Multiplexer::available(|_| true), Some(Multiplexer::Tmux));
+        assert_eq!(Multiplexer::available(|_| false), None);
+
+        let at = chrono::Local.with_ymd_and_hms(2024, 5, 1, 15, 30, 0).unwrap();
+        let name = session_name("snapshot create", at);
+        assert_eq!(name, "arch-suite-snapshot-20240501-153000");
+        let script = script(&["/usr/bin/arch-suite".to_string(), "--work-dir".to_string(), "/data/o'brien".to_string(), "snapshot".to_string(), "create".to_string()]);
+        assert!(script.starts_with("'/usr/bin/arch-suite' '--work-dir' '/data/o'\\''brien' 'snapshot' 'create'; status=$?;"));
+        assert!(script.ends_with("read _"));
+        assert_eq!(Multiplexer::Tmux.start_args(&name, "true"), ["new-session", "-d", "-s", name.as_str(), "sh", "-c", "true"]);
+        assert_eq!(Multiplexer::Screen.start_args(&name, "true"), ["-dmS", name.as_str(), "sh", "-c", "true"]);
+        assert_eq!(Multiplexer::Screen.attach_command(&name), "screen -r arch-suite-snapshot-20240501-153000");
+    }
+}
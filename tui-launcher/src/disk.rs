@@ -0,0 +1,208 @@
+// ===================================================================
+// Block Device Module
+// ===================================================================
+// Parses `lsblk --json` into a tree of block devices. Different
+// util-linux releases encode sizes and flags as numbers, strings or
+// booleans, so the fields are decoded leniently.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use std::path::Path;
+use tokio::process::Command;
+
+/// Where the running system, or the live medium it booted from, is mounted.
+const SYSTEM_MOUNTS: [&str; 9] = ["/", "/boot", "/efi", "/boot/efi", "/usr", "/var", "/home", "[SWAP]", "/run/archiso/bootmnt"];
+
+const LSBLK_COLUMNS: &str = "NAME,SIZE,TYPE,FSTYPE,LABEL,MOUNTPOINT,RM,MODEL,SERIAL,TRAN";
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct BlockDevice {
+    /// Full device path (`lsblk -p`), e.g. `/dev/sda1`.
+    pub name: String,
+    #[serde(deserialize_with = "lenient_u64")]
+    pub size: u64,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub fstype: Option<String>,
+    pub label: Option<String>,
+    pub mountpoint: Option<String>,
+    #[serde(default, deserialize_with = "lenient_bool")]
+    pub rm: bool,
+    pub model: Option<String>,
+    #[serde(default)]
+    pub serial: Option<String>,
+    /// Transport, e.g. `usb`, `sata` or `nvme`.
+    #[serde(default)]
+    pub tran: Option<String>,
+    #[serde(default)]
+    pub children: Vec<BlockDevice>,
+}
+
+#[derive(Deserialize)]
+struct LsblkOutput {
+    blockdevices: Vec<BlockDevice>,
+}
+
+fn lenient_u64<'de, D: Deserializer<'de>>(d: D) -> Result<u64, D::Error> {
+    Ok(match Value::deserialize(d)? {
+        Value::Number(n) => n.as_u64().unwrap_or(0),
+        Value::String(s) => s.trim().parse().unwrap_or(0),
+        _ => 0,
+    })
+}
+
+fn lenient_bool<'de, D: Deserializer<'de>>(d: D) -> Result<bool, D::Error> {
+    Ok(match Value::deserialize(d)? {
+        Value::Bool(b) => b,
+        Value::Number(n) => n.as_u64() == Some(1),
+        Value::String(s) => s == "1" || s == "true",
+        _ => false,
+    })
+}
+
+impl BlockDevice {
+    /// This device followed by all of its descendants, depth first.
+    pub fn flatten(&self) -> Vec<&BlockDevice> {
+        let mut out = vec![self];
+        for child in &self.children {
+            out.extend(child.flatten());
+        }
+        out
+    }
+
+    /// Whether this device, or anything on it, holds the running system or the medium it booted
+    /// from. Writing it would destroy the system mid-flight, so it is never offered as a target.
+    pub fn runs_the_system(&self) -> bool {
+        self.flatten().iter().any(|d| d.mountpoint.as_deref().is_some_and(|m| SYSTEM_MOUNTS.contains(&m)))
+    }
+
+    /// One line per partition (or the bare disk, if it has none) with filesystem, label and
+    /// mountpoint. Mounted entries start with `MOUNTED_MARK` so popups can highlight them.
+    pub fn layout_lines(&self) -> Vec<String> {
+        let entries: Vec<&BlockDevice> = if self.children.is_empty() { vec![self] } else { self.flatten().into_iter().skip(1).collect() };
+        entries
+            .iter()
+            .map(|d| {
+                let (fstype, label) = (d.fstype.as_deref().unwrap_or("-"), d.label.as_deref().unwrap_or("-"));
+                match &d.mountpoint {
+                    Some(mountpoint) => format!("{} {}  {}  {}  {}  mounted on {}", MOUNTED_MARK, d.name, human_size(d.size), fstype, label, mountpoint),
+                    None => format!("  {}  {}  {}  {}  not mounted", d.name, human_size(d.size), fstype, label),
+                }
+            })
+            .collect()
+    }
+
+    /// `/dev/sda  931.5G  Samsung SSD 870`, as disk choices are listed.
+    pub fn label(&self) -> String {
+        format!("{}  {}  {}", self.name, human_size(self.size), self.model.as_deref().unwrap_or("").trim())
+    }
+
+    /// The disk's partitions side by side in `width` columns, each as wide as its share of the disk
+    /// but at least one column, followed by the space they leave unused (from 1% of the disk on;
+    /// alignment gaps are left out).
+    pub fn layout_bar(&self, width: usize) -> Vec<BarSegment> {
+        let segment = |d: &BlockDevice| BarSegment { name: Some(d.name.clone()), fstype: d.fstype.clone(), size: d.size, width: 0 };
+        // Without partitions, a filesystem may sit straight on the disk.
+        let mut segments: Vec<BarSegment> = if !self.children.is_empty() {
+            self.children.iter().map(segment).collect()
+        } else if self.fstype.is_some() {
+            vec![segment(self)]
+        } else {
+            Vec::new()
+        };
+        let used: u64 = segments.iter().map(|s| s.size).sum();
+        let total = self.size.max(used).max(1);
+        if total - used >= total / 100 {
+            segments.push(BarSegment { name: None, fstype: None, size: total - used, width: 0 });
+        }
+        for segment in &mut segments {
+            segment.width = ((segment.size as u128 * width as u128 / total as u128) as usize).max(1);
+        }
+        // Rounding leaves a few columns over (given to the largest) or, with many small
+        // partitions, too many (taken from the largest).
+        loop {
+            let sum: usize = segments.iter().map(|s| s.width).sum();
+            let Some(largest) = segments.iter_mut().max_by_key(|s| s.width) else { break };
+            match sum.cmp(&width) {
+                std::cmp::Ordering::Less => largest.width += width - sum,
+                std::cmp::Ordering::Greater if largest.width > 1 => largest.width -= 1,
+                _ => break,
+            }
+        }
+        segments
+    }
+}
+
+/// One stretch of a disk's layout bar: a partition, or space no partition uses (`name` is `None`).
+#[derive(Clone, PartialEq, Debug)]
+pub struct BarSegment {
+    pub name: Option<String>,
+    pub fstype: Option<String>,
+    pub size: u64,
+    /// Columns it takes up.
+    pub width: usize,
+}
+
+/// Prefix of device lines describing something in use.
+pub const MOUNTED_MARK: &str = "⚠";
+
+pub fn parse_lsblk(json: &str) -> Result<Vec<BlockDevice>> {
+    Ok(serde_json::from_str::<LsblkOutput>(json)?.blockdevices)
+}
+
+/// Lists every block device (with partitions as children), sizes in bytes.
+pub async fn list() -> Result<Vec<BlockDevice>> {
+    let output = Command::new("lsblk").args(["--json", "-b", "-p", "-o", LSBLK_COLUMNS]).output().await?;
+    if !output.status.success() {
+        return Err(anyhow!("lsblk failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    parse_lsblk(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// The size of the disk `dev` (e.g. `/dev/sda`) in bytes, read from sysfs, which counts 512-byte
+/// sectors whatever the disk's own sector size.
+pub fn size_of(dev: &str) -> Option<u64> {
+    let name = Path::new(dev).file_name()?.to_string_lossy().to_string();
+    let sectors = std::fs::read_to_string(format!("/sys/class/block/{}/size", name)).ok()?;
+    sectors.trim().parse::<u64>().ok().map(|sectors| sectors * 512)
+}
+
+/// Formats a byte count with binary units, e.g. `931.5G`.
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "K", "M", "G", "T", "P"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 { format!("{}B", bytes) } else { format!("{:.1}{}", value, UNITS[unit]) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LSBLK: &str = r#"{"blockdevices": [{"name": "/dev/sda", "size": "1000", "type": "disk", "fstype": null, "label": null, "mountpoint": null, "rm": false, "model": "Test Disk ", "children": [
+        {"name": "/dev/sda1", "size": 100, "type": "part", "fstype": "vfat", "label": null, "mountpoint": null, "rm": "0", "model": null},
+        {"name": "/dev/sda2", "size": 600, "type": "part", "fstype": "ext4", "label": "root", "mountpoint": "/", "rm": "0", "model": null},
+        {"name": "/dev/sda3", "size": 1, "type": "part", "fstype": "swap", "label": null, "mountpoint": null, "rm": "0", "model": null}
+    ]}]}"#;
+
+    #[test]
+    fn the_layout_bar_is_proportional_and_fills_its_width() {
+        let disk = &parse_lsblk(LSBLK).unwrap()[0];
+        assert_eq!(disk.label(), "/dev/sda  1000B  Test Disk");
+        assert!(disk.runs_the_system() && !disk.children[0].runs_the_system());
+        let bar = disk.layout_bar(40);
+        let widths: Vec<usize> = bar.iter().map(|s| s.width).collect();
+        // 600 of 1000 bytes gets 24 columns; the 1-byte partition still gets one.
+        assert_eq!(widths, [4, 24, 1, 11]);
+        assert_eq!((bar[3].name.as_deref(), bar[3].size), (None, 299));
+        assert_eq!(bar[1].fstype.as_deref(), Some("ext4"));
+
+        let blank = BlockDevice { children: Vec::new(), ..disk.clone() };
+        assert_eq!(blank.layout_bar(10), [BarSegment { name: None, fstype: None, size: 1000, width: 10 }]);
+    }
+}
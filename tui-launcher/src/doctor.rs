@@ -0,0 +1,143 @@
+// ===================================================================
+// Doctor Module
+// ===================================================================
+// One self-check that gathers the preflight checks scattered across
+// the actions (Arch, dependencies, sudo, network, disk space, terminal)
+// into a single checklist, so an odd failure can be diagnosed up front.
+
+use crate::actions::{self, DEPENDENCIES};
+use crate::config::Config;
+use crate::disk::human_size;
+use crate::paths;
+use tokio::process::Command;
+
+/// Below this much free space in the work directory a snapshot is unlikely to fit.
+const LOW_SPACE: u64 = 10 << 30;
+const NO_SPACE: u64 = 1 << 30;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl Status {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Status::Pass => "✓",
+            Status::Warn => "⚠",
+            Status::Fail => "✗",
+        }
+    }
+}
+
+pub struct Check {
+    pub status: Status,
+    pub name: &'static str,
+    pub detail: String,
+}
+
+pub fn check(status: Status, name: &'static str, detail: impl Into<String>) -> Check {
+    Check { status, name, detail: detail.into() }
+}
+
+/// Runs every check. None of them changes the system.
+pub async fn run() -> Vec<Check> {
+    let mut checks = vec![match actions::ensure_arch() {
+        Ok(()) => check(Status::Pass, "Arch Linux", "/etc/arch-release found"),
+        Err(e) => check(Status::Fail, "Arch Linux", e.to_string()),
+    }];
+    checks.extend(dependencies().await);
+    checks.push(sudo().await);
+    checks.push(match actions::check_connectivity().await {
+        Ok(()) => check(Status::Pass, "Network", "probe host reachable"),
+        Err(e) => check(Status::Fail, "Network", e.to_string()),
+    });
+    checks.push(work_dir_space());
+    checks.extend(terminal());
+    checks
+}
+
+/// The checklist as popup text, with a tally at the end.
+pub fn render(checks: &[Check]) -> String {
+    let width = checks.iter().map(|c| c.name.len()).max().unwrap_or(0);
+    let mut lines: Vec<String> = checks.iter().map(|c| format!("{} {:width$}  {}", c.status.symbol(), c.name, c.detail, width = width)).collect();
+    let count = |status| checks.iter().filter(|c| c.status == status).count();
+    lines.push(String::new());
+    lines.push(format!("{} passed, {} warnings, {} failed", count(Status::Pass), count(Status::Warn), count(Status::Fail)));
+    lines.join("\n")
+}
+
+async fn dependencies() -> Vec<Check> {
+    // `pacman -Q` lists what it finds even when some packages are missing.
+    let output = Command::new("pacman").arg("-Q").args(DEPENDENCIES).output().await;
+    let installed = match output {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).to_string(),
+        Err(e) => return vec![check(Status::Fail, "Dependencies", format!("pacman could not be run ({})", e))],
+    };
+    let versions: Vec<(&str, &str)> = installed.lines().filter_map(|line| line.split_once(' ')).collect();
+    DEPENDENCIES
+        .iter()
+        .map(|dep| match versions.iter().find(|(name, _)| name == dep) {
+            Some((_, version)) => check(Status::Pass, "Dependency", format!("{} {}", dep, version)),
+            None => check(Status::Fail, "Dependency", format!("{} is missing (sudo pacman -S {})", dep, dep)),
+        })
+        .collect()
+}
+
+async fn sudo() -> Check {
+    let is_root = Command::new("id").arg("-u").output().await.is_ok_and(|o| String::from_utf8_lossy(&o.stdout).trim() == "0");
+    if is_root {
+        return check(Status::Pass, "Privileges", "running as root");
+    }
+    let escalation = Config::load().map(|c| c.privileges.escalation).unwrap_or_default();
+    let tool = escalation.program();
+    if !actions::command_exists(tool) {
+        return check(Status::Fail, "Privileges", format!("{} is not installed; system changes will fail", tool));
+    }
+    match actions::escalated().args([escalation.non_interactive_flag(), "true"]).output().await {
+        Ok(output) if output.status.success() => check(Status::Pass, "Privileges", format!("{} works without a password prompt", tool)),
+        _ => check(Status::Warn, "Privileges", format!("{} needs a password; authenticate once before long actions", tool)),
+    }
+}
+
+fn work_dir_space() -> Check {
+    let dir = match paths::work_dir() {
+        Ok(dir) => dir,
+        Err(e) => return check(Status::Fail, "Work directory", e.to_string()),
+    };
+    let Some(avail) = paths::free_space(&dir) else { return check(Status::Warn, "Work directory", format!("{}: free space unknown", dir.display())) };
+    let detail = format!("{}: {} free", dir.display(), human_size(avail));
+    let status = if avail < NO_SPACE { Status::Fail } else if avail < LOW_SPACE { Status::Warn } else { Status::Pass };
+    check(status, "Work directory", detail)
+}
+
+fn terminal() -> Vec<Check> {
+    let env = |key: &str| std::env::var(key).unwrap_or_default();
+    let term = env("TERM");
+    let colorterm = env("COLORTERM");
+    let locale = [env("LC_ALL"), env("LC_CTYPE"), env("LANG")].into_iter().find(|v| !v.is_empty()).unwrap_or_default();
+    let mut checks = vec![
+        if term.is_empty() || term == "dumb" {
+            check(Status::Fail, "Terminal", format!("TERM is '{}'; the TUI needs a full terminal", term))
+        } else {
+            check(Status::Pass, "Terminal", format!("TERM={}", term))
+        },
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            check(Status::Pass, "Colours", "24-bit colour")
+        } else {
+            check(Status::Warn, "Colours", "no COLORTERM=truecolor; colours are approximated")
+        },
+        if locale.to_uppercase().replace('-', "").contains("UTF8") {
+            check(Status::Pass, "Unicode", locale)
+        } else {
+            check(Status::Warn, "Unicode", format!("locale '{}' is not UTF-8; symbols may show as '?'", locale))
+        },
+    ];
+    if let Ok((cols, rows)) = crossterm::terminal::size() {
+        let status = if cols >= 80 && rows >= 24 { Status::Pass } else { Status::Warn };
+        checks.push(check(status, "Size", format!("{}x{} (80x24 or larger recommended)", cols, rows)));
+    }
+    checks
+}
@@ -0,0 +1,162 @@
+// ===================================================================
+// Action Error Module
+// ===================================================================
+// The error type every action returns. Variants the UI can help with
+// carry what it needs to render tailored guidance; everything else is
+// folded into `Other` with its full context chain.
+
+use crate::disk::human_size;
+use std::fmt;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::time::Duration;
+
+/// How many trailing lines of a failed command's output are kept.
+pub const TAIL_LINES: usize = 20;
+
+/// Whether sudo, doas or run0 gave up because it would have had to ask for a password.
+pub fn needs_password(stderr: &str) -> bool {
+    stderr.lines().any(|line| line.contains("a password is required") || line.contains("Interactive authentication required"))
+}
+
+#[derive(Debug)]
+pub enum ActionError {
+    /// The suite manages pacman-based systems; `/etc/arch-release` is missing.
+    NotOnArch,
+    /// The probe host could not be resolved or reached.
+    NoNetwork { reason: String },
+    /// The device (or one of its partitions) is mounted or otherwise in use.
+    DeviceBusy { dev: String },
+    /// A command ran but exited unsuccessfully. `code` is `None` when it was killed by a signal;
+    /// `stderr` and `stdout` hold the last `TAIL_LINES` lines of each.
+    CommandFailed { cmd: String, code: Option<i32>, stderr: String, stdout: String },
+    /// A command ran longer than its budget allows and was killed. `setting` is the [timeouts] key.
+    Timeout { cmd: String, after: Duration, setting: &'static str },
+    /// A required tool is not installed.
+    MissingDependency { tool: String, package: String },
+    PermissionDenied { what: String },
+    /// Less than `needed` bytes are free where the action writes; `needed` is 0 when a write failed
+    /// because the disk is full.
+    NoSpace { dir: PathBuf, needed: u64, available: u64 },
+    /// The directory is on a filesystem mounted read-only.
+    ReadOnly { dir: PathBuf },
+    /// The user supplied something unusable: a missing file, a bad hostname, an invalid plan.
+    InvalidInput(String),
+    Other(String),
+}
+
+impl ActionError {
+    /// A command that stopped to ask for a root password it was told not to ask for is reported
+    /// as such, with how to authenticate beforehand.
+    pub fn command_failed(cmd: impl Into<String>, status: ExitStatus, stdout: &str, stderr: &str) -> Self {
+        let cmd = cmd.into();
+        if needs_password(stderr) {
+            return ActionError::PermissionDenied {
+                what: format!("'{}' needs a password, which cannot be typed while the suite is on screen. Authenticate first (e.g. `sudo -v`) and start it again, or start it as root.", cmd),
+            };
+        }
+        ActionError::CommandFailed { cmd, code: status.code(), stderr: tail(stderr), stdout: tail(stdout) }
+    }
+
+    /// The popup title for this error.
+    pub fn title(&self) -> &'static str {
+        match self {
+            ActionError::NotOnArch => "Not an Arch System",
+            ActionError::NoNetwork { .. } => "No Network",
+            ActionError::DeviceBusy { .. } => "Device Busy",
+            ActionError::CommandFailed { .. } => "Command Failed",
+            ActionError::Timeout { .. } => "Timed Out",
+            ActionError::MissingDependency { .. } => "Missing Dependency",
+            ActionError::PermissionDenied { .. } => "Permission Denied",
+            ActionError::NoSpace { .. } => "Not Enough Space",
+            ActionError::ReadOnly { .. } => "Read-Only Filesystem",
+            _ => "Error",
+        }
+    }
+
+    /// What the user can do about it, when there is something specific to suggest.
+    pub fn guidance(&self) -> Option<String> {
+        match self {
+            ActionError::NotOnArch => Some("Run the suite from an Arch Linux system or the Arch live ISO.".to_string()),
+            ActionError::NoNetwork { .. } => Some("Check the cable or Wi-Fi (iwctl on the live ISO). On restricted networks, point [network] probe_host in the config file at a reachable mirror.".to_string()),
+            ActionError::DeviceBusy { dev } => Some(format!("Unmount everything on {} first (umount -R, swapoff), then try again.", dev)),
+            ActionError::Timeout { setting, .. } => Some(format!(
+                "If it was still making progress (a slow mirror or a large disk), raise `{}` under [timeouts] in the config file, or set it to 0 for no limit.",
+                setting
+            )),
+            ActionError::MissingDependency { package, .. } => Some(format!("Install it with: sudo pacman -S {}", package)),
+            ActionError::PermissionDenied { .. } => Some("Make sure your user can run sudo, or start the suite as root.".to_string()),
+            ActionError::CommandFailed { stderr, stdout, .. } if stderr.lines().chain(stdout.lines()).any(crate::packages::is_keyring_error) => {
+                Some("This looks like an outdated pacman keyring. Utilities → Repair Pacman Keyring refreshes archlinux-keyring and repopulates it; then try again.".to_string())
+            }
+            ActionError::NoSpace { .. } | ActionError::ReadOnly { .. } => Some("Free up space there, or point `work_dir` (or `backup_dir`) under [snapshot] in the config file at another disk.".to_string()),
+            _ => None,
+        }
+    }
+
+    /// The package to install before trying again, when a tool is missing.
+    pub fn missing_package(&self) -> Option<&str> {
+        match self { ActionError::MissingDependency { package, .. } => Some(package), _ => None }
+    }
+}
+
+impl fmt::Display for ActionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActionError::NotOnArch => write!(f, "This does not look like an Arch Linux system (/etc/arch-release is missing)."),
+            ActionError::NoNetwork { reason } => write!(f, "No network connection: {}", reason),
+            ActionError::DeviceBusy { dev } => write!(f, "{} is in use.", dev),
+            ActionError::CommandFailed { cmd, code, stderr, stdout } => {
+                match code {
+                    Some(code) => write!(f, "'{}' exited with code {}.", cmd, code)?,
+                    None => write!(f, "'{}' was terminated by a signal.", cmd)?,
+                }
+                if !stderr.is_empty() {
+                    write!(f, "\n\nstderr (last lines):\n{}", stderr)?;
+                }
+                if !stdout.is_empty() {
+                    write!(f, "\n\nstdout (last lines):\n{}", stdout)?;
+                }
+                Ok(())
+            }
+            ActionError::Timeout { cmd, after, .. } => write!(f, "'{}' did not finish within {}s and was stopped.", cmd, after.as_secs()),
+            ActionError::MissingDependency { tool, .. } => write!(f, "'{}' is required but not installed.", tool),
+            ActionError::PermissionDenied { what } => write!(f, "Permission denied: {}", what),
+            ActionError::NoSpace { dir, needed: 0, .. } => write!(f, "The disk holding {} is full.", dir.display()),
+            ActionError::NoSpace { dir, needed, available } => write!(f, "This needs about {} in {}, but only {} is free there.", human_size(*needed), dir.display(), human_size(*available)),
+            ActionError::ReadOnly { dir } => write!(f, "{} is on a read-only filesystem.", dir.display()),
+            ActionError::InvalidInput(message) | ActionError::Other(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for ActionError {}
+
+/// The last `TAIL_LINES` non-empty lines of `text`.
+fn tail(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).collect();
+    lines[lines.len().saturating_sub(TAIL_LINES)..].join("\n")
+}
+
+impl From<std::io::Error> for ActionError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::PermissionDenied => ActionError::PermissionDenied { what: e.to_string() },
+            _ => ActionError::Other(e.to_string()),
+        }
+    }
+}
+
+impl From<anyhow::Error> for ActionError {
+    fn from(e: anyhow::Error) -> Self {
+        // Keep typed errors that were wrapped on the way up.
+        match e.downcast::<ActionError>() {
+            Ok(e) => e,
+            Err(e) => ActionError::Other(format!("{:#}", e)),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ActionError {
+    fn from(e: serde_json::Error) -> Self { ActionError::Other(e.to_string()) }
+}
@@ -1,147 +1,1719 @@
 // ===================================================================
 // Event Handling Module
 // ===================================================================
-use crate::app::{Action, App, AppView, MenuItem, Popup, StatefulList};
+use crate::app::{Action, ActionResult, App, AppView, KeyPrefix, MenuItem, Popup, Progress, Prompt, RunningTask, StatefulList};
+use crate::components::key_prefix::Feed;
+use crate::highlight;
+use crate::history::{History, Recall};
+use crate::services::ServiceOp;
+use crate::workflow::{Checklist, ItemTable};
 use anyhow::Result;
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
 use ratatui::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 use tui_input::backend::crossterm::EventHandler;
+use tui_input::Input;
 
-pub async fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App<'_>) -> Result<()> {
-    while !app.should_quit {
-        terminal.draw(|f| crate::ui::ui(f, app))?;
-        if event::poll(std::time::Duration::from_millis(50))? {
-            if let Event::Key(key) = event::read()? {
-                if key.kind == KeyEventKind::Press {
-                    handle_key_event(app, key).await?;
+/// Poll interval while nothing on screen changes without user input; a key press still wakes the loop immediately.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+/// The most already-queued events handled before the next redraw. Drawing after every event
+/// lets a held key's repeats pile up faster than the screen can follow, so it keeps scrolling
+/// after release; handling the backlog first draws only where the selection ends up.
+const BURST: usize = 64;
+/// A running action's output keeps only its newest lines, so redrawing stays cheap however long
+/// it runs.
+const OUTPUT_LINES: usize = 5000;
+/// How long waiting for a key goes without looking for a termination signal.
+const SIGNAL_CHECK: Duration = Duration::from_millis(100);
+
+/// Set once SIGTERM, SIGINT or SIGHUP arrives.
+static TERMINATED: AtomicBool = AtomicBool::new(false);
+
+/// Catches SIGTERM, SIGINT and SIGHUP, so being killed by a multiplexer or a closed terminal ends
+/// the event loop like 'q' does and the terminal is restored, instead of left in raw mode.
+pub fn handle_signals() -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+    for kind in [SignalKind::terminate(), SignalKind::interrupt(), SignalKind::hangup()] {
+        let mut stream = signal(kind)?;
+        tokio::spawn(async move {
+            if stream.recv().await.is_some() {
+                TERMINATED.store(true, Ordering::SeqCst);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Where the event loop gets its input from; swapped for a scripted source in tests.
+pub trait EventSource {
+    /// Waits up to `timeout` for the next event.
+    fn next_event(&mut self, timeout: Duration) -> Result<Option<Event>>;
+    /// Whether the source will never produce another event.
+    fn finished(&self) -> bool { false }
+    /// Whether the process was told to terminate.
+    fn terminated(&self) -> bool { false }
+}
+
+/// Reads events from the real terminal.
+pub struct CrosstermEvents;
+
+impl EventSource for CrosstermEvents {
+    fn next_event(&mut self, timeout: Duration) -> Result<Option<Event>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if event::poll(left.min(SIGNAL_CHECK))? {
+                return Ok(Some(event::read()?));
+            }
+            if left <= SIGNAL_CHECK || self.terminated() {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn terminated(&self) -> bool {
+        TERMINATED.load(Ordering::SeqCst)
+    }
+}
+
+pub async fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App<'_>, events: &mut dyn EventSource) -> Result<()> {
+    if let Some(action) = app.pending_action.take() {
+        execute_action(app, action).await?;
+    }
+    // When and on which screen the last frame was drawn.
+    let mut drawn: Option<(Instant, (AppView, Popup))> = None;
+    while !app.should_quit && !events.finished() {
+        if events.terminated() {
+            // Its commands are killed as the task is dropped.
+            if let Some(task) = app.task.take() {
+                task.handle.abort();
+            }
+            app.should_quit = true;
+            break;
+        }
+        // A running action's spinner moves on every frame.
+        if update_task(app).await || app.needs_tick() || app.expire_notification() {
+            app.dirty = true;
+        }
+        // Whatever the old size left outside the new layout is wiped, not drawn around.
+        if std::mem::take(&mut app.resized) {
+            terminal.autoresize()?;
+            terminal.clear()?;
+            drawn = None;
+        }
+        // Redraws of one screen wait for the frame clock; a new view or popup is drawn at once.
+        let screen = (app.current_view, app.active_popup);
+        if app.dirty && drawn.is_none_or(|(at, on)| on != screen || at.elapsed() >= app.frame) {
+            terminal.draw(|f| crate::ui::ui(f, app))?;
+            app.dirty = false;
+            drawn = Some((Instant::now(), screen));
+        }
+        let mut timeout = if app.needs_tick() { app.tick } else { IDLE_TIMEOUT };
+        if let Some((at, _)) = drawn.filter(|_| app.dirty) {
+            timeout = timeout.min(app.frame.saturating_sub(at.elapsed()));
+        }
+        if let Some(left) = app.notification_left() {
+            timeout = timeout.min(left);
+        }
+        let Some(first) = events.next_event(timeout)? else { continue };
+        let screen = (app.current_view, app.active_popup);
+        handle_event(app, first).await?;
+        for _ in 1..BURST {
+            // Keys after a change of view or popup are meant for what it shows, so draw it first.
+            if app.should_quit || (app.current_view, app.active_popup) != screen {
+                break;
+            }
+            let Some(event) = events.next_event(Duration::ZERO)? else { break };
+            handle_event(app, event).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn handle_event(app: &mut App<'_>, event: Event) -> Result<()> {
+    match event {
+        Event::Key(key) if key.kind == KeyEventKind::Press => {
+            handle_key_event(app, key).await?;
+            app.dirty = true;
+            // The heads-up on start is dismissed by moving on.
+            if app.current_view != AppView::MainMenu {
+                app.drift = None;
+            }
+        }
+        Event::Resize(..) => {
+            app.dirty = true;
+            app.resized = true;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Feeds progress from the running action into the popup and shows its result once it finishes.
+/// Returns whether anything visible changed.
+async fn update_task(app: &mut App<'_>) -> bool {
+    let Some(task) = app.task.as_mut() else { return false };
+    let mut changed = false;
+    while let Ok(progress) = task.progress.try_recv() {
+        changed = true;
+        match progress {
+            Progress::Message(line) => {
+                if !app.popup_text.is_empty() {
+                    app.popup_text.push('\n');
                 }
+                app.popup_text.push_str(&highlight::strip_ansi(&line));
+            }
+            Progress::Replace(line) => {
+                let start = app.popup_text.rfind('\n').map_or(0, |i| i + 1);
+                app.popup_text.replace_range(start.., &line);
+            }
+            Progress::Percent(percent) => app.popup_progress = Some(percent.min(100)),
+            Progress::Label(label) => app.popup_progress_label = Some(label),
+            Progress::Checklist(labels) => app.popup_checklist = Some(Checklist::new(labels)),
+            Progress::Items(keys) => app.popup_items = Some(ItemTable::new(keys)),
+            Progress::Item(update) => app.popup_items.get_or_insert_with(|| ItemTable::new(Vec::new())).update(update),
+            Progress::Check(i) => {
+                if let Some(checklist) = app.popup_checklist.as_mut() {
+                    checklist.start(i);
+                }
+            }
+            Progress::Follow(title) => {
+                task.follow = true;
+                app.popup_follow = true;
+                app.popup_title = title;
             }
         }
     }
+    if changed {
+        let excess = app.popup_text.lines().count().saturating_sub(OUTPUT_LINES);
+        if let Some((cut, _)) = app.popup_text.match_indices('\n').nth(excess.saturating_sub(1)).filter(|_| excess > 0) {
+            app.popup_text.drain(..=cut);
+        }
+        if app.popup_follow {
+            // Clamped to the last page when drawn.
+            app.popup_scroll = u16::MAX;
+        }
+    }
+    if !task.handle.is_finished() {
+        return changed;
+    }
+    let Some(task) = app.task.take() else { return changed };
+    app.popup_progress = None;
+    app.popup_progress_label = None;
+    app.popup_retry = None;
+    app.popup_scroll = 0;
+    app.active_popup = Popup::Action;
+    let checklist = app.popup_checklist.take();
+    let items = app.popup_items.take();
+    match task.handle.await {
+        Ok(Ok(result)) => {
+            let next = show_result(app, result);
+            if app.active_popup == Popup::Action {
+                append_checklist(app, checklist, true);
+                append_items(app, items, true);
+            }
+            // A prompt is only a step on the way to a result.
+            if matches!(app.active_popup, Popup::Action | Popup::Report) {
+                app.record_result(true);
+            }
+            // A one-line success goes by at the bottom of the screen; the results panel keeps it.
+            app.notify_if_quick();
+            if let Some(next) = next {
+                if let Err(e) = execute_action(app, next).await {
+                    app.popup_title = "Error".to_string();
+                    app.popup_text = format!("An error occurred: {}", e);
+                }
+            }
+        }
+        Ok(Err(e)) => {
+            app.workflow.failed();
+            app.popup_title = e.title().to_string();
+            app.popup_text = format!("An error occurred: {}", e);
+            if let Some(guidance) = e.guidance() {
+                app.popup_text.push_str(&format!("\n\n💡 {}", guidance));
+            }
+            // Retrying cannot help until the tool is back, so 'r' installs it first.
+            app.popup_retry = Some(match e.missing_package() {
+                Some(package) => {
+                    app.popup_text.push_str(&format!("\nPress 'r' to install {} and try again.", package));
+                    crate::actions::install_dependencies(vec![package.to_string()], Some(task.retry), false)
+                }
+                None => task.retry,
+            });
+            append_checklist(app, checklist, false);
+            append_items(app, items, false);
+            app.record_result(false);
+        }
+        Err(e) => {
+            app.workflow.failed();
+            app.popup_title = "Error".to_string();
+            app.popup_text = format!("The action stopped unexpectedly: {}", e);
+            append_checklist(app, checklist, false);
+            append_items(app, items, false);
+            app.record_result(false);
+        }
+    }
+    true
+}
+
+/// Stops a followed action, such as a journal, keeping what it printed.
+fn stop_following(app: &mut App<'_>) {
+    if let Some(task) = app.task.take() {
+        task.handle.abort();
+    }
+    app.popup_title = app.popup_title.split(" | ").next().unwrap_or_default().to_string() + " (stopped)";
+    app.record_result(true);
+}
+
+/// Stops the running action: its commands are sent SIGTERM and given `STOP_GRACE` to exit, then
+/// the task is dropped, killing what is left. What it printed stays in the popup, 'r' starts it
+/// over, and whatever an interrupted snapshot or backup left behind is offered for removal.
+async fn cancel_task(app: &mut App<'_>) -> Result<()> {
+    let Some(mut task) = app.task.take() else { return Ok(()) };
+    let started = task.started;
+    let signalled = crate::actions::terminate_children().await;
+    // A command that exits makes the task fail and finish; one that ignores SIGTERM keeps it waiting.
+    let finished = signalled > 0 && tokio::time::timeout(crate::actions::STOP_GRACE, &mut task.handle).await.is_ok();
+    if !finished {
+        // Once the aborted task is gone, so are its commands and they can no longer write.
+        task.handle.abort();
+        let _ = task.handle.await;
+    }
+    // Without commands running there was nothing for the abort to outlive.
+    let stopped = finished || signalled == 0;
+    app.workflow.failed();
+    app.popup_progress = None;
+    app.popup_progress_label = None;
+    app.popup_title = "Cancelled".to_string();
+    let (checklist, items) = (app.popup_checklist.take(), app.popup_items.take());
+    append_checklist(app, checklist, false);
+    append_items(app, items, false);
+    let outcome = if stopped {
+        "the commands it started were stopped.".to_string()
+    } else {
+        format!(
+            "its commands were still running {}s after being asked to stop and were killed. One run as root may have outlived its sudo; check with `ps -ef` before starting it again.",
+            crate::actions::STOP_GRACE.as_secs()
+        )
+    };
+    app.popup_text.push_str(&format!("\n\n⛔ Cancelled after {}s; {}", started.elapsed().as_secs(), outcome));
+    app.popup_retry = Some(task.retry);
+    app.record_result(false);
+    let leftovers = crate::actions::interrupted_leftovers().await;
+    if !leftovers.is_empty() {
+        execute_action(app, crate::actions::clean_up_leftovers(leftovers)).await?;
+    }
     Ok(())
 }
 
+/// Ends a finished action's checklist and keeps it below the result, with how long each step took.
+fn append_checklist(app: &mut App<'_>, checklist: Option<Checklist>, ok: bool) {
+    if let Some(mut checklist) = checklist {
+        checklist.finish(ok);
+        app.popup_text.push_str(&format!("\n\nSteps:\n{}", checklist));
+    }
+}
+
+/// Keeps a finished action's per-item results below it.
+fn append_items(app: &mut App<'_>, items: Option<ItemTable>, ok: bool) {
+    if let Some(mut items) = items {
+        items.finish(ok);
+        app.popup_text.push_str(&format!("\n\nResults: {}", items));
+    }
+}
+
+/// Routes a finished action's result to the popup that renders it best.
+/// Returns the action to continue with, if the result chains into one.
+fn show_result(app: &mut App<'_>, result: ActionResult) -> Option<Action> {
+    app.popup_title = "Success".to_string();
+    match result {
+        ActionResult::Message(message) => app.popup_text = message,
+        ActionResult::File { path, details } => {
+            app.popup_text = format!("{}\n\n{}", path.display(), details);
+            app.popup_path = Some(path);
+        }
+        ActionResult::Report(report) => {
+            app.popup_title = "System Report".to_string();
+            app.popup_text = report.to_string();
+            app.system_report = Some(*report);
+            app.active_popup = Popup::Report;
+        }
+        // Expert mode answers the yes/no questions that default to yes. Warnings and destructive
+        // confirmations default to no and still come up, as does the Phrase of a disk-erasing action.
+        ActionResult::Prompt(Prompt::Confirm { default: true, then, .. }) if app.expert_mode => {
+            app.active_popup = Popup::None;
+            return Some(*then);
+        }
+        ActionResult::Prompt(prompt) => {
+            app.workflow.waiting();
+            open_prompt(app, prompt);
+        }
+        ActionResult::Services { units, notice } => app.show_services(units, notice),
+        ActionResult::Snapshots { snapshots, notice } => app.show_snapshots(snapshots, notice),
+        ActionResult::Step { step, details, next } => {
+            app.workflow.finished(step.kind.id(), next.is_some());
+            app.popup_text = details;
+            app.install.record(step);
+            if let Err(e) = app.install.save() {
+                app.popup_notice = Some(format!("History not saved: {}", e));
+            }
+            if next.is_none() && app.workflow.run_all {
+                return next_workflow_step(app);
+            }
+            return next.map(|next| *next);
+        }
+    }
+    None
+}
+
+/// Activates the first step that is not done and returns its action; ends the sequence when none is left.
+fn next_workflow_step(app: &mut App<'_>) -> Option<Action> {
+    let Some(i) = app.workflow.current() else {
+        app.workflow.run_all = false;
+        return None;
+    };
+    app.workflow.start(i);
+    app.manual_install_menu.items.get(i).map(|item| item.action_for(app))
+}
+
+/// Scrolls the help manual. '/' starts a search that jumps to the first match from the top row
+/// shown as it is typed, Enter ends it, and 'n'/'N' go to the next and previous match. Esc clears
+/// the search before it leaves the manual.
+fn handle_manual_keys(app: &mut App<'_>, key_event: KeyEvent) {
+    let jump = |app: &mut App<'_>, from: usize, forward: bool| {
+        app.manual_match = crate::manual::find(&app.manual_rows, app.manual_search.value(), from, forward);
+        if let Some(row) = app.manual_match {
+            app.manual_scroll = row as u16;
+        }
+    };
+    if app.manual_searching {
+        match key_event.code {
+            KeyCode::Enter => {
+                app.manual_searching = false;
+                jump(app, app.manual_scroll as usize, true);
+            }
+            KeyCode::Esc => {
+                app.manual_searching = false;
+                app.manual_search.reset();
+                app.manual_match = None;
+            }
+            _ => {
+                if app.manual_search.handle_event(&Event::Key(key_event)).is_some_and(|change| change.value) {
+                    jump(app, app.manual_scroll as usize, true);
+                }
+            }
+        }
+        return;
+    }
+    let current = app.manual_match.unwrap_or(app.manual_scroll as usize);
+    match key_event.code {
+        KeyCode::Char('/') => {
+            app.manual_searching = true;
+            app.manual_search.reset();
+            app.manual_match = None;
+        }
+        KeyCode::Char('n') => jump(app, current + 1, true),
+        KeyCode::Char('N') => jump(app, current + app.manual_rows.len().saturating_sub(1), false),
+        KeyCode::Char('j') | KeyCode::Down => app.manual_scroll = app.manual_scroll.saturating_add(1),
+        KeyCode::Char('k') | KeyCode::Up => app.manual_scroll = app.manual_scroll.saturating_sub(1),
+        KeyCode::PageDown | KeyCode::Char(' ') => app.manual_scroll = app.manual_scroll.saturating_add(app.manual_page),
+        KeyCode::PageUp => app.manual_scroll = app.manual_scroll.saturating_sub(app.manual_page),
+        KeyCode::Home | KeyCode::Char('g') => app.manual_scroll = 0,
+        KeyCode::End | KeyCode::Char('G') => app.manual_scroll = u16::MAX,
+        KeyCode::Esc if !app.manual_search.value().is_empty() => {
+            app.manual_search.reset();
+            app.manual_match = None;
+        }
+        KeyCode::Char('q') | KeyCode::Esc => {
+            app.back();
+            app.manual_scroll = 0;
+            app.manual_search.reset();
+            app.manual_match = None;
+        }
+        _ => {}
+    }
+}
+
+/// Narrows a Select list to the items containing the filter, keeping the highlighted one when it
+/// still matches.
+fn filter_list(app: &mut App<'_>) {
+    let selected = app.popup_list.selected_item().cloned();
+    let query = app.popup_search.value();
+    app.popup_list.items = app.popup_unfiltered.iter().filter(|item| query.is_empty() || !crate::manual::match_ranges(item, query).is_empty()).cloned().collect();
+    let i = selected.and_then(|selected| app.popup_list.items.iter().position(|item| *item == selected));
+    app.popup_list.state.select(i.or((!app.popup_list.items.is_empty()).then_some(0)));
+}
+
+/// Highlights the first item of the Select or MultiSelect list matching the search, `offset` rows
+/// on from the highlighted one in the direction given; nothing moves without a match.
+fn search_list(app: &mut App<'_>, offset: usize, forward: bool) {
+    let from = app.popup_list.state.selected().unwrap_or(0) + offset;
+    if let Some(i) = crate::manual::find(&app.popup_list.items, app.popup_search.value(), from, forward) {
+        app.popup_list.state.select(Some(i));
+    }
+}
+
+/// Opens the popup that collects a prompt's answer; its continuation runs on submit.
+fn open_prompt(app: &mut App<'_>, prompt: Prompt) {
+    app.popup_search.reset();
+    app.popup_searching = false;
+    match prompt {
+        Prompt::MultiSelect { title, items, checked, then } => {
+            app.popup_title = title;
+            app.popup_checked = checked.iter().enumerate().filter(|(_, c)| **c).map(|(i, _)| i).collect();
+            app.popup_list = StatefulList::with_items(items);
+            app.popup_diffs.clear();
+            app.popup_on_choices = Some(then);
+            app.active_popup = Popup::MultiSelect;
+        }
+        Prompt::Merge { title, files, diffs, then } => {
+            app.popup_title = title;
+            app.popup_checked = (0..files.len()).collect();
+            app.popup_list = StatefulList::with_items(files);
+            app.popup_diffs = diffs;
+            app.popup_on_choices = Some(then);
+            app.active_popup = Popup::MultiSelect;
+        }
+        Prompt::Input { title, default, then } => open_prompt(app, Prompt::Checked { title, default, check: |_| Ok(()), then }),
+        Prompt::Checked { title, default, check, then } => {
+            app.popup_recall = Recall::new(History::load().entries(&title));
+            app.popup_title = title;
+            app.popup_input = Input::new(default);
+            app.popup_masked = false;
+            app.popup_check = Some(check);
+            app.popup_invalid = None;
+            app.popup_on_input = Some(then);
+            app.active_popup = Popup::Input;
+        }
+        Prompt::Secret { title, then } => {
+            app.popup_title = title;
+            app.popup_input = Input::default();
+            app.popup_recall = Recall::default();
+            app.popup_masked = true;
+            app.popup_check = None;
+            app.popup_invalid = None;
+            app.popup_on_input = Some(then);
+            app.active_popup = Popup::Input;
+        }
+        Prompt::Size { title, field, default, then } => {
+            app.popup_title = title;
+            app.popup_input = Input::new(default);
+            app.popup_recall = Recall::default();
+            app.popup_masked = false;
+            app.popup_size = Some(field);
+            app.popup_check = None;
+            app.popup_invalid = None;
+            app.popup_on_input = Some(then);
+            app.active_popup = Popup::Input;
+        }
+        Prompt::Select { title, items, then } => {
+            app.popup_title = title;
+            app.popup_unfiltered = items.clone();
+            app.popup_list = StatefulList::with_items(items);
+            app.popup_disks.clear();
+            app.popup_on_input = Some(then);
+            app.active_popup = Popup::Select;
+        }
+        Prompt::SelectDisk { title, disks, then } => {
+            app.popup_title = title;
+            app.popup_unfiltered = disks.iter().map(|disk| disk.label()).collect();
+            app.popup_list = StatefulList::with_items(app.popup_unfiltered.clone());
+            app.popup_disks = disks;
+            // Labels start with the device, which is what the continuation wants.
+            app.popup_on_input = Some(std::sync::Arc::new(move |label: String| then(label.split_whitespace().next().unwrap_or_default().to_string())));
+            app.active_popup = Popup::Select;
+        }
+        Prompt::Confirm { title, text, default, then } => {
+            app.popup_confirm_origin = Some(Action::Prompt(Prompt::Confirm { title: title.clone(), text: text.clone(), default, then: then.clone() }));
+            app.popup_title = title;
+            app.popup_text = text;
+            app.popup_confirm_default = default;
+            app.popup_confirm_focus = default;
+            app.popup_action = Some(*then);
+            app.active_popup = Popup::Confirm;
+        }
+        Prompt::Phrase { title, text, phrase, then } => {
+            app.popup_confirm_origin = Some(Action::Prompt(Prompt::Phrase { title: title.clone(), text: text.clone(), phrase: phrase.clone(), then: then.clone() }));
+            app.popup_title = title;
+            app.popup_text = text;
+            app.popup_phrase = phrase;
+            app.popup_input = Input::default();
+            app.popup_notice = None;
+            app.popup_action = Some(*then);
+            app.active_popup = Popup::Phrase;
+        }
+    }
+}
+
 async fn handle_key_event(app: &mut App<'_>, key_event: KeyEvent) -> Result<()> {
+    // The cheatsheet swallows the key that closes it, so it never triggers anything underneath.
+    if key_event.code == KeyCode::F(1) || app.show_keys {
+        app.show_keys = !app.show_keys && key_event.code == KeyCode::F(1);
+        return Ok(());
+    }
     if app.active_popup != Popup::None {
         handle_popup_keys(app, key_event).await?;
         return Ok(());
     }
+    // A search being typed takes every key, the global ones included.
+    if app.current_view == AppView::HelpManual && app.manual_searching {
+        handle_manual_keys(app, key_event);
+        return Ok(());
+    }
     if key_event.code == KeyCode::Char('?') {
         app.active_popup = Popup::Help;
         return Ok(());
     }
+    if key_event.code == KeyCode::Char('H') {
+        app.show_results();
+        return Ok(());
+    }
+    if key_event.code == KeyCode::Char('i') {
+        app.help_footer = !app.help_footer;
+        return Ok(());
+    }
+    if key_event.code == KeyCode::Char('X') {
+        app.expert_mode = !app.expert_mode;
+        return Ok(());
+    }
+    // Each tab keeps its own selection, so switching away and back lands where it was.
+    let tab = match key_event.code {
+        KeyCode::Tab => Some(app.current_view.next_tab(1)),
+        KeyCode::BackTab => Some(app.current_view.next_tab(-1)),
+        // Plain digits are motion counts, so the tabs are on Alt.
+        KeyCode::Char(digit @ '1'..='9') if key_event.modifiers.contains(KeyModifiers::ALT) => AppView::TABS.get(digit as usize - '1' as usize).copied(),
+        _ => None,
+    };
+    if let Some(tab) = tab {
+        app.key_prefix = KeyPrefix::default();
+        // Tabs are a level of their own: Esc from one goes to the main menu.
+        app.view_history.clear();
+        app.current_view = tab;
+        return Ok(());
+    }
     
-    let action_to_perform = match app.current_view {
-        AppView::MainMenu => handle_menu_keys(&mut app.main_menu, key_event.code),
-        AppView::Replicator => handle_menu_keys(&mut app.replicator_menu, key_event.code),
-        AppView::Cloner => handle_menu_keys(&mut app.cloner_menu, key_event.code),
-        AppView::Utilities => handle_menu_keys(&mut app.utilities_menu, key_event.code),
-        AppView::ManualInstaller => handle_menu_keys(&mut app.manual_install_menu, key_event.code),
+    let chosen = match app.current_view {
+        AppView::MainMenu => handle_menu_keys(&mut app.main_menu, &mut app.key_prefix, key_event.code, app.list_page),
+        AppView::Replicator => handle_menu_keys(&mut app.replicator_menu, &mut app.key_prefix, key_event.code, app.list_page),
+        AppView::Cloner => handle_menu_keys(&mut app.cloner_menu, &mut app.key_prefix, key_event.code, app.list_page),
+        AppView::Utilities => handle_menu_keys(&mut app.utilities_menu, &mut app.key_prefix, key_event.code, app.list_page),
+        AppView::ManualInstaller => {
+            // `r` jumps to the failed step and runs it again, leaving the steps before it done.
+            if let (KeyCode::Char('r'), Some(i)) = (key_event.code, app.workflow.failed_step()) {
+                app.manual_install_menu.state.select(Some(i));
+                app.workflow.start(i);
+                app.activity = app.manual_install_menu.items[i].text.to_string();
+                return execute_action(app, app.manual_install_menu.items[i].action_for(app)).await;
+            }
+            let chosen = handle_menu_keys(&mut app.manual_install_menu, &mut app.key_prefix, key_event.code, app.list_page);
+            if let (true, Some(i)) = (chosen, app.manual_install_menu.state.selected()) {
+                app.workflow.start(i);
+            }
+            chosen
+        }
         AppView::HelpManual => {
-            if key_event.code == KeyCode::Char('q') || key_event.code == KeyCode::Esc {
-                app.current_view = AppView::MainMenu;
+            handle_manual_keys(app, key_event);
+            false
+        }
+        AppView::Logs => {
+            if !list_motion(&mut app.log_list, &mut app.key_prefix, key_event.code, app.list_page) && key_event.code == KeyCode::Enter {
+                if let Some(i) = app.log_list.state.selected() {
+                    app.reopen_result(i);
+                }
             }
-            None
+            false
+        }
+        AppView::Snapshots => {
+            if !list_motion(&mut app.snapshot_list, &mut app.key_prefix, key_event.code, app.list_page) {
+                if let Some(action) = snapshot_action(app, key_event.code) {
+                    return execute_action(app, action).await;
+                }
+            }
+            false
         }
-        _ => None,
     };
+    // An item that cannot run yet explains why instead (the Message also ends a workflow step).
+    let selected = if chosen { app.current_menu().and_then(|menu| menu.selected_item()).map(|item| (item.text.to_string(), item.action_for(app))) } else { None };
+    let action_to_perform = selected.map(|(name, action)| {
+        app.activity = name;
+        action
+    });
 
     if let Some(action) = action_to_perform {
         execute_action(app, action).await?;
     } else if key_event.code == KeyCode::Esc && app.current_view != AppView::MainMenu {
-        app.current_view = AppView::MainMenu;
+        app.back();
     }
 
     Ok(())
 }
 
+/// What Enter (deploy) or 'd' (delete, once confirmed) does to the highlighted snapshot; either
+/// changes data, so safe mode blocks both.
+fn snapshot_action(app: &mut App<'_>, key_code: KeyCode) -> Option<Action> {
+    if !matches!(key_code, KeyCode::Enter | KeyCode::Char('d') | KeyCode::Delete) {
+        return None;
+    }
+    let snapshot = app.selected_snapshot()?.clone();
+    if app.safe_mode {
+        return Some(Action::Message { title: "Disabled in Safe Mode".to_string(), text: crate::app::SAFE_MODE.to_string() });
+    }
+    if key_code == KeyCode::Enter {
+        app.activity = "Deploy from Snapshot".to_string();
+        return Some(crate::actions::deploy_file(snapshot.path.display().to_string()));
+    }
+    app.activity = "Delete Snapshot".to_string();
+    let path = snapshot.path.clone();
+    Some(Action::Prompt(Prompt::Confirm {
+        title: "Delete Snapshot".to_string(),
+        text: format!("Delete {}?\n\n{}\n\nIt cannot be recovered.", snapshot.path.display(), snapshot.label(0)),
+        default: false,
+        then: Box::new(Action::execute(move |progress| crate::actions::delete_snapshot(path.clone(), progress))),
+    }))
+}
+
 async fn handle_popup_keys(app: &mut App<'_>, key_event: KeyEvent) -> Result<()> {
     match app.active_popup {
-        Popup::Help | Popup::Action => {
+        Popup::Help => {
             app.active_popup = Popup::None;
         }
-        Popup::Confirm => match key_event.code {
-            KeyCode::Char('y') | KeyCode::Char('Y') => {
-                if let Some(action) = app.popup_action.take() {
+        Popup::Action | Popup::Report => match key_event.code {
+            KeyCode::Char('j') | KeyCode::Down => app.popup_scroll = app.popup_scroll.saturating_add(1),
+            KeyCode::Char('k') | KeyCode::Up => {
+                app.popup_follow = false;
+                app.popup_scroll = app.popup_scroll.saturating_sub(1);
+            }
+            KeyCode::PageDown => app.popup_scroll = app.popup_scroll.saturating_add(app.popup_page),
+            KeyCode::PageUp => {
+                app.popup_follow = false;
+                app.popup_scroll = app.popup_scroll.saturating_sub(app.popup_page);
+            }
+            KeyCode::End => {
+                app.popup_follow = true;
+                app.popup_scroll = u16::MAX;
+            }
+            KeyCode::Char('w') => {
+                app.popup_wrap = !app.popup_wrap;
+                app.popup_hscroll = 0;
+            }
+            KeyCode::Char('l') | KeyCode::Right if !app.popup_wrap => app.popup_hscroll = app.popup_hscroll.saturating_add(4),
+            KeyCode::Char('h') | KeyCode::Left if !app.popup_wrap => app.popup_hscroll = app.popup_hscroll.saturating_sub(4),
+            KeyCode::Char('s') if app.task.is_none() => {
+                let name = format!("arch-suite-output-{}.txt", chrono::Local::now().format("%Y%m%d-%H%M%S"));
+                let default_path = crate::paths::base_dir(crate::paths::BaseDir::State).map_or(format!("~/{}", name), |dir| dir.join("logs").join(name).display().to_string());
+                app.popup_action = Some(Action::SaveText { title: app.popup_title.clone(), text: app.popup_text.clone(), popup: app.active_popup });
+                app.popup_title = "Save output to file".to_string();
+                app.popup_input = Input::new(default_path);
+                app.popup_recall = Recall::default();
+                app.popup_masked = false;
+                app.active_popup = Popup::Input;
+            }
+            KeyCode::Char('p') if app.popup_path.is_some() => {
+                let path = app.popup_path.as_ref().map(|p| p.display().to_string()).unwrap_or_default();
+                app.popup_notice = Some(match crate::clipboard::copy(&path).await {
+                    Ok(tool) => format!("Copied path via {}", tool),
+                    Err(e) => e.to_string(),
+                });
+            }
+            KeyCode::Char('r') if app.popup_retry.is_some() => {
+                if let Some(action) = app.popup_retry.take() {
+                    app.active_popup = Popup::None;
+                    if let Some(i) = app.workflow.failed_step().filter(|_| app.current_view == AppView::ManualInstaller) {
+                        app.workflow.start(i);
+                    }
                     execute_action(app, action).await?;
                 }
+            }
+            // Stopping a followed log keeps what it showed.
+            KeyCode::Esc | KeyCode::Char('q') if app.task.as_ref().is_some_and(|task| task.follow) => stop_following(app),
+            KeyCode::Char('c') if key_event.modifiers.contains(KeyModifiers::CONTROL) && app.task.as_ref().is_some_and(|task| task.follow) => stop_following(app),
+            KeyCode::Esc if app.task.is_some() => cancel_task(app).await?,
+            KeyCode::Char('c') if key_event.modifiers.contains(KeyModifiers::CONTROL) && app.task.is_some() => cancel_task(app).await?,
+            KeyCode::Char('c') => {
+                app.popup_notice = Some(match crate::clipboard::copy(&app.popup_text).await {
+                    Ok(tool) => format!("Copied to clipboard via {}", tool),
+                    Err(e) => e.to_string(),
+                });
+            }
+            // A running action keeps its popup open; only scrolling is allowed until it finishes.
+            _ if app.task.is_some() => {}
+            // Output opened from the services list returns to it.
+            _ if !app.services.is_empty() => {
+                app.popup_retry = None;
+                app.reopen_services();
+            }
+            _ => {
+                app.popup_retry = None;
                 app.active_popup = Popup::None;
             }
-            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+        },
+        Popup::Confirm => {
+            let confirmed = match key_event.code {
+                KeyCode::Char('y') | KeyCode::Char('Y') => Some(true),
+                KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => Some(false),
+                KeyCode::Enter => Some(app.popup_confirm_focus),
+                KeyCode::Char('h') | KeyCode::Char('l') | KeyCode::Left | KeyCode::Right | KeyCode::Tab | KeyCode::BackTab => {
+                    app.popup_confirm_focus = !app.popup_confirm_focus;
+                    None
+                }
+                _ => None,
+            };
+            if let Some(confirmed) = confirmed {
                 app.active_popup = Popup::None;
+                let action = app.popup_action.take();
+                match action.filter(|_| confirmed) {
+                    Some(action) => execute_action(app, action).await?,
+                    None => app.workflow.stop(),
+                }
+                app.popup_confirm_origin = None;
+            }
+        }
+        Popup::Phrase => match key_event.code {
+            KeyCode::Enter if app.popup_input.value().trim() == app.popup_phrase => {
+                app.active_popup = Popup::None;
+                app.popup_input.reset();
+                if let Some(action) = app.popup_action.take() {
+                    execute_action(app, action).await?;
+                }
+                app.popup_confirm_origin = None;
+            }
+            KeyCode::Enter => app.popup_notice = Some(format!("Type {} exactly to continue, or Esc to cancel.", app.popup_phrase)),
+            KeyCode::Esc => {
+                app.active_popup = Popup::None;
+                app.popup_action = None;
+                app.popup_confirm_origin = None;
+                app.popup_input.reset();
+                app.workflow.stop();
+            }
+            _ => {
+                app.popup_input.handle_event(&Event::Key(key_event));
             }
-            _ => {}
         },
         Popup::Input => match key_event.code {
+            KeyCode::Enter if app.popup_size.as_ref().is_some_and(|field| field.parse(app.popup_input.value()).is_err()) => {}
+            KeyCode::Enter if app.popup_check.is_some_and(|check| check(app.popup_input.value()).is_err()) => {
+                app.popup_invalid = app.popup_check.and_then(|check| check(app.popup_input.value()).err());
+            }
+            KeyCode::Char(c @ ('+' | '-')) if app.popup_size.is_some() => {
+                let text = app.popup_size.as_ref().map(|field| field.step(app.popup_input.value(), c == '+')).unwrap_or_default();
+                app.popup_input = Input::new(text);
+            }
             KeyCode::Enter => {
-                if let Some(action) = app.popup_action.take() {
+                app.active_popup = Popup::None;
+                app.popup_size = None;
+                app.popup_check = None;
+                if let Some(then) = app.popup_on_input.take() {
+                    let value = app.popup_input.value().to_string();
+                    if app.popup_masked {
+                        app.popup_input.reset();
+                    } else {
+                        let mut history = History::load();
+                        history.record(&app.popup_title, &value);
+                        // Only a convenience; a read-only state directory must not fail the prompt.
+                        let _ = history.save();
+                    }
+                    execute_action(app, then(value)).await?;
+                } else if let Some(action) = app.popup_action.take() {
                     execute_action(app, action).await?;
                 }
-                app.active_popup = Popup::None;
             }
             KeyCode::Esc => {
+                app.popup_on_input = None;
+                app.popup_size = None;
+                app.popup_check = None;
+                app.popup_invalid = None;
+                app.popup_input.reset();
                 app.active_popup = Popup::None;
+                app.workflow.stop();
+            }
+            KeyCode::Up | KeyCode::Down => {
+                if let Some(text) = app.popup_recall.step(app.popup_input.value(), key_event.code == KeyCode::Up) {
+                    app.popup_input = Input::new(text);
+                    app.popup_invalid = None;
+                }
+            }
+            KeyCode::Tab if !app.popup_masked => {
+                let home = crate::paths::real_user().map(|user| user.home).unwrap_or_default();
+                if let Some(text) = app.popup_completion.complete(app.popup_input.value(), &home) {
+                    app.popup_input = Input::new(text);
+                    app.popup_invalid = None;
+                }
             }
             _ => {
-                app.popup_input.handle_event(&Event::Key(key_event));
+                // The reason shown no longer applies once the text changes.
+                if app.popup_input.handle_event(&Event::Key(key_event)).is_some_and(|change| change.value) {
+                    app.popup_invalid = None;
+                }
+            }
+        },
+        // While filtering, every key edits the filter; Enter keeps it, Esc clears it.
+        Popup::Services if app.service_filtering => {
+            let selected = app.selected_service().map(|unit| unit.name.clone());
+            match key_event.code {
+                KeyCode::Enter => app.service_filtering = false,
+                KeyCode::Esc => {
+                    app.service_filtering = false;
+                    app.service_filter.reset();
+                }
+                _ => {
+                    app.service_filter.handle_event(&Event::Key(key_event));
+                }
+            }
+            app.filter_services(selected.as_deref());
+        }
+        // While filtering, typed keys narrow the list; Enter keeps the filter, Esc drops it.
+        Popup::Select if app.popup_searching => {
+            match key_event.code {
+                KeyCode::Enter => app.popup_searching = false,
+                KeyCode::Esc => {
+                    app.popup_searching = false;
+                    app.popup_search.reset();
+                }
+                _ => {
+                    app.popup_search.handle_event(&Event::Key(key_event));
+                }
+            }
+            filter_list(app);
+        }
+        Popup::Select if key_event.code == KeyCode::Char('/') => {
+            app.popup_searching = true;
+        }
+        // A kept filter is cleared by the first Esc, so the second one closes.
+        Popup::Select if key_event.code == KeyCode::Esc && !app.popup_search.value().is_empty() => {
+            app.popup_search.reset();
+            filter_list(app);
+        }
+        // While searching, typed keys edit the search and the highlight jumps to the first match
+        // from it; Enter keeps the search for 'n'/'N', Esc drops it.
+        Popup::MultiSelect if app.popup_searching => {
+            match key_event.code {
+                KeyCode::Enter => app.popup_searching = false,
+                KeyCode::Esc => {
+                    app.popup_searching = false;
+                    app.popup_search.reset();
+                }
+                _ => {
+                    app.popup_search.handle_event(&Event::Key(key_event));
+                    search_list(app, 0, true);
+                }
+            }
+        }
+        Popup::MultiSelect if matches!(key_event.code, KeyCode::Char('/' | 'n' | 'N')) => match key_event.code {
+            KeyCode::Char('/') => {
+                app.popup_searching = true;
+                app.popup_search.reset();
             }
+            KeyCode::Char('n') => search_list(app, 1, true),
+            _ => search_list(app, app.popup_list.items.len().saturating_sub(1), false),
         },
+        Popup::Select | Popup::MultiSelect | Popup::Results | Popup::Services if list_motion(&mut app.popup_list, &mut app.key_prefix, key_event.code, app.popup_page) => {}
         Popup::Select => match key_event.code {
-            KeyCode::Char('k') | KeyCode::Up => app.popup_list.previous(),
-            KeyCode::Char('j') | KeyCode::Down => app.popup_list.next(),
             KeyCode::Enter => {
-                if let Some(action) = app.popup_action.take() {
-                    execute_action(app, action).await?;
+                app.active_popup = Popup::None;
+                let choice = app.popup_list.selected_item().cloned();
+                if let (Some(then), Some(choice)) = (app.popup_on_input.take(), choice) {
+                    execute_action(app, then(choice)).await?;
                 }
+            }
+            KeyCode::Esc => {
+                app.popup_on_input = None;
                 app.active_popup = Popup::None;
+                app.workflow.stop();
+            }
+            _ => {}
+        },
+        Popup::MultiSelect => match key_event.code {
+            KeyCode::Char(' ') => {
+                if let Some(i) = app.popup_list.state.selected() {
+                    if !app.popup_checked.remove(&i) {
+                        app.popup_checked.insert(i);
+                    }
+                }
+            }
+            // Ticks every item, or unticks them all when they already are.
+            KeyCode::Char('a') => {
+                if app.popup_checked.len() == app.popup_list.items.len() {
+                    app.popup_checked.clear();
+                } else {
+                    app.popup_checked = (0..app.popup_list.items.len()).collect();
+                }
+            }
+            KeyCode::Char('d') if !app.popup_diffs.is_empty() => {
+                app.popup_scroll = 0;
+                app.active_popup = Popup::Diff;
+            }
+            KeyCode::Enter => {
+                app.active_popup = Popup::None;
+                let chosen = app.popup_list.items.iter().enumerate().filter(|(i, _)| app.popup_checked.contains(i)).map(|(_, item)| item.clone()).collect();
+                if let Some(then) = app.popup_on_choices.take() {
+                    execute_action(app, then(chosen)).await?;
+                }
             }
             KeyCode::Esc => {
+                app.popup_on_choices = None;
                 app.active_popup = Popup::None;
+                app.workflow.stop();
             }
             _ => {}
         },
+        // Back to the file list with the ticks as they were.
+        Popup::Diff => match key_event.code {
+            KeyCode::Char('j') | KeyCode::Down => app.popup_scroll = app.popup_scroll.saturating_add(1),
+            KeyCode::Char('k') | KeyCode::Up => app.popup_scroll = app.popup_scroll.saturating_sub(1),
+            KeyCode::PageDown | KeyCode::Char(' ') => app.popup_scroll = app.popup_scroll.saturating_add(app.popup_page),
+            KeyCode::PageUp => app.popup_scroll = app.popup_scroll.saturating_sub(app.popup_page),
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('d') => app.active_popup = Popup::MultiSelect,
+            _ => {}
+        },
+        Popup::Services => match key_event.code {
+            KeyCode::Char('/') => {
+                app.service_filtering = true;
+                app.popup_notice = None;
+            }
+            KeyCode::Char(key) if ServiceOp::from_key(key).is_some() => {
+                if let (Some(unit), Some(op)) = (app.selected_service().map(|unit| unit.name.clone()), ServiceOp::from_key(key)) {
+                    app.activity = format!("{} {}", op.command(), unit);
+                    execute_action(app, Action::execute(move |progress| crate::actions::service_op(unit.clone(), op, progress))).await?;
+                }
+            }
+            KeyCode::Char('p') => {
+                app.journal_priority = (app.journal_priority + 1) % crate::services::PRIORITIES.len();
+                app.popup_notice = Some(format!("Journal priority: {} and more severe", app.journal_priority()));
+            }
+            KeyCode::Char(key @ ('l' | 'f')) => {
+                if let Some(unit) = app.selected_service().map(|unit| unit.name.clone()) {
+                    let priority = app.journal_priority();
+                    app.activity = format!("journal {}", unit);
+                    execute_action(app, Action::execute(move |progress| crate::actions::service_journal(unit.clone(), priority, key == 'f', progress))).await?;
+                }
+            }
+            KeyCode::Esc | KeyCode::Char('q') => app.close_services(),
+            _ => {}
+        },
+        Popup::Results => match key_event.code {
+            // Reopens the entry's full output; the list is newest first.
+            KeyCode::Enter => {
+                if let Some(i) = app.popup_list.state.selected() {
+                    app.reopen_result(i);
+                }
+            }
+            KeyCode::Esc | KeyCode::Char('q') => app.active_popup = Popup::None,
+            _ => {}
+        },
         Popup::None => {}
     }
     Ok(())
 }
 
-fn handle_menu_keys<'a>(list: &mut StatefulList<MenuItem<'a>>, key_code: KeyCode) -> Option<Action> {
+/// Feeds a key to the count prefix and moves `list` if it completed a motion, or if it is
+/// Home/End or PgUp/PgDn, which move by `page` rows. Returns false when the key is not a motion.
+fn list_motion<T>(list: &mut StatefulList<T>, prefix: &mut KeyPrefix, key_code: KeyCode, page: u16) -> bool {
+    match prefix.feed(key_code) {
+        Feed::Motion(motion) => list.apply(motion),
+        Feed::Pending => {}
+        Feed::Pass => match key_code {
+            KeyCode::Home => list.first(),
+            KeyCode::End => list.last(),
+            KeyCode::PageDown => list.next_page(page.max(1).into()),
+            KeyCode::PageUp => list.previous_page(page.max(1).into()),
+            _ => return false,
+        },
+    }
+    true
+}
+
+/// Moves through `list`; returns whether Enter, or the letter in an item's icon, chose an item.
+/// Motions and the global keys are handled first, so 'j' or 'G' never trigger an item; a letter
+/// matches either case, and the first item with it wins.
+fn handle_menu_keys(list: &mut StatefulList<MenuItem<'_>>, prefix: &mut KeyPrefix, key_code: KeyCode, page: u16) -> bool {
+    if list_motion(list, prefix, key_code, page) {
+        return false;
+    }
     match key_code {
-        KeyCode::Char('k') | KeyCode::Up => list.previous(),
-        KeyCode::Char('j') | KeyCode::Down => list.next(),
-        KeyCode::Enter => {
-            if let Some(item) = list.selected_item() {
-                return Some(item.action.clone());
+        KeyCode::Enter => list.selected_item().is_some(),
+        KeyCode::Char(c) => match list.items.iter().position(|item| item.hotkey.is_some_and(|key| key.eq_ignore_ascii_case(&c))) {
+            Some(i) => {
+                list.state.select(Some(i));
+                true
             }
-        }
-        _ => {}
+            None => false,
+        },
+        _ => false,
     }
-    None
 }
 
 async fn execute_action(app: &mut App<'_>, action: Action) -> Result<()> {
     match action {
         Action::Quit => app.should_quit = true,
-        Action::SetView(view) => app.current_view = view,
+        Action::SetView(view) => app.open_view(view),
         Action::Execute(func) => {
+            tracing::info!("Started: {}", if app.activity.is_empty() { "an action" } else { &app.activity });
+            app.workflow.running();
+            let (progress_tx, progress_rx) = tokio::sync::mpsc::unbounded_channel();
             app.popup_title = "Working...".to_string();
-            app.popup_text = "Please wait while the task completes.".to_string();
+            app.popup_text = String::new();
+            app.popup_scroll = 0;
+            // The newest output stays in view until the user scrolls up.
+            app.popup_follow = true;
+            app.popup_hscroll = 0;
+            app.popup_notice = None;
+            app.popup_progress = None;
+            app.popup_progress_label = None;
+            app.popup_checklist = None;
+            app.popup_items = None;
+            app.popup_path = None;
             app.active_popup = Popup::Action;
+            app.popup_retry = None;
+            let retry = app.popup_confirm_origin.take().unwrap_or_else(|| Action::Execute(func.clone()));
+            app.task = Some(RunningTask { handle: tokio::spawn(func(progress_tx)), progress: progress_rx, retry, follow: false, started: Instant::now() });
+        }
+        Action::Resolve(resolve) => {
+            // Boxed: a resolved action recurses back into `execute_action`.
+            let action = resolve(app);
+            Box::pin(execute_action(app, action)).await?;
+        }
+        Action::Prompt(Prompt::Confirm { default: true, then, .. }) if app.expert_mode => Box::pin(execute_action(app, *then)).await?,
+        Action::Prompt(prompt) => open_prompt(app, prompt),
+        Action::RunWorkflow => {
+            app.workflow.run_all = true;
+            let action = next_workflow_step(app).unwrap_or_else(|| Action::Message {
+                title: "Nothing Left to Run".to_string(),
+                text: "Every manual installer step is done. Wipe a disk to start over.".to_string(),
+            });
+            Box::pin(execute_action(app, action)).await?;
+        }
+        Action::Message { title, text } => {
+            // A step that cannot start (e.g. an earlier one is missing) ends a sequence.
+            app.workflow.stop();
+            app.popup_title = title;
+            app.popup_text = text;
+            app.popup_scroll = 0;
+            app.popup_hscroll = 0;
+            app.popup_notice = None;
+            app.popup_path = None;
+            app.active_popup = Popup::Action;
+        }
+        Action::SaveText { title, text, popup } => {
+            let notice = match crate::actions::save_text(app.popup_input.value(), &text) {
+                Ok(path) => format!("Saved to {}", path.display()),
+                Err(e) => format!("Save failed: {:#}", e),
+            };
+            app.popup_title = title;
+            app.popup_text = text;
+            app.popup_notice = Some(notice);
+            app.active_popup = popup;
+        }
+    }
+    Ok(())
+}
 
-            let mut terminal = crate::init_terminal()?;
-            terminal.draw(|f| crate::ui::ui(f, app))?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
 
-            match func().await {
-                Ok(message) => {
-                    app.popup_title = "Success".to_string();
-                    app.popup_text = message;
-                }
-                Err(e) => {
-                    app.popup_title = "Error".to_string();
-                    app.popup_text = format!("An error occurred: {}", e);
-                }
+    /// Yields a fixed sequence of key presses, then reports itself finished.
+    struct ScriptedEvents(VecDeque<Event>);
+
+    impl ScriptedEvents {
+        fn keys(codes: &[KeyCode]) -> Self {
+            ScriptedEvents(codes.iter().map(|code| Event::Key(KeyEvent::new(*code, KeyModifiers::NONE))).collect())
+        }
+    }
+
+    impl EventSource for ScriptedEvents {
+        fn next_event(&mut self, _timeout: Duration) -> Result<Option<Event>> { Ok(self.0.pop_front()) }
+        fn finished(&self) -> bool { self.0.is_empty() }
+    }
+
+    async fn run(codes: &[KeyCode]) -> App<'static> {
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        let mut app = App::new();
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(codes)).await.unwrap();
+        app
+    }
+
+    #[tokio::test]
+    async fn down_down_enter_opens_utilities() {
+        let app = run(&[KeyCode::Down, KeyCode::Down, KeyCode::Enter]).await;
+        assert_eq!(app.current_view, AppView::Utilities);
+        assert_eq!(app.active_popup, Popup::None);
+    }
+
+    #[tokio::test]
+    async fn esc_returns_to_main_menu() {
+        let app = run(&[KeyCode::Char('j'), KeyCode::Enter, KeyCode::Esc]).await;
+        assert_eq!(app.current_view, AppView::MainMenu);
+        assert_eq!(app.main_menu.state.selected(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn esc_returns_through_the_views_it_came_from() {
+        let mut app = run(&[KeyCode::Char('j'), KeyCode::Char('j'), KeyCode::Enter, KeyCode::Char('M'), KeyCode::Esc]).await;
+        assert_eq!(app.current_view, AppView::Utilities);
+        handle_key_event(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)).await.unwrap();
+        assert_eq!(app.current_view, AppView::MainMenu);
+        assert!(app.view_history.is_empty());
+    }
+
+    #[tokio::test]
+    async fn counts_and_jumps_move_the_menu_selection() {
+        let app = run(&[KeyCode::Char('2'), KeyCode::Char('j')]).await;
+        assert_eq!(app.main_menu.state.selected(), Some(2));
+        let last = app.main_menu.items.len() - 1;
+        let app = run(&[KeyCode::Char('G')]).await;
+        assert_eq!(app.main_menu.state.selected(), Some(last));
+        let app = run(&[KeyCode::Char('G'), KeyCode::Char('g'), KeyCode::Char('g')]).await;
+        assert_eq!(app.main_menu.state.selected(), Some(0));
+        let app = run(&[KeyCode::End]).await;
+        assert_eq!(app.main_menu.state.selected(), Some(last));
+        let app = run(&[KeyCode::End, KeyCode::Home]).await;
+        assert_eq!(app.main_menu.state.selected(), Some(0));
+        // A page is taller than the menu, so paging stops at an end instead of wrapping.
+        let app = run(&[KeyCode::PageDown]).await;
+        assert_eq!((app.list_page as usize > last, app.main_menu.state.selected()), (true, Some(last)));
+        let app = run(&[KeyCode::Char('j'), KeyCode::PageUp, KeyCode::PageUp]).await;
+        assert_eq!(app.main_menu.state.selected(), Some(0));
+        // A non-motion key drops the pending count.
+        let app = run(&[KeyCode::Char('3'), KeyCode::Char('x'), KeyCode::Char('j')]).await;
+        assert_eq!(app.main_menu.state.selected(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn help_popup_closes_on_any_key() {
+        let app = run(&[KeyCode::Char('?')]).await;
+        assert_eq!(app.active_popup, Popup::Help);
+        let app = run(&[KeyCode::Char('?'), KeyCode::Char('x')]).await;
+        assert_eq!(app.active_popup, Popup::None);
+    }
+
+    #[tokio::test]
+    async fn f1_toggles_the_cheatsheet_without_closing_the_popup() {
+        let app = run(&[KeyCode::Char('?'), KeyCode::F(1)]).await;
+        assert!(app.show_keys);
+        assert_eq!(app.active_popup, Popup::Help);
+        let app = run(&[KeyCode::Char('?'), KeyCode::F(1), KeyCode::Char('x')]).await;
+        assert!(!app.show_keys);
+        assert_eq!(app.active_popup, Popup::Help);
+    }
+
+    #[tokio::test]
+    async fn quit_item_stops_the_loop() {
+        let app = run(&[KeyCode::Up, KeyCode::Enter]).await;
+        assert!(app.should_quit);
+    }
+
+    #[tokio::test]
+    async fn icon_letters_run_their_item_but_never_shadow_navigation() {
+        let app = run(&[KeyCode::Char('h')]).await;
+        assert_eq!(app.current_view, AppView::HelpManual);
+        let app = run(&[KeyCode::Char('Q')]).await;
+        assert!(app.should_quit);
+        // 'G' jumps to the last item (Quit) instead of running anything.
+        let app = run(&[KeyCode::Char('G')]).await;
+        assert!(!app.should_quit);
+        assert_eq!(app.main_menu.selected_item().map(|item| item.text), Some("Quit"));
+    }
+
+    #[test]
+    fn hotkeys_are_unique_per_menu_and_leave_the_motions_alone() {
+        let app = App::new();
+        for menu in [&app.main_menu, &app.replicator_menu, &app.cloner_menu, &app.utilities_menu, &app.manual_install_menu] {
+            let keys: Vec<char> = menu.items.iter().filter_map(|item| item.hotkey.map(|c| c.to_ascii_lowercase())).collect();
+            assert!(keys.iter().all(|c| !"jkg".contains(*c)), "{:?}", keys);
+            assert!(keys.iter().enumerate().all(|(i, c)| !keys[..i].contains(c)), "{:?}", keys);
+        }
+    }
+
+    #[tokio::test]
+    async fn an_unavailable_step_explains_itself_instead_of_running() {
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        let mut app = App::new();
+        app.install = crate::install::InstallState::default();
+        app.current_view = AppView::ManualInstaller;
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[KeyCode::Char('j'), KeyCode::Enter])).await.unwrap();
+        assert_eq!((app.active_popup, app.popup_title.as_str()), (Popup::Action, "Not Available Yet"));
+        assert!(app.popup_text.starts_with("Wipe a disk first"));
+        assert!(app.task.is_none());
+    }
+
+    #[tokio::test]
+    async fn safe_mode_blocks_destructive_items_and_leaves_the_rest() {
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        let mut app = App::new();
+        app.safe_mode = true;
+        app.current_view = AppView::ManualInstaller;
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[KeyCode::Enter])).await.unwrap();
+        assert_eq!((app.active_popup, app.popup_title.as_str()), (Popup::Action, "Disabled in Safe Mode"));
+        assert!(app.task.is_none());
+        assert!(app.utilities_menu.items.iter().filter(|item| item.unavailable_note(&app).is_some_and(|note| note.starts_with('🔒'))).map(|item| item.text).eq(["Flash ISO to USB"]));
+
+        app.active_popup = Popup::None;
+        app.current_view = AppView::Cloner;
+        terminal.draw(|f| crate::ui::ui(f, &mut app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol.as_str()).collect();
+        assert!(screen.contains("Safe mode: destructive actions are blocked"));
+    }
+
+    #[tokio::test]
+    async fn the_services_filter_takes_letters_that_are_keys_in_the_list() {
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        let mut app = App::new();
+        let units = crate::services::parse_units("dbus.service loaded active running D-Bus\nsshd.service loaded failed failed OpenSSH Daemon\n", "");
+        app.show_services(units, None);
+        let keys = [KeyCode::Char('j'), KeyCode::Char('/'), KeyCode::Char('s'), KeyCode::Char('s'), KeyCode::Char('h'), KeyCode::Enter];
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&keys)).await.unwrap();
+        assert!(app.task.is_none(), "typing the filter must not start a service");
+        assert_eq!((app.popup_list.items.len(), app.selected_service().map(|u| u.name.as_str())), (1, Some("sshd.service")));
+
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[KeyCode::Char('/'), KeyCode::Esc])).await.unwrap();
+        assert_eq!((app.popup_list.items.len(), app.selected_service().map(|u| u.name.as_str())), (2, Some("sshd.service")));
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[KeyCode::Esc])).await.unwrap();
+        assert_eq!((app.active_popup, app.services.len()), (Popup::None, 0));
+    }
+
+    #[tokio::test]
+    async fn results_panel_reopens_the_newest_result_first() {
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        let mut app = App::new();
+        for (name, ok) in [("Create Snapshot", true), ("Flash ISO to USB", false)] {
+            app.activity = name.to_string();
+            (app.popup_title, app.popup_text) = (format!("{} title", name), format!("\n{} output", name));
+            app.record_result(ok);
+        }
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[KeyCode::Char('H')])).await.unwrap();
+        assert_eq!(app.active_popup, Popup::Results);
+        assert!(app.popup_list.items[0].starts_with("✗ ") && app.popup_list.items[0].ends_with("Flash ISO to USB: Flash ISO to USB output"));
+
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[KeyCode::Char('j'), KeyCode::Enter])).await.unwrap();
+        assert_eq!((app.active_popup, app.popup_title.as_str()), (Popup::Action, "Create Snapshot title"));
+    }
+
+    #[tokio::test]
+    async fn expert_mode_skips_yes_no_but_erasing_wants_the_device_typed() {
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        let mut app = App::new();
+        let done = || Box::new(Action::Message { title: "Done".to_string(), text: String::new() });
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[KeyCode::Char('X')])).await.unwrap();
+        assert!(app.expert_mode);
+        app.pending_action = Some(Action::Prompt(Prompt::Confirm { title: "Verify the drive?".to_string(), text: String::new(), default: true, then: done() }));
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[])).await.unwrap();
+        assert_eq!((app.active_popup, app.popup_title.as_str()), (Popup::Action, "Done"));
+
+        // A warning defaults to no, so it is asked even in expert mode.
+        app.active_popup = Popup::None;
+        let unsigned = Prompt::Confirm { title: "Snapshot signature not verified".to_string(), text: "Deploy it anyway?".to_string(), default: false, then: done() };
+        app.pending_action = Some(Action::Prompt(unsigned.clone()));
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[])).await.unwrap();
+        assert_eq!((app.active_popup, app.popup_title.as_str()), (Popup::Confirm, "Snapshot signature not verified"));
+        app.active_popup = Popup::None;
+        assert!(show_result(&mut app, ActionResult::Prompt(unsigned)).is_none());
+        assert_eq!(app.active_popup, Popup::Confirm);
+        // Leftovers may be another instance's work in progress, so removing them is always asked.
+        app.active_popup = Popup::None;
+        app.pending_action = Some(crate::actions::clean_up_leftovers(vec![(std::path::PathBuf::from("/tmp/snapshot_tmp"), 4096)]));
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[])).await.unwrap();
+        assert_eq!((app.active_popup, app.popup_title.as_str()), (Popup::Confirm, "Clean up an interrupted operation?"));
+
+        app.active_popup = Popup::None;
+        app.pending_action = Some(Action::Prompt(Prompt::Phrase { title: "This cannot be undone".to_string(), text: String::new(), phrase: "/dev/sdz".to_string(), then: done() }));
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[KeyCode::Char('y'), KeyCode::Enter])).await.unwrap();
+        assert_eq!(app.active_popup, Popup::Phrase);
+        assert!(app.popup_notice.as_deref().is_some_and(|n| n.starts_with("Type /dev/sdz exactly")));
+        let mut keys = vec![KeyCode::Backspace];
+        keys.extend("/dev/sdz".chars().map(KeyCode::Char));
+        keys.push(KeyCode::Enter);
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&keys)).await.unwrap();
+        assert_eq!((app.active_popup, app.popup_title.as_str()), (Popup::Action, "Done"));
+    }
+
+    #[tokio::test]
+    async fn merges_show_each_diff_and_hand_on_the_ticked_files() {
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        let mut app = App::new();
+        let files = vec!["pacman.conf".to_string(), "hosts".to_string()];
+        let diffs = vec!["--- target /etc/pacman.conf\n+++ snapshot /etc/pacman.conf\n-#Color\n+Color".to_string(), "-a\n+b".to_string()];
+        let then: crate::actions::ChoicesFn = std::sync::Arc::new(|take| Action::Message { title: "Merged".to_string(), text: take.join(",") });
+        app.pending_action = Some(Action::Prompt(Prompt::Merge { title: "Differ".to_string(), files, diffs, then }));
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[KeyCode::Char('d')])).await.unwrap();
+        assert_eq!(app.active_popup, Popup::Diff);
+        terminal.draw(|f| crate::ui::ui(f, &mut app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol.as_str()).collect();
+        assert!(screen.contains("/etc/pacman.conf: - target, + snapshot") && screen.contains("+Color"));
+
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[KeyCode::Esc, KeyCode::Char(' '), KeyCode::Enter])).await.unwrap();
+        assert_eq!((app.popup_title.as_str(), app.popup_text.trim()), ("Merged", "hosts"));
+    }
+
+    #[tokio::test]
+    async fn a_searched_list_jumps_between_matches_and_ticks_them() {
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        let mut app = App::new();
+        let items = ["Germany (DE, 52 mirrors)", "Netherlands (NL, 40 mirrors)", "United Kingdom (GB, 30 mirrors)", "United States (US, 160 mirrors)"].map(String::from).to_vec();
+        let then: crate::actions::ChoicesFn = std::sync::Arc::new(|chosen| Action::Message { title: "Countries".to_string(), text: chosen.join(",") });
+        app.pending_action = Some(Action::Prompt(Prompt::MultiSelect { title: "Countries".to_string(), checked: vec![false; items.len()], items, then }));
+        let mut keys = vec![KeyCode::Char('/')];
+        keys.extend("united".chars().map(KeyCode::Char));
+        keys.extend([KeyCode::Enter, KeyCode::Char(' '), KeyCode::Char('n'), KeyCode::Char(' ')]);
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&keys)).await.unwrap();
+        terminal.draw(|f| crate::ui::ui(f, &mut app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol.as_str()).collect();
+        assert!(screen.contains("'united': 2 | 'n'/'N' next/previous"));
+
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[KeyCode::Char('N'), KeyCode::Enter])).await.unwrap();
+        assert_eq!(app.popup_text.trim(), "United Kingdom (GB, 30 mirrors),United States (US, 160 mirrors)");
+    }
+
+    #[tokio::test]
+    async fn a_checked_input_stays_open_with_the_reason_until_its_text_passes() {
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        let mut app = App::new();
+        let then: crate::actions::InputFn = Arc::new(|name| Action::Message { title: "User".to_string(), text: name });
+        app.pending_action = Some(Action::Prompt(Prompt::Checked { title: "User name".to_string(), default: "Ada".to_string(), check: crate::actions::user_name, then }));
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[KeyCode::Enter])).await.unwrap();
+        assert_eq!(app.active_popup, Popup::Input);
+        terminal.draw(|f| crate::ui::ui(f, &mut app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol.as_str()).collect();
+        assert!(screen.contains("'Ada' is not a user name"));
+
+        let mut keys = vec![KeyCode::Backspace; 3];
+        keys.push(KeyCode::Char('a'));
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&keys)).await.unwrap();
+        assert!(app.popup_invalid.is_none());
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[KeyCode::Enter])).await.unwrap();
+        assert_eq!((app.popup_title.as_str(), app.popup_text.as_str()), ("User", "a"));
+
+        assert!(crate::actions::existing_file("/nonexistent/archlinux.iso").is_err_and(|e| e.ends_with("does not exist.")));
+        assert!(crate::actions::existing_file("/").is_err() && crate::actions::existing_file("").is_err());
+        assert!(crate::actions::hostname_or_empty("").is_ok() && crate::actions::hostname_or_empty("my_host").is_err());
+    }
+
+    #[tokio::test]
+    async fn syncing_home_names_the_destination_it_deletes_from_first() {
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        let mut app = App::new();
+        app.expert_mode = true;
+        app.pending_action = Some(crate::actions::sync_home(&app));
+        let mut keys: Vec<KeyCode> = "/backup/home".chars().map(KeyCode::Char).collect();
+        keys.push(KeyCode::Enter);
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&keys)).await.unwrap();
+        assert_eq!((app.active_popup, app.popup_title.as_str(), app.popup_confirm_default), (Popup::Confirm, "Sync home?", false));
+        assert!(app.popup_text.contains("files in /backup/home that are not in your home directory will be deleted"));
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[KeyCode::Enter])).await.unwrap();
+        assert!(app.task.is_none() && app.active_popup == Popup::None);
+    }
+
+    #[tokio::test]
+    async fn a_ticks_every_item_and_again_unticks_them() {
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        let mut app = App::new();
+        let items = ["mesa", "vulkan-radeon", "amd-ucode"].map(String::from).to_vec();
+        let then: crate::actions::ChoicesFn = std::sync::Arc::new(|chosen| Action::Message { title: "Drivers".to_string(), text: chosen.join(",") });
+        app.pending_action = Some(Action::Prompt(Prompt::MultiSelect { title: "Drivers".to_string(), checked: vec![true, false, false], items, then }));
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[KeyCode::Char('a')])).await.unwrap();
+        terminal.draw(|f| crate::ui::ui(f, &mut app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol.as_str()).collect();
+        assert!(screen.contains("3 of 3 ticked") && screen.contains("a all | Enter continue"));
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[KeyCode::Char('a'), KeyCode::Char('a'), KeyCode::Char('j'), KeyCode::Char(' '), KeyCode::Enter])).await.unwrap();
+        assert_eq!(app.popup_text.trim(), "mesa,amd-ucode");
+    }
+
+    #[tokio::test]
+    async fn a_filtered_select_list_chooses_from_the_matches_and_esc_clears_first() {
+        let chosen = Arc::new(Mutex::new(String::new()));
+        let seen = chosen.clone();
+        let items = ["linux", "linux-lts", "linux-zen", "nvidia"].map(String::from).to_vec();
+        let mut app = App::new();
+        app.pending_action = Some(Action::Prompt(Prompt::Select { title: "Kernel".to_string(), items, then: Arc::new(move |choice| {
+            *seen.lock().unwrap() = choice;
+            Action::Quit
+        }) }));
+        let mut keys = vec![KeyCode::Char('/')];
+        keys.extend("-Z".chars().map(KeyCode::Char));
+        keys.push(KeyCode::Enter);
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&keys)).await.unwrap();
+        assert_eq!(app.popup_list.items, ["linux-zen"]);
+        terminal.draw(|f| crate::ui::ui(f, &mut app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol.as_str()).collect();
+        assert!(screen.contains("'-Z': 1 of 4 | Esc clears"));
+
+        handle_popup_keys(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)).await.unwrap();
+        assert_eq!((app.active_popup, app.popup_list.items.len()), (Popup::Select, 4));
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[KeyCode::Char('/'), KeyCode::Char('n'), KeyCode::Char('v'), KeyCode::Enter, KeyCode::Enter])).await.unwrap();
+        assert_eq!(*chosen.lock().unwrap(), "nvidia");
+    }
+
+    #[tokio::test]
+    async fn a_size_that_does_not_fit_is_refused_and_plus_steps_it() {
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        let mut app = App::new();
+        let field = crate::components::size_field::SizeField { label: "Root".to_string(), total: 100 << 30, used: vec![("EFI".to_string(), 512 << 20)], rest: true };
+        let then: crate::actions::InputFn = std::sync::Arc::new(|size| Action::Message { title: format!("Root {}", size), text: String::new() });
+        app.pending_action = Some(Action::Prompt(Prompt::Size { title: "Root size".to_string(), field, default: "120G".to_string(), then }));
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[KeyCode::Enter])).await.unwrap();
+        assert_eq!(app.active_popup, Popup::Input);
+        terminal.draw(|f| crate::ui::ui(f, &mut app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol.as_str()).collect();
+        assert!(screen.contains("120.0G is more than the 99.5G left on the disk."));
+
+        let mut keys = vec![KeyCode::Backspace; 4];
+        keys.extend("20G+".chars().map(KeyCode::Char));
+        keys.push(KeyCode::Enter);
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&keys)).await.unwrap();
+        assert_eq!((app.popup_title.as_str(), app.popup_size.is_none()), ("Root 21G", true));
+    }
+
+    #[tokio::test]
+    async fn tabs_switch_views_and_each_keeps_its_selection() {
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        let mut app = App::new();
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[KeyCode::Tab, KeyCode::Char('j'), KeyCode::Tab, KeyCode::Tab, KeyCode::BackTab, KeyCode::BackTab])).await.unwrap();
+        assert_eq!((app.current_view, app.replicator_menu.state.selected()), (AppView::Replicator, Some(1)));
+
+        (app.activity, app.popup_title, app.popup_text) = ("Doctor (Self-Check)".to_string(), "Result".to_string(), "all good".to_string());
+        app.record_result(true);
+        let alt = |c| Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::ALT));
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents(VecDeque::from([alt('5')]))).await.unwrap();
+        assert_eq!(app.current_view, AppView::Logs);
+        terminal.draw(|f| crate::ui::ui(f, &mut app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol.as_str()).collect();
+        assert!(screen.contains("5 Logs") && screen.contains("Doctor (Self-Check): all good"));
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[KeyCode::Enter])).await.unwrap();
+        assert_eq!((app.active_popup, app.popup_title.as_str()), (Popup::Action, "Result"));
+    }
+
+    /// Key presses that are all already queued when the loop first waits, counting the waits.
+    struct HeldKey { queue: VecDeque<Event>, waits: usize }
+
+    impl EventSource for HeldKey {
+        fn next_event(&mut self, timeout: Duration) -> Result<Option<Event>> {
+            if !timeout.is_zero() {
+                self.waits += 1;
             }
+            Ok(self.queue.pop_front())
         }
+        fn finished(&self) -> bool { self.queue.is_empty() }
+    }
+
+    #[tokio::test]
+    async fn queued_key_repeats_are_handled_before_one_redraw() {
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        let mut app = App::new();
+        let mut held = HeldKey { queue: ScriptedEvents::keys(&[KeyCode::Char('j'); 3]).0, waits: 0 };
+        run_app(&mut terminal, &mut app, &mut held).await.unwrap();
+        assert_eq!(app.main_menu.state.selected(), Some(3));
+        assert_eq!(held.waits, 1);
+    }
+
+    #[tokio::test]
+    async fn the_manual_searches_and_jumps_between_matches() {
+        let mut keys = vec![KeyCode::Char('h'), KeyCode::Char('/')];
+        keys.extend("SNAPSHOT".chars().map(KeyCode::Char));
+        keys.push(KeyCode::Enter);
+        let mut app = run(&keys).await;
+        let first = app.manual_match.expect("a match");
+        assert!(app.manual_rows[first].to_lowercase().contains("snapshot"));
+        handle_manual_keys(&mut app, KeyEvent::new(KeyCode::Char('n'), KeyModifiers::NONE));
+        assert!(app.manual_match.is_some_and(|row| row > first));
+        handle_manual_keys(&mut app, KeyEvent::new(KeyCode::Char('N'), KeyModifiers::NONE));
+        assert_eq!(app.manual_match, Some(first));
+
+        // Typing jumps ahead of Enter, and Esc clears the search before it leaves.
+        handle_manual_keys(&mut app, KeyEvent::new(KeyCode::Char('g'), KeyModifiers::NONE));
+        handle_manual_keys(&mut app, KeyEvent::new(KeyCode::Char('/'), KeyModifiers::NONE));
+        for c in "snapshot".chars() {
+            handle_manual_keys(&mut app, KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+        assert!(app.manual_searching && app.manual_match == Some(first));
+        handle_manual_keys(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        handle_manual_keys(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        assert_eq!((app.current_view, app.manual_search.value(), app.manual_match), (AppView::HelpManual, "", None));
+        handle_manual_keys(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        assert_ne!(app.current_view, AppView::HelpManual);
+    }
+
+    #[tokio::test]
+    async fn the_manual_shows_a_scrollbar_that_follows_the_scroll() {
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        let mut app = run(&[KeyCode::Char('h')]).await;
+        let thumb_rows = |terminal: &Terminal<TestBackend>| {
+            let buffer = terminal.backend().buffer();
+            (0..40).filter(|&y| buffer.get(119, y).symbol == "█").collect::<Vec<u16>>()
+        };
+        terminal.draw(|f| crate::ui::ui(f, &mut app)).unwrap();
+        let top = thumb_rows(&terminal);
+        handle_manual_keys(&mut app, KeyEvent::new(KeyCode::End, KeyModifiers::NONE));
+        terminal.draw(|f| crate::ui::ui(f, &mut app)).unwrap();
+        let bottom = thumb_rows(&terminal);
+        assert!(!top.is_empty() && !bottom.is_empty() && bottom[0] > top[0]);
+    }
+
+    /// Never produces a key; the signal has already arrived.
+    struct Terminated;
+
+    impl EventSource for Terminated {
+        fn next_event(&mut self, _timeout: Duration) -> Result<Option<Event>> { Ok(None) }
+        fn terminated(&self) -> bool { true }
+    }
+
+    #[tokio::test]
+    async fn a_termination_signal_quits_and_stops_the_running_task() {
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        let mut app = App::new();
+        app.pending_action = Some(Action::execute(|_| {
+            Box::pin(async {
+                tokio::time::sleep(Duration::from_secs(3600)).await;
+                Ok(ActionResult::Message(String::new()))
+            })
+        }));
+        run_app(&mut terminal, &mut app, &mut Terminated).await.unwrap();
+        assert!(app.should_quit);
+        assert!(app.task.is_none());
+    }
+
+    #[tokio::test]
+    async fn a_running_actions_output_streams_in_and_stays_scrolled_to_the_end() {
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        let mut app = App::new();
+        execute_action(&mut app, Action::execute(|progress| {
+            Box::pin(async move {
+                for i in 0..100 {
+                    let _ = progress.send(Progress::Message(format!("etc/file{}", i)));
+                }
+                tokio::time::sleep(Duration::from_secs(3600)).await;
+                Ok(ActionResult::Message(String::new()))
+            })
+        }))
+        .await
+        .unwrap();
+        tokio::task::yield_now().await;
+        assert!(update_task(&mut app).await);
+        terminal.draw(|f| crate::ui::ui(f, &mut app)).unwrap();
+        assert!(app.popup_scroll > 0 && app.popup_follow);
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol.as_str()).collect();
+        assert!(screen.contains("etc/file99") && !screen.contains("etc/file0 "));
+
+        handle_popup_keys(&mut app, KeyEvent::new(KeyCode::Up, KeyModifiers::NONE)).await.unwrap();
+        let paused = app.popup_scroll;
+        app.task.as_ref().unwrap().handle.abort();
+        assert!(!app.popup_follow && paused > 0);
+    }
+
+    #[tokio::test]
+    async fn esc_cancels_a_running_action_and_r_starts_it_over() {
+        let mut app = App::new();
+        execute_action(&mut app, Action::execute(|progress| {
+            Box::pin(async move {
+                let _ = progress.send(Progress::Checklist(vec!["Archiving /etc".to_string()]));
+                let _ = progress.send(Progress::Message("etc/fstab".to_string()));
+                crate::actions::run_checked(tokio::process::Command::new("sleep").arg("3600")).await?;
+                Ok(ActionResult::Message(String::new()))
+            })
+        }))
+        .await
+        .unwrap();
+        tokio::task::yield_now().await;
+        update_task(&mut app).await;
+        // Plain 'c' still copies; only Ctrl-C and Esc cancel.
+        handle_popup_keys(&mut app, KeyEvent::new(KeyCode::Char('c'), KeyModifiers::NONE)).await.unwrap();
+        assert!(app.task.is_some());
+        handle_popup_keys(&mut app, KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)).await.unwrap();
+        assert!(app.task.is_none() && app.popup_retry.is_some());
+        let cancelled = app.results.last().unwrap();
+        assert!(!cancelled.ok && cancelled.title == "Cancelled");
+        assert!(cancelled.text.starts_with("etc/fstab\n\nSteps:") && cancelled.text.contains("⛔ Cancelled after 0s"));
+        // The sleep exited on SIGTERM, well before it would have been killed.
+        assert!(cancelled.text.ends_with("the commands it started were stopped."));
+    }
+
+    #[tokio::test]
+    async fn the_snapshot_browser_deletes_the_highlighted_snapshot_once_confirmed() {
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        let dir = std::env::temp_dir().join(format!("arch-suite-browse-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let files: Vec<crate::snapshot::SnapshotFile> = ["snapshot-20240502.tar.gz", "dotfiles-20240501.tar.gz"]
+            .iter()
+            .map(|name| {
+                std::fs::write(dir.join(name), "archive").unwrap();
+                crate::snapshot::SnapshotFile { path: dir.join(name), modified: None, bytes: 7 << 20, packages: Some(843) }
+            })
+            .collect();
+        let mut app = App::new();
+        app.show_snapshots(files, None);
+        terminal.draw(|f| crate::ui::ui(f, &mut app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol.as_str()).collect();
+        assert!(screen.contains("Snapshots (2) | Enter deploys") && screen.contains("dotfiles-20240501.tar.gz  unknown date        7.0M  843 packages"));
+
+        app.safe_mode = true;
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[KeyCode::Char('j'), KeyCode::Char('d')])).await.unwrap();
+        assert_eq!(app.popup_title, "Disabled in Safe Mode");
+        app.safe_mode = false;
+        run_app(&mut terminal, &mut app, &mut ScriptedEvents::keys(&[KeyCode::Enter, KeyCode::Char('d'), KeyCode::Char('y')])).await.unwrap();
+        while app.task.is_some() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            update_task(&mut app).await;
+        }
+        assert!(!dir.join("dotfiles-20240501.tar.gz").exists() && dir.join("snapshot-20240502.tar.gz").exists());
+        assert_eq!((app.current_view, app.active_popup), (AppView::Snapshots, Popup::None));
+        assert!(app.snapshot_notice.as_deref().is_some_and(|notice| notice.starts_with("Deleted ") && notice.ends_with("dotfiles-20240501.tar.gz.")));
+        app.back();
+        assert_eq!(app.current_view, AppView::MainMenu);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn a_one_line_success_goes_by_as_a_notification() {
+        let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+        let mut app = App::new();
+        let finish = |text: &'static str| Action::execute(move |_| Box::pin(async move { Ok(ActionResult::Message(text.to_string())) }));
+        execute_action(&mut app, finish("✅ Mirrorlist updated.")).await.unwrap();
+        while app.task.is_some() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            update_task(&mut app).await;
+        }
+        assert_eq!(app.active_popup, Popup::None);
+        assert_eq!(app.results.len(), 1);
+        terminal.draw(|f| crate::ui::ui(f, &mut app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol.as_str()).collect();
+        assert!(screen.contains("Mirrorlist updated."));
+
+        app.notification = app.notification.take().map(|(text, at)| (text, at - crate::app::NOTIFICATION_TIME));
+        assert!(app.expire_notification() && app.notification.is_none());
+
+        execute_action(&mut app, finish("✅ Done.\nThe second line keeps it in the popup.")).await.unwrap();
+        while app.task.is_some() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            update_task(&mut app).await;
+        }
+        assert_eq!(app.active_popup, Popup::Action);
+        assert!(app.notification.is_none());
+    }
+
+    /// Delivers each event on its own wait, as typed rather than held; `None` is a timeout.
+    struct Typed(VecDeque<Option<Event>>);
+
+    impl EventSource for Typed {
+        fn next_event(&mut self, _timeout: Duration) -> Result<Option<Event>> { Ok(self.0.pop_front().flatten()) }
+        fn finished(&self) -> bool { self.0.is_empty() }
+    }
+
+    #[tokio::test]
+    async fn redraws_wait_for_the_frame_clock_unless_the_screen_changes() {
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        let mut app = App::new();
+        app.frame = Duration::from_secs(3600);
+        let key = |code| Some(Event::Key(KeyEvent::new(code, KeyModifiers::NONE)));
+        run_app(&mut terminal, &mut app, &mut Typed(VecDeque::from([key(KeyCode::Char('j')), None, None]))).await.unwrap();
+        assert!(app.dirty);
+        run_app(&mut terminal, &mut app, &mut Typed(VecDeque::from([key(KeyCode::Char('h')), None, None]))).await.unwrap();
+        assert_eq!(app.current_view, AppView::HelpManual);
+        assert!(!app.dirty && !app.manual_rows.is_empty());
+        // A resize does not wait either, even on the same screen.
+        run_app(&mut terminal, &mut app, &mut Typed(VecDeque::from([key(KeyCode::Char('j')), None, Some(Event::Resize(100, 30)), None, None]))).await.unwrap();
+        assert!(!app.dirty && !app.resized);
     }
-    Ok(())
 }
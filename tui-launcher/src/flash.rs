@@ -0,0 +1,266 @@
+// ===================================================================
+// ISO Flasher Module
+// ===================================================================
+// Identifies USB drives by stable attributes so the device chosen in
+// the picker is provably the one written to, even if drives were
+// unplugged or re-enumerated while the confirmation was open.
+
+use crate::disk::{self, BlockDevice};
+use crate::error::ActionError;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const BY_ID_DIR: &str = "/dev/disk/by-id";
+/// ISO 9660 sectors; the primary volume descriptor is sector 16, the El Torito boot record 17.
+const SECTOR: usize = 2048;
+const PVD_OFFSET: usize = 16 * SECTOR;
+/// Enough of an image to classify it.
+pub const IMAGE_HEAD: usize = 18 * SECTOR;
+
+/// A drive as it was when the user picked it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlashTarget {
+    /// Kernel name at selection time, e.g. `/dev/sdb`.
+    pub dev: String,
+    /// The whole-disk `/dev/disk/by-id` link, when udev created one.
+    pub by_id: Option<PathBuf>,
+    pub model: String,
+    pub serial: Option<String>,
+    pub size: u64,
+}
+
+impl FlashTarget {
+    pub fn from_device(device: &BlockDevice) -> Self {
+        FlashTarget {
+            dev: device.name.clone(),
+            by_id: by_id_path(&device.name),
+            model: device.model.as_deref().unwrap_or("").trim().to_string(),
+            serial: device.serial.as_deref().map(str::trim).filter(|s| !s.is_empty()).map(String::from),
+            size: device.size,
+        }
+    }
+
+    /// The picker line: name, size, model and serial.
+    pub fn label(&self) -> String {
+        format!("{}  {}  {}  (serial {})", self.dev, disk::human_size(self.size), self.model, self.serial.as_deref().unwrap_or("unknown"))
+    }
+
+    /// Looks the drive up again and fails unless the same physical device is still at `dev`.
+    pub async fn recheck(&self) -> Result<(), ActionError> {
+        let devices = disk::list().await?;
+        let current = devices.iter().find(|d| d.name == self.dev).map(FlashTarget::from_device);
+        let changed = |what: &str| ActionError::InvalidInput(format!("{} {}; nothing was written. Pick the drive again.", self.dev, what));
+        let Some(current) = current else { return Err(changed("has disappeared")) };
+        if let Some(by_id) = &self.by_id {
+            let resolved = std::fs::canonicalize(by_id).ok();
+            if resolved.as_deref() != Some(Path::new(&self.dev)) {
+                return Err(changed("is no longer the drive that was selected"));
+            }
+        }
+        if current.serial != self.serial || current.model != self.model || current.size != self.size {
+            return Err(changed("now belongs to a different drive"));
+        }
+        Ok(())
+    }
+}
+
+/// A progress reading from the writer: bytes copied after `secs` seconds.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Copied {
+    pub bytes: u64,
+    pub secs: f64,
+}
+
+impl Copied {
+    /// Parses `pv -n -t -b` output (`<secs> <bytes>`) or a `dd status=progress` line
+    /// (`<bytes> bytes (...) copied, <secs> s, <rate>`).
+    pub fn parse(line: &str) -> Option<Copied> {
+        let line = line.trim();
+        if let Some((bytes, rest)) = line.split_once(" bytes") {
+            let secs = rest.split(", ").find_map(|part| part.trim().strip_suffix(" s"))?;
+            return Some(Copied { bytes: bytes.trim().parse().ok()?, secs: secs.parse().ok()? });
+        }
+        let (secs, bytes) = line.split_once(' ')?;
+        Some(Copied { bytes: bytes.trim().parse().ok()?, secs: secs.parse().ok()? })
+    }
+
+    pub fn percent(&self, total: u64) -> u8 {
+        (self.bytes.saturating_mul(100) / total.max(1)).min(100) as u8
+    }
+
+    /// E.g. `1.2G of 2.4G at 31.5M/s`.
+    pub fn label(&self, total: u64) -> String {
+        let rate = if self.secs > 0.0 { (self.bytes as f64 / self.secs) as u64 } else { 0 };
+        format!("{} of {} at {}/s", disk::human_size(self.bytes), disk::human_size(total), disk::human_size(rate))
+    }
+}
+
+/// Whole disks that look like USB sticks: USB transport or removable media, never partitions,
+/// and never the disk the system runs from.
+pub fn candidates(devices: &[BlockDevice]) -> Vec<FlashTarget> {
+    devices
+        .iter()
+        .filter(|d| d.kind == "disk" && (d.rm || d.tran.as_deref() == Some("usb")) && d.size > 0 && !d.runs_the_system())
+        .map(FlashTarget::from_device)
+        .collect()
+}
+
+/// The by-id link pointing at `dev` itself (not one of its partitions), preferring `usb-` links.
+fn by_id_path(dev: &str) -> Option<PathBuf> {
+    let mut links: Vec<PathBuf> = std::fs::read_dir(BY_ID_DIR)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| !path.to_string_lossy().contains("-part"))
+        .filter(|path| std::fs::canonicalize(path).ok().as_deref() == Some(Path::new(dev)))
+        .collect();
+    links.sort_by_key(|path| !path.file_name().is_some_and(|n| n.to_string_lossy().starts_with("usb-")));
+    links.into_iter().next()
+}
+
+/// SHA256 of the first `len` bytes of `reader`, calling `on_percent` whenever the whole
+/// percentage read changes. Fails if the reader ends early.
+pub async fn sha256_prefix<R: AsyncRead + Unpin>(reader: R, len: u64, mut on_percent: impl FnMut(u8)) -> std::io::Result<String> {
+    let mut reader = reader.take(len);
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1 << 20];
+    let (mut done, mut last_percent) = (0u64, None);
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        done += n as u64;
+        let percent = (done * 100 / len.max(1)) as u8;
+        if last_percent != Some(percent) {
+            on_percent(percent);
+            last_percent = Some(percent);
+        }
+    }
+    if done < len {
+        return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, format!("only {} of {} bytes could be read", done, len)));
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Where an Arch ISO release, its checksums (`sha256sums.txt`) and signature (`<iso>.sig`) are published.
+pub const ARCH_RELEASE_URL: &str = "https://archlinux.org/iso/{version}";
+
+/// The release of an official image name, e.g. `2024.05.01` for `archlinux-2024.05.01-x86_64.iso`.
+pub fn arch_iso_version(file_name: &str) -> Option<&str> {
+    let version = file_name.strip_prefix("archlinux-")?.strip_suffix("-x86_64.iso")?;
+    let parts: Vec<&str> = version.split('.').collect();
+    (parts.len() == 3 && parts.iter().all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))).then_some(version)
+}
+
+pub fn is_sha256(text: &str) -> bool {
+    text.len() == 64 && text.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// The hash listed for `file_name` in a `sha256sum` output (`<hash>  <name>`, `*<name>` in binary mode).
+pub fn hash_from_sums(sums: &str, file_name: &str) -> Option<String> {
+    sums.lines().find_map(|line| {
+        let (hash, name) = line.trim().split_once(char::is_whitespace)?;
+        (name.trim().trim_start_matches('*') == file_name && is_sha256(hash)).then(|| hash.to_lowercase())
+    })
+}
+
+/// What an image file looks like from its first sectors.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImageKind {
+    /// ISO 9660 that firmware can boot (El Torito record or a hybrid MBR); `arch` when the volume
+    /// label is an Arch release label such as `ARCH_202405`.
+    BootableIso { label: String, arch: bool },
+    /// ISO 9660 without anything to boot from.
+    Iso { label: String },
+    NotIso,
+}
+
+impl ImageKind {
+    /// Why flashing the image is probably a mistake, if it is.
+    pub fn warning(&self, path: &Path) -> Option<String> {
+        match self {
+            ImageKind::BootableIso { arch: true, .. } => None,
+            ImageKind::BootableIso { label, .. } => Some(format!("{} is a bootable ISO labelled '{}', not an Arch Linux image (ARCH_YYYYMM).", path.display(), label)),
+            ImageKind::Iso { label } => Some(format!("{} is an ISO image ('{}') without a boot record; the drive will not boot from it.", path.display(), label)),
+            ImageKind::NotIso => Some(format!("{} is not an ISO 9660 image. It may be an archive, another kind of file or a partial download.", path.display())),
+        }
+    }
+}
+
+/// Classifies an image from its first `IMAGE_HEAD` bytes (fewer if the file is shorter).
+pub fn classify_image(head: &[u8]) -> ImageKind {
+    let descriptor = |sector: usize| head.get(sector * SECTOR..sector * SECTOR + 7);
+    // Type byte, "CD001", version 1.
+    if descriptor(16).map(|d| (d[0], &d[1..6], d[6])) != Some((1, b"CD001".as_slice(), 1)) {
+        return ImageKind::NotIso;
+    }
+    let label = String::from_utf8_lossy(&head[PVD_OFFSET + 40..PVD_OFFSET + 72]).trim_end_matches([' ', '\0']).to_string();
+    let el_torito = descriptor(17).is_some_and(|d| d[0] == 0 && &d[1..6] == b"CD001")
+        && head.get(17 * SECTOR + 7..17 * SECTOR + 30).is_some_and(|id| id == b"EL TORITO SPECIFICATION");
+    let hybrid_mbr = head.get(510..512) == Some(&[0x55, 0xAA]);
+    if el_torito || hybrid_mbr {
+        let arch = label.starts_with("ARCH_");
+        ImageKind::BootableIso { label, arch }
+    } else {
+        ImageKind::Iso { label }
+    }
+}
+
+/// Reads the start of `path` for `classify_image`.
+pub fn read_image_head(path: &Path) -> std::io::Result<Vec<u8>> {
+    use std::io::Read;
+    let mut head = Vec::with_capacity(IMAGE_HEAD);
+    std::fs::File::open(path)?.take(IMAGE_HEAD as u64).read_to_end(&mut head)?;
+    Ok(head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(label: &str, el_torito: bool) -> Vec<u8> {
+        let mut head = vec![0u8; IMAGE_HEAD];
+        head[PVD_OFFSET..PVD_OFFSET + 7].copy_from_slice(b"\x01CD001\x01");
+        head[PVD_OFFSET + 40..PVD_OFFSET + 72].copy_from_slice(format!("{:32}", label).as_bytes());
+        if el_torito {
+            let record = 17 * SECTOR;
+            head[record..record + 7].copy_from_slice(b"\x00CD001\x01");
+            head[record + 7..record + 30].copy_from_slice(b"EL TORITO SPECIFICATION");
+        }
+        head
+    }
+
+    #[test]
+    fn images_are_classified_by_their_volume_descriptors() {
+        assert_eq!(classify_image(&image("ARCH_202405", true)), ImageKind::BootableIso { label: "ARCH_202405".to_string(), arch: true });
+        assert_eq!(classify_image(&image("UBUNTU", true)), ImageKind::BootableIso { label: "UBUNTU".to_string(), arch: false });
+        assert_eq!(classify_image(&image("DATA", false)), ImageKind::Iso { label: "DATA".to_string() });
+        // A gzip header, or a download that stopped before sector 16.
+        assert_eq!(classify_image(&[0x1f, 0x8b, 0x08, 0x00]), ImageKind::NotIso);
+        assert_eq!(classify_image(&image("ARCH_202405", true)[..PVD_OFFSET]), ImageKind::NotIso);
+        assert!(ImageKind::NotIso.warning(Path::new("a.tar.gz")).is_some_and(|w| w.starts_with("a.tar.gz is not an ISO")));
+    }
+
+    #[test]
+    fn the_drive_the_system_booted_from_is_never_a_target() {
+        let usb = |name: &str, mountpoint: &str| format!(r#"{{"name": "/dev/{0}", "size": 8000, "type": "disk", "rm": true, "tran": "usb", "model": "Stick", "children": [
+            {{"name": "/dev/{0}1", "size": 8000, "type": "part", "mountpoint": {1}}}]}}"#, name, mountpoint);
+        let json = format!(r#"{{"blockdevices": [{}, {}, {}]}}"#, usb("sdb", "null"), usb("sdc", r#""/run/archiso/bootmnt""#), usb("sdd", r#""/media/stick""#));
+        let targets: Vec<String> = candidates(&disk::parse_lsblk(&json).unwrap()).into_iter().map(|t| t.dev).collect();
+        assert_eq!(targets, ["/dev/sdb", "/dev/sdd"]);
+    }
+
+    #[test]
+    fn checksums_are_found_for_official_release_names() {
+        assert_eq!(arch_iso_version("archlinux-2024.05.01-x86_64.iso"), Some("2024.05.01"));
+        assert_eq!(arch_iso_version("archlinux-x86_64.iso"), None);
+        let hash = "a".repeat(64);
+        let sums = format!("{}  archlinux-bootstrap-2024.05.01-x86_64.tar.zst\n{}  archlinux-2024.05.01-x86_64.iso\n", "b".repeat(64), hash.to_uppercase());
+        assert_eq!(hash_from_sums(&sums, "archlinux-2024.05.01-x86_64.iso"), Some(hash));
+        assert_eq!(hash_from_sums(&sums, "other.iso"), None);
+        assert!(!is_sha256("abc"));
+    }
+}
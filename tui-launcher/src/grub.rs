@@ -0,0 +1,61 @@
+// ===================================================================
+// GRUB Configuration Module
+// ===================================================================
+// Reads and rewrites the kernel command line in /etc/default/grub.
+// Only the one variable is touched; every other line, comment and
+// its order are kept as they are.
+
+pub const DEFAULT_GRUB: &str = "/etc/default/grub";
+pub const GRUB_CFG: &str = "/boot/grub/grub.cfg";
+/// The parameters added to every non-recovery boot entry.
+pub const CMDLINE_DEFAULT: &str = "GRUB_CMDLINE_LINUX_DEFAULT";
+
+/// The value of the last uncommented `key=` line, without its quotes.
+pub fn value(text: &str, key: &str) -> Option<String> {
+    text.lines().rev().find_map(|line| {
+        let value = line.trim().strip_prefix(key)?.strip_prefix('=')?;
+        let unquoted = ['"', '\''].iter().find_map(|q| value.strip_prefix(*q)?.strip_suffix(*q)).unwrap_or(value);
+        Some(unquoted.to_string())
+    })
+}
+
+/// `text` with the last uncommented `key=` line replaced by `key="value"`, or that line appended.
+pub fn set_value(text: &str, key: &str, value: &str) -> String {
+    let entry = format!("{}=\"{}\"", key, value);
+    let mut lines: Vec<String> = text.lines().map(String::from).collect();
+    match lines.iter().rposition(|line| line.trim().strip_prefix(key).is_some_and(|rest| rest.starts_with('='))) {
+        Some(i) => lines[i] = entry,
+        None => lines.push(entry),
+    }
+    lines.join("\n") + "\n"
+}
+
+/// Why `value` cannot be written between double quotes, if it cannot.
+pub fn invalid_value(value: &str) -> Option<&'static str> {
+    if value.contains(['"', '\\', '$', '`']) {
+        Some("Quotes, backslashes, '$' and '`' would break the shell syntax of the file.")
+    } else if value.contains('\n') {
+        Some("The parameters must be on one line.")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFAULT: &str = "# GRUB boot loader configuration\nGRUB_DEFAULT=0\n#GRUB_CMDLINE_LINUX_DEFAULT=\"old\"\nGRUB_CMDLINE_LINUX_DEFAULT=\"loglevel=3 quiet\"\nGRUB_CMDLINE_LINUX=\"\"\n";
+
+    #[test]
+    fn the_cmdline_is_read_and_replaced_in_place() {
+        assert_eq!(value(DEFAULT, CMDLINE_DEFAULT).as_deref(), Some("loglevel=3 quiet"));
+        assert_eq!(value("GRUB_CMDLINE_LINUX_DEFAULT='quiet'\n", CMDLINE_DEFAULT).as_deref(), Some("quiet"));
+        assert_eq!(value(DEFAULT, "GRUB_TIMEOUT"), None);
+
+        let edited = set_value(DEFAULT, CMDLINE_DEFAULT, "loglevel=3 mitigations=off");
+        assert_eq!(edited, DEFAULT.replace("\"loglevel=3 quiet\"", "\"loglevel=3 mitigations=off\""));
+        assert!(set_value("GRUB_DEFAULT=0\n", CMDLINE_DEFAULT, "quiet").ends_with("GRUB_DEFAULT=0\nGRUB_CMDLINE_LINUX_DEFAULT=\"quiet\"\n"));
+        assert!(invalid_value("quiet splash").is_none() && invalid_value("a\"b").is_some());
+    }
+}
@@ -0,0 +1,84 @@
+// ===================================================================
+// Headless Runner Module
+// ===================================================================
+// Runs a single action without the TUI, for scripts and CI. Progress
+// goes to stderr; the result goes to stdout, either as plain text or
+// (with `--json`) as one JSON object.
+
+use crate::actions::{ActionResult, AppAction, Progress, ProgressTx};
+use crate::error::ActionError;
+use serde_json::{json, Value};
+
+/// Runs the action built by `start` and prints its result. Returns whether it succeeded.
+pub async fn run(start: impl FnOnce(ProgressTx) -> AppAction, json: bool) -> bool {
+    let (progress_tx, mut progress_rx) = tokio::sync::mpsc::unbounded_channel();
+    let task = tokio::spawn(start(progress_tx));
+    let mut checklist = Vec::new();
+    while let Some(progress) = progress_rx.recv().await {
+        match progress {
+            Progress::Message(line) => eprintln!("{}", line),
+            Progress::Checklist(labels) => checklist = labels,
+            Progress::Check(i) => eprintln!("{}...", checklist.get(i).map_or("", String::as_str)),
+            _ => {}
+        }
+    }
+    let result = match task.await {
+        Ok(Ok(ActionResult::Prompt(_))) => Err(needs_input()),
+        Ok(result) => result,
+        Err(e) => Err(ActionError::Other(format!("The action panicked: {}", e))),
+    };
+    let ok = result.is_ok();
+    if json {
+        println!("{}", to_json(result));
+    } else {
+        match result {
+            Ok(ActionResult::File { path, details }) => {
+                eprintln!("{}", details);
+                // Last line of stdout, so callers (e.g. fleet snapshots) can pick it up.
+                println!("{}", path.display());
+            }
+            Ok(result) => println!("{}", text(result)),
+            Err(e) => {
+                eprintln!("{}: {}", e.title(), e);
+                if let Some(guidance) = e.guidance() {
+                    eprintln!("{}", guidance);
+                }
+            }
+        }
+    }
+    ok
+}
+
+/// `{"status": "ok", "message": .., "path": ..}` or `{"status": "error", "error": {..}}`.
+pub fn to_json(result: Result<ActionResult, ActionError>) -> Value {
+    match result {
+        Ok(ActionResult::File { path, details }) => json!({ "status": "ok", "message": details, "path": path }),
+        Ok(ActionResult::Report(report)) => {
+            let rows: Vec<Value> = report.rows().into_iter().map(|(section, name, value)| json!({ "section": section, "name": name, "value": value })).collect();
+            json!({ "status": "ok", "message": report.to_string(), "report": rows, "recommended": report.recommended })
+        }
+        Ok(result) => json!({ "status": "ok", "message": text(result) }),
+        Err(e) => json!({ "status": "error", "error": { "kind": e.title(), "message": e.to_string(), "guidance": e.guidance() } }),
+    }
+}
+
+fn text(result: ActionResult) -> String {
+    match result {
+        ActionResult::Message(message) | ActionResult::Step { details: message, .. } => message,
+        ActionResult::File { path, details } => format!("{}\n\n{}", path.display(), details),
+        ActionResult::Report(report) => report.to_string(),
+        ActionResult::Prompt(_) => needs_input().to_string(),
+        ActionResult::Services { units, notice } => {
+            let width = units.iter().map(|unit| unit.name.len()).max().unwrap_or(0);
+            notice.into_iter().chain(units.iter().map(|unit| unit.label(width))).collect::<Vec<_>>().join("\n")
+        }
+        ActionResult::Snapshots { snapshots, notice } => {
+            let width = snapshots.iter().map(|snapshot| snapshot.name().len()).max().unwrap_or(0);
+            notice.into_iter().chain(snapshots.iter().map(|snapshot| snapshot.label(width))).collect::<Vec<_>>().join("\n")
+        }
+    }
+}
+
+fn needs_input() -> ActionError {
+    ActionError::InvalidInput("This action needs interactive input; run it from the TUI.".to_string())
+}
@@ -0,0 +1,140 @@
+// ===================================================================
+// Output Highlighting Module
+// ===================================================================
+// Sorts the lines a command prints into errors, warnings and headings
+// by the words they contain, so the Action popup can colour them. The
+// words come from [highlight] in the configuration. Escape codes the
+// command printed itself are stripped before anything is matched, and
+// progress bars redrawn with '\r' replace their line as in a terminal.
+
+use crate::config::HighlightConfig;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LineKind {
+    Error,
+    Warning,
+    /// A section marker, such as pacman's `::` and makepkg's `==>`.
+    Heading,
+}
+
+/// What `line` is, checked in that order: a line with an error and a warning is an error.
+/// Patterns match case-insensitively anywhere in the line, or with a leading '^' only at its start;
+/// nothing matches when colouring is off.
+pub fn classify(config: &HighlightConfig, line: &str) -> Option<LineKind> {
+    if !config.enabled {
+        return None;
+    }
+    let line = line.trim_start().to_lowercase();
+    let matches = |patterns: &[String]| {
+        patterns.iter().any(|p| match p.strip_prefix('^') {
+            Some(start) => line.starts_with(&start.to_lowercase()),
+            None => line.contains(&p.to_lowercase()),
+        })
+    };
+    [(LineKind::Error, &config.errors), (LineKind::Warning, &config.warnings), (LineKind::Heading, &config.headings)]
+        .into_iter()
+        .find(|(_, patterns)| matches(patterns))
+        .map(|(kind, _)| kind)
+}
+
+/// `text` without terminal escape sequences: colours and cursor movement (CSI), titles (OSC),
+/// character set switches and the other two-byte escapes.
+pub fn strip_ansi(text: &str) -> String {
+    if !text.contains('\x1b') {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            // Parameters and intermediates, up to a final byte in @..~.
+            Some('[') => while chars.next().is_some_and(|c| !('@'..='~').contains(&c)) {},
+            // Up to BEL or ST (ESC \).
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            Some('(' | ')' | '*' | '+') => {
+                chars.next();
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Cuts a command's output into lines as a terminal would show them. A line ended by '\r' comes
+/// back marked to be overwritten: the next line replaces it instead of following it.
+#[derive(Default)]
+pub struct LineSplitter {
+    partial: Vec<u8>,
+    overwrite: bool,
+}
+
+impl LineSplitter {
+    /// The lines `bytes` completes, each with whether it replaces the one before, stripped of
+    /// escape codes.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<(String, bool)> {
+        let mut lines = Vec::new();
+        for &byte in bytes {
+            if byte != b'\n' && byte != b'\r' {
+                self.partial.push(byte);
+                continue;
+            }
+            let line = strip_ansi(&String::from_utf8_lossy(&self.partial));
+            self.partial.clear();
+            // The '\n' of a "\r\n" only ends the line the '\r' already did.
+            if !(byte == b'\n' && self.overwrite && line.is_empty()) {
+                lines.push((line, self.overwrite));
+            }
+            self.overwrite = byte == b'\r';
+        }
+        lines
+    }
+
+    /// What is left when the output ends without a final newline.
+    pub fn finish(&mut self) -> Option<(String, bool)> {
+        let line = strip_ansi(&String::from_utf8_lossy(&std::mem::take(&mut self.partial)));
+        (!line.is_empty()).then_some((line, self.overwrite))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_classified_after_their_escape_codes_are_stripped() {
+        let line = strip_ansi("\x1b[1;31merror:\x1b[0m failed to commit transaction\x1b]0;pacman\x07");
+        assert_eq!(line, "error: failed to commit transaction");
+        assert_eq!(strip_ansi("10+0 records in\x1b(B"), "10+0 records in");
+
+        let config = HighlightConfig::default();
+        assert_eq!(classify(&config, &line), Some(LineKind::Error));
+        assert_eq!(classify(&config, "warning: /etc/pacman.conf installed as /etc/pacman.conf.pacnew"), Some(LineKind::Warning));
+        assert_eq!(classify(&config, ":: Synchronizing package databases..."), Some(LineKind::Heading));
+        assert_eq!(classify(&config, "==> Creating install root at /mnt"), Some(LineKind::Heading));
+        assert_eq!(classify(&config, "==> ERROR: Failed to install packages"), Some(LineKind::Error));
+        assert_eq!(classify(&config, " core downloading... :: 100%"), None);
+        assert_eq!(classify(&HighlightConfig { enabled: false, ..HighlightConfig::default() }, &line), None);
+    }
+
+    #[test]
+    fn progress_redrawn_with_carriage_returns_overwrites_its_line() {
+        let mut splitter = LineSplitter::default();
+        let mut lines = splitter.feed(b"Copying\n1048576 bytes\r2097152 by");
+        lines.extend(splitter.feed(b"tes\r\x1b[K\n3+0 records out\r\nDone\n"));
+        let expected = [("Copying", false), ("1048576 bytes", false), ("2097152 bytes", true), ("3+0 records out", false), ("Done", false)];
+        assert_eq!(lines, expected.map(|(line, replace)| (line.to_string(), replace)));
+        assert_eq!(splitter.feed(b"no newline"), []);
+        assert_eq!(splitter.finish(), Some(("no newline".to_string(), false)));
+        assert_eq!(splitter.finish(), None);
+    }
+}
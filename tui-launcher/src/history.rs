@@ -0,0 +1,119 @@
+// ===================================================================
+// Input History Module
+// ===================================================================
+// What was typed into each Input prompt, newest last, so Up and Down
+// bring earlier answers back. Prompts are told apart by their title;
+// secrets are never recorded. Kept in the state directory:
+//
+//   $XDG_STATE_HOME/arch-suite/input-history.json
+
+use crate::paths::{self, BaseDir};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// The most answers kept per prompt; the oldest go first.
+pub const LIMIT: usize = 20;
+
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+pub struct History {
+    prompts: BTreeMap<String, Vec<String>>,
+}
+
+impl History {
+    fn path() -> Result<PathBuf> {
+        Ok(paths::base_dir(BaseDir::State)?.join("input-history.json"))
+    }
+
+    pub fn load() -> History {
+        Self::path().ok().and_then(|path| std::fs::read_to_string(path).ok()).and_then(|text| serde_json::from_str(&text).ok()).unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn entries(&self, prompt: &str) -> &[String] {
+        self.prompts.get(prompt).map_or(&[], Vec::as_slice)
+    }
+
+    /// Adds `value` as the newest answer to `prompt`, moving it there if it was given before.
+    pub fn record(&mut self, prompt: &str, value: &str) {
+        let value = value.trim();
+        if value.is_empty() {
+            return;
+        }
+        let entries = self.prompts.entry(prompt.to_string()).or_default();
+        entries.retain(|entry| entry != value);
+        entries.push(value.to_string());
+        let excess = entries.len().saturating_sub(LIMIT);
+        entries.drain(..excess);
+    }
+}
+
+/// Walks one prompt's history with Up and Down. What was being typed before the first Up comes
+/// back after the newest entry.
+#[derive(Default, Debug)]
+pub struct Recall {
+    entries: Vec<String>,
+    at: Option<usize>,
+    draft: String,
+}
+
+impl Recall {
+    pub fn new(entries: &[String]) -> Self {
+        Recall { entries: entries.to_vec(), at: None, draft: String::new() }
+    }
+
+    /// The text to show after stepping to an `older` (Up) or newer (Down) entry, or `None` when
+    /// there is nothing further that way.
+    pub fn step(&mut self, current: &str, older: bool) -> Option<String> {
+        match (self.at, older) {
+            (None, true) if !self.entries.is_empty() => {
+                self.draft = current.to_string();
+                self.at = Some(self.entries.len() - 1);
+            }
+            (Some(i), true) if i > 0 => self.at = Some(i - 1),
+            (Some(i), false) if i + 1 < self.entries.len() => self.at = Some(i + 1),
+            (Some(_), false) => {
+                self.at = None;
+                return Some(self.draft.clone());
+            }
+            _ => return None,
+        }
+        self.at.map(|i| self.entries[i].clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_are_deduplicated_capped_and_recalled_newest_first() {
+        let mut history = History::default();
+        for value in ["/a.iso", "/b.iso", " /a.iso ", ""] {
+            history.record("ISO image to flash", value);
+        }
+        assert_eq!(history.entries("ISO image to flash"), ["/b.iso", "/a.iso"]);
+        assert!(history.entries("Directory to back up").is_empty());
+        for i in 0..LIMIT + 5 {
+            history.record("Swap size", &i.to_string());
+        }
+        assert_eq!((history.entries("Swap size").len(), history.entries("Swap size")[0].as_str()), (LIMIT, "5"));
+
+        let mut recall = Recall::new(history.entries("ISO image to flash"));
+        assert_eq!(recall.step("/c", false), None);
+        assert_eq!(recall.step("/c", true).as_deref(), Some("/a.iso"));
+        assert_eq!(recall.step("/a.iso", true).as_deref(), Some("/b.iso"));
+        assert_eq!(recall.step("/b.iso", true), None);
+        assert_eq!(recall.step("/b.iso", false).as_deref(), Some("/a.iso"));
+        assert_eq!(recall.step("/a.iso", false).as_deref(), Some("/c"));
+    }
+}
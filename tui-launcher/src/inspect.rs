@@ -0,0 +1,357 @@
+// ===================================================================
+// System Inspector Module
+// ===================================================================
+// Gathers hardware details from the running system and derives the
+// driver packages a fresh installation on this machine would need.
+
+use crate::disk::{self, human_size, BlockDevice};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use tokio::process::Command;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum GpuVendor { Nvidia, Amd, Intel, Other }
+
+impl GpuVendor {
+    pub fn name(self) -> &'static str {
+        match self { GpuVendor::Nvidia => "NVIDIA", GpuVendor::Amd => "AMD", GpuVendor::Intel => "Intel", GpuVendor::Other => "other" }
+    }
+}
+
+/// The CPU and GPU vendors of a machine; recorded in a snapshot's manifest to compare against the target.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct Hardware {
+    pub cpu_vendor: String,
+    pub gpu_vendors: Vec<GpuVendor>,
+}
+
+impl Hardware {
+    fn new(cpu_vendor: String, gpus: &[Gpu]) -> Self {
+        let mut gpu_vendors: Vec<GpuVendor> = Vec::new();
+        for gpu in gpus {
+            if !gpu_vendors.contains(&gpu.vendor) {
+                gpu_vendors.push(gpu.vendor);
+            }
+        }
+        Hardware { cpu_vendor, gpu_vendors }
+    }
+
+    /// Microcode and driver packages this hardware needs.
+    pub fn packages(&self) -> Vec<&'static str> {
+        let mut packages: Vec<&'static str> = microcode_package(&self.cpu_vendor).into_iter().collect();
+        for package in self.gpu_vendors.iter().flat_map(|&v| driver_packages(v)).chain(self.hybrid().then_some(&"nvidia-prime")) {
+            if !packages.contains(package) {
+                packages.push(package);
+            }
+        }
+        packages
+    }
+
+    /// Whether an NVIDIA GPU sits next to an integrated one, as in most laptops with one: by
+    /// default the integrated GPU draws the desktop and `prime-run` starts a program on the NVIDIA.
+    pub fn hybrid(&self) -> bool {
+        self.gpu_vendors.contains(&GpuVendor::Nvidia) && self.gpu_vendors.iter().any(|&v| v == GpuVendor::Intel || v == GpuVendor::Amd)
+    }
+
+    pub fn describe(&self) -> String {
+        let gpus = if self.gpu_vendors.is_empty() { "no".to_string() } else { self.gpu_vendors.iter().map(|v| v.name()).collect::<Vec<_>>().join(" + ") };
+        format!("{} CPU, {} GPU", self.cpu_vendor, gpus)
+    }
+}
+
+/// Hardware-specific packages in a snapshot that do not fit the target, and the target's own.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct DriverSwap {
+    pub remove: Vec<String>,
+    pub add: Vec<String>,
+}
+
+impl DriverSwap {
+    pub fn is_empty(&self) -> bool {
+        self.remove.is_empty() && self.add.is_empty()
+    }
+}
+
+/// The GPU vendor a driver package is built for; shared packages such as mesa have none.
+fn gpu_specific(package: &str) -> Option<GpuVendor> {
+    let package = package.strip_prefix("lib32-").unwrap_or(package);
+    if package.starts_with("nvidia") || package == "opencl-nvidia" {
+        Some(GpuVendor::Nvidia)
+    } else if ["vulkan-radeon", "xf86-video-amdgpu", "xf86-video-ati", "amdvlk"].contains(&package) {
+        Some(GpuVendor::Amd)
+    } else if ["vulkan-intel", "intel-media-driver", "libva-intel-driver", "xf86-video-intel"].contains(&package) {
+        Some(GpuVendor::Intel)
+    } else {
+        None
+    }
+}
+
+/// Compares a snapshot's `packages` with the `target` machine. Packages specific to hardware the
+/// target lacks are removed by name, so this also works for snapshots taken before the manifest
+/// recorded `source`; the target's packages are added when something is removed or the recorded
+/// hardware differs.
+pub fn driver_swap(source: Option<&Hardware>, target: &Hardware, packages: &[String]) -> DriverSwap {
+    let target_microcode = microcode_package(&target.cpu_vendor);
+    let remove: Vec<String> = packages
+        .iter()
+        .filter(|p| {
+            let wrong_gpu = gpu_specific(p).is_some_and(|v| !target.gpu_vendors.contains(&v));
+            let wrong_microcode = p.ends_with("-ucode") && Some(p.as_str()) != target_microcode;
+            wrong_gpu || wrong_microcode
+        })
+        .cloned()
+        .collect();
+    let add = if !remove.is_empty() || source.is_some_and(|s| s != target) {
+        target.packages().into_iter().filter(|p| !packages.iter().any(|q| q == p)).map(String::from).collect()
+    } else {
+        Vec::new()
+    };
+    DriverSwap { remove, add }
+}
+
+#[derive(Clone, Debug)]
+pub struct Gpu {
+    pub vendor: GpuVendor,
+    pub description: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Microcode {
+    pub package: &'static str,
+    pub installed: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MemoryInfo {
+    pub total_kb: u64,
+    pub available_kb: u64,
+    pub swap_total_kb: u64,
+    pub swap_free_kb: u64,
+}
+
+/// Every probe is independent: a failed one leaves its `Err` here and the rest of the report intact.
+#[derive(Clone, Debug)]
+pub struct SystemReport {
+    pub kernel: Result<String, String>,
+    pub init: Result<String, String>,
+    pub cpu_vendor: String,
+    pub cpu_model: String,
+    pub microcode: Option<Microcode>,
+    pub memory: Result<MemoryInfo, String>,
+    pub gpus: Vec<Gpu>,
+    pub devices: Result<Vec<BlockDevice>, String>,
+    pub recommended: Vec<String>,
+}
+
+fn or_unavailable(value: &Result<String, String>) -> String {
+    match value {
+        Ok(v) => v.clone(),
+        Err(e) => format!("unavailable ({})", e),
+    }
+}
+
+fn kb(kb: u64) -> String { human_size(kb * 1024) }
+
+impl SystemReport {
+    /// The report as `(section, property, value)` rows, in display order.
+    pub fn rows(&self) -> Vec<(&'static str, String, String)> {
+        let mut rows = vec![
+            ("System", "Kernel".to_string(), or_unavailable(&self.kernel)),
+            ("System", "Init".to_string(), or_unavailable(&self.init)),
+            ("CPU", "Vendor".to_string(), self.cpu_vendor.clone()),
+            ("CPU", "Model".to_string(), self.cpu_model.clone()),
+        ];
+        if let Some(ucode) = &self.microcode {
+            let state = if ucode.installed { "installed" } else { "not installed" };
+            rows.push(("CPU", "Microcode".to_string(), format!("{} ({})", ucode.package, state)));
+            rows.push(("CPU", "Note".to_string(), format!("The bootloader must load /boot/{}.img before the initramfs (grub-mkconfig adds it automatically).", ucode.package)));
+        }
+        match &self.memory {
+            Ok(m) => {
+                rows.push(("Memory", "RAM total".to_string(), kb(m.total_kb)));
+                rows.push(("Memory", "RAM available".to_string(), kb(m.available_kb)));
+                rows.push(("Memory", "Swap".to_string(), format!("{} ({} free)", kb(m.swap_total_kb), kb(m.swap_free_kb))));
+            }
+            Err(e) => rows.push(("Memory", "RAM".to_string(), format!("unavailable ({})", e))),
+        }
+        if self.gpus.is_empty() {
+            rows.push(("GPU", "Detected".to_string(), "none".to_string()));
+        }
+        for (i, gpu) in self.gpus.iter().enumerate() {
+            rows.push(("GPU", format!("GPU {}", i + 1), gpu.description.clone()));
+        }
+        if Hardware::new(String::new(), &self.gpus).hybrid() {
+            rows.push(("GPU", "Hybrid".to_string(), "Drivers for every GPU are recommended; nvidia-prime's `prime-run` starts a program on the NVIDIA one.".to_string()));
+        }
+        match &self.devices {
+            Ok(devices) => {
+                for device in devices.iter().flat_map(|d| d.flatten()) {
+                    let mut details = vec![human_size(device.size), device.kind.clone()];
+                    details.extend(device.fstype.clone());
+                    details.extend(device.label.as_ref().map(|l| format!("\"{}\"", l)));
+                    details.extend(device.model.as_ref().map(|m| m.trim().to_string()));
+                    details.extend(device.mountpoint.as_ref().map(|m| format!("on {}", m)));
+                    if device.rm && device.kind == "disk" {
+                        details.push("removable".to_string());
+                    }
+                    rows.push(("Storage", device.name.clone(), details.join("  ")));
+                }
+            }
+            Err(e) => rows.push(("Storage", "Devices".to_string(), format!("unavailable ({})", e))),
+        }
+        rows.push(("Drivers", "Recommended".to_string(), self.recommended.join(" ")));
+        rows
+    }
+}
+
+impl fmt::Display for SystemReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut section = "";
+        for (heading, key, value) in self.rows() {
+            if heading != section {
+                if !section.is_empty() {
+                    writeln!(f)?;
+                }
+                writeln!(f, "== {} ==", heading)?;
+                section = heading;
+            }
+            writeln!(f, "{:<14} {}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs a probe command and returns its stdout, or an empty string if it is unavailable.
+async fn probe(program: &str, args: &[&str]) -> String {
+    try_probe(program, args).await.unwrap_or_default()
+}
+
+async fn try_probe(program: &str, args: &[&str]) -> Result<String, String> {
+    match Command::new(program).args(args).output().await {
+        Ok(output) if output.status.success() => Ok(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+        Ok(output) => Err(format!("{} exited with {}", program, output.status)),
+        Err(e) => Err(format!("{}: {}", program, e)),
+    }
+}
+
+pub fn parse_meminfo(text: &str) -> Result<MemoryInfo, String> {
+    let value = |key: &str| -> Result<u64, String> {
+        text.lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix(':'))
+            .and_then(|v| v.split_whitespace().next()?.parse().ok())
+            .ok_or_else(|| format!("{} missing from /proc/meminfo", key))
+    };
+    Ok(MemoryInfo {
+        total_kb: value("MemTotal")?,
+        available_kb: value("MemAvailable")?,
+        swap_total_kb: value("SwapTotal")?,
+        swap_free_kb: value("SwapFree")?,
+    })
+}
+
+/// Looks up a `Key: value` field in `lscpu`-style output.
+fn field<'a>(text: &'a str, key: &str) -> Option<&'a str> {
+    text.lines().find_map(|line| {
+        let (k, v) = line.split_once(':')?;
+        (k.trim() == key).then(|| v.trim())
+    })
+}
+
+/// Extracts display controllers from `lspci` output, keeping every match for hybrid setups.
+fn parse_gpus(lspci: &str) -> Vec<Gpu> {
+    lspci
+        .lines()
+        .filter(|l| l.contains("VGA compatible controller") || l.contains("3D controller") || l.contains("Display controller"))
+        .map(|line| {
+            let description = line.splitn(3, ':').nth(2).unwrap_or(line).trim().to_string();
+            let vendor = if description.contains("NVIDIA") {
+                GpuVendor::Nvidia
+            } else if description.contains("AMD") || description.contains("ATI") {
+                GpuVendor::Amd
+            } else if description.contains("Intel") {
+                GpuVendor::Intel
+            } else {
+                GpuVendor::Other
+            };
+            Gpu { vendor, description }
+        })
+        .collect()
+}
+
+fn microcode_package(cpu_vendor: &str) -> Option<&'static str> {
+    match cpu_vendor {
+        "GenuineIntel" => Some("intel-ucode"),
+        "AuthenticAMD" => Some("amd-ucode"),
+        _ => None,
+    }
+}
+
+fn driver_packages(vendor: GpuVendor) -> &'static [&'static str] {
+    match vendor {
+        GpuVendor::Nvidia => &["nvidia", "nvidia-utils"],
+        GpuVendor::Amd => &["mesa", "vulkan-radeon", "xf86-video-amdgpu"],
+        GpuVendor::Intel => &["mesa", "vulkan-intel", "intel-media-driver"],
+        GpuVendor::Other => &["mesa"],
+    }
+}
+
+/// Only the vendor probes of `gather`, for recording and comparing snapshots.
+pub async fn hardware() -> Hardware {
+    let lscpu = probe("lscpu", &[]).await;
+    Hardware::new(field(&lscpu, "Vendor ID").unwrap_or("unknown").to_string(), &parse_gpus(&probe("lspci", &[]).await))
+}
+
+pub async fn gather() -> SystemReport {
+    let lscpu = probe("lscpu", &[]).await;
+    let gpus = parse_gpus(&probe("lspci", &[]).await);
+    let cpu_vendor = field(&lscpu, "Vendor ID").unwrap_or("unknown").to_string();
+    let recommended = Hardware::new(cpu_vendor.clone(), &gpus).packages().into_iter().map(String::from).collect();
+    let mut microcode = None;
+    if let Some(package) = microcode_package(&cpu_vendor) {
+        let installed = try_probe("pacman", &["-Q", package]).await.is_ok();
+        microcode = Some(Microcode { package, installed });
+    }
+    SystemReport {
+        kernel: try_probe("uname", &["-r"]).await,
+        init: std::fs::read_to_string("/proc/1/comm").map(|c| c.trim().to_string()).map_err(|e| e.to_string()),
+        cpu_vendor,
+        cpu_model: field(&lscpu, "Model name").unwrap_or("unknown").to_string(),
+        microcode,
+        memory: std::fs::read_to_string("/proc/meminfo").map_err(|e| e.to_string()).and_then(|m| parse_meminfo(&m)),
+        gpus,
+        devices: disk::list().await.map_err(|e| e.to_string()),
+        recommended,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packages(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn nvidia_snapshot_on_amd_swaps_drivers_and_microcode() {
+        let source = Hardware { cpu_vendor: "GenuineIntel".to_string(), gpu_vendors: vec![GpuVendor::Nvidia] };
+        let target = Hardware { cpu_vendor: "AuthenticAMD".to_string(), gpu_vendors: vec![GpuVendor::Amd] };
+        let installed = packages(&["base", "mesa", "nvidia-dkms", "nvidia-utils", "lib32-nvidia-utils", "intel-ucode", "firefox"]);
+        let swap = driver_swap(Some(&source), &target, &installed);
+        assert_eq!(swap.remove, packages(&["nvidia-dkms", "nvidia-utils", "lib32-nvidia-utils", "intel-ucode"]));
+        assert_eq!(swap.add, packages(&["amd-ucode", "vulkan-radeon", "xf86-video-amdgpu"]));
+    }
+
+    #[test]
+    fn matching_hardware_needs_no_swap() {
+        let hardware = Hardware { cpu_vendor: "AuthenticAMD".to_string(), gpu_vendors: vec![GpuVendor::Amd, GpuVendor::Nvidia] };
+        let installed = packages(&["amd-ucode", "nvidia", "vulkan-radeon"]);
+        assert!(driver_swap(Some(&hardware), &hardware, &installed).is_empty());
+        // Both GPUs of a hybrid laptop keep their drivers, with PRIME to switch between them.
+        assert!(hardware.hybrid());
+        assert_eq!(hardware.packages(), ["amd-ucode", "mesa", "vulkan-radeon", "xf86-video-amdgpu", "nvidia", "nvidia-utils", "nvidia-prime"]);
+        // Without a recorded source, nothing is added unless something had to go.
+        assert!(driver_swap(None, &hardware, &installed).is_empty());
+    }
+}
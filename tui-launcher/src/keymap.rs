@@ -0,0 +1,198 @@
+// ===================================================================
+// Key Map Module
+// ===================================================================
+// The keybindings available in each context, as shown by the F1
+// cheatsheet. Keep these tables in step with `event.rs`.
+
+use crate::app::{App, AppView, Popup};
+
+/// Where keyboard input is currently going.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum KeyContext { Menu, HelpManual, Help, TextPopup, ReportPopup, Confirm, Phrase, Input, Select, MultiSelect, Diff, Results, Services, Logs, Snapshots }
+
+impl KeyContext {
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyContext::Menu => "Menu",
+            KeyContext::HelpManual => "Help Manual",
+            KeyContext::Help => "Help",
+            KeyContext::TextPopup => "Output",
+            KeyContext::ReportPopup => "Report",
+            KeyContext::Confirm => "Confirmation",
+            KeyContext::Phrase => "Typed Confirmation",
+            KeyContext::Input => "Text Input",
+            KeyContext::Select => "Selection",
+            KeyContext::MultiSelect => "Checklist",
+            KeyContext::Diff => "Diff",
+            KeyContext::Results => "Session Results",
+            KeyContext::Services => "Services",
+            KeyContext::Logs => "Logs",
+            KeyContext::Snapshots => "Snapshots",
+        }
+    }
+}
+
+pub struct Binding {
+    pub keys: &'static str,
+    pub description: &'static str,
+}
+
+const fn bind(keys: &'static str, description: &'static str) -> Binding { Binding { keys, description } }
+
+const MENU: &[Binding] = &[
+    bind("j / ↓", "Next item"),
+    bind("k / ↑", "Previous item"),
+    bind("N j / N k", "Move N items (type the count first)"),
+    bind("gg / G, Home / End", "First / last item (N G: item N)"),
+    bind("PgDn / PgUp", "A page down / up, stopping at the ends"),
+    bind("Enter", "Run the selected item"),
+    bind("letter", "Run the item with that letter in its icon, e.g. s for [S]"),
+    bind("Esc", "Back to the previous view"),
+    bind("Tab / Shift-Tab", "Next / previous tab"),
+    bind("Alt+1 .. Alt+5", "Go to that tab"),
+    bind("?", "Help for the selected item"),
+    bind("H", "Results of this session's actions"),
+    bind("i", "Show or hide the help below the menu"),
+    bind("X", "Expert mode: skip yes/no confirmations that default to yes"),
+];
+const LOGS: &[Binding] = &[
+    bind("j / k, ↓ / ↑", "Move (N j / N k: move N)"),
+    bind("gg / G", "Newest / oldest"),
+    bind("Enter", "Reopen the result's full output"),
+    bind("Tab / Shift-Tab, Alt+N", "Switch tabs"),
+    bind("Esc", "Back to the previous view"),
+];
+const SNAPSHOTS: &[Binding] = &[
+    bind("j / k, ↓ / ↑", "Move (N j / N k: move N)"),
+    bind("gg / G", "Newest / oldest"),
+    bind("Enter", "Deploy the highlighted snapshot"),
+    bind("d / Delete", "Delete it (asks first)"),
+    bind("Esc", "Back to the previous view"),
+];
+const HELP_MANUAL: &[Binding] = &[
+    bind("j / k, ↓ / ↑", "Scroll a line"),
+    bind("PgDn / PgUp", "Scroll a page"),
+    bind("g / G", "Top / bottom"),
+    bind("/", "Search (ignoring case), jumping to the first match as you type; Enter ends it"),
+    bind("n / N", "Next / previous match"),
+    bind("Esc", "Clear the search, then back to the previous view"),
+    bind("q", "Back to the previous view"),
+];
+const HELP: &[Binding] = &[bind("any key", "Close")];
+const TEXT_POPUP: &[Binding] = &[
+    bind("j / k, ↓ / ↑", "Scroll a line"),
+    bind("PgDn / PgUp", "Scroll a page"),
+    bind("w", "Toggle line wrapping"),
+    bind("h / l, ← / →", "Pan sideways (unwrapped)"),
+    bind("c", "Copy the text to the clipboard"),
+    bind("p", "Copy the produced file's path"),
+    bind("s", "Save the text to a file"),
+    bind("r", "Retry the failed action"),
+    bind("End", "Keep the newest line of a running action in view"),
+    bind("Esc / Ctrl-C", "Cancel the running action"),
+    bind("q / Esc", "Stop following a log"),
+    bind("any other key", "Close (once the action finished)"),
+];
+const REPORT_POPUP: &[Binding] = &[
+    bind("j / k, ↓ / ↑", "Scroll a row"),
+    bind("PgDn / PgUp", "Scroll a page"),
+    bind("c", "Copy the report as text"),
+    bind("s", "Save the report to a file"),
+    bind("any other key", "Close"),
+];
+const CONFIRM: &[Binding] = &[
+    bind("y / n", "Answer directly"),
+    bind("h / l, Tab", "Move between Yes and No"),
+    bind("Enter", "Activate the highlighted button"),
+    bind("Esc", "Cancel"),
+];
+const PHRASE: &[Binding] = &[bind("Enter", "Continue, once the device name is typed exactly"), bind("Esc", "Cancel")];
+const INPUT: &[Binding] = &[bind("Enter", "Submit"), bind("↑ / ↓", "Earlier answers to this prompt"), bind("Tab", "Complete the path (again: next match)"), bind("+ / -", "Step a partition size up or down"), bind("Esc", "Cancel")];
+const SELECT: &[Binding] = &[
+    bind("j / k, ↓ / ↑", "Move (N j / N k: move N)"),
+    bind("gg / G, Home / End", "First / last item"),
+    bind("PgDn / PgUp", "A page down / up, stopping at the ends"),
+    bind("/", "Filter (ignoring case); Enter keeps the filter"),
+    bind("Enter", "Choose"),
+    bind("Esc", "Clear the filter, then cancel"),
+];
+const MULTI_SELECT: &[Binding] = &[
+    bind("j / k, ↓ / ↑", "Move (N j / N k: move N)"),
+    bind("gg / G", "First / last item"),
+    bind("/", "Search (ignoring case); the highlight jumps to the first match"),
+    bind("n / N", "Next / previous match"),
+    bind("Space", "Tick or untick"),
+    bind("a", "Tick all, or untick all when all are ticked"),
+    bind("d", "Show the highlighted file's differences (when comparing files)"),
+    bind("Enter", "Continue with the ticked items"),
+    bind("Esc", "Cancel"),
+];
+const DIFF: &[Binding] = &[
+    bind("j / k, ↓ / ↑", "Scroll a line"),
+    bind("PgDn / PgUp, Space", "Scroll a page"),
+    bind("d / q / Esc", "Back to the file list"),
+];
+const RESULTS: &[Binding] = &[
+    bind("j / k, ↓ / ↑", "Move (N j / N k: move N)"),
+    bind("gg / G", "Newest / oldest result"),
+    bind("Enter", "Reopen the result's full output"),
+    bind("q / Esc", "Close"),
+];
+const SERVICES: &[Binding] = &[
+    bind("j / k, ↓ / ↑", "Move (N j / N k: move N)"),
+    bind("gg / G", "First / last service"),
+    bind("/", "Filter; Enter keeps the filter, Esc clears it"),
+    bind("s / x", "Start / stop the selected service"),
+    bind("r", "Restart the selected service"),
+    bind("e / d", "Enable / disable it at boot"),
+    bind("l / f", "Show its last journal lines / follow new ones live"),
+    bind("p", "Cycle the lowest journal priority shown"),
+    bind("q / Esc", "Close"),
+];
+/// Available everywhere, listed after the context's own bindings.
+const GLOBAL: &[Binding] = &[bind("F1", "Show or hide this cheatsheet")];
+
+pub struct KeyMap;
+
+impl KeyMap {
+    pub fn context(app: &App<'_>) -> KeyContext {
+        match app.active_popup {
+            Popup::None if app.current_view == AppView::HelpManual => KeyContext::HelpManual,
+            Popup::None if app.current_view == AppView::Logs => KeyContext::Logs,
+            Popup::None if app.current_view == AppView::Snapshots => KeyContext::Snapshots,
+            Popup::None => KeyContext::Menu,
+            Popup::Help => KeyContext::Help,
+            Popup::Action => KeyContext::TextPopup,
+            Popup::Report => KeyContext::ReportPopup,
+            Popup::Confirm => KeyContext::Confirm,
+            Popup::Phrase => KeyContext::Phrase,
+            Popup::Input => KeyContext::Input,
+            Popup::Select => KeyContext::Select,
+            Popup::MultiSelect => KeyContext::MultiSelect,
+            Popup::Diff => KeyContext::Diff,
+            Popup::Results => KeyContext::Results,
+            Popup::Services => KeyContext::Services,
+        }
+    }
+
+    pub fn bindings(context: KeyContext) -> impl Iterator<Item = &'static Binding> {
+        let own = match context {
+            KeyContext::Menu => MENU,
+            KeyContext::HelpManual => HELP_MANUAL,
+            KeyContext::Help => HELP,
+            KeyContext::TextPopup => TEXT_POPUP,
+            KeyContext::ReportPopup => REPORT_POPUP,
+            KeyContext::Confirm => CONFIRM,
+            KeyContext::Phrase => PHRASE,
+            KeyContext::Input => INPUT,
+            KeyContext::Select => SELECT,
+            KeyContext::MultiSelect => MULTI_SELECT,
+            KeyContext::Diff => DIFF,
+            KeyContext::Results => RESULTS,
+            KeyContext::Services => SERVICES,
+            KeyContext::Logs => LOGS,
+            KeyContext::Snapshots => SNAPSHOTS,
+        };
+        own.iter().chain(GLOBAL)
+    }
+}
@@ -0,0 +1,97 @@
+// ===================================================================
+// Instance Lock Module
+// ===================================================================
+// Keeps a second arch-suite from running next to the first, where both
+// could partition the same disk or write the same snapshot. The lock is
+// an flock on a file in the runtime directory, so it goes away with the
+// process however it ends; the file also names the holder's PID.
+
+use crate::paths;
+use anyhow::{anyhow, Context, Result};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+pub const LOCK_FILE: &str = "arch-suite.lock";
+
+/// Held for as long as this instance runs; dropping it releases the lock.
+pub struct InstanceLock {
+    _file: File,
+}
+
+pub fn lock_path() -> PathBuf {
+    paths::runtime_dir().join(LOCK_FILE)
+}
+
+/// The PID written in a lock file.
+pub fn holder(contents: &str) -> Option<u32> {
+    contents.trim().parse().ok()
+}
+
+fn is_running(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+/// Takes the lock at `lock_path()`, or explains which instance has it.
+pub fn acquire() -> Result<InstanceLock> {
+    acquire_at(&lock_path())
+}
+
+pub fn acquire_at(path: &Path) -> Result<InstanceLock> {
+    match try_acquire(path)? {
+        Ok(lock) => Ok(lock),
+        // A lock whose holder is gone (e.g. on a filesystem that keeps locks of dead processes)
+        // is stale: the file is replaced, and the lock taken on the new one.
+        Err(Some(pid)) if !is_running(pid) => {
+            std::fs::remove_file(path).with_context(|| format!("Failed to remove the stale lock {}", path.display()))?;
+            try_acquire(path)?.map_err(|_| anyhow!("Another arch-suite took {} at the same time.", path.display()))
+        }
+        Err(pid) => Err(anyhow!(
+            "Another arch-suite is already running{}. Close it first: two instances could work on the same disk or snapshot at once.\n(Lock: {})",
+            pid.map_or(String::new(), |pid| format!(" (PID {})", pid)),
+            path.display()
+        )),
+    }
+}
+
+/// The lock, or the PID of the instance holding it, if the file names one.
+fn try_acquire(path: &Path) -> Result<Result<InstanceLock, Option<u32>>> {
+    // A lock file left by a run under sudo belongs to root; it can still be locked read-only.
+    let mut file = match OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => File::open(path)?,
+        Err(e) => return Err(e).with_context(|| format!("Failed to open the lock {}", path.display())),
+    };
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            let mut contents = String::new();
+            let _ = file.read_to_string(&mut contents);
+            return Ok(Err(holder(&contents)));
+        }
+        Err(TryLockError::Error(e)) => return Err(e).with_context(|| format!("Failed to lock {}", path.display())),
+    }
+    // Best effort: a read-only lock file keeps the previous PID.
+    if file.set_len(0).is_ok() && file.rewind().is_ok() {
+        let _ = write!(file, "{}", std::process::id());
+    }
+    Ok(Ok(InstanceLock { _file: file }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_second_instance_is_refused_until_the_first_lets_go() {
+        let path = std::env::temp_dir().join(format!("arch-suite-lock-{}", std::process::id()));
+        let first = acquire_at(&path).unwrap();
+        assert_eq!(holder(&std::fs::read_to_string(&path).unwrap()), Some(std::process::id()));
+        let refused = acquire_at(&path).err().unwrap().to_string();
+        assert!(refused.contains(&format!("PID {}", std::process::id())), "{}", refused);
+        drop(first);
+        assert!(acquire_at(&path).is_ok());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(holder("garbage"), None);
+    }
+}
@@ -0,0 +1,85 @@
+// ===================================================================
+// Log File Module
+// ===================================================================
+// A record of what the suite ran, kept in the state directory for
+// looking into a failed action after its popup is gone: each action
+// started and how it ended, every command line, and how the commands
+// that failed ended. Events go through `tracing` to a file that rolls
+// over daily (arch-suite.<date>.log, the last MAX_FILES kept). An env
+// filter in ARCH_SUITE_LOG, or else RUST_LOG, sets how much, e.g.
+// `debug` or `arch_suite=off`; without one, info and up is logged.
+
+use crate::paths::{self, BaseDir};
+use std::path::Path;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+use tracing_subscriber::fmt::time::ChronoLocal;
+
+/// How many days of logs are kept.
+const MAX_FILES: usize = 7;
+
+/// The filter from ARCH_SUITE_LOG when it is set, and from RUST_LOG otherwise.
+pub fn env_filter() -> EnvFilter {
+    let var = if std::env::var_os("ARCH_SUITE_LOG").is_some() { "ARCH_SUITE_LOG" } else { EnvFilter::DEFAULT_ENV };
+    EnvFilter::builder().with_default_directive(LevelFilter::INFO.into()).with_env_var(var).from_env_lossy()
+}
+
+/// The daily log files in `dir`.
+pub fn appender(dir: &Path) -> Option<RollingFileAppender> {
+    std::fs::create_dir_all(dir).ok()?;
+    RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("arch-suite")
+        .filename_suffix("log")
+        .max_log_files(MAX_FILES)
+        .build(dir)
+        .ok()
+}
+
+/// Starts logging for this session; the returned guard flushes the log when dropped, so it is held
+/// until the suite exits. Without a state directory nothing is logged; the suite runs the same
+/// either way.
+pub fn init() -> Option<WorkerGuard> {
+    let appender = appender(&paths::base_dir(BaseDir::State).ok()?)?;
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+    tracing_subscriber::fmt()
+        .with_env_filter(env_filter())
+        .with_writer(writer)
+        .with_ansi(false)
+        .with_target(false)
+        .with_timer(ChronoLocal::new("%Y-%m-%d %H:%M:%S%.3f".into()))
+        .try_init()
+        .ok()?;
+    tracing::info!("arch-suite {} started", env!("CARGO_PKG_VERSION"));
+    Some(guard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_land_in_the_daily_log_as_the_filter_allows() {
+        let dir = std::env::temp_dir().join(format!("arch-suite-log-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let subscriber = tracing_subscriber::fmt()
+            .with_env_filter(EnvFilter::new("arch_suite=info"))
+            .with_writer(appender(&dir).unwrap())
+            .with_ansi(false)
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::error!("'tar -cf x' exited with code 2");
+            tracing::debug!("  ran for 0.1s: tar -cf x");
+        });
+
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().flatten().map(|e| e.path()).collect();
+        assert_eq!(files.len(), 1);
+        let name = files[0].file_name().unwrap().to_string_lossy().into_owned();
+        assert!(name.starts_with("arch-suite.") && name.ends_with(".log"), "{}", name);
+        let log = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(log.contains("ERROR") && log.contains("exited with code 2"), "{}", log);
+        assert!(!log.contains("ran for"), "{}", log);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
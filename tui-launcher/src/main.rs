@@ -4,31 +4,255 @@
 mod app;
 mod event;
 mod ui;
+mod workflow;
 mod actions;
+mod clipboard;
 mod components;
+mod config;
+mod detach;
+mod disk;
+mod doctor;
+mod error;
+mod flash;
+mod grub;
+mod headless;
+mod highlight;
+mod history;
+mod inspect;
+mod install;
+mod iso;
+mod keymap;
+mod lock;
+mod logfile;
+mod manual;
+mod menu_doc;
+mod mirrors;
+mod packages;
+mod paths;
+mod registry;
+mod services;
+mod signature;
+mod snapshot;
+mod timeshift;
+mod vm;
 
 use anyhow::Result;
 use app::App;
+use clap::builder::PossibleValuesParser;
+use clap::{Parser, Subcommand};
 use crossterm::terminal::{
     disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
 };
+use crossterm::cursor::Show;
 use crossterm::ExecutableCommand;
 use ratatui::prelude::*;
 use std::io::{self, stdout, Stdout};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// A replication, cloning and installation suite for Arch Linux. Without a subcommand the TUI starts.
+#[derive(Parser, Debug)]
+#[command(name = "arch-suite", version)]
+struct Cli {
+    /// Run an install plan (TOML) in the manual installer on start.
+    #[arg(long, value_name = "FILE")]
+    plan: Option<PathBuf>,
+    /// Open the TUI on this view instead of the main menu; Esc still leads back to it.
+    #[arg(long, visible_alias = "goto", value_name = "VIEW", value_enum, conflicts_with_all = ["plan", "resume"])]
+    view: Option<app::AppView>,
+    /// Open the TUI on the view it was last closed on.
+    #[arg(long, conflicts_with = "plan")]
+    resume: bool,
+    /// Open the TUI with this action started from its menu; `--dump-menu json` shows each item's.
+    #[arg(long, value_name = "ACTION", value_parser = PossibleValuesParser::new(registry::names()), conflicts_with_all = ["plan", "view", "resume"])]
+    run: Option<String>,
+    /// Skip confirmations: the plan's destructive steps, a destructive subcommand, or installing
+    /// the dependencies missing on start.
+    #[arg(short, long, global = true)]
+    yes: bool,
+    /// Explore without risk: everything that erases or overwrites data is blocked, as with
+    /// `safe_mode` under [ui].
+    #[arg(long, global = true)]
+    safe: bool,
+    /// Print a subcommand's result as a JSON object.
+    #[arg(long, global = true)]
+    json: bool,
+    /// Keep snapshots and fleet archives here instead of the configured work directory.
+    #[arg(long, global = true, value_name = "DIR")]
+    work_dir: Option<PathBuf>,
+    /// Print every menu item and its help as Markdown or JSON, then exit.
+    #[arg(long, value_name = "FORMAT", value_enum)]
+    dump_menu: Option<menu_doc::MenuFormat>,
+    /// Same as `snapshot create`; kept for fleet hosts running older configs.
+    #[arg(long, hide = true)]
+    snapshot: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Clone, Debug, PartialEq)]
+enum Command {
+    /// Print the system inspector's report.
+    Inspect,
+    /// Manage system snapshots.
+    Snapshot {
+        #[command(subcommand)]
+        action: SnapshotCommand,
+    },
+    /// Write an ISO image to a USB drive (destructive; needs --yes).
+    Flash {
+        #[arg(long, value_name = "FILE")]
+        iso: PathBuf,
+        /// The whole-disk device, e.g. /dev/sdb.
+        #[arg(long, value_name = "DEV")]
+        device: String,
+        /// Skip reading the image back to verify it.
+        #[arg(long)]
+        no_verify: bool,
+        /// Write the file even if it does not look like a bootable Arch ISO.
+        #[arg(long)]
+        any_image: bool,
+        /// Write the ISO even if its `.sig` is missing, bad or from an untrusted key.
+        #[arg(long)]
+        allow_unsigned: bool,
+    },
+}
+
+#[derive(Subcommand, Clone, Debug, PartialEq)]
+enum SnapshotCommand {
+    /// Create a snapshot and print its path.
+    Create {
+        /// Where to write the archive instead of the work directory.
+        #[arg(long, value_name = "FILE")]
+        out: Option<PathBuf>,
+    },
+}
+
+impl Cli {
+    /// The subcommand to run headlessly, if any.
+    fn headless(&self) -> Option<Command> {
+        match &self.command {
+            Some(command) => Some(command.clone()),
+            None if self.snapshot => Some(Command::Snapshot { action: SnapshotCommand::Create { out: None } }),
+            None => None,
+        }
+    }
+}
+
+impl Command {
+    fn is_destructive(&self) -> bool {
+        matches!(self, Command::Flash { .. })
+    }
+}
+
+/// Runs `command` without the TUI. Destructive commands are refused unless `yes` is set, and
+/// always in safe mode.
+async fn run_headless(command: Command, yes: bool, safe: bool, json: bool) -> bool {
+    let refusal = match (command.is_destructive(), safe, yes) {
+        (true, true, _) => Some("Refusing to run a destructive command in safe mode."),
+        (true, false, false) => Some("Refusing to run a destructive command without --yes."),
+        _ => None,
+    };
+    if let Some(refusal) = refusal {
+        let refusal = error::ActionError::InvalidInput(refusal.to_string());
+        return headless::run(move |_| Box::pin(async move { Err(refusal) }), json).await;
+    }
+    match command {
+        Command::Inspect => headless::run(actions::inspect_system, json).await,
+        Command::Snapshot { action: SnapshotCommand::Create { out } } => headless::run(|progress| actions::create_snapshot_at(out, progress), json).await,
+        Command::Flash { iso, device, no_verify, any_image, allow_unsigned } => {
+            headless::run(|progress| actions::flash_unattended(iso, device, !no_verify, any_image, allow_unsigned, progress), json).await
+        }
+    }
+}
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    if !actions::check_and_install_dependencies().await? {
-        println!("Cannot proceed without dependencies. Aborting.");
-        return Ok(());
+async fn main() -> Result<ExitCode> {
+    let args = Cli::parse();
+    if let Some(dir) = &args.work_dir {
+        paths::set_work_dir(dir.clone());
+    }
+    let _log = logfile::init();
+    if let Some(format) = args.dump_menu {
+        println!("{}", menu_doc::render(&App::new(), format));
+        return Ok(ExitCode::SUCCESS);
     }
+    let safe = args.safe || config::Config::load().is_ok_and(|config| config.ui.safe_mode);
+    if let Some(command) = args.headless() {
+        // Flashing must not race a TUI writing the same drive. Snapshots are left unlocked: the
+        // pre-upgrade hook runs them while the TUI is upgrading packages.
+        let _lock = match command.is_destructive().then(lock::acquire).transpose() {
+            Ok(lock) => lock,
+            Err(e) => {
+                eprintln!("{}", e);
+                return Ok(ExitCode::FAILURE);
+            }
+        };
+        let ok = run_headless(command, args.yes, safe, args.json).await;
+        return Ok(if ok { ExitCode::SUCCESS } else { ExitCode::FAILURE });
+    }
+    // Refused before anything else, so a second instance changes nothing.
+    let _lock = lock::acquire()?;
+    // Installed from inside the TUI, once it is up.
+    let missing = actions::missing_dependencies().await?;
+    // Validate the plan before taking over the terminal, so mistakes are reported plainly.
+    let plan = match &args.plan {
+        Some(_) if safe => anyhow::bail!("--plan wipes and partitions a disk, which safe mode does not allow."),
+        Some(path) => {
+            let plan = install::InstallPlan::load(path)?;
+            plan.validate().await?;
+            Some(plan)
+        }
+        None => None,
+    };
+    config::Config::load()?.ui.theme.set();
+    let (colors, color_warnings) = config::ThemeColors::load();
+    colors.set();
+    event::handle_signals()?;
+    install_panic_hook();
+    // Behind the alternate screen a password prompt can be neither seen nor answered.
+    actions::never_ask_passwords();
     let mut terminal = init_terminal()?;
     let mut app = App::new();
-    event::run_app(&mut terminal, &mut app).await?;
-    restore_terminal(&mut terminal)?;
-    Ok(())
+    app.safe_mode = safe;
+    app.drift = actions::snapshot_drift();
+    if let Some(view) = args.view.or_else(|| args.resume.then(app::AppView::last).flatten()) {
+        app.current_view = view;
+    }
+    if let Some(name) = &args.run {
+        app.pending_action = app.choose_registered(name);
+    }
+    if let Some(plan) = &plan {
+        app.current_view = app::AppView::ManualInstaller;
+        app.pending_action = Some(actions::plan_action(plan, !args.yes));
+        app.activity = "Run Install Plan".to_string();
+    } else if config::Config::is_first_run() {
+        app.pending_action = Some(actions::setup_wizard());
+        app.activity = "Setup Wizard".to_string();
+    } else {
+        let leftovers = actions::interrupted_leftovers().await;
+        if !leftovers.is_empty() {
+            app.pending_action = Some(actions::clean_up_leftovers(leftovers));
+            app.activity = "Clean Up".to_string();
+        }
+    }
+    if !color_warnings.is_empty() && app.pending_action.is_none() {
+        app.pending_action = Some(actions::Action::Message { title: "Theme Colours".to_string(), text: color_warnings.join("\n") });
+    }
+    if !missing.is_empty() {
+        app.pending_action = Some(actions::install_dependencies(missing, app.pending_action.take(), args.yes));
+        app.activity = "Install Dependencies".to_string();
+    }
+    // Restored whichever way the loop ended, errors included.
+    let result = event::run_app(&mut terminal, &mut app, &mut event::CrosstermEvents).await;
+    restore_terminal()?;
+    let _ = app.current_view.save_last();
+    result?;
+    Ok(ExitCode::SUCCESS)
 }
 
+/// Called once: `event::run_app` borrows this terminal for the whole session, and actions only
+/// report progress to it, so nothing enters the alternate screen a second time.
 fn init_terminal() -> io::Result<Terminal<CrosstermBackend<Stdout>>> {
     stdout().execute(EnterAlternateScreen)?;
     enable_raw_mode()?;
@@ -37,9 +261,85 @@ fn init_terminal() -> io::Result<Terminal<CrosstermBackend<Stdout>>> {
     Ok(terminal)
 }
 
-fn restore_terminal(terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> io::Result<()> {
+fn restore_terminal() -> io::Result<()> {
     disable_raw_mode()?;
-    terminal.backend_mut().execute(LeaveAlternateScreen)?;
-    terminal.show_cursor()?;
+    stdout().execute(LeaveAlternateScreen)?.execute(Show)?;
     Ok(())
 }
+
+/// Restores the terminal before a panic on the main thread is reported, so a crash leaves the
+/// shell usable. An action's task panicking is caught and shown in its popup instead, with the
+/// TUI still running, so those pass silently.
+fn install_panic_hook() {
+    let report = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if std::thread::current().name() == Some("main") {
+            let _ = restore_terminal();
+            report(info);
+        }
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Cli {
+        Cli::try_parse_from(std::iter::once("arch-suite").chain(args.iter().copied())).unwrap()
+    }
+
+    #[test]
+    fn no_subcommand_starts_the_tui() {
+        assert_eq!(parse(&[]).headless(), None);
+        assert_eq!(parse(&["--plan", "plan.toml", "--yes"]).headless(), None);
+        assert_eq!(parse(&["--dump-menu", "json"]).dump_menu, Some(menu_doc::MenuFormat::Json));
+    }
+
+    #[test]
+    fn the_tui_opens_on_a_named_view_and_rejects_unknown_ones() {
+        assert_eq!(parse(&["--view", "manual-installer"]).view, Some(app::AppView::ManualInstaller));
+        assert_eq!(parse(&["--goto", "utilities"]).view, Some(app::AppView::Utilities));
+        let unknown = Cli::try_parse_from(["arch-suite", "--view", "installer"]).unwrap_err();
+        assert_eq!(unknown.kind(), clap::error::ErrorKind::InvalidValue);
+        assert!(unknown.to_string().contains("possible values: main-menu, help-manual, replicator, cloner, utilities, manual-installer, logs"));
+        assert!(Cli::try_parse_from(["arch-suite", "--view", "logs", "--plan", "plan.toml"]).is_err());
+    }
+
+    #[test]
+    fn run_takes_a_registered_action_and_starts_it_from_its_menu() {
+        assert_eq!(parse(&["--run", "create-snapshot"]).run.as_deref(), Some("create-snapshot"));
+        assert_eq!(Cli::try_parse_from(["arch-suite", "--run", "make-coffee"]).unwrap_err().kind(), clap::error::ErrorKind::InvalidValue);
+        assert!(Cli::try_parse_from(["arch-suite", "--run", "doctor", "--view", "logs"]).is_err());
+
+        let mut app = app::App::new();
+        assert!(app.choose_registered("generate-fstab").is_some());
+        assert_eq!(app.current_view, app::AppView::ManualInstaller);
+        assert_eq!(app.manual_install_menu.state.selected().map(|i| app.manual_install_menu.items[i].id), Some("generate-fstab"));
+        assert!(app.choose_registered("make-coffee").is_none());
+    }
+
+    #[test]
+    fn snapshot_create_and_the_legacy_flag_agree() {
+        let create = Command::Snapshot { action: SnapshotCommand::Create { out: None } };
+        assert_eq!(parse(&["snapshot", "create"]).headless(), Some(create.clone()));
+        assert_eq!(parse(&["--snapshot"]).headless(), Some(create));
+        let cli = parse(&["--json", "snapshot", "create", "--out", "/tmp/s.tar.gz"]);
+        assert!(cli.json);
+        assert_eq!(cli.headless(), Some(Command::Snapshot { action: SnapshotCommand::Create { out: Some(PathBuf::from("/tmp/s.tar.gz")) } }));
+    }
+
+    #[test]
+    fn flash_needs_its_arguments_and_is_destructive() {
+        assert!(Cli::try_parse_from(["arch-suite", "flash", "--iso", "a.iso"]).is_err());
+        let cli = parse(&["flash", "--iso", "a.iso", "--device", "/dev/sdz", "--yes"]);
+        assert!(cli.yes);
+        assert!(cli.headless().is_some_and(|c| c.is_destructive()));
+    }
+
+    #[tokio::test]
+    async fn destructive_commands_are_refused_without_yes_or_in_safe_mode() {
+        let flash = Command::Flash { iso: PathBuf::from("/nonexistent.iso"), device: "/dev/nonexistent".to_string(), no_verify: true, any_image: false, allow_unsigned: false };
+        assert!(!run_headless(flash.clone(), false, false, true).await);
+        assert!(!run_headless(flash, true, true, true).await);
+    }
+}
@@ -0,0 +1,67 @@
+// ===================================================================
+// Help Manual Module
+// ===================================================================
+// The help manual's text, written from the menus so it describes
+// exactly what is on screen, and the case-insensitive search over its
+// wrapped rows ('/' to search, 'n'/'N' for the next and previous match).
+
+use crate::app::{App, AppView};
+use std::ops::Range;
+
+const INTRO: &str = "Arch System Suite replicates, clones and installs Arch Linux systems.
+
+Move with j/k or the arrow keys, open an item with Enter or its hotkey, and press '?' on any item for its help. Tab and Shift+Tab switch between the Replicator, Cloner, Utilities, Installer and Logs tabs. Esc or 'q' goes back.
+
+Actions that change the system ask first and show their progress in a popup; every result stays under the Logs tab for the rest of the session.";
+
+/// The manual: the introduction, then every menu with each item's help.
+pub fn text(app: &App<'_>) -> String {
+    let mut text = format!("Arch System Suite {}\n\n{}\n", env!("CARGO_PKG_VERSION"), INTRO);
+    for view in AppView::MENUS {
+        let Some(menu) = app.menu(view) else { continue };
+        text.push_str(&format!("\n{}\n{}\n", view.title(), "─".repeat(view.title().chars().count())));
+        for item in &menu.items {
+            text.push_str(&format!("\n{} {}\n    {}\n", item.icon, item.text, item.help));
+        }
+    }
+    text
+}
+
+/// The byte ranges of `row` that match `query`, ignoring case. Rows whose lowercase form has a
+/// different length cannot be mapped back and are matched exactly.
+pub fn match_ranges(row: &str, query: &str) -> Vec<Range<usize>> {
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let (lower_row, lower_query) = (row.to_lowercase(), query.to_lowercase());
+    let (haystack, needle) = if lower_row.len() == row.len() { (lower_row.as_str(), lower_query.as_str()) } else { (row, query) };
+    haystack.match_indices(needle).map(|(start, found)| start..start + found.len()).collect()
+}
+
+/// The first row matching `query` from `from` on in the direction given, wrapping around the ends.
+pub fn find(rows: &[String], query: &str, from: usize, forward: bool) -> Option<usize> {
+    let len = rows.len();
+    let from = from % len.max(1);
+    (0..len)
+        .map(|step| if forward { (from + step) % len } else { (from + len - step) % len })
+        .find(|&row| !match_ranges(&rows[row], query).is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn searches_ignore_case_and_wrap_around() {
+        let rows: Vec<String> = ["Create Snapshot", "deploy", "Quick Backup", "snapshot compare"].map(String::from).to_vec();
+        assert_eq!(match_ranges("Create Snapshot of /etc, snapshot", "SNAPSHOT"), [7..15, 25..33]);
+        assert_eq!(find(&rows, "snapshot", 1, true), Some(3));
+        assert_eq!(find(&rows, "snapshot", 4, true), Some(0));
+        assert_eq!(find(&rows, "snapshot", 2, false), Some(0));
+        assert_eq!(find(&rows, "snapshot", rows.len() - 1, false), Some(3));
+        assert_eq!(find(&rows, "restore", 0, true), None);
+
+        let app = App::new();
+        assert!(text(&app).contains("Quick Backup"));
+    }
+}
@@ -0,0 +1,78 @@
+// ===================================================================
+// Menu Documentation Module
+// ===================================================================
+// Prints every menu, with each item's text and help, as Markdown or
+// JSON (`--dump-menu`), so runbooks and the help manual can be kept
+// in step with the UI. Only the menus are read; no terminal is used.
+
+use crate::actions::Action;
+use crate::app::{App, AppView, MenuItem};
+use clap::ValueEnum;
+use serde_json::{json, Value};
+
+#[derive(Clone, Copy, PartialEq, Debug, ValueEnum)]
+pub enum MenuFormat {
+    Markdown,
+    Json,
+}
+
+/// The menu `item` leads to, if it opens one.
+fn opens(item: &MenuItem<'_>) -> Option<&'static str> {
+    match item.action() {
+        Action::SetView(view) => Some(view.title()),
+        _ => None,
+    }
+}
+
+fn menus<'a, 'b>(app: &'b App<'a>) -> impl Iterator<Item = (AppView, &'b [MenuItem<'a>])> {
+    AppView::MENUS.into_iter().filter_map(|view| Some((view, app.menu(view)?.items.as_slice())))
+}
+
+pub fn markdown(app: &App<'_>) -> String {
+    let mut doc = format!("# Arch System Suite {} menus\n", env!("CARGO_PKG_VERSION"));
+    for (view, items) in menus(app) {
+        doc.push_str(&format!("\n## {}\n\n", view.title()));
+        for item in items {
+            let opens = opens(item).map_or(String::new(), |title| format!(" Opens the {}.", title));
+            doc.push_str(&format!("- **{} {}**: {}{}\n", item.icon, item.text, item.help, opens));
+        }
+    }
+    doc
+}
+
+pub fn json(app: &App<'_>) -> Value {
+    let views: Vec<Value> = menus(app)
+        .map(|(view, items)| {
+            let items: Vec<Value> = items.iter().map(|item| json!({ "id": item.id, "icon": item.icon, "hotkey": item.hotkey, "text": item.text, "help": item.help, "opens": opens(item) })).collect();
+            json!({ "view": view.title(), "items": items })
+        })
+        .collect();
+    json!({ "version": env!("CARGO_PKG_VERSION"), "views": views })
+}
+
+pub fn render(app: &App<'_>, format: MenuFormat) -> String {
+    match format {
+        MenuFormat::Markdown => markdown(app),
+        MenuFormat::Json => serde_json::to_string_pretty(&json(app)).unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_menu_item_is_documented() {
+        let app = App::new();
+        let doc = markdown(&app);
+        assert!(doc.contains("\n## Utilities Menu\n\n- **[C] Doctor (Self-Check)**: Checks the environment"));
+        assert!(doc.contains("- **[R] Replicator (Recommended)**: ") && doc.contains("Opens the Replicator Menu."));
+
+        let tree = json(&app);
+        let views = tree["views"].as_array().unwrap();
+        assert_eq!(views.len(), AppView::MENUS.len());
+        let total: usize = views.iter().map(|v| v["items"].as_array().unwrap().len()).sum();
+        assert_eq!(total, menus(&app).map(|(_, items)| items.len()).sum::<usize>());
+        assert_eq!(views[0]["items"][0]["hotkey"], "R");
+    }
+}
@@ -0,0 +1,64 @@
+// ===================================================================
+// Mirrors Module
+// ===================================================================
+// The countries reflector knows mirrors in, read from its
+// `--list-countries` table, and the reflector command line that ranks
+// the mirrors of the ones picked into a new pacman mirrorlist.
+
+pub const MIRRORLIST: &str = "/etc/pacman.d/mirrorlist";
+/// How many of the most recently synced mirrors reflector ranks.
+const LATEST: &str = "20";
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Country {
+    pub name: String,
+    /// The ISO 3166 code reflector's `--country` takes, e.g. `DE`.
+    pub code: String,
+    pub mirrors: u32,
+}
+
+impl Country {
+    /// The row shown in the country list, e.g. "Germany (DE, 52 mirrors)".
+    pub fn label(&self) -> String {
+        format!("{} ({}, {} mirrors)", self.name, self.code, self.mirrors)
+    }
+}
+
+/// The countries in `reflector --list-countries` output: a header, a dashed rule, then one
+/// "name code count" row each, where the name may contain spaces.
+pub fn parse_countries(output: &str) -> Vec<Country> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace().rev();
+            let mirrors = words.next()?.parse().ok()?;
+            let code = words.next().filter(|code| code.len() == 2 && code.chars().all(|c| c.is_ascii_uppercase()))?;
+            let name = words.rev().collect::<Vec<_>>().join(" ");
+            (!name.is_empty()).then(|| Country { name, code: code.to_string(), mirrors })
+        })
+        .collect()
+}
+
+/// The reflector arguments that print the `LATEST` HTTPS mirrors in `codes`, fastest first.
+pub fn rank_args(codes: &[String]) -> Vec<String> {
+    ["--country", &codes.join(","), "--protocol", "https", "--latest", LATEST, "--sort", "rate"].map(String::from).to_vec()
+}
+
+/// The servers in a mirrorlist, in order.
+pub fn servers(mirrorlist: &str) -> Vec<&str> {
+    mirrorlist.lines().filter_map(|line| line.trim().strip_prefix("Server")?.trim_start().strip_prefix('=')).map(str::trim).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn countries_are_read_from_reflectors_table() {
+        let output = "Country                Code Count\n---------------------- ---- -----\nGermany                DE     52\nUnited States          US    160\nWorldwide              --      7\n";
+        let countries = parse_countries(output);
+        assert_eq!(countries.iter().map(Country::label).collect::<Vec<_>>(), ["Germany (DE, 52 mirrors)", "United States (US, 160 mirrors)"]);
+        assert_eq!(rank_args(&["DE".to_string(), "US".to_string()])[..2], ["--country", "DE,US"]);
+        assert_eq!(servers("## Germany\nServer = https://a.de/$repo/os/$arch\n#Server = https://off\n"), ["https://a.de/$repo/os/$arch"]);
+    }
+}
@@ -0,0 +1,401 @@
+// ===================================================================
+// Package Database Module
+// ===================================================================
+// Reads installed-package metadata from `pacman -Qi`, used to check
+// what still depends on a package before anything is removed, finds
+// the installed kernels, and previews and tracks package installs.
+// Package recipes are plain package lists in sections:
+//
+//   [native]          explicitly installed, from the repositories
+//   [foreign]         explicitly installed, from the AUR or elsewhere
+//   [native deps]     dependencies, recorded only when asked for
+//   [foreign deps]
+//
+// Lines before any section are native, so a `pacman -Qqe` list is a
+// recipe too. Signature errors from an outdated keyring are recognised
+// in pacman's output and log, so the keyring repair can be suggested.
+
+use crate::disk::human_size;
+use std::path::Path;
+
+/// The reverse dependencies pacman records for an installed package.
+#[derive(Clone, Debug, Default)]
+pub struct PackageInfo {
+    pub name: String,
+    pub required_by: Vec<String>,
+    pub optional_for: Vec<String>,
+}
+
+/// Parses `pacman -Qi` output for one or more packages. Long values wrap onto
+/// indented continuation lines, which are folded back into their field.
+pub fn parse_qi(text: &str) -> Vec<PackageInfo> {
+    let mut packages = Vec::new();
+    let mut current = PackageInfo::default();
+    let mut field = String::new();
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            if !current.name.is_empty() {
+                packages.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        let value = match line.split_once(" : ") {
+            Some((key, value)) if !line.starts_with(' ') => {
+                field = key.trim().to_string();
+                value
+            }
+            _ => line,
+        };
+        let names = value.split_whitespace().filter(|v| *v != "None").map(String::from);
+        match field.as_str() {
+            "Name" => current.name = value.trim().to_string(),
+            "Required By" => current.required_by.extend(names),
+            "Optional For" => current.optional_for.extend(names),
+            _ => {}
+        }
+    }
+    if !current.name.is_empty() {
+        packages.push(current);
+    }
+    packages
+}
+
+/// For each of `targets` that something outside `targets` still needs, the package
+/// and a description of its dependents.
+pub fn dependents(infos: &[PackageInfo], targets: &[String]) -> Vec<(String, String)> {
+    let outside = |names: &[String]| names.iter().filter(|n| !targets.contains(n)).cloned().collect::<Vec<_>>();
+    infos
+        .iter()
+        .filter_map(|info| {
+            let (required, optional) = (outside(&info.required_by), outside(&info.optional_for));
+            let mut parts = Vec::new();
+            if !required.is_empty() {
+                parts.push(format!("required by {}", required.join(", ")));
+            }
+            if !optional.is_empty() {
+                parts.push(format!("optional for {}", optional.join(", ")));
+            }
+            (!parts.is_empty()).then(|| (info.name.clone(), parts.join("; ")))
+        })
+        .collect()
+}
+
+/// The kernels offered for installation; any other installed kernel is listed as well.
+pub const KERNELS: [&str; 4] = ["linux", "linux-lts", "linux-zen", "linux-hardened"];
+
+/// An installed kernel: the package and the module directory (`uname -r`) it installed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Kernel {
+    pub name: String,
+    pub release: String,
+    pub running: bool,
+}
+
+/// The kernels under `modules` (/usr/lib/modules): every release directory with a `vmlinuz`
+/// names its package in `pkgbase`. `running` is `uname -r`.
+pub fn installed_kernels(modules: &Path, running: &str) -> Vec<Kernel> {
+    let mut kernels: Vec<Kernel> = std::fs::read_dir(modules)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let dir = entry.ok()?.path();
+            if !dir.join("vmlinuz").is_file() {
+                return None;
+            }
+            let name = std::fs::read_to_string(dir.join("pkgbase")).ok()?.trim().to_string();
+            let release = dir.file_name()?.to_string_lossy().to_string();
+            Some(Kernel { running: release == running, name, release })
+        })
+        .collect();
+    kernels.sort_by(|a, b| a.name.cmp(&b.name));
+    kernels
+}
+
+/// The kernels to install and to remove so that exactly `chosen` are installed.
+pub fn kernel_changes(installed: &[String], chosen: &[String]) -> (Vec<String>, Vec<String>) {
+    let install = chosen.iter().filter(|k| !installed.contains(k)).cloned().collect();
+    let remove = installed.iter().filter(|k| !chosen.contains(k)).cloned().collect();
+    (install, remove)
+}
+
+/// The `--print-format` that makes `pacman -Sp` list what a transaction would download.
+pub const PRINT_FORMAT: &str = "%n %v %s";
+
+/// A package a transaction would install or upgrade, with its download size in bytes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PendingPackage {
+    pub name: String,
+    pub version: String,
+    pub size: u64,
+}
+
+/// Parses `pacman -Sp --print-format PRINT_FORMAT` output; other lines (warnings) are skipped.
+pub fn parse_pending(text: &str) -> Vec<PendingPackage> {
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (name, version, size) = (fields.next()?, fields.next()?, fields.next()?.parse().ok()?);
+            Some(PendingPackage { name: name.to_string(), version: version.to_string(), size })
+        })
+        .collect()
+}
+
+/// What `pending` downloads, for a confirmation: the total and the largest packages. Files already
+/// in a package cache are not fetched again, so the total is an upper bound.
+pub fn download_summary(pending: &[PendingPackage]) -> String {
+    if pending.is_empty() {
+        return "📥 Nothing to download: every package is installed already.".to_string();
+    }
+    let mut largest: Vec<&PendingPackage> = pending.iter().collect();
+    largest.sort_by_key(|p| std::cmp::Reverse(p.size));
+    let largest: Vec<String> = largest.iter().take(3).map(|p| format!("{} {}", p.name, human_size(p.size))).collect();
+    format!(
+        "📥 Download: up to {} for {} packages (largest: {}), per the local package databases.",
+        human_size(pending.iter().map(|p| p.size).sum()),
+        pending.len(),
+        largest.join(", ")
+    )
+}
+
+/// Tracks a running pacman transaction from its output without a terminal (no progress bars):
+/// ` foo-1.0-1-x86_64.pkg.tar.zst downloading...` per download and `installing foo...` per package.
+/// Downloads fill the first `DOWNLOAD_SHARE` percent of the gauge by size, installs the rest by count.
+#[derive(Default)]
+pub struct TransactionProgress {
+    downloaded: u64,
+    installed: usize,
+}
+
+const DOWNLOAD_SHARE: u64 = 70;
+
+impl TransactionProgress {
+    /// The gauge position after `line`, if the line moved it.
+    pub fn feed(&mut self, line: &str, pending: &[PendingPackage]) -> Option<u8> {
+        let line = line.trim();
+        let total: u64 = pending.iter().map(|p| p.size).sum();
+        if line.contains("downloading") {
+            let package = pending.iter().find(|p| line.contains(&format!("{}-{}", p.name, p.version)))?;
+            self.downloaded += package.size;
+            return Some((self.downloaded * DOWNLOAD_SHARE / total.max(1)).min(DOWNLOAD_SHARE) as u8);
+        }
+        let name = ["installing ", "upgrading ", "reinstalling "].iter().find_map(|verb| line.strip_prefix(verb)?.strip_suffix("..."))?;
+        pending.iter().find(|p| p.name == name)?;
+        self.installed += 1;
+        Some((DOWNLOAD_SHARE + self.installed as u64 * (100 - DOWNLOAD_SHARE) / pending.len().max(1) as u64).min(100) as u8)
+    }
+}
+
+/// `conf` (a pacman.conf) with `ParallelDownloads = downloads` in its [options] section, in place of
+/// the setting or its commented-out default if there is one.
+pub fn with_parallel_downloads(conf: &str, downloads: u32) -> String {
+    let entry = format!("ParallelDownloads = {}", downloads);
+    let (mut lines, mut section, mut set) = (Vec::new(), String::new(), false);
+    for line in conf.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            if section == "[options]" && !set {
+                lines.push(entry.clone());
+                set = true;
+            }
+            section = trimmed.to_string();
+        } else if section == "[options]" && trimmed.trim_start_matches('#').trim_start().starts_with("ParallelDownloads") {
+            if !set {
+                lines.push(entry.clone());
+                set = true;
+            }
+            continue;
+        }
+        lines.push(line.to_string());
+    }
+    if !set {
+        if section != "[options]" {
+            lines.push("[options]".to_string());
+        }
+        lines.push(entry);
+    }
+    lines.join("\n") + "\n"
+}
+
+/// Why pacman failed, when its `stderr` says no mirror could be reached: a refresh that could not
+/// download the databases, or packages that could not be fetched.
+pub fn unreachable_mirrors(stderr: &str) -> Option<String> {
+    const SIGNS: [&str; 4] = ["failed to synchronize all databases", "failed to retrieve some files", "could not resolve host", "could not connect to server"];
+    let lower = stderr.to_lowercase();
+    SIGNS.iter().any(|sign| lower.contains(sign)).then(|| {
+        let line = stderr.lines().map(str::trim).find(|line| SIGNS.iter().any(|sign| line.to_lowercase().contains(sign))).unwrap_or_default();
+        format!("pacman could not reach the mirrors ({}).", line.trim_start_matches("error: "))
+    })
+}
+
+/// The AUR helpers foreign packages can be installed with, in order of preference.
+pub const AUR_HELPERS: [&str; 2] = ["paru", "yay"];
+
+pub fn aur_helper(exists: impl Fn(&str) -> bool) -> Option<&'static str> {
+    AUR_HELPERS.into_iter().find(|helper| exists(helper))
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Recipe {
+    pub native: Vec<String>,
+    pub foreign: Vec<String>,
+    pub native_deps: Vec<String>,
+    pub foreign_deps: Vec<String>,
+}
+
+const RECIPE_SECTIONS: [&str; 4] = ["native", "foreign", "native deps", "foreign deps"];
+
+impl Recipe {
+    fn sections(&self) -> [&Vec<String>; 4] {
+        [&self.native, &self.foreign, &self.native_deps, &self.foreign_deps]
+    }
+
+    pub fn parse(text: &str) -> Recipe {
+        let mut recipe = Recipe::default();
+        let mut section = 0;
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+                // Unknown sections are read as native rather than lost.
+                section = RECIPE_SECTIONS.iter().position(|known| *known == name.trim()).unwrap_or(0);
+                continue;
+            }
+            let list = match section { 1 => &mut recipe.foreign, 2 => &mut recipe.native_deps, 3 => &mut recipe.foreign_deps, _ => &mut recipe.native };
+            list.push(line.to_string());
+        }
+        recipe
+    }
+
+    /// The recipe file, under a comment naming where it came from; empty sections are left out.
+    pub fn render(&self, header: &str) -> String {
+        let mut text = format!("# {}\n", header);
+        for (name, packages) in RECIPE_SECTIONS.iter().zip(self.sections()) {
+            if !packages.is_empty() {
+                text.push_str(&format!("\n[{}]\n{}\n", name, packages.join("\n")));
+            }
+        }
+        text
+    }
+
+    pub fn len(&self) -> usize {
+        self.sections().iter().map(|packages| packages.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// What pacman says when the keyring cannot vouch for a package's signature, lowercased.
+const KEYRING_ERRORS: [&str; 6] = [
+    "invalid or corrupted package (pgp signature)",
+    "signature from",
+    "could not be looked up remotely",
+    "required key missing from keyring",
+    "keyring is not writable",
+    "public key not found",
+];
+
+pub fn is_keyring_error(line: &str) -> bool {
+    let line = line.to_lowercase();
+    KEYRING_ERRORS.iter().any(|error| line.contains(error))
+}
+
+/// The keyring errors in pacman.log since archlinux-keyring was last installed or upgraded,
+/// newest first and each once, at most `limit` of them.
+pub fn keyring_errors(log: &str, limit: usize) -> Vec<String> {
+    let lines: Vec<&str> = log.lines().collect();
+    let since = lines.iter().rposition(|line| line.contains("] installed archlinux-keyring ") || line.contains("] upgraded archlinux-keyring ")).map_or(0, |i| i + 1);
+    let mut errors: Vec<String> = Vec::new();
+    for line in lines[since..].iter().rev().filter(|line| is_keyring_error(line)) {
+        // Drop the timestamp and source, e.g. `[2024-05-01T10:00:00+0200] [ALPM] `.
+        let message = line.rsplit_once("] ").map_or(*line, |(_, message)| message).to_string();
+        if !errors.contains(&message) && errors.len() < limit {
+            errors.push(message);
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernels_are_found_by_pkgbase_and_diffed_against_the_choice() {
+        let modules = std::env::temp_dir().join(format!("arch-suite-modules-{}", std::process::id()));
+        for (release, pkgbase, vmlinuz) in [("6.9.1-arch1-1", "linux", true), ("6.6.30-1-lts", "linux-lts", true), ("6.8.0-extramodules", "", false)] {
+            let dir = modules.join(release);
+            std::fs::create_dir_all(&dir).unwrap();
+            if vmlinuz {
+                std::fs::write(dir.join("vmlinuz"), "").unwrap();
+                std::fs::write(dir.join("pkgbase"), format!("{}\n", pkgbase)).unwrap();
+            }
+        }
+        let kernels = installed_kernels(&modules, "6.6.30-1-lts");
+        std::fs::remove_dir_all(&modules).unwrap();
+        assert_eq!(kernels.iter().map(|k| (k.name.as_str(), k.running)).collect::<Vec<_>>(), [("linux", false), ("linux-lts", true)]);
+
+        let list = |items: &[&str]| items.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let (install, remove) = kernel_changes(&list(&["linux", "linux-lts"]), &list(&["linux-lts", "linux-zen"]));
+        assert_eq!((install, remove), (list(&["linux-zen"]), list(&["linux"])));
+    }
+
+    #[test]
+    fn parallel_downloads_replace_the_options_setting() {
+        let conf = "[options]\nHoldPkg = pacman glibc\n#ParallelDownloads = 5\n\n[core]\nInclude = /etc/pacman.d/mirrorlist\n";
+        assert_eq!(with_parallel_downloads(conf, 10), conf.replace("#ParallelDownloads = 5", "ParallelDownloads = 10"));
+        let without = "[options]\nHoldPkg = pacman glibc\n[core]\n";
+        assert_eq!(with_parallel_downloads(without, 3), "[options]\nHoldPkg = pacman glibc\nParallelDownloads = 3\n[core]\n");
+        assert_eq!(with_parallel_downloads("[core]\nServer = x\n", 3), "[core]\nServer = x\n[options]\nParallelDownloads = 3\n");
+    }
+
+    #[test]
+    fn transaction_output_drives_the_gauge() {
+        let pending = parse_pending("warning: gum-0.14.0-1 is up to date -- skipping\ngum 0.14.0-1 3000000\nrsync 3.3.0-1 1000000\n");
+        assert_eq!(pending, [
+            PendingPackage { name: "gum".to_string(), version: "0.14.0-1".to_string(), size: 3000000 },
+            PendingPackage { name: "rsync".to_string(), version: "3.3.0-1".to_string(), size: 1000000 },
+        ]);
+        let mut progress = TransactionProgress::default();
+        let steps: Vec<Option<u8>> = [":: Retrieving packages...", " gum-0.14.0-1-x86_64.pkg.tar.zst downloading...", " rsync-3.3.0-1-x86_64.pkg.tar.zst downloading...", "installing gum...", "upgrading rsync...", "Optional dependencies for rsync"]
+            .iter()
+            .map(|line| progress.feed(line, &pending))
+            .collect();
+        assert_eq!(steps, [None, Some(52), Some(70), Some(85), Some(100), None]);
+        assert_eq!(download_summary(&pending), "📥 Download: up to 3.8M for 2 packages (largest: gum 2.9M, rsync 976.6K), per the local package databases.");
+    }
+
+    #[test]
+    fn recipes_round_trip_and_plain_lists_are_native() {
+        let recipe = Recipe { native: vec!["vim".to_string(), "git".to_string()], foreign: vec!["paru-bin".to_string()], native_deps: vec!["zlib".to_string()], foreign_deps: Vec::new() };
+        let text = recipe.render("arch-suite package recipe of box");
+        assert!(text.starts_with("# arch-suite package recipe of box\n\n[native]\nvim\ngit\n") && !text.contains("[foreign deps]"));
+        assert_eq!(Recipe::parse(&text), recipe);
+        assert_eq!(Recipe::parse("vim\n# comment\n\ngit\n").native, ["vim", "git"]);
+        assert_eq!(recipe.len(), 4);
+        assert_eq!(aur_helper(|tool| tool == "yay"), Some("yay"));
+    }
+
+    #[test]
+    fn unreachable_mirrors_are_told_apart_from_other_pacman_failures() {
+        let offline = "error: failed retrieving file 'core.db' from geo.mirror.pkgbuild.com : Could not resolve host: geo.mirror.pkgbuild.com\nerror: failed to synchronize all databases (download library error)\n";
+        assert_eq!(unreachable_mirrors(offline).as_deref(), Some("pacman could not reach the mirrors (failed retrieving file 'core.db' from geo.mirror.pkgbuild.com : Could not resolve host: geo.mirror.pkgbuild.com)."));
+        assert_eq!(unreachable_mirrors("error: target not found: yay\n"), None);
+    }
+
+    #[test]
+    fn keyring_errors_since_the_last_keyring_upgrade_are_found() {
+        let log = "[2024-04-01T10:00:00+0200] [ALPM] error: gum: signature from \"Old Key\" is unknown trust
+[2024-04-02T10:00:00+0200] [ALPM] upgraded archlinux-keyring (20240313-1 -> 20240401-1)
+[2024-05-01T10:00:00+0200] [PACMAN] Running 'pacman -S rsync'
+[2024-05-01T10:00:01+0200] [ALPM] error: rsync: signature from \"Packager <p@archlinux.org>\" is marginal trust
+[2024-05-01T10:00:02+0200] [ALPM] error: rsync: signature from \"Packager <p@archlinux.org>\" is marginal trust
+[2024-05-01T10:00:03+0200] [ALPM] error: failed to commit transaction (invalid or corrupted package (PGP signature))
+";
+        assert_eq!(keyring_errors(log, 5), [
+            "error: failed to commit transaction (invalid or corrupted package (PGP signature))",
+            "error: rsync: signature from \"Packager <p@archlinux.org>\" is marginal trust",
+        ]);
+        assert_eq!(keyring_errors(log, 1).len(), 1);
+        assert!(!is_keyring_error("error: target not found: foo"));
+    }
+}
@@ -0,0 +1,227 @@
+// ===================================================================
+// Paths Module
+// ===================================================================
+// Resolves the user the suite works for and the directories it keeps
+// its data in. Under sudo the environment (HOME, XDG_*) describes root,
+// so everything is derived from the invoking user instead.
+//
+//   config   $XDG_CONFIG_HOME/arch-suite   config.toml, theme.toml
+//   data     $XDG_DATA_HOME/arch-suite     snapshots (the default work directory)
+//   state    $XDG_STATE_HOME/arch-suite    install-state.json, arch-suite.<date>.log, saved logs
+//   cache    $XDG_CACHE_HOME/arch-suite    scratch space for comparisons and audits
+
+use crate::config::Config;
+use crate::error::ActionError;
+use anyhow::{anyhow, Context, Result};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+/// Set from `--work-dir`; takes precedence over the config file.
+static WORK_DIR_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+/// The user does not change while the suite runs, so `whoami` and `getent` run once.
+static REAL_USER: OnceLock<RealUser> = OnceLock::new();
+
+/// The user the suite acts for and their home directory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RealUser {
+    pub name: String,
+    pub home: PathBuf,
+    /// Whether the suite runs elevated on this user's behalf (via sudo).
+    pub elevated: bool,
+}
+
+/// `$SUDO_USER` when running under sudo, otherwise the current user, with the home directory from
+/// the passwd database.
+pub fn real_user() -> Result<RealUser> {
+    if let Some(user) = REAL_USER.get() {
+        return Ok(user.clone());
+    }
+    let user = resolve_user(std::env::var("SUDO_USER").ok(), || command_output(&mut Command::new("whoami")), |name| command_output(Command::new("getent").args(["passwd", name])))?;
+    Ok(REAL_USER.get_or_init(|| user).clone())
+}
+
+/// `real_user` with its lookups injected: `whoami` is only consulted when `sudo_user` does not name
+/// an unprivileged user, and `getent` returns the user's passwd entry.
+fn resolve_user(sudo_user: Option<String>, whoami: impl FnOnce() -> Result<String>, getent: impl FnOnce(&str) -> Result<String>) -> Result<RealUser> {
+    let sudo_user = sudo_user.filter(|u| !u.is_empty() && u != "root");
+    let elevated = sudo_user.is_some();
+    let name = match sudo_user {
+        Some(name) => name,
+        None => whoami()?,
+    };
+    let passwd = getent(&name)?;
+    // name:password:uid:gid:gecos:home:shell
+    let home = passwd.split(':').nth(5).filter(|h| !h.is_empty()).ok_or_else(|| anyhow!("No home directory for '{}' in the passwd database", name))?;
+    Ok(RealUser { name, home: PathBuf::from(home), elevated })
+}
+
+fn command_output(cmd: &mut Command) -> Result<String> {
+    let output = cmd.output().with_context(|| format!("Failed to run {:?}", cmd.get_program()))?;
+    if !output.status.success() {
+        return Err(anyhow!("{:?} failed: {}", cmd.get_program(), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The XDG base directories the suite keeps its files in.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BaseDir {
+    Config,
+    Data,
+    State,
+    Cache,
+}
+
+impl BaseDir {
+    fn var(self) -> &'static str {
+        match self {
+            BaseDir::Config => "XDG_CONFIG_HOME",
+            BaseDir::Data => "XDG_DATA_HOME",
+            BaseDir::State => "XDG_STATE_HOME",
+            BaseDir::Cache => "XDG_CACHE_HOME",
+        }
+    }
+
+    /// The spec's default, relative to the home directory.
+    fn fallback(self) -> &'static str {
+        match self {
+            BaseDir::Config => ".config",
+            BaseDir::Data => ".local/share",
+            BaseDir::State => ".local/state",
+            BaseDir::Cache => ".cache",
+        }
+    }
+}
+
+/// The real user's `arch-suite` directory of kind `base`: `$XDG_*_HOME/arch-suite`, or the spec's
+/// default under their home.
+pub fn base_dir(base: BaseDir) -> Result<PathBuf> {
+    let user = real_user()?;
+    Ok(resolve_base_dir(base, std::env::var_os(base.var()), &user))
+}
+
+/// `base_dir` with the variable's value injected. Under sudo it is root's, so it is ignored; so is
+/// a relative path, which the spec says to treat as unset.
+fn resolve_base_dir(base: BaseDir, value: Option<OsString>, user: &RealUser) -> PathBuf {
+    let set = value.map(PathBuf::from).filter(|dir| !user.elevated && dir.is_absolute());
+    set.unwrap_or_else(|| user.home.join(base.fallback())).join("arch-suite")
+}
+
+pub fn set_work_dir(dir: PathBuf) {
+    let _ = WORK_DIR_OVERRIDE.set(dir);
+}
+
+/// Where snapshots and fleet archives are kept: `--work-dir`, then `work_dir` under [snapshot] in the
+/// config file, then `$XDG_DATA_HOME/arch-suite` of the real user.
+pub fn work_dir() -> Result<PathBuf> {
+    let user = real_user()?;
+    let configured = WORK_DIR_OVERRIDE.get().cloned().or(Config::load()?.snapshot.work_dir);
+    match configured {
+        Some(dir) => Ok(under_home(dir, &user)),
+        None => base_dir(BaseDir::Data),
+    }
+}
+
+/// The real user's runtime directory (`/run/user/<uid>`), so their runs with and without sudo
+/// share it; the temporary directory when there is none.
+pub fn runtime_dir() -> PathBuf {
+    let runtime = match std::env::var("SUDO_UID") {
+        Ok(uid) if !uid.is_empty() && uid != "0" => Some(PathBuf::from(format!("/run/user/{}", uid))),
+        _ => std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from),
+    };
+    runtime.filter(|dir| dir.is_dir()).unwrap_or_else(std::env::temp_dir)
+}
+
+/// Where quick backups are written: `backup_dir` under [snapshot], or the work directory.
+pub fn backup_dir() -> Result<PathBuf> {
+    match Config::load()?.snapshot.backup_dir {
+        Some(dir) => Ok(under_home(dir, &real_user()?)),
+        None => work_dir(),
+    }
+}
+
+/// `dir` with a leading `~` replaced by the user's home.
+pub fn under_home(dir: PathBuf, user: &RealUser) -> PathBuf {
+    match dir.strip_prefix("~") {
+        Ok(rest) => user.home.join(rest),
+        Err(_) => dir,
+    }
+}
+
+/// Creates `dir` if needed and checks a file can be written in it.
+pub fn ensure_writable(dir: &Path) -> Result<(), ActionError> {
+    let unwritable = |e: std::io::Error| match e.kind() {
+        std::io::ErrorKind::ReadOnlyFilesystem => ActionError::ReadOnly { dir: dir.to_path_buf() },
+        std::io::ErrorKind::StorageFull => ActionError::NoSpace { dir: dir.to_path_buf(), needed: 0, available: 0 },
+        _ => ActionError::PermissionDenied { what: format!("the work directory {} is not writable ({})", dir.display(), e) },
+    };
+    std::fs::create_dir_all(dir).map_err(unwritable)?;
+    let probe = dir.join(format!(".arch-suite-write-test-{}", std::process::id()));
+    std::fs::write(&probe, b"").map_err(unwritable)?;
+    let _ = std::fs::remove_file(probe);
+    Ok(())
+}
+
+/// Bytes free for unprivileged use on the filesystem `dir` is on, or will be created on.
+pub fn free_space(dir: &Path) -> Option<u64> {
+    let existing = dir.ancestors().find(|p| p.exists()).unwrap_or(Path::new("/"));
+    let output = Command::new("df").args(["-B1", "--output=avail"]).arg(existing).output().ok()?;
+    String::from_utf8_lossy(&output.stdout).lines().nth(1)?.trim().parse().ok()
+}
+
+/// `ensure_writable`, and room for about `needed` bytes. Run before a long action writes to `dir`,
+/// so a full or read-only disk is reported up front instead of by tar halfway through.
+pub fn precheck_work_dir(dir: &Path, needed: u64) -> Result<(), ActionError> {
+    ensure_writable(dir)?;
+    match free_space(dir) {
+        Some(available) if available < needed => Err(ActionError::NoSpace { dir: dir.to_path_buf(), needed, available }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn getent(name: &str) -> Result<String> {
+        Ok(match name {
+            "alice" => "alice:x:1000:1000:Alice:/srv/users/alice:/bin/bash".to_string(),
+            "root" => "root:x:0:0::/root:/bin/bash".to_string(),
+            _ => String::new(),
+        })
+    }
+
+    #[test]
+    fn sudo_user_wins_over_whoami() {
+        let user = resolve_user(Some("alice".to_string()), || panic!("whoami must not run under sudo"), getent).unwrap();
+        assert_eq!(user, RealUser { name: "alice".to_string(), home: PathBuf::from("/srv/users/alice"), elevated: true });
+    }
+
+    #[test]
+    fn without_sudo_user_whoami_is_used() {
+        let user = resolve_user(None, || Ok("alice".to_string()), getent).unwrap();
+        assert!(!user.elevated);
+        assert_eq!(user.home, PathBuf::from("/srv/users/alice"));
+        // `sudo -i` from a root shell, or an empty variable, is not elevation on someone's behalf.
+        let user = resolve_user(Some("root".to_string()), || Ok("root".to_string()), getent).unwrap();
+        assert_eq!((user.name.as_str(), user.elevated), ("root", false));
+        assert!(resolve_user(Some(String::new()), || Ok("alice".to_string()), getent).is_ok_and(|u| u.name == "alice"));
+    }
+
+    #[test]
+    fn base_directories_follow_xdg_except_under_sudo() {
+        let mut user = RealUser { name: "alice".to_string(), home: PathBuf::from("/home/alice"), elevated: false };
+        assert_eq!(resolve_base_dir(BaseDir::State, None, &user), PathBuf::from("/home/alice/.local/state/arch-suite"));
+        assert_eq!(resolve_base_dir(BaseDir::Config, Some("/xdg/config".into()), &user), PathBuf::from("/xdg/config/arch-suite"));
+        assert_eq!(resolve_base_dir(BaseDir::Cache, Some("relative".into()), &user), PathBuf::from("/home/alice/.cache/arch-suite"));
+        user.elevated = true;
+        assert_eq!(resolve_base_dir(BaseDir::Data, Some("/root/.local/share".into()), &user), PathBuf::from("/home/alice/.local/share/arch-suite"));
+    }
+
+    #[test]
+    fn a_user_without_a_passwd_entry_is_an_error() {
+        assert!(resolve_user(Some("ghost".to_string()), || Ok("root".to_string()), getent).is_err());
+    }
+}
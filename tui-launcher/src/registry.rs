@@ -0,0 +1,108 @@
+// ===================================================================
+// Action Registry Module
+// ===================================================================
+// Every action the suite offers, under a stable name such as
+// `create-snapshot`. Menu items name their action here rather than
+// building it, and `--run` starts one by name, so what an action is
+// stays apart from where it appears. Names are kept once released.
+
+use crate::actions::{self, Action};
+use crate::app::AppView;
+
+pub struct Entry {
+    pub name: &'static str,
+    pub build: fn() -> Action,
+}
+
+/// The registered actions, in menu order.
+pub const ACTIONS: &[Entry] = &[
+    // Main menu
+    Entry { name: "open-replicator", build: || Action::SetView(AppView::Replicator) },
+    Entry { name: "open-cloner", build: || Action::SetView(AppView::Cloner) },
+    Entry { name: "open-utilities", build: || Action::SetView(AppView::Utilities) },
+    Entry { name: "open-help", build: || Action::SetView(AppView::HelpManual) },
+    Entry { name: "quit", build: || Action::Quit },
+    // Replicator
+    Entry { name: "create-snapshot", build: || Action::Resolve(actions::choose_snapshot_components) },
+    Entry { name: "detached-snapshot", build: || Action::execute(actions::detached_snapshot) },
+    Entry { name: "dotfiles-snapshot", build: || Action::Resolve(actions::dotfiles_snapshot) },
+    Entry { name: "browse-snapshots", build: || Action::execute(actions::browse_snapshots) },
+    Entry { name: "compare-snapshot", build: || Action::Resolve(actions::compare_snapshot) },
+    Entry { name: "deploy-snapshot", build: || Action::Resolve(actions::deploy_snapshot) },
+    Entry { name: "audit-deploy", build: || Action::Resolve(actions::audit_deploy) },
+    Entry { name: "sync-home", build: || Action::Resolve(actions::sync_home) },
+    Entry { name: "restore-points", build: || Action::Resolve(actions::restore_points) },
+    Entry { name: "upgrade-hook", build: || Action::Resolve(actions::upgrade_hook) },
+    Entry { name: "snapshot-fleet", build: || Action::execute(actions::snapshot_fleet) },
+    // Cloner
+    Entry { name: "create-iso", build: || Action::Resolve(actions::create_iso) },
+    // Utilities
+    Entry { name: "doctor", build: || Action::execute(actions::run_doctor) },
+    Entry { name: "inspect-system", build: || Action::execute(actions::inspect_system) },
+    Entry { name: "install-drivers", build: || Action::Resolve(actions::install_recommended_drivers) },
+    Entry { name: "remove-orphans", build: || Action::execute(actions::remove_orphans) },
+    Entry { name: "quick-backup", build: || Action::Resolve(actions::quick_backup) },
+    Entry { name: "flash-iso", build: || Action::Resolve(actions::flash_iso) },
+    Entry { name: "test-boot-iso", build: || Action::Resolve(actions::test_boot_iso) },
+    Entry { name: "manage-kernels", build: || Action::execute(actions::manage_kernels) },
+    Entry { name: "edit-kernel-parameters", build: || Action::Resolve(actions::edit_kernel_parameters) },
+    Entry { name: "manage-services", build: || Action::execute(actions::manage_services) },
+    Entry { name: "repair-keyring", build: || Action::execute(actions::repair_keyring) },
+    Entry { name: "rank-mirrors", build: || Action::execute(actions::rank_mirrors) },
+    Entry { name: "export-recipe", build: || Action::Resolve(actions::export_recipe) },
+    Entry { name: "apply-recipe", build: || Action::Resolve(actions::apply_recipe) },
+    Entry { name: "detached-sessions", build: || Action::execute(actions::detached_sessions) },
+    Entry { name: "setup-wizard", build: || actions::setup_wizard() },
+    Entry { name: "open-manual-installer", build: || Action::SetView(AppView::ManualInstaller) },
+    // Manual installer
+    Entry { name: "wipe-disk", build: || Action::execute(actions::manual_wipe_disk) },
+    Entry { name: "partition-disk", build: || Action::Resolve(actions::manual_partition_disk) },
+    Entry { name: "format-partitions", build: || Action::Resolve(actions::manual_format_partitions) },
+    Entry { name: "mount-partitions", build: || Action::Resolve(actions::manual_mount_partitions) },
+    Entry { name: "install-base-system", build: || Action::Resolve(actions::manual_pacstrap) },
+    Entry { name: "generate-fstab", build: || Action::execute(actions::manual_fstab) },
+    Entry { name: "setup-bootloader", build: || Action::Resolve(actions::manual_chroot_grub) },
+    Entry { name: "set-timezone", build: || Action::Resolve(actions::manual_timezone) },
+    Entry { name: "generate-locales", build: || Action::Resolve(actions::manual_locale) },
+    Entry { name: "set-keymap", build: || Action::Resolve(actions::manual_keymap) },
+    Entry { name: "create-user", build: || Action::Resolve(actions::manual_user) },
+    Entry { name: "set-passwords", build: || Action::Resolve(actions::manual_passwords) },
+    Entry { name: "run-remaining-steps", build: || Action::RunWorkflow },
+    Entry { name: "install-history", build: || Action::Resolve(actions::show_install_history) },
+    Entry { name: "run-install-plan", build: || Action::Resolve(actions::import_install_plan) },
+    Entry { name: "export-install-script", build: || Action::Resolve(actions::export_install_script) },
+];
+
+/// The action registered as `name`, if any.
+pub fn action(name: &str) -> Option<Action> {
+    ACTIONS.iter().find(|entry| entry.name == name).map(|entry| (entry.build)())
+}
+
+pub fn names() -> impl Iterator<Item = &'static str> {
+    ACTIONS.iter().map(|entry| entry.name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::App;
+
+    #[test]
+    fn every_menu_item_names_a_registered_action_once() {
+        let app = App::new();
+        let mut used = Vec::new();
+        for view in AppView::MENUS {
+            for item in &app.menu(view).unwrap().items {
+                assert!(action(item.id).is_some(), "{} names no registered action: {}", item.text, item.id);
+                used.push(item.id);
+            }
+        }
+        let mut registered: Vec<&str> = names().collect();
+        assert_eq!(used.len(), registered.len(), "every registered action appears in exactly one menu");
+        registered.sort_unstable();
+        registered.dedup();
+        assert_eq!(registered.len(), ACTIONS.len(), "names are unique");
+        assert!(matches!(action("open-manual-installer"), Some(Action::SetView(AppView::ManualInstaller))));
+        assert!(action("no-such-action").is_none());
+    }
+}
@@ -0,0 +1,173 @@
+// ===================================================================
+// Services Module
+// ===================================================================
+// systemd service units and what can be done to them. The list joins
+// `systemctl list-units` (loaded units and their state) with
+// `systemctl list-unit-files` (whether each one starts at boot).
+
+/// The systemctl arguments that list every service, loaded or not, without decoration.
+pub const LIST_UNITS: &[&str] = &["list-units", "--type=service", "--all", "--plain", "--no-legend", "--no-pager"];
+pub const LIST_UNIT_FILES: &[&str] = &["list-unit-files", "--type=service", "--no-legend", "--no-pager"];
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Unit {
+    pub name: String,
+    /// `active`, `inactive`, `failed`, ...; `inactive` for units only known from their file.
+    pub active: String,
+    /// The finer state, e.g. `running` or `exited`.
+    pub sub: String,
+    /// The unit file state: `enabled`, `disabled`, `static`, `masked`, ...; `-` without a file.
+    pub enabled: String,
+    pub description: String,
+}
+
+impl Unit {
+    /// One list row, with the name padded to `width`.
+    pub fn label(&self, width: usize) -> String {
+        format!("{:width$}  {:9} {:8} {:8}  {}", self.name, self.enabled, self.active, self.sub, self.description, width = width)
+    }
+
+    /// Whether every word of `query` appears, ignoring case, in the name, states or description.
+    pub fn matches(&self, query: &str) -> bool {
+        let haystack = format!("{} {} {} {} {}", self.name, self.enabled, self.active, self.sub, self.description).to_lowercase();
+        query.to_lowercase().split_whitespace().all(|word| haystack.contains(word))
+    }
+}
+
+/// Joins the output of `LIST_UNITS` and `LIST_UNIT_FILES`, sorted by name. Templates (`foo@.service`)
+/// are left out: they cannot be started or enabled without an instance name.
+pub fn parse_units(units: &str, unit_files: &str) -> Vec<Unit> {
+    let files: Vec<(&str, &str)> = unit_files.lines().filter_map(|line| {
+        let mut fields = line.split_whitespace();
+        Some((fields.next()?, fields.next()?))
+    }).collect();
+    let enabled = |name: &str| files.iter().find(|(file, _)| *file == name).map_or("-", |(_, state)| state).to_string();
+    let mut list: Vec<Unit> = units.lines().filter_map(|line| {
+        // UNIT LOAD ACTIVE SUB DESCRIPTION...
+        let mut fields = line.split_whitespace();
+        let name = fields.next()?.to_string();
+        let (_load, active, sub) = (fields.next()?, fields.next()?.to_string(), fields.next()?.to_string());
+        let description = fields.collect::<Vec<_>>().join(" ");
+        Some(Unit { enabled: enabled(&name), name, active, sub, description })
+    }).collect();
+    for (name, state) in &files {
+        if !name.contains("@.") && !list.iter().any(|unit| unit.name == *name) {
+            list.push(Unit { name: name.to_string(), active: "inactive".to_string(), sub: "dead".to_string(), enabled: state.to_string(), description: String::new() });
+        }
+    }
+    list.sort_by(|a, b| a.name.cmp(&b.name));
+    list
+}
+
+/// How many journal lines are shown without following.
+pub const JOURNAL_LINES: usize = 200;
+/// The journal priorities the list cycles through, each showing it and everything more severe.
+pub const PRIORITIES: [&str; 5] = ["debug", "info", "notice", "warning", "err"];
+
+/// The journalctl arguments for `unit`'s recent entries at `priority` or more severe, and with
+/// `follow` the new ones as they arrive.
+pub fn journal_args(unit: &str, priority: &str, follow: bool) -> Vec<String> {
+    let mut args = vec!["-u".to_string(), unit.to_string(), "-n".to_string(), JOURNAL_LINES.to_string(), "--no-pager".to_string()];
+    // debug is the lowest priority, so it filters nothing.
+    if priority != "debug" {
+        args.extend(["-p".to_string(), priority.to_string()]);
+    }
+    if follow {
+        args.push("-f".to_string());
+    }
+    args
+}
+
+/// What the services list can do to the selected unit, each on its own key.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ServiceOp {
+    Start,
+    Stop,
+    Restart,
+    Enable,
+    Disable,
+}
+
+impl ServiceOp {
+    pub const ALL: [ServiceOp; 5] = [ServiceOp::Start, ServiceOp::Stop, ServiceOp::Restart, ServiceOp::Enable, ServiceOp::Disable];
+
+    pub fn key(self) -> char {
+        match self {
+            ServiceOp::Start => 's',
+            ServiceOp::Stop => 'x',
+            ServiceOp::Restart => 'r',
+            ServiceOp::Enable => 'e',
+            ServiceOp::Disable => 'd',
+        }
+    }
+
+    pub fn from_key(key: char) -> Option<Self> {
+        Self::ALL.into_iter().find(|op| op.key() == key)
+    }
+
+    /// The systemctl subcommand.
+    pub fn command(self) -> &'static str {
+        match self {
+            ServiceOp::Start => "start",
+            ServiceOp::Stop => "stop",
+            ServiceOp::Restart => "restart",
+            ServiceOp::Enable => "enable",
+            ServiceOp::Disable => "disable",
+        }
+    }
+
+    pub fn done(self) -> &'static str {
+        match self {
+            ServiceOp::Start => "Started",
+            ServiceOp::Stop => "Stopped",
+            ServiceOp::Restart => "Restarted",
+            ServiceOp::Enable => "Enabled",
+            ServiceOp::Disable => "Disabled",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNITS: &str = "\
+dbus.service                 loaded    active   running D-Bus System Message Bus
+sshd.service                 loaded    failed   failed  OpenSSH Daemon
+systemd-remount-fs.service   loaded    active   exited  Remount Root and Kernel File Systems
+";
+    const UNIT_FILES: &str = "\
+cups.service                 disabled        disabled
+dbus.service                 static          -
+getty@.service               enabled         enabled
+sshd.service                 enabled         disabled
+";
+
+    #[test]
+    fn loaded_units_and_unit_files_are_joined() {
+        let units = parse_units(UNITS, UNIT_FILES);
+        let names: Vec<&str> = units.iter().map(|u| u.name.as_str()).collect();
+        assert_eq!(names, ["cups.service", "dbus.service", "sshd.service", "systemd-remount-fs.service"]);
+        let sshd = &units[2];
+        assert_eq!((sshd.enabled.as_str(), sshd.active.as_str(), sshd.description.as_str()), ("enabled", "failed", "OpenSSH Daemon"));
+        assert_eq!((units[0].active.as_str(), units[0].enabled.as_str()), ("inactive", "disabled"));
+        assert_eq!(units[3].enabled, "-");
+    }
+
+    #[test]
+    fn filters_match_every_word_in_any_column() {
+        let units = parse_units(UNITS, UNIT_FILES);
+        let found = |query: &str| units.iter().filter(|u| u.matches(query)).map(|u| u.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(found("SSH"), ["sshd.service"]);
+        assert_eq!(found("failed"), ["sshd.service"]);
+        assert_eq!(found("active running"), ["dbus.service"]);
+        assert_eq!(found("").len(), 4);
+        assert!(ServiceOp::ALL.iter().all(|op| ServiceOp::from_key(op.key()) == Some(*op)));
+    }
+
+    #[test]
+    fn journal_arguments_filter_by_priority_and_follow() {
+        assert_eq!(journal_args("sshd.service", "debug", false), ["-u", "sshd.service", "-n", "200", "--no-pager"]);
+        assert_eq!(journal_args("sshd.service", "warning", true), ["-u", "sshd.service", "-n", "200", "--no-pager", "-p", "warning", "-f"]);
+    }
+}
@@ -0,0 +1,239 @@
+// ===================================================================
+// Signature Module
+// ===================================================================
+// Detached GPG signatures: checking an ISO or snapshot against its
+// `.sig` file and signing snapshots. gpg's machine-readable status
+// output is parsed, so the report does not depend on its locale.
+
+use crate::actions::require_tools;
+use crate::error::ActionError;
+use crate::paths;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+/// The keys of the Arch developers, installed with archlinux-keyring; ISOs are signed with one of them.
+pub const ARCH_KEYRING: &str = "/usr/share/pacman/keyrings/archlinux.gpg";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Verdict {
+    Good,
+    /// The file was changed after it was signed.
+    Bad,
+    /// The signing key is not in any keyring gpg was given.
+    UnknownKey,
+    ExpiredKey,
+    RevokedKey,
+    /// There is no `.sig` file.
+    Missing,
+    /// gpg could not read the signature at all.
+    Invalid,
+}
+
+/// How far the signing key is trusted, as gpg's web of trust decides.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Trust {
+    Undefined,
+    Never,
+    Marginal,
+    Full,
+    Ultimate,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Signature {
+    pub verdict: Verdict,
+    /// The signing key's fingerprint, or its long key id when gpg did not have the key.
+    pub key: Option<String>,
+    /// The key's primary user id, e.g. `Pierre Schmitz <pierre@archlinux.org>`.
+    pub signer: Option<String>,
+    pub trust: Option<Trust>,
+}
+
+impl Signature {
+    fn missing() -> Self {
+        Signature { verdict: Verdict::Missing, key: None, signer: None, trust: None }
+    }
+
+    /// Good, and made with a key the user fully trusts.
+    pub fn trusted(&self) -> bool {
+        self.verdict == Verdict::Good && matches!(self.trust, Some(Trust::Full | Trust::Ultimate))
+    }
+
+    /// One line naming the signer and how far to believe them.
+    pub fn describe(&self) -> String {
+        let who = match (&self.signer, &self.key) {
+            (Some(signer), Some(key)) => format!("{} (key {})", signer, key),
+            (None, Some(key)) => format!("key {}", key),
+            _ => "an unknown key".to_string(),
+        };
+        let trust = match self.trust {
+            Some(Trust::Ultimate) => "ultimately trusted",
+            Some(Trust::Full) => "fully trusted",
+            Some(Trust::Marginal) => "only marginally trusted",
+            Some(Trust::Never) => "explicitly distrusted",
+            Some(Trust::Undefined) | None => "not certified as trusted",
+        };
+        match self.verdict {
+            Verdict::Good => format!("Good signature from {}, {}.", who, trust),
+            Verdict::Bad => format!("BAD signature from {}: the file was modified after signing.", who),
+            Verdict::UnknownKey => format!("Signed with {}, which is not in your keyring.", who),
+            Verdict::ExpiredKey => format!("Signed with {}, which has expired.", who),
+            Verdict::RevokedKey => format!("Signed with {}, which has been REVOKED.", who),
+            Verdict::Missing => "No signature file.".to_string(),
+            Verdict::Invalid => "The signature file could not be read.".to_string(),
+        }
+    }
+
+    /// The warning to show before going ahead with `file`, unless the signature is good and trusted.
+    pub fn warning(&self, file: &Path, sig: &Path) -> Option<String> {
+        if self.trusted() {
+            return None;
+        }
+        let advice = match self.verdict {
+            Verdict::Good => "Anyone can make a key with any name. Check the fingerprint against one you know (e.g. archlinux.org/download) and sign or trust the key if it is right.",
+            Verdict::Bad => "Do not use it: it is corrupt or has been tampered with.",
+            Verdict::UnknownKey => "Import the key (gpg --locate-external-key <email>, or install archlinux-keyring) and check its fingerprint.",
+            Verdict::Missing => "Without it, who made the file cannot be checked.",
+            _ => "Who made the file cannot be confirmed.",
+        };
+        let name = file.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let what = match self.verdict {
+            Verdict::Missing => format!("{} has no signature ({} not found). {}", name, sig.display(), advice),
+            _ => format!("{}\n{} {}", name, self.describe(), advice),
+        };
+        Some(what)
+    }
+}
+
+/// The detached signature gpg looks for by default: `file` with `.sig` appended.
+pub fn sig_path(file: &Path) -> PathBuf {
+    let mut sig = file.as_os_str().to_owned();
+    sig.push(".sig");
+    PathBuf::from(sig)
+}
+
+/// Reads `gpg --status-fd` output. Of several signatures in one file, the last is reported.
+pub fn parse_status(status: &str) -> Signature {
+    let mut signature = Signature { verdict: Verdict::Invalid, key: None, signer: None, trust: None };
+    for line in status.lines() {
+        let Some(line) = line.strip_prefix("[GNUPG:] ") else { continue };
+        let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let mut fields = rest.splitn(2, ' ');
+        let (first, tail) = (fields.next().filter(|f| !f.is_empty()).map(String::from), fields.next().map(String::from));
+        let verdict = match keyword {
+            "GOODSIG" => Verdict::Good,
+            "BADSIG" => Verdict::Bad,
+            "EXPKEYSIG" => Verdict::ExpiredKey,
+            "REVKEYSIG" => Verdict::RevokedKey,
+            "NO_PUBKEY" => Verdict::UnknownKey,
+            // ERRSIG <keyid> <algo> <hash> <class> <time> <rc>; rc 9 is a missing key.
+            "ERRSIG" => {
+                signature.key = signature.key.take().or(first.clone());
+                if rest.split(' ').nth(5) == Some("9") { Verdict::UnknownKey } else { Verdict::Invalid }
+            }
+            // VALIDSIG <fingerprint> ... ; the last field is the primary key's fingerprint.
+            "VALIDSIG" => {
+                signature.key = rest.rsplit(' ').next().map(String::from);
+                continue;
+            }
+            "TRUST_UNDEFINED" | "TRUST_NEVER" | "TRUST_MARGINAL" | "TRUST_FULLY" | "TRUST_ULTIMATE" => {
+                signature.trust = Some(match keyword {
+                    "TRUST_NEVER" => Trust::Never,
+                    "TRUST_MARGINAL" => Trust::Marginal,
+                    "TRUST_FULLY" => Trust::Full,
+                    "TRUST_ULTIMATE" => Trust::Ultimate,
+                    _ => Trust::Undefined,
+                });
+                continue;
+            }
+            _ => continue,
+        };
+        signature.verdict = verdict;
+        if keyword != "ERRSIG" && keyword != "NO_PUBKEY" {
+            signature.key = signature.key.take().or(first);
+            signature.signer = tail;
+        }
+    }
+    signature
+}
+
+/// gpg with the real user's keyring; under sudo, root's would be used otherwise.
+fn gpg() -> Result<Command, ActionError> {
+    require_tools(&["gpg"])?;
+    let mut cmd = Command::new("gpg");
+    cmd.arg("--batch");
+    let user = paths::real_user()?;
+    if user.elevated && std::env::var_os("GNUPGHOME").is_none() {
+        cmd.env("GNUPGHOME", user.home.join(".gnupg"));
+    }
+    Ok(cmd)
+}
+
+/// Checks `file` against the detached signature `sig` with `gpg --verify`, also looking the key up
+/// in `keyring` (which does not make it trusted). A missing `sig` is reported, not an error.
+pub async fn verify_signature(file: &Path, sig: &Path, keyring: Option<&Path>) -> Result<Signature, ActionError> {
+    if !sig.is_file() {
+        return Ok(Signature::missing());
+    }
+    let mut cmd = gpg()?;
+    cmd.args(["--status-fd", "1"]);
+    if let Some(keyring) = keyring.filter(|k| k.is_file()) {
+        cmd.arg("--keyring").arg(keyring);
+    }
+    cmd.arg("--verify").arg(sig).arg(file);
+    // gpg exits non-zero for bad or unverifiable signatures; the status lines say which.
+    let output = cmd.kill_on_drop(true).output().await?;
+    Ok(parse_status(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Writes a detached signature of `file` to `sig_path(file)`, with `key` or gpg's default key.
+/// gpg needs a pinentry it can show outside the TUI, or a cached passphrase.
+pub async fn sign_file(file: &Path, key: Option<&str>) -> Result<PathBuf, ActionError> {
+    let sig = sig_path(file);
+    let mut cmd = gpg()?;
+    cmd.args(["--yes", "--detach-sign"]);
+    if let Some(key) = key {
+        cmd.args(["--local-user", key]);
+    }
+    cmd.arg("--output").arg(&sig).arg(file);
+    let output = cmd.kill_on_drop(true).output().await?;
+    if !output.status.success() {
+        return Err(ActionError::command_failed("gpg --detach-sign", output.status, &String::from_utf8_lossy(&output.stdout), &String::from_utf8_lossy(&output.stderr)));
+    }
+    Ok(sig)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FPR: &str = "3E80CA1A8B89F69CBA57D98A76A5EF9054449A5C";
+
+    #[test]
+    fn good_signatures_report_the_signer_and_trust() {
+        let status = format!(
+            "[GNUPG:] NEWSIG\n[GNUPG:] GOODSIG 76A5EF9054449A5C Pierre Schmitz <pierre@archlinux.org>\n[GNUPG:] VALIDSIG {0} 2024-05-01 1714550000 0 4 0 22 10 00 {0}\n[GNUPG:] TRUST_UNDEFINED 0 pgp\n",
+            FPR
+        );
+        let signature = parse_status(&status);
+        assert_eq!(signature.verdict, Verdict::Good);
+        assert_eq!(signature.key.as_deref(), Some(FPR));
+        assert_eq!(signature.signer.as_deref(), Some("Pierre Schmitz <pierre@archlinux.org>"));
+        assert!(!signature.trusted());
+        assert!(signature.warning(Path::new("a.iso"), Path::new("a.iso.sig")).is_some_and(|w| w.contains("not certified as trusted")));
+
+        let trusted = parse_status(&status.replace("TRUST_UNDEFINED", "TRUST_FULLY"));
+        assert!(trusted.trusted() && trusted.warning(Path::new("a.iso"), Path::new("a.iso.sig")).is_none());
+    }
+
+    #[test]
+    fn bad_and_unverifiable_signatures_are_told_apart() {
+        assert_eq!(parse_status("[GNUPG:] BADSIG 76A5EF9054449A5C Pierre Schmitz <pierre@archlinux.org>\n").verdict, Verdict::Bad);
+        let unknown = parse_status("[GNUPG:] ERRSIG 76A5EF9054449A5C 22 10 00 1714550000 9 -\n[GNUPG:] NO_PUBKEY 76A5EF9054449A5C\n");
+        assert_eq!((unknown.verdict, unknown.key.as_deref()), (Verdict::UnknownKey, Some("76A5EF9054449A5C")));
+        assert_eq!(parse_status("[GNUPG:] NODATA 4\n").verdict, Verdict::Invalid);
+        assert_eq!(sig_path(Path::new("/tmp/archlinux.iso")), PathBuf::from("/tmp/archlinux.iso.sig"));
+        let missing = Signature::missing().warning(Path::new("/tmp/a.iso"), Path::new("/tmp/a.iso.sig"));
+        assert!(missing.is_some_and(|w| w.starts_with("a.iso has no signature")));
+    }
+}
@@ -0,0 +1,824 @@
+// ===================================================================
+// Snapshot Format Module
+// ===================================================================
+// Types describing the contents of a snapshot archive. The manifest is
+// stored as `manifest.json` at the root of every snapshot.
+
+use crate::disk::human_size;
+use crate::inspect::{DriverSwap, Hardware};
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+/// Kept next to the unpacked snapshot while it is deployed; see `DeployState`.
+pub const DEPLOY_STATE_FILE: &str = "deploy-state.json";
+
+/// The compressor used for the archives inside a snapshot and for the snapshot itself.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+impl Compression {
+    /// The binary `tar` shells out to for this algorithm.
+    pub fn tool(self) -> &'static str {
+        match self { Compression::Gzip => "gzip", Compression::Zstd => "zstd", Compression::Xz => "xz" }
+    }
+
+    pub fn tar_flag(self) -> &'static str {
+        match self { Compression::Gzip => "-z", Compression::Zstd => "--zstd", Compression::Xz => "-J" }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self { Compression::Gzip => "tar.gz", Compression::Zstd => "tar.zst", Compression::Xz => "tar.xz" }
+    }
+
+    /// The levels the tool accepts, fastest first.
+    pub fn levels(self) -> RangeInclusive<u32> {
+        match self { Compression::Gzip => 1..=9, Compression::Zstd => 1..=19, Compression::Xz => 0..=9 }
+    }
+
+    /// `level`, moved into `levels()` when it is out of range.
+    pub fn clamp_level(self, level: u32) -> u32 {
+        level.clamp(*self.levels().start(), *self.levels().end())
+    }
+}
+
+/// What a snapshot holds. A full one has /etc and the localization; a dotfiles one only the
+/// package lists and home files. Both may carry the whole home directory or chosen dotfiles.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SnapshotKind {
+    #[default]
+    Full,
+    Dotfiles,
+}
+
+impl SnapshotKind {
+    /// Whether the snapshot carries /etc and the localization to restore.
+    pub fn has_system(self) -> bool {
+        self == SnapshotKind::Full
+    }
+}
+
+/// What goes into a snapshot besides the package lists, which every snapshot has.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Components {
+    /// /etc and the localization.
+    pub etc: bool,
+    /// The home-relative paths to archive; `None` for the whole home directory.
+    pub dotfiles: Option<Vec<String>>,
+    /// Patterns left out of the home archive on top of the caches and sensitive paths.
+    pub excludes: Vec<String>,
+}
+
+impl Components {
+    /// Everything: /etc and the whole home directory, as `arch-suite snapshot create` takes.
+    pub fn full() -> Self {
+        Components { etc: true, dotfiles: None, excludes: Vec::new() }
+    }
+
+    pub fn kind(&self) -> SnapshotKind {
+        if self.etc { SnapshotKind::Full } else { SnapshotKind::Dotfiles }
+    }
+
+    /// Exclude patterns as typed: separated by spaces, or commas for one with a space in it.
+    pub fn parse_excludes(text: &str) -> Vec<String> {
+        let separator: &[char] = if text.contains(',') { &[','] } else { &[' ', '\t'] };
+        text.split(separator).map(str::trim).filter(|pattern| !pattern.is_empty()).map(String::from).collect()
+    }
+
+    /// What the snapshot holds, for its success message, e.g. "the package lists, /etc and .config".
+    pub fn describe(&self) -> String {
+        let home = match &self.dotfiles {
+            Some(dotfiles) => dotfiles.join(", "),
+            None => "the home directory".to_string(),
+        };
+        let mut text = format!("the package lists{} and {}", if self.etc { ", /etc" } else { "" }, home);
+        if !self.excludes.is_empty() {
+            text.push_str(&format!(", leaving out {}", self.excludes.join(", ")));
+        }
+        text
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Manifest {
+    pub format_version: u32,
+    #[serde(default)]
+    pub kind: SnapshotKind,
+    pub created: String,
+    pub hostname: String,
+    /// The account whose home directory is in `home.tar.gz`.
+    #[serde(default)]
+    pub user: String,
+    #[serde(default)]
+    pub compression: Compression,
+    /// The compression level used; `None` for the tool's default.
+    #[serde(default)]
+    pub level: Option<u32>,
+    /// Whether `VERSIONS_FILE` records every package's version for deploys to install.
+    #[serde(default)]
+    pub pinned: bool,
+    /// Sensitive categories that were left out of `home.tar.gz`.
+    #[serde(default)]
+    pub excluded_sensitive: Vec<String>,
+    /// The machine the snapshot was taken on; absent in older snapshots.
+    #[serde(default)]
+    pub hardware: Option<Hardware>,
+    #[serde(default)]
+    pub localization: Localization,
+    /// Whether the archives were made reproducibly; `created` is then the fixed date they carry.
+    #[serde(default)]
+    pub reproducible: bool,
+}
+
+impl Manifest {
+    pub fn new() -> Self {
+        let hostname = std::fs::read_to_string("/etc/hostname").map(|h| h.trim().to_string()).unwrap_or_else(|_| "unknown".to_string());
+        Manifest { format_version: 1, kind: SnapshotKind::default(), created: chrono::Local::now().to_rfc3339(), hostname, user: String::new(), compression: Compression::default(), level: None, pinned: false, excluded_sensitive: Vec::new(), hardware: None, localization: Localization::default(), reproducible: false }
+    }
+}
+
+/// The date a reproducible snapshot gives everything: `$SOURCE_DATE_EPOCH`, the reproducible
+/// builds convention, or the Unix epoch.
+pub fn reproducible_epoch() -> i64 {
+    std::env::var("SOURCE_DATE_EPOCH").ok().and_then(|epoch| epoch.trim().parse().ok()).unwrap_or(0)
+}
+
+/// The tar options that make an archive depend only on the files' contents, names, modes and
+/// owners: a fixed order and a fixed `epoch` as every entry's mtime.
+pub fn reproducible_tar_args(epoch: i64) -> Vec<String> {
+    vec!["--sort=name".to_string(), format!("--mtime=@{}", epoch), "--format=gnu".to_string()]
+}
+
+/// Locale, time zone and console keymap of the source system. A part the source did not have
+/// is `None` (or empty) and left at the new system's default on deploy.
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct Localization {
+    pub locale_conf: Option<String>,
+    /// Zone name such as `Europe/Berlin`, from the target of the `localtime` link.
+    pub timezone: Option<String>,
+    pub vconsole_conf: Option<String>,
+    /// Enabled entries of `locale.gen`, e.g. `de_DE.UTF-8 UTF-8`.
+    pub locales: Vec<String>,
+}
+
+impl Localization {
+    /// Reads the settings from the `etc` directory given.
+    pub fn capture(etc: &Path) -> Self {
+        let read = |name: &str| std::fs::read_to_string(etc.join(name)).ok();
+        Localization {
+            locale_conf: read("locale.conf"),
+            timezone: std::fs::read_link(etc.join("localtime")).ok().and_then(|target| zone_name(&target)),
+            vconsole_conf: read("vconsole.conf"),
+            locales: read("locale.gen").map(|text| enabled_locales(&text)).unwrap_or_default(),
+        }
+    }
+}
+
+fn zone_name(target: &Path) -> Option<String> {
+    let target = target.to_string_lossy();
+    let (_, zone) = target.split_once("zoneinfo/")?;
+    (!zone.is_empty()).then(|| zone.to_string())
+}
+
+pub fn enabled_locales(locale_gen: &str) -> Vec<String> {
+    locale_gen.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')).map(String::from).collect()
+}
+
+/// `locale_gen` with `locales` enabled: the last commented line naming each one is uncommented
+/// (the file's header repeats a few as examples), and those not listed at all are appended.
+pub fn enable_locales(locale_gen: &str, locales: &[String]) -> String {
+    let mut missing: Vec<&String> = locales.iter().collect();
+    let mut lines: Vec<String> = locale_gen
+        .lines()
+        .rev()
+        .map(|line| {
+            let entry = line.trim_start_matches('#').trim();
+            match missing.iter().position(|locale| locale.as_str() == entry) {
+                Some(i) => {
+                    missing.remove(i);
+                    entry.to_string()
+                }
+                None => line.to_string(),
+            }
+        })
+        .collect();
+    lines.reverse();
+    lines.extend(missing.into_iter().cloned());
+    lines.join("\n") + "\n"
+}
+
+/// The dotfiles offered for a dotfiles snapshot: the hidden top-level entries of `home` except
+/// `.cache`, plus the `configured` paths (which may be nested, e.g. `.config/nvim`) that exist.
+pub fn detect_dotfiles(home: &Path, configured: &[String]) -> Vec<String> {
+    let mut found: Vec<String> = std::fs::read_dir(home)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name.starts_with('.') && name != ".cache")
+        .collect();
+    found.extend(configured.iter().filter(|path| home.join(path).exists()).cloned());
+    found.sort();
+    found.dedup();
+    found
+}
+
+/// tar's record size; `--checkpoint` counts records of the archive before it is compressed.
+const TAR_RECORD: u64 = 10240;
+
+/// How much tar has archived at a checkpoint line (`tar: Write checkpoint 3000`).
+pub fn checkpoint_bytes(line: &str) -> Option<u64> {
+    let records = line.trim().rsplit_once("checkpoint ")?.1.parse::<u64>().ok()?;
+    Some(records * TAR_RECORD)
+}
+
+/// The grand total of `du -sbc` output, its last line (`123456\ttotal`).
+pub fn du_total(output: &str) -> Option<u64> {
+    output.lines().last()?.split_whitespace().next()?.parse().ok()
+}
+
+/// The quick backup of `dir` made at `at`, e.g. `backup-Documents-20240501-1530.tar.zst`.
+pub fn backup_name(dir: &Path, at: chrono::DateTime<chrono::Local>, extension: &str) -> String {
+    let name = dir.file_name().map_or("root".to_string(), |n| n.to_string_lossy().trim_start_matches('.').to_string());
+    format!("backup-{}-{}.{}", name, at.format("%Y%m%d-%H%M%S"), extension)
+}
+
+/// The scratch directories snapshots (in the work directory), comparisons, audits and ISO builds
+/// (in the cache directory) work in; one still there was left by an operation that did not finish.
+pub const SCRATCH_DIRS: [&str; 4] = ["snapshot_tmp", "compare_tmp", "audit_tmp", "iso_work"];
+
+/// Where `file` is written until it is complete, so an interrupted archive is never mistaken
+/// for a snapshot or backup.
+pub fn partial_path(file: &Path) -> PathBuf {
+    let mut partial = file.as_os_str().to_owned();
+    partial.push(".part");
+    PathBuf::from(partial)
+}
+
+pub fn is_partial(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "part")
+}
+
+/// The extension of snapshots encrypted with `gpg --symmetric`; deploys decrypt them first.
+pub const ENCRYPTED_EXTENSION: &str = "gpg";
+
+pub fn is_encrypted(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == ENCRYPTED_EXTENSION)
+}
+
+/// `file` once encrypted, e.g. snapshot-20240501.tar.zst.gpg.
+pub fn encrypted_path(file: &Path) -> PathBuf {
+    let mut encrypted = file.as_os_str().to_owned();
+    encrypted.push(".");
+    encrypted.push(ENCRYPTED_EXTENSION);
+    PathBuf::from(encrypted)
+}
+
+/// gpg reads the passphrase from standard input, so it is never on a command line `ps` shows,
+/// and the agent does not keep it once gpg exits.
+const PASSPHRASE_ON_STDIN: [&str; 7] = ["--batch", "--yes", "--pinentry-mode", "loopback", "--passphrase-fd", "0", "--no-symkey-cache"];
+
+/// The gpg arguments that encrypt `input` to `output` with AES-256 and a passphrase.
+pub fn encrypt_args(input: &Path, output: &Path) -> Vec<String> {
+    let mut args: Vec<String> = PASSPHRASE_ON_STDIN.map(String::from).to_vec();
+    args.extend(["--symmetric", "--cipher-algo", "AES256", "--output"].map(String::from));
+    args.extend([output.display().to_string(), input.display().to_string()]);
+    args
+}
+
+/// The gpg arguments that decrypt `input` to `output`.
+pub fn decrypt_args(input: &Path, output: &Path) -> Vec<String> {
+    let mut args: Vec<String> = PASSPHRASE_ON_STDIN.map(String::from).to_vec();
+    args.extend(["--decrypt".to_string(), "--output".to_string(), output.display().to_string(), input.display().to_string()]);
+    args
+}
+
+/// A finished snapshot in the work directory, as the snapshot browser lists it.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SnapshotFile {
+    pub path: PathBuf,
+    pub modified: Option<chrono::DateTime<chrono::Local>>,
+    pub bytes: u64,
+    /// The lines of its `packages.x86_64.txt`; `None` when it could not be read in time.
+    pub packages: Option<usize>,
+}
+
+impl SnapshotFile {
+    pub fn name(&self) -> String {
+        self.path.file_name().map_or(String::new(), |name| name.to_string_lossy().to_string())
+    }
+
+    /// The row in the snapshot browser, with the name padded to `width`, e.g.
+    /// "snapshot-20240501.tar.gz  2024-05-01 15:30  1.2G  843 packages".
+    pub fn label(&self, width: usize) -> String {
+        let date = self.modified.map_or("unknown date    ".to_string(), |at| at.format("%Y-%m-%d %H:%M").to_string());
+        let unknown = if is_encrypted(&self.path) { "encrypted" } else { "packages unknown" };
+        let packages = self.packages.map_or(unknown.to_string(), |n| format!("{} packages", n));
+        format!("{:width$}  {}  {:>6}  {}", self.name(), date, human_size(self.bytes), packages, width = width)
+    }
+}
+
+/// What interrupted operations left in `dirs`: scratch directories and partial archives.
+pub fn leftovers(dirs: &[PathBuf]) -> Vec<PathBuf> {
+    let mut found: Vec<PathBuf> = Vec::new();
+    for dir in dirs {
+        let scratch = SCRATCH_DIRS.iter().map(|name| dir.join(name)).filter(|path| path.is_dir());
+        let partial = std::fs::read_dir(dir).into_iter().flatten().flatten().map(|entry| entry.path()).filter(|path| is_partial(path));
+        for path in scratch.chain(partial) {
+            if !found.contains(&path) {
+                found.push(path);
+            }
+        }
+    }
+    found.sort();
+    found
+}
+
+/// The pacman hook that snapshots the system before upgrades of chosen packages.
+pub const PRESNAPSHOT_HOOK: &str = "/etc/pacman.d/hooks/arch-suite-presnapshot.hook";
+
+/// Packages whose upgrade triggers the hook unless the user picks others.
+pub const DEFAULT_HOOK_TARGETS: [&str; 4] = ["linux", "linux-lts", "systemd", "glibc"];
+
+/// The hook file: on upgrading any of `targets` (all packages when empty), pacman runs
+/// `exe snapshot create` first. pacman runs hooks as root, so `SUDO_USER` makes the snapshot
+/// land in `user`'s work directory and belong to them. A failed snapshot does not stop the upgrade.
+pub fn presnapshot_hook(exe: &Path, user: &str, targets: &[String]) -> String {
+    let targets: Vec<String> = if targets.is_empty() { vec!["Target = *".to_string()] } else { targets.iter().map(|t| format!("Target = {}", t)).collect() };
+    format!(
+        "# Written by arch-suite; disable it from the Replicator menu.\n[Trigger]\nOperation = Upgrade\nType = Package\n{}\n\n[Action]\nDescription = Taking an arch-suite snapshot before the upgrade...\nWhen = PreTransaction\nExec = /usr/bin/env SUDO_USER={} {} snapshot create\n",
+        targets.join("\n"),
+        user,
+        exe.display(),
+    )
+}
+
+/// The `Target` entries of an installed hook, for offering them again.
+pub fn hook_targets(hook: &str) -> Vec<String> {
+    hook.lines().filter_map(|line| line.strip_prefix("Target = ")).filter(|t| *t != "*").map(String::from).collect()
+}
+
+/// The distinct top-level entries of `/etc` in a `tar -t` listing of `etc.tar.*`, sorted.
+pub fn top_level_etc_entries(listing: &str) -> Vec<String> {
+    let mut entries: Vec<String> = listing
+        .lines()
+        .filter_map(|line| line.trim_start_matches('/').strip_prefix("etc/")?.split('/').next())
+        .filter(|entry| !entry.is_empty())
+        .map(String::from)
+        .collect();
+    entries.sort();
+    entries.dedup();
+    entries
+}
+
+/// How a snapshot's explicit package list compares with this system's.
+#[derive(Debug, PartialEq)]
+pub struct PackageDiff {
+    /// Deploying the snapshot would install these.
+    pub only_snapshot: Vec<String>,
+    pub only_here: Vec<String>,
+    pub common: usize,
+}
+
+/// `pacman -Q` of the source system, in pinned snapshots.
+pub const VERSIONS_FILE: &str = "packages.versions.txt";
+/// The package files of the pinned versions found in pacman's cache, in pinned snapshots.
+pub const PACKAGE_DIR: &str = "pkg";
+pub const PACMAN_CACHE: &str = "/var/cache/pacman/pkg";
+
+/// `name version` lines, as `pacman -Q` prints them.
+pub fn parse_versions(text: &str) -> Vec<(String, String)> {
+    text.lines().filter_map(|line| {
+        let (name, version) = line.trim().split_once(' ')?;
+        Some((name.to_string(), version.trim().to_string()))
+    }).collect()
+}
+
+/// The package file of `name` at `version` among `files` (paths or names), e.g.
+/// `linux-6.9.1.arch1-1-x86_64.pkg.tar.zst`; signatures are not package files.
+pub fn package_file<'a>(files: &'a [String], name: &str, version: &str) -> Option<&'a String> {
+    let prefix = format!("{}-{}-", name, version);
+    files.iter().find(|file| {
+        let base = file.rsplit('/').next().unwrap_or(file);
+        base.starts_with(&prefix) && base.contains(".pkg.tar") && !base.ends_with(".sig")
+    })
+}
+
+/// How a deploy gets from what it installed to the pinned versions.
+#[derive(Debug, PartialEq, Default)]
+pub struct PinPlan {
+    /// Package files that install a pinned version, for `pacman -U`.
+    pub files: Vec<String>,
+    /// Packages left at another version because no file of the pinned one was found.
+    pub unavailable: Vec<String>,
+}
+
+/// Compares the `installed` versions with the `pinned` ones. Packages the deploy did not install
+/// (e.g. swapped-out drivers) are left alone.
+pub fn pin_plan(pinned: &[(String, String)], installed: &[(String, String)], files: &[String]) -> PinPlan {
+    let mut plan = PinPlan::default();
+    for (name, want) in pinned {
+        let Some((_, have)) = installed.iter().find(|(installed, _)| installed == name) else { continue };
+        if have == want {
+            continue;
+        }
+        match package_file(files, name, want) {
+            Some(file) => plan.files.push(file.clone()),
+            None => plan.unavailable.push(format!("{} (snapshot {}, installed {})", name, want, have)),
+        }
+    }
+    plan
+}
+
+/// The lines a deploy's summary gives its packages: how many of the repository ones `installed` now
+/// holds and which are missing, then the `foreign` ones pacman cannot install at all.
+pub fn install_summary(wanted: &[String], foreign: &[String], installed: &[String]) -> Vec<String> {
+    let repo: Vec<&String> = wanted.iter().filter(|p| !foreign.contains(p)).collect();
+    let missing: Vec<&str> = repo.iter().filter(|p| !installed.contains(p)).map(|p| p.as_str()).collect();
+    let mut summary = vec![format!("Installed {} of the snapshot's {} repository packages.", repo.len() - missing.len(), repo.len())];
+    if !missing.is_empty() {
+        summary.push(format!("⚠️ Not installed: {}.", missing.join(", ")));
+    }
+    if !foreign.is_empty() {
+        summary.push(format!("⚠️ Manual install needed for {} foreign packages (AUR or local builds): {}.", foreign.len(), foreign.join(", ")));
+    }
+    summary
+}
+
+pub fn package_diff(snapshot: &[String], installed: &[String]) -> PackageDiff {
+    let only = |a: &[String], b: &[String]| a.iter().filter(|p| !b.contains(p)).cloned().collect::<Vec<_>>();
+    let only_snapshot = only(snapshot, installed);
+    PackageDiff { common: snapshot.len() - only_snapshot.len(), only_snapshot, only_here: only(installed, snapshot) }
+}
+
+/// The /etc paths (relative to /etc) that differ between a snapshot and this system.
+#[derive(Debug, Default, PartialEq)]
+pub struct EtcDiff {
+    pub differ: Vec<String>,
+    pub only_snapshot: Vec<String>,
+    pub only_here: Vec<String>,
+}
+
+/// Parses `diff -rq --no-dereference <snapshot> <system>`: "Files a and b differ" (or "Symbolic
+/// links ... differ", "File a is a ... while file b is a ...") and "Only in dir: name" lines.
+pub fn parse_etc_diff(output: &str, snapshot: &str, system: &str) -> EtcDiff {
+    let relative = |path: &str, root: &str| path.strip_prefix(root).unwrap_or(path).trim_start_matches('/').to_string();
+    let mut diff = EtcDiff::default();
+    for line in output.lines() {
+        if let Some((dir, name)) = line.strip_prefix("Only in ").and_then(|rest| rest.split_once(": ")) {
+            let path = format!("{}/{}", dir, name);
+            if dir.starts_with(snapshot) {
+                diff.only_snapshot.push(relative(&path, snapshot));
+            } else if dir.starts_with(system) {
+                diff.only_here.push(relative(&path, system));
+            }
+        } else if let Some(start) = line.find(snapshot) {
+            let path = &line[start..];
+            let end = path.find(" and ").or_else(|| path.find(" is a ")).unwrap_or(path.len());
+            diff.differ.push(relative(&path[..end], snapshot));
+        }
+    }
+    diff
+}
+
+/// Whether the /etc path `file` is the top-level `entry` or inside it.
+pub fn is_under(file: &str, entry: &str) -> bool {
+    file == entry || file.strip_prefix(entry).is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// The tar arguments that extract the `restore` entries of an /etc archive except the `keep` files.
+pub fn etc_extract_args(restore: &[String], keep: &[String]) -> Vec<String> {
+    // Anchored, so excluding `hosts` cannot also skip a `hosts` deeper down.
+    let mut args: Vec<String> = if keep.is_empty() { Vec::new() } else { vec!["--anchored".to_string()] };
+    args.extend(keep.iter().map(|file| format!("--exclude=etc/{}", file)));
+    args.extend(restore.iter().map(|entry| format!("etc/{}", entry)));
+    args
+}
+
+/// The units enabled through `etc/systemd/system/<target>.wants/` links among `paths` (e.g. a
+/// `tar -t` listing of the /etc archive), sorted.
+pub fn enabled_units<'a>(paths: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let mut units: Vec<String> = paths
+        .into_iter()
+        .filter_map(|path| {
+            let (wants, unit) = path.trim_start_matches('/').strip_prefix("etc/systemd/system/")?.trim_end_matches('/').split_once('/')?;
+            (wants.ends_with(".wants") && !unit.is_empty() && !unit.contains('/')).then(|| unit.to_string())
+        })
+        .collect();
+    units.sort();
+    units.dedup();
+    units
+}
+
+/// The parts of `expected` that `actual` does not match. What the snapshot did not capture was
+/// never restored, so it is not compared.
+pub fn localization_mismatches(expected: &Localization, actual: &Localization) -> Vec<&'static str> {
+    let mut differ = Vec::new();
+    let mut compare = |name, expected: &Option<String>, actual: &Option<String>| {
+        if expected.is_some() && expected != actual {
+            differ.push(name);
+        }
+    };
+    compare("locale.conf", &expected.locale_conf, &actual.locale_conf);
+    compare("time zone", &expected.timezone, &actual.timezone);
+    compare("vconsole.conf", &expected.vconsole_conf, &actual.vconsole_conf);
+    if !expected.locales.is_empty() && expected.locales != actual.locales {
+        differ.push("locale.gen");
+    }
+    differ
+}
+
+/// The parts of a deploy, in the order they run. Each can run again without harm, so a deploy
+/// that stopped resumes with the first one not done.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum DeployPhase {
+    Packages,
+    Etc,
+    Localization,
+    Home,
+}
+
+impl DeployPhase {
+    pub const ALL: [DeployPhase; 4] = [DeployPhase::Packages, DeployPhase::Etc, DeployPhase::Localization, DeployPhase::Home];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            DeployPhase::Packages => "packages installed",
+            DeployPhase::Etc => "/etc restored",
+            DeployPhase::Localization => "locale, time zone and keymap restored",
+            DeployPhase::Home => "home directory restored",
+        }
+    }
+}
+
+/// What was chosen for a deploy and how far it got, so a failed one picks up where it stopped
+/// instead of unpacking the snapshot again.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DeployState {
+    pub snapshot: PathBuf,
+    /// The top-level /etc entries to restore, out of `entries`.
+    pub restore: Vec<String>,
+    pub entries: Vec<String>,
+    /// Files (relative to /etc) under `restore` that differ on the target and keep its version.
+    #[serde(default)]
+    pub keep: Vec<String>,
+    #[serde(default)]
+    pub swap: DriverSwap,
+    #[serde(default)]
+    pub done: Vec<DeployPhase>,
+}
+
+impl DeployState {
+    /// The state of an earlier deploy of `snapshot`, if one was started from it.
+    pub fn load(staging: &Path, snapshot: &Path) -> Option<Self> {
+        let text = std::fs::read_to_string(staging.join(DEPLOY_STATE_FILE)).ok()?;
+        serde_json::from_str::<DeployState>(&text).ok().filter(|state| state.snapshot == snapshot)
+    }
+
+    pub fn is_done(&self, phase: DeployPhase) -> bool {
+        self.done.contains(&phase)
+    }
+
+    /// What is done, for the offer to resume; "nothing yet" when it failed in the first phase.
+    pub fn describe_done(&self) -> String {
+        let done: Vec<&str> = DeployPhase::ALL.iter().filter(|phase| self.is_done(**phase)).map(|phase| phase.label()).collect();
+        if done.is_empty() { "nothing yet".to_string() } else { done.join(", ") }
+    }
+}
+
+/// How far the system moved on since the latest snapshot, from pacman's log and /etc's mtimes.
+#[derive(Debug, Default, PartialEq)]
+pub struct Drift {
+    pub installed: usize,
+    pub removed: usize,
+    pub upgraded: usize,
+    pub etc_changed: usize,
+}
+
+impl Drift {
+    /// Upgrades are left out: a new snapshot would not change what a deploy installs for them.
+    pub fn total(&self) -> usize {
+        self.installed + self.removed + self.etc_changed
+    }
+
+    pub fn summary(&self) -> String {
+        format!("{} packages installed, {} removed, {} upgraded, {} /etc files changed", self.installed, self.removed, self.upgraded, self.etc_changed)
+    }
+}
+
+/// Counts the installs, removals and upgrades pacman.log records after `since`. Lines whose
+/// timestamp cannot be read (e.g. the older `[2019-01-01 10:00]` form) are skipped.
+pub fn package_drift(log: &str, since: chrono::DateTime<chrono::FixedOffset>) -> Drift {
+    let mut drift = Drift::default();
+    for line in log.lines() {
+        let Some((stamp, rest)) = line.strip_prefix('[').and_then(|line| line.split_once("] ")) else { continue };
+        if !chrono::DateTime::parse_from_str(stamp, "%Y-%m-%dT%H:%M:%S%z").is_ok_and(|at| at > since) {
+            continue;
+        }
+        let Some(event) = rest.strip_prefix("[ALPM] ") else { continue };
+        match event.split_whitespace().next() {
+            Some("installed") => drift.installed += 1,
+            Some("removed") => drift.removed += 1,
+            Some("upgraded" | "downgraded") => drift.upgraded += 1,
+            _ => {}
+        }
+    }
+    drift
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCALE_GEN: &str = "# Each line is of the form:\n#  en_US.UTF-8 UTF-8\n\n#de_DE.UTF-8 UTF-8\n#en_US.UTF-8 UTF-8\nfr_FR.UTF-8 UTF-8\n";
+
+    #[test]
+    fn quick_backups_are_named_after_the_directory_and_follow_checkpoints() {
+        use chrono::TimeZone;
+        let at = chrono::Local.with_ymd_and_hms(2024, 5, 1, 15, 30, 0).unwrap();
+        assert_eq!(backup_name(Path::new("/home/ada/Documents"), at, "tar.zst"), "backup-Documents-20240501-153000.tar.zst");
+        assert_eq!(backup_name(Path::new("/home/ada/.config"), at, "tar.gz"), "backup-config-20240501-153000.tar.gz");
+        assert_eq!(checkpoint_bytes("tar: Write checkpoint 3000"), Some(3000 * 10240));
+        assert_eq!(checkpoint_bytes("tar: ./a: file changed as we read it"), None);
+        assert_eq!(du_total("4096\t/home/ada/.config\n512\t/home/ada/.bashrc\n4608\ttotal\n"), Some(4608));
+        assert_eq!(du_total(""), None);
+    }
+
+    #[test]
+    fn components_describe_what_the_snapshot_holds() {
+        assert_eq!(Components::parse_excludes(" *.iso  Videos\t"), ["*.iso", "Videos"]);
+        assert_eq!(Components::parse_excludes("My Videos, *.iso"), ["My Videos", "*.iso"]);
+        let recipe = Components { etc: true, dotfiles: Some(vec![".config".to_string(), ".bashrc".to_string()]), excludes: Vec::new() };
+        assert_eq!((recipe.kind(), recipe.describe()), (SnapshotKind::Full, "the package lists, /etc and .config, .bashrc".to_string()));
+        let home = Components { etc: false, dotfiles: None, excludes: vec!["Videos".to_string()] };
+        assert_eq!((home.kind(), home.describe()), (SnapshotKind::Dotfiles, "the package lists and the home directory, leaving out Videos".to_string()));
+    }
+
+    #[test]
+    fn enabled_locales_skip_comments() {
+        assert_eq!(enabled_locales(LOCALE_GEN), ["fr_FR.UTF-8 UTF-8"]);
+    }
+
+    #[test]
+    fn enabling_locales_uncomments_the_real_entry_and_appends_unknown_ones() {
+        let locales = ["en_US.UTF-8 UTF-8", "fr_FR.UTF-8 UTF-8", "eo UTF-8"].map(String::from);
+        let text = enable_locales(LOCALE_GEN, &locales);
+        assert_eq!(text, "# Each line is of the form:\n#  en_US.UTF-8 UTF-8\n\n#de_DE.UTF-8 UTF-8\nen_US.UTF-8 UTF-8\nfr_FR.UTF-8 UTF-8\neo UTF-8\n");
+        assert_eq!(enabled_locales(&text), locales);
+    }
+
+    #[test]
+    fn presnapshot_hook_lists_its_targets_and_runs_for_the_user() {
+        let targets = ["linux".to_string(), "systemd".to_string()];
+        let hook = presnapshot_hook(Path::new("/usr/bin/arch-suite"), "alice", &targets);
+        assert!(hook.contains("Operation = Upgrade\nType = Package\nTarget = linux\nTarget = systemd\n"));
+        assert!(hook.contains("When = PreTransaction\nExec = /usr/bin/env SUDO_USER=alice /usr/bin/arch-suite snapshot create\n"));
+        assert_eq!(hook_targets(&hook), targets);
+
+        let every_package = presnapshot_hook(Path::new("/usr/bin/arch-suite"), "alice", &[]);
+        assert!(every_package.contains("Target = *\n"));
+        assert!(hook_targets(&every_package).is_empty());
+    }
+
+    #[test]
+    fn detected_dotfiles_are_hidden_entries_and_existing_configured_paths() {
+        let home = std::env::temp_dir().join(format!("arch-suite-dotfiles-{}", std::process::id()));
+        for dir in [".config/nvim", ".cache", "Documents"] {
+            std::fs::create_dir_all(home.join(dir)).unwrap();
+        }
+        std::fs::write(home.join(".bashrc"), "").unwrap();
+        let configured = [".config/nvim", ".zshrc"].map(String::from);
+        let found = detect_dotfiles(&home, &configured);
+        std::fs::remove_dir_all(&home).unwrap();
+        assert_eq!(found, [".bashrc", ".config", ".config/nvim"]);
+    }
+
+    #[test]
+    fn packages_and_etc_are_compared_both_ways() {
+        let list = |items: &[&str]| items.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let diff = package_diff(&list(&["base", "vim", "steam"]), &list(&["base", "vim", "emacs"]));
+        assert_eq!(diff, PackageDiff { only_snapshot: list(&["steam"]), only_here: list(&["emacs"]), common: 2 });
+        assert_eq!(
+            install_summary(&list(&["base", "vim", "steam", "yay"]), &list(&["yay"]), &list(&["base", "vim"])),
+            ["Installed 2 of the snapshot's 3 repository packages.", "⚠️ Not installed: steam.", "⚠️ Manual install needed for 1 foreign packages (AUR or local builds): yay."]
+        );
+
+        let output = "Files /tmp/cmp/etc/pacman.conf and /etc/pacman.conf differ\n\
+            Only in /tmp/cmp/etc/systemd: timesyncd.conf.d\n\
+            Only in /etc: machine-id\n\
+            Symbolic links /tmp/cmp/etc/localtime and /etc/localtime differ\n\
+            File /tmp/cmp/etc/mtab is a symbolic link while file /etc/mtab is a regular file\n";
+        let diff = parse_etc_diff(output, "/tmp/cmp/etc", "/etc");
+        assert_eq!(diff.differ, ["pacman.conf", "localtime", "mtab"]);
+        assert_eq!(diff.only_snapshot, ["systemd/timesyncd.conf.d"]);
+        assert_eq!(diff.only_here, ["machine-id"]);
+
+        assert!(is_under("pacman.d/mirrorlist", "pacman.d") && is_under("hosts", "hosts") && !is_under("hosts.deny", "hosts"));
+        assert_eq!(etc_extract_args(&list(&["hosts"]), &[]), ["etc/hosts"]);
+        assert_eq!(etc_extract_args(&list(&["pacman.d", "ssh"]), &list(&["pacman.d/mirrorlist"])), ["--anchored", "--exclude=etc/pacman.d/mirrorlist", "etc/pacman.d", "etc/ssh"]);
+    }
+
+    #[test]
+    fn audits_compare_enabled_units_and_the_localization() {
+        let listing = "etc/\netc/systemd/system/multi-user.target.wants/\netc/systemd/system/multi-user.target.wants/sshd.service\netc/systemd/system/timers.target.wants/fstrim.timer\netc/systemd/system/getty.target.wants/getty@tty1.service\netc/systemd/system/sshd.service.d/override.conf\n";
+        assert_eq!(enabled_units(listing.lines()), ["fstrim.timer", "getty@tty1.service", "sshd.service"]);
+
+        let expected = Localization { locale_conf: Some("LANG=de_DE.UTF-8\n".to_string()), timezone: Some("Europe/Berlin".to_string()), vconsole_conf: None, locales: vec!["de_DE.UTF-8 UTF-8".to_string()] };
+        let actual = Localization { timezone: Some("UTC".to_string()), vconsole_conf: Some("KEYMAP=us\n".to_string()), ..expected.clone() };
+        assert_eq!(localization_mismatches(&expected, &actual), ["time zone"]);
+        assert!(localization_mismatches(&expected, &expected).is_empty());
+    }
+
+    #[test]
+    fn a_deploy_resumes_only_from_the_same_snapshot() {
+        let staging = std::env::temp_dir().join(format!("arch-suite-deploy-{}", std::process::id()));
+        std::fs::create_dir_all(&staging).unwrap();
+        let snapshot = PathBuf::from("/home/ada/snapshot-20240501.tar.zst");
+        let state = DeployState { snapshot: snapshot.clone(), restore: vec!["pacman.conf".to_string()], entries: vec!["pacman.conf".to_string(), "fstab".to_string()], keep: Vec::new(), swap: DriverSwap::default(), done: vec![DeployPhase::Packages, DeployPhase::Etc] };
+        std::fs::write(staging.join(DEPLOY_STATE_FILE), serde_json::to_string(&state).unwrap()).unwrap();
+        let loaded = DeployState::load(&staging, &snapshot);
+        let other = DeployState::load(&staging, Path::new("/home/ada/other.tar.zst"));
+        std::fs::remove_dir_all(&staging).unwrap();
+        assert_eq!(loaded.as_ref(), Some(&state));
+        assert!(other.is_none());
+        assert_eq!(state.describe_done(), "packages installed, /etc restored");
+        assert!(!state.is_done(DeployPhase::Home));
+    }
+
+    #[test]
+    fn zone_name_is_taken_from_the_localtime_link() {
+        assert_eq!(zone_name(Path::new("/usr/share/zoneinfo/Europe/Berlin")).as_deref(), Some("Europe/Berlin"));
+        assert_eq!(zone_name(Path::new("../usr/share/zoneinfo/UTC")).as_deref(), Some("UTC"));
+        assert_eq!(zone_name(Path::new("/etc/somewhere")), None);
+    }
+
+    #[test]
+    fn interrupted_work_is_found_and_never_listed_as_a_snapshot() {
+        let dir = std::env::temp_dir().join(format!("arch-suite-leftovers-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("snapshot_tmp")).unwrap();
+        let file = dir.join("snapshot-20240501.tar.zst");
+        std::fs::write(partial_path(&file), "half").unwrap();
+        std::fs::write(dir.join("backup-Documents-20240501-153000.tar.zst"), "done").unwrap();
+        assert_eq!(partial_path(&file), dir.join("snapshot-20240501.tar.zst.part"));
+        assert!(is_partial(&partial_path(&file)) && !is_partial(&file));
+        assert_eq!(leftovers(&[dir.clone(), dir.clone()]), [dir.join("snapshot-20240501.tar.zst.part"), dir.join("snapshot_tmp")]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn encrypted_snapshots_take_the_passphrase_on_stdin_only() {
+        let file = Path::new("/work/snapshot-20240501.tar.zst");
+        let encrypted = encrypted_path(file);
+        assert_eq!(encrypted, Path::new("/work/snapshot-20240501.tar.zst.gpg"));
+        assert!(is_encrypted(&encrypted) && !is_encrypted(file));
+        let args = encrypt_args(file, &encrypted);
+        assert!(args.windows(2).any(|pair| pair == ["--passphrase-fd", "0"]) && args.iter().any(|arg| arg == "--symmetric"));
+        assert!(args.ends_with(&["/work/snapshot-20240501.tar.zst.gpg".to_string(), "/work/snapshot-20240501.tar.zst".to_string()]));
+        assert!(decrypt_args(&encrypted, file).windows(3).any(|args| args == ["--decrypt", "--output", "/work/snapshot-20240501.tar.zst"]));
+        let listed = SnapshotFile { path: encrypted, modified: None, bytes: 0, packages: None };
+        assert!(listed.label(0).ends_with("encrypted"));
+    }
+
+    #[test]
+    fn compression_levels_are_clamped_per_algorithm() {
+        assert_eq!(Compression::Zstd.clamp_level(25), 19);
+        assert_eq!(Compression::Gzip.clamp_level(0), 1);
+        assert_eq!(Compression::Xz.clamp_level(0), 0);
+        assert_eq!(Compression::Gzip.clamp_level(6), 6);
+    }
+
+    #[test]
+    fn pinned_versions_come_from_package_files_or_are_reported() {
+        let pinned = parse_versions("linux 6.9.1.arch1-1\nmesa 1:24.1.0-1\nnvidia 550.78-1\nvim 9.1.0-1\n");
+        let installed = parse_versions("linux 6.9.3.arch1-1\nmesa 1:24.1.1-1\nvim 9.1.0-1\n");
+        let files = ["/tmp/arch-suite-snapshot/pkg/linux-6.9.1.arch1-1-x86_64.pkg.tar.zst", "/tmp/arch-suite-snapshot/pkg/linux-6.9.1.arch1-1-x86_64.pkg.tar.zst.sig", "/tmp/arch-suite-snapshot/pkg/linux-api-headers-6.8-1-any.pkg.tar.zst"].map(String::from);
+        let plan = pin_plan(&pinned, &installed, &files);
+        assert_eq!(plan.files, ["/tmp/arch-suite-snapshot/pkg/linux-6.9.1.arch1-1-x86_64.pkg.tar.zst"]);
+        assert_eq!(plan.unavailable, ["mesa (snapshot 1:24.1.0-1, installed 1:24.1.1-1)"]);
+    }
+
+    #[test]
+    fn drift_counts_what_pacman_did_after_the_snapshot() {
+        let log = "[2019-01-01 10:00] [ALPM] installed ancient (1.0-1)
+[2024-05-01T09:00:00+0200] [ALPM] installed before (1.0-1)
+[2024-05-01T11:00:00+0200] [PACMAN] Running 'pacman -S vim'
+[2024-05-01T11:00:01+0200] [ALPM] installed vim (9.1-1)
+[2024-05-01T11:00:02+0200] [ALPM] upgraded glibc (2.39-1 -> 2.39-2)
+[2024-05-02T08:00:00+0200] [ALPM] removed nano (8.0-1)
+[2024-05-02T08:00:01+0200] [ALPM-SCRIPTLET] installed nothing
+";
+        let since = chrono::DateTime::parse_from_rfc3339("2024-05-01T10:00:00+02:00").unwrap();
+        let drift = Drift { etc_changed: 3, ..package_drift(log, since) };
+        assert_eq!(drift, Drift { installed: 1, removed: 1, upgraded: 1, etc_changed: 3 });
+        assert_eq!(drift.total(), 5);
+        assert_eq!(drift.summary(), "1 packages installed, 1 removed, 1 upgraded, 3 /etc files changed");
+    }
+}
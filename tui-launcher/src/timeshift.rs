@@ -0,0 +1,141 @@
+// ===================================================================
+// Timeshift Module
+// ===================================================================
+// Restore points in Timeshift's rsync layout, so Timeshift lists the
+// ones made here and the other way round:
+//
+//   <dir>/snapshots/2024-05-01_15-30-00/info.json
+//   <dir>/snapshots/2024-05-01_15-30-00/exclude.list
+//   <dir>/snapshots/2024-05-01_15-30-00/localhost/   (the files)
+//
+// Files unchanged since the previous restore point are hard links to
+// it (`rsync --link-dest`), so each one only costs what changed.
+
+use chrono::{DateTime, Local, TimeZone};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+/// Timeshift's default exclusions: pseudo and temporary filesystems, other mounts, the restore
+/// points themselves, and the contents of home directories, which are user data.
+pub const EXCLUDES: [&str; 14] = [
+    "/dev/*", "/proc/*", "/sys/*", "/run/*", "/tmp/*", "/mnt/*", "/media/*", "/lost+found",
+    "/timeshift/*", "/swapfile", "/var/run/*", "/var/lock/*", "/home/*/**", "/root/**",
+];
+
+/// The tag Timeshift gives restore points made by hand ("on demand").
+const ON_DEMAND: &str = "O";
+
+/// The directory of a restore point made at `at`, e.g. `2024-05-01_15-30-00`.
+pub fn snapshot_name(at: DateTime<Local>) -> String {
+    at.format("%Y-%m-%d_%H-%M-%S").to_string()
+}
+
+/// The `info.json` Timeshift reads to list a restore point.
+pub fn info_json(at: DateTime<Local>, sys_uuid: &str, distro: &str, comment: &str) -> String {
+    let info = json!({
+        "created": at.timestamp().to_string(),
+        "sys-uuid": sys_uuid,
+        "sys-distro": distro,
+        "app-version": format!("arch-suite {}", env!("CARGO_PKG_VERSION")),
+        "tags": ON_DEMAND,
+        "comments": comment,
+    });
+    serde_json::to_string_pretty(&info).unwrap_or_default()
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct RestorePoint {
+    /// The directory it is kept in.
+    pub path: PathBuf,
+    pub name: String,
+    pub created: Option<DateTime<Local>>,
+    /// Timeshift's schedule letters: O(n demand), B(oot), H(ourly), D(aily), W(eekly), M(onthly).
+    pub tags: String,
+    pub comment: String,
+}
+
+impl RestorePoint {
+    /// Reads a restore point's `info.json`; one without it is not a restore point.
+    pub fn parse(path: &Path, info: &str) -> Option<Self> {
+        let info: Value = serde_json::from_str(info).ok()?;
+        let field = |key: &str| info.get(key).and_then(Value::as_str).unwrap_or_default().to_string();
+        let created = field("created").parse::<i64>().ok().and_then(|t| Local.timestamp_opt(t, 0).single());
+        Some(RestorePoint { path: path.to_path_buf(), name: path.file_name()?.to_string_lossy().to_string(), created, tags: field("tags"), comment: field("comments") })
+    }
+
+    pub fn label(&self) -> String {
+        let created = self.created.map_or(self.name.clone(), |at| at.format("%Y-%m-%d %H:%M").to_string());
+        format!("{}  [{}]  {}", created, self.tags, self.comment)
+    }
+
+    /// Where the files are.
+    pub fn files(&self) -> PathBuf {
+        self.path.join("localhost")
+    }
+}
+
+/// The restore points under `dir`, newest first.
+pub fn list(dir: &Path) -> Vec<RestorePoint> {
+    let mut points: Vec<RestorePoint> = std::fs::read_dir(dir.join("snapshots"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| RestorePoint::parse(&entry.path(), &std::fs::read_to_string(entry.path().join("info.json")).ok()?))
+        .collect();
+    points.sort_by(|a, b| b.name.cmp(&a.name));
+    points
+}
+
+/// The rsync arguments that copy this system into `point`, hard-linking what has not changed
+/// since `previous`.
+pub fn create_args(point: &Path, previous: Option<&RestorePoint>) -> Vec<String> {
+    let mut args: Vec<String> = ["-aAXH", "--delete", "--info=progress2"].map(String::from).to_vec();
+    if let Some(previous) = previous {
+        args.push(format!("--link-dest={}/", previous.files().display()));
+    }
+    args.push(format!("--exclude-from={}", point.join("exclude.list").display()));
+    args.extend(["/".to_string(), format!("{}/", point.join("localhost").display())]);
+    args
+}
+
+/// The rsync arguments that bring `root` back to `point`. Excluded paths are neither copied nor
+/// deleted, so home directories and mounts are left alone.
+pub fn restore_args(point: &RestorePoint, root: &Path) -> Vec<String> {
+    let mut args: Vec<String> = ["-aAXH", "--delete", "--info=progress2"].map(String::from).to_vec();
+    let exclude_list = point.path.join("exclude.list");
+    if exclude_list.is_file() {
+        args.push(format!("--exclude-from={}", exclude_list.display()));
+    } else {
+        args.extend(EXCLUDES.iter().map(|pattern| format!("--exclude={}", pattern)));
+    }
+    args.extend([format!("{}/", point.files().display()), format!("{}/", root.display().to_string().trim_end_matches('/'))]);
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restore_points_round_trip_through_info_json() {
+        let at = Local.with_ymd_and_hms(2024, 5, 1, 15, 30, 0).unwrap();
+        assert_eq!(snapshot_name(at), "2024-05-01_15-30-00");
+        let path = Path::new("/timeshift/snapshots/2024-05-01_15-30-00");
+        let point = RestorePoint::parse(path, &info_json(at, "1234-abcd", "Arch Linux", "before kernel update")).unwrap();
+        assert_eq!((point.created, point.tags.as_str(), point.comment.as_str()), (Some(at), "O", "before kernel update"));
+        assert_eq!(point.label(), "2024-05-01 15:30  [O]  before kernel update");
+        assert!(RestorePoint::parse(path, "not json").is_none());
+    }
+
+    #[test]
+    fn new_restore_points_link_to_the_previous_one() {
+        let previous = RestorePoint::parse(Path::new("/timeshift/snapshots/old"), r#"{"created": "0"}"#).unwrap();
+        let args = create_args(Path::new("/timeshift/snapshots/new"), Some(&previous));
+        assert!(args.contains(&"--link-dest=/timeshift/snapshots/old/localhost/".to_string()));
+        assert_eq!(&args[args.len() - 2..], ["/", "/timeshift/snapshots/new/localhost/"]);
+
+        let restore = restore_args(&previous, Path::new("/mnt/"));
+        assert!(restore.contains(&"--exclude=/home/*/**".to_string()));
+        assert_eq!(&restore[restore.len() - 2..], ["/timeshift/snapshots/old/localhost/", "/mnt/"]);
+    }
+}
@@ -2,120 +2,858 @@
 // UI Rendering Module
 // ===================================================================
 use crate::app::{App, AppView, MenuItem, Popup, StatefulList};
+use crate::config::ThemeColors;
+use crate::disk::{human_size, BlockDevice, MOUNTED_MARK};
+use crate::highlight::{self, LineKind};
+use crate::keymap::KeyMap;
+use crate::workflow::{StepStatus, Workflow};
 use ratatui::{
     prelude::*,
-    widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Wrap},
+    widgets::{
+        block::{Position, Title},
+        Block, Borders, Cell, Clear, Gauge, List, ListItem, Paragraph, Row, Scrollbar, ScrollbarOrientation, ScrollbarState, Table, Tabs, Wrap,
+    },
 };
+use serde::Deserialize;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
 use textwrap::wrap;
 
+/// The active theme; set from [ui] in the config file at start-up and by the setup wizard.
+static THEME: RwLock<Theme> = RwLock::new(Theme::Dark);
+/// The colours `theme.toml` sets, drawn instead of the theme's own; set at start-up.
+static COLORS: RwLock<ThemeColors> = RwLock::new(ThemeColors { accent: None, highlight_bg: None, popup_bg: None, text: None });
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    #[default]
+    Dark,
+    /// For terminals with a light background.
+    Light,
+    HighContrast,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::Dark, Theme::Light, Theme::HighContrast];
+
+    /// The name used in the config file.
+    pub fn name(self) -> &'static str {
+        match self { Theme::Dark => "dark", Theme::Light => "light", Theme::HighContrast => "high-contrast" }
+    }
+
+    pub fn current() -> Theme {
+        *THEME.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn set(self) {
+        *THEME.write().unwrap_or_else(|e| e.into_inner()) = self;
+    }
+
+    fn colors() -> ThemeColors {
+        *COLORS.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn accent(self) -> Color {
+        Self::colors().accent.unwrap_or(match self { Theme::Dark => Color::Rgb(110, 125, 224), Theme::Light => Color::Rgb(40, 60, 170), Theme::HighContrast => Color::Cyan })
+    }
+
+    fn selection(self) -> Color {
+        Self::colors().highlight_bg.unwrap_or(match self { Theme::Dark => Color::Rgb(60, 60, 90), Theme::Light => Color::Rgb(200, 205, 235), Theme::HighContrast => Color::Blue })
+    }
+
+    /// Menu items that cannot run yet.
+    fn muted(self) -> Color {
+        match self { Theme::HighContrast => Color::Gray, _ => Color::DarkGray }
+    }
+
+    fn text(self) -> Color {
+        Self::colors().text.unwrap_or(match self { Theme::Light => Color::Black, _ => Color::White })
+    }
+
+    /// Background and text of popups.
+    fn surface(self) -> Style {
+        let bg = Self::colors().popup_bg.unwrap_or(match self { Theme::Dark => Color::Rgb(40, 40, 60), Theme::Light => Color::Rgb(235, 235, 242), Theme::HighContrast => Color::Black });
+        Style::default().bg(bg).fg(self.text())
+    }
+}
+
+impl ThemeColors {
+    pub fn set(self) {
+        *COLORS.write().unwrap_or_else(|e| e.into_inner()) = self;
+    }
+}
+
 pub fn ui(f: &mut Frame, app: &mut App) {
-    let main_layout = Layout::default().constraints([Constraint::Percentage(100)]).split(f.size());
+    let screen = Layout::default().constraints([Constraint::Length(1), Constraint::Min(0)]).split(f.size());
+    render_tabs(f, app.current_view, screen[0]);
+    // Wide terminals show the selected item's help beside the menu instead of only on '?'.
+    let two_pane = app.two_pane_width > 0 && f.size().width >= app.two_pane_width && app.current_menu().is_some();
+    let main_layout = if two_pane {
+        Layout::default().direction(Direction::Horizontal).constraints([Constraint::Percentage(55), Constraint::Percentage(45)]).split(screen[1])
+    } else {
+        Layout::default().constraints([Constraint::Percentage(100)]).split(screen[1])
+    };
+
+    // The two-pane layout already shows the help beside the menu.
+    let preview = app.current_menu().and_then(|menu| menu.selected_item()).map(|item| item.help).filter(|_| app.help_footer && !two_pane);
+
+    // Why each item of the current menu cannot run yet, if it cannot.
+    let blocked: Vec<Option<String>> = app.current_menu().map_or_else(Vec::new, |menu| menu.items.iter().map(|item| item.is_available(app).err()).collect());
+
+    let decor = MenuDecor { workflow: None, blocked: &blocked, preview, expert: app.expert_mode, safe: app.safe_mode, banner: app.banner.as_deref(), drift: app.drift.as_ref() };
 
     // The main view is always rendered. Popups are drawn on top.
-    match app.current_view {
-        AppView::MainMenu => render_menu(f, &mut app.main_menu, "Main Menu", main_layout[0], true),
-        AppView::Replicator => render_menu(f, &mut app.replicator_menu, "Replicator Menu", main_layout[0], false),
-        AppView::Cloner => render_menu(f, &mut app.cloner_menu, "Cloner Menu", main_layout[0], false),
-        AppView::Utilities => render_menu(f, &mut app.utilities_menu, "Utilities Menu", main_layout[0], false),
-        AppView::ManualInstaller => render_menu(f, &mut app.manual_install_menu, "Manual Installer", main_layout[0], false),
-        AppView::HelpManual => render_help_manual(f, main_layout[0]),
+    let menu_rows = match app.current_view {
+        AppView::MainMenu => Some(render_menu(f, &mut app.main_menu, AppView::MainMenu.title(), main_layout[0], true, &decor)),
+        AppView::Replicator => Some(render_menu(f, &mut app.replicator_menu, AppView::Replicator.title(), main_layout[0], false, &decor)),
+        AppView::Cloner => Some(render_menu(f, &mut app.cloner_menu, AppView::Cloner.title(), main_layout[0], false, &decor)),
+        AppView::Utilities => Some(render_menu(f, &mut app.utilities_menu, AppView::Utilities.title(), main_layout[0], false, &decor)),
+        AppView::ManualInstaller => {
+            let done = app.workflow.steps.iter().filter(|s| s.status == StepStatus::Done).count();
+            let title = format!("{} ({} of {} steps done)", AppView::ManualInstaller.title(), done, app.workflow.steps.len());
+            Some(render_menu(f, &mut app.manual_install_menu, &title, main_layout[0], false, &MenuDecor { workflow: Some(&app.workflow), ..decor }))
+        }
+        AppView::HelpManual => {
+            render_help_manual(f, app, main_layout[0]);
+            None
+        }
+        AppView::Logs => {
+            render_logs(f, app, main_layout[0]);
+            None
+        }
+        AppView::Snapshots => {
+            render_snapshots(f, app, main_layout[0]);
+            None
+        }
+    };
+    if let Some(rows) = menu_rows {
+        app.list_page = rows;
+    }
+    if two_pane {
+        render_details(f, app, main_layout[1]);
     }
 
     match app.active_popup {
         Popup::Help => render_help_popup(f, app),
         Popup::Action => render_action_popup(f, app),
+        Popup::Report => render_report_popup(f, app),
         Popup::Confirm => render_confirm_popup(f, app),
+        Popup::Phrase => render_phrase_popup(f, app),
         Popup::Input => render_input_popup(f, app),
         Popup::Select => render_select_popup(f, app),
+        Popup::MultiSelect => render_multi_select_popup(f, app),
+        Popup::Diff => render_diff_popup(f, app),
+        Popup::Results => render_results_popup(f, app),
+        Popup::Services => render_services_popup(f, app),
         Popup::None => {}
     }
+    if let Some((text, _)) = &app.notification {
+        render_notification(f, text);
+    }
+    if app.show_keys {
+        render_keys_overlay(f, app);
+    }
 }
 
-fn render_menu(f: &mut Frame, list: &mut StatefulList<MenuItem>, title: &str, area: Rect, show_art: bool) {
-    let chunks = if show_art {
-        Layout::default().direction(Direction::Vertical).margin(2)
-            .constraints([Constraint::Length(8), Constraint::Min(0), Constraint::Length(1)]).split(area)
-    } else {
-        Layout::default().direction(Direction::Vertical).margin(2)
-            .constraints([Constraint::Min(0), Constraint::Length(1)]).split(area)
-    };
-    if show_art {
-        let art = Paragraph::new(ASCII_ART).style(Style::default().fg(Color::Rgb(110, 125, 224))).alignment(Alignment::Center);
+/// A quick success, in a bordered line above the bottom of the screen until it times out.
+fn render_notification(f: &mut Frame, text: &str) {
+    let screen = f.size();
+    let width = (text.chars().count() as u16 + 4).min(screen.width);
+    let area = Rect { x: screen.x + (screen.width - width) / 2, y: screen.bottom().saturating_sub(4), width, height: 3.min(screen.height) };
+    let block = Block::default().borders(Borders::ALL).border_style(Style::default().fg(Color::Green));
+    f.render_widget(Clear, area);
+    f.render_widget(Paragraph::new(text).block(block), area);
+}
+
+/// The F1 cheatsheet: the keys that work in the current context, from the `KeyMap`.
+fn render_keys_overlay(f: &mut Frame, app: &App<'_>) {
+    let context = KeyMap::context(app);
+    let area = centered_rect(60, 60, f.size());
+    let title = format!(" Keys: {} ", context.name());
+    let rows: Vec<Row> = KeyMap::bindings(context)
+        .map(|b| Row::new(vec![Cell::from(b.keys).style(Style::default().fg(Theme::current().accent()).add_modifier(Modifier::BOLD)), Cell::from(b.description)]))
+        .collect();
+    let table = Table::new(rows)
+        .header(Row::new(vec!["Key", "Action"]).style(Style::default().add_modifier(Modifier::UNDERLINED)))
+        .block(popup_block(&title).title(Title::from(" any key to close ").position(Position::Bottom).alignment(Alignment::Right)))
+        .widths(&[Constraint::Length(18), Constraint::Min(10)]);
+    f.render_widget(Clear, area);
+    f.render_widget(table, area);
+}
+
+/// What a menu shows besides its items.
+#[derive(Clone, Copy)]
+struct MenuDecor<'a> {
+    /// Its steps (the first menu items) show their status and the next one is highlighted.
+    workflow: Option<&'a Workflow>,
+    /// Items with a reason are dimmed, and the reason replaces the key hints while one is selected.
+    blocked: &'a [Option<String>],
+    /// The selected item's help, shown wrapped between the menu and the key hints.
+    preview: Option<&'a str>,
+    /// Expert mode is on; the status bar says so.
+    expert: bool,
+    /// Safe mode is on; the status bar says so, ahead of expert mode.
+    safe: bool,
+    /// The art above the main menu, unless it is turned off.
+    banner: Option<&'a str>,
+    /// What changed since the latest snapshot, for the main menu's status bar.
+    drift: Option<&'a (String, bool)>,
+}
+
+/// The item's icon with its hotkey underlined, e.g. "[" "S" "]".
+fn icon_spans<'a>(item: &MenuItem<'a>) -> Vec<Span<'a>> {
+    let at = item.hotkey.and_then(|key| item.icon.find(key));
+    match (item.hotkey, at) {
+        (Some(key), Some(at)) => vec![
+            Span::raw(&item.icon[..at]),
+            Span::styled(&item.icon[at..at + key.len_utf8()], Style::default().add_modifier(Modifier::UNDERLINED)),
+            Span::raw(&item.icon[at + key.len_utf8()..]),
+        ],
+        _ => vec![Span::raw(item.icon)],
+    }
+}
+
+/// Returns the rows the list shows, the page PgUp/PgDn move by.
+fn render_menu(f: &mut Frame, list: &mut StatefulList<MenuItem>, title: &str, area: Rect, show_art: bool, decor: &MenuDecor) -> u16 {
+    let MenuDecor { workflow, blocked, preview, expert, safe, banner, drift } = *decor;
+    let banner = banner.filter(|_| show_art);
+    let mut constraints = vec![Constraint::Min(0), Constraint::Length(if preview.is_some() { 4 } else { 0 }), Constraint::Length(1)];
+    if let Some(banner) = banner {
+        constraints.insert(0, Constraint::Length(banner.lines().count() as u16 + 1));
+    }
+    let chunks = Layout::default().direction(Direction::Vertical).margin(2).constraints(constraints).split(area);
+    if let Some(banner) = banner {
+        // Every line padded to the widest, so centering keeps the art's shape.
+        let widest = banner.lines().map(|line| Line::from(line).width()).max().unwrap_or(0);
+        let lines: Vec<Line> = banner.lines().map(|line| Line::from(format!("{:width$}", line, width = widest))).collect();
+        let art = Paragraph::new(lines).style(Style::default().fg(Theme::current().accent())).alignment(Alignment::Center);
         f.render_widget(art, chunks[0]);
     }
-    let list_chunk = if show_art { chunks[1] } else { chunks[0] };
-    let status_chunk = if show_art { chunks[2] } else { chunks[1] };
-    let items: Vec<ListItem> = list.items.iter().map(|i| ListItem::new(format!("{} {}", i.icon, i.text)).style(Style::default().fg(Color::White))).collect();
+    let offset = usize::from(banner.is_some());
+    let (list_chunk, preview_chunk, status_chunk) = (chunks[offset], chunks[offset + 1], chunks[offset + 2]);
+    if let Some(help) = preview {
+        let paragraph = Paragraph::new(help).block(Block::default().borders(Borders::TOP).title("Help")).style(Style::default().fg(Theme::current().muted())).wrap(Wrap { trim: true });
+        f.render_widget(paragraph, preview_chunk);
+    }
+    let current = workflow.and_then(Workflow::current);
+    // Borders and the highlight symbol; the rest is shared by the mark, the icon and the text.
+    let room = list_chunk.width.saturating_sub(2 + 3) as usize;
+    let items: Vec<ListItem> = list
+        .items
+        .iter()
+        .enumerate()
+        .map(|(n, i)| {
+            let (mark, note, style) = match workflow.and_then(|w| w.steps.get(n)) {
+                Some(step) if step.status == StepStatus::Failed => (step.status.mark(), "  ← failed, press r to re-run", Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)),
+                Some(step) if current == Some(n) => (step.status.mark(), "  ← next", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+                Some(step) => {
+                    let color = match step.status {
+                        StepStatus::Done => Color::Green,
+                        StepStatus::Stale => Color::Magenta,
+                        _ => Theme::current().text(),
+                    };
+                    (step.status.mark(), "", Style::default().fg(color))
+                }
+                None => ("", "", Style::default().fg(Theme::current().text())),
+            };
+            let style = if blocked.get(n).is_some_and(Option::is_some) { Style::default().fg(Theme::current().muted()) } else { style };
+            let mut spans = if mark.is_empty() { Vec::new() } else { vec![Span::raw(format!("{} ", mark))] };
+            spans.extend(icon_spans(i));
+            let used: usize = spans.iter().map(Span::width).sum();
+            spans.push(Span::raw(truncate(&format!(" {}{}", i.text, note), room.saturating_sub(used))));
+            ListItem::new(Line::from(spans)).style(style)
+        })
+        .collect();
     let list_widget = List::new(items)
         .block(Block::default().borders(Borders::ALL).title(title))
-        .highlight_style(Style::default().bg(Color::Rgb(60, 60, 90)).add_modifier(Modifier::BOLD))
+        .highlight_style(Style::default().bg(Theme::current().selection()).add_modifier(Modifier::BOLD))
         .highlight_symbol(">> ");
     f.render_stateful_widget(list_widget, list_chunk, &mut list.state);
     let status_text = if show_art { "v4.0.0 | 'j'/'k' to navigate | 'Enter' to select | '?' for help | 'q' to quit" } else { "'j'/'k' to navigate | 'Enter' to select | '?' for help | 'Esc' to go back" };
-    let status = Paragraph::new(status_text).alignment(Alignment::Center);
+    let status = match list.state.selected().and_then(|n| blocked.get(n)).and_then(Option::as_deref) {
+        Some(reason) => Paragraph::new(format!("⛔ {}", reason)).style(Style::default().fg(Color::Yellow)),
+        None if safe => Paragraph::new("🔒 Safe mode: destructive actions are blocked").style(Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)),
+        None if expert => Paragraph::new("⚡ Expert mode: confirmations that default to yes are skipped | 'X' to leave").style(Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD)),
+        None => match drift.filter(|_| show_art) {
+            Some((note, true)) => Paragraph::new(format!("📸 {}", note)).style(Style::default().fg(Color::Yellow)),
+            Some((note, false)) => Paragraph::new(note.as_str()).style(Style::default().fg(Theme::current().muted())),
+            None => Paragraph::new(status_text),
+        },
+    }
+    .alignment(Alignment::Center);
     f.render_widget(status, status_chunk);
+    list_chunk.height.saturating_sub(2).max(1)
 }
 
-fn render_help_manual(f: &mut Frame, area: Rect) {
-    let help_text = "This is the main help page for Arch System Suite v4.0.0.\n\nIt contains detailed sections on the Replicator, Cloner, and all Utilities, explaining each feature in depth.\n\nPress 'q' or 'Esc' to return to the main menu.";
-    let paragraph = Paragraph::new(help_text).block(Block::default().borders(Borders::ALL).title("Help Manual")).wrap(Wrap { trim: true });
+/// The right-hand pane of the two-pane layout: the selected item's help and, if it cannot run yet, why.
+fn render_details(f: &mut Frame, app: &App<'_>, area: Rect) {
+    // Same margins as the menu, so both boxes line up.
+    let area = Layout::default().margin(2).constraints([Constraint::Min(0), Constraint::Length(1)]).split(area)[0];
+    let Some(item) = app.current_menu().and_then(|menu| menu.selected_item()) else { return };
+    let mut lines = vec![
+        Line::styled(format!("{} {}", item.icon, item.text), Style::default().fg(Theme::current().accent()).add_modifier(Modifier::BOLD)),
+        Line::from(""),
+        Line::from(item.help),
+    ];
+    if let Some(note) = item.unavailable_note(app) {
+        lines.push(Line::from(""));
+        lines.push(Line::styled(note, Style::default().fg(Color::Yellow)));
+    }
+    let paragraph = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Details")).wrap(Wrap { trim: true });
     f.render_widget(paragraph, area);
 }
 
+/// The tab bar; nothing is highlighted on the main menu and the help manual, which have no tab.
+fn render_tabs(f: &mut Frame, view: AppView, area: Rect) {
+    let titles: Vec<Line> = AppView::TABS.iter().enumerate().map(|(i, tab)| Line::from(format!("{} {}", i + 1, tab.tab_label()))).collect();
+    let selected = AppView::TABS.iter().position(|tab| *tab == view).unwrap_or(usize::MAX);
+    let tabs = Tabs::new(titles)
+        .select(selected)
+        .style(Style::default().fg(Color::DarkGray))
+        .highlight_style(Style::default().fg(Theme::current().accent()).add_modifier(Modifier::BOLD | Modifier::REVERSED));
+    f.render_widget(tabs, area);
+}
+
+/// The Logs tab: this session's results, newest first; Enter reopens one.
+fn render_logs(f: &mut Frame, app: &mut App<'_>, area: Rect) {
+    let block = Block::default().borders(Borders::ALL).title(format!("{} ({}) | Enter reopens", AppView::Logs.title(), app.results.len()));
+    if app.results.is_empty() {
+        f.render_widget(Paragraph::new("Nothing has finished in this session yet. Results appear here as actions complete.").block(block).wrap(Wrap { trim: true }), area);
+        return;
+    }
+    let items: Vec<ListItem> = app
+        .results
+        .iter()
+        .rev()
+        .zip(&app.log_list.items)
+        .map(|(entry, line)| ListItem::new(line.clone()).style(Style::default().fg(if entry.ok { Theme::current().text() } else { Color::Red })))
+        .collect();
+    let list = List::new(items).block(block).highlight_style(Style::default().bg(Theme::current().selection()).add_modifier(Modifier::BOLD)).highlight_symbol(">> ");
+    app.list_page = area.height.saturating_sub(2).max(1);
+    f.render_stateful_widget(list, area, &mut app.log_list.state);
+}
+
+/// The snapshot browser: the work directory's snapshots, newest first, and what was last deleted.
+fn render_snapshots(f: &mut Frame, app: &mut App<'_>, area: Rect) {
+    let block = Block::default().borders(Borders::ALL).title(format!("{} ({}) | Enter deploys | 'd' deletes", AppView::Snapshots.title(), app.snapshot_files.len()));
+    let block = match &app.snapshot_notice {
+        Some(notice) => block.title(Title::from(format!(" {} ", notice)).position(Position::Bottom)),
+        None => block,
+    };
+    if app.snapshot_files.is_empty() {
+        f.render_widget(Paragraph::new("There are no snapshots in the work directory. Create one from the Replicator menu.").block(block).wrap(Wrap { trim: true }), area);
+        return;
+    }
+    let items: Vec<ListItem> = app.snapshot_list.items.iter().map(|line| ListItem::new(line.clone()).style(Style::default().fg(Theme::current().text()))).collect();
+    let list = List::new(items).block(block).highlight_style(Style::default().bg(Theme::current().selection()).add_modifier(Modifier::BOLD)).highlight_symbol(">> ");
+    app.list_page = area.height.saturating_sub(2).max(1);
+    f.render_stateful_widget(list, area, &mut app.snapshot_list.state);
+}
+
+/// The manual, wrapped to the view, with search matches highlighted and the one last jumped to
+/// in the selection colour.
+fn render_help_manual(f: &mut Frame, app: &mut App<'_>, area: Rect) {
+    app.manual_rows = wrap(&crate::manual::text(app), area.width.saturating_sub(2).max(1) as usize).iter().map(|row| row.to_string()).collect();
+    let query = app.manual_search.value();
+    let lines: Vec<Line> = app
+        .manual_rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let style = Style::default().bg(if app.manual_match == Some(i) { Theme::current().selection() } else { Color::Yellow }).fg(Color::Black);
+            let mut spans = Vec::new();
+            let mut at = 0;
+            for range in crate::manual::match_ranges(row, query) {
+                spans.push(Span::raw(row[at..range.start].to_string()));
+                spans.push(Span::styled(row[range.clone()].to_string(), style));
+                at = range.end;
+            }
+            spans.push(Span::raw(row[at..].to_string()));
+            Line::from(spans)
+        })
+        .collect();
+    let visible = area.height.saturating_sub(2);
+    let max_scroll = (lines.len() as u16).saturating_sub(visible);
+    app.manual_scroll = app.manual_scroll.min(max_scroll);
+    app.manual_page = visible.max(1);
+    let status = match (app.manual_searching, query) {
+        (true, query) => format!(" /{}▏ ", query),
+        (false, "") => " '/' search | 'j'/'k' or PgUp/PgDn to scroll | 'q' back ".to_string(),
+        (false, query) => {
+            let found = app.manual_rows.iter().filter(|row| !crate::manual::match_ranges(row, query).is_empty()).count();
+            if found == 0 { format!(" No match for '{}' ", query) } else { format!(" '{}': {} rows | 'n'/'N' next/previous ", query, found) }
+        }
+    };
+    let block = Block::default().borders(Borders::ALL).title("Help Manual").title(Title::from(status).position(Position::Bottom).alignment(Alignment::Right));
+    f.render_widget(Paragraph::new(lines).block(block).scroll((app.manual_scroll, 0)), area);
+    render_scrollbar(f, area, app.manual_scroll, max_scroll);
+}
+
+/// A scrollbar on the right border of the bordered `area`, when there is anything to scroll.
+fn render_scrollbar(f: &mut Frame, area: Rect, scroll: u16, max_scroll: u16) {
+    if max_scroll == 0 {
+        return;
+    }
+    let mut state = ScrollbarState::new(max_scroll as usize + 1).position(scroll as usize);
+    let bar = Scrollbar::new(ScrollbarOrientation::VerticalRight).begin_symbol(None).end_symbol(None);
+    f.render_stateful_widget(bar, area.inner(&Margin { vertical: 1, horizontal: 0 }), &mut state);
+}
+
 fn render_help_popup(f: &mut Frame, app: &App<'_>) {
-    let help_text = match app.current_view {
-        AppView::MainMenu => app.main_menu.selected_item().map_or("", |i| i.help),
-        AppView::Replicator => app.replicator_menu.selected_item().map_or("", |i| i.help),
-        AppView::Cloner => app.cloner_menu.selected_item().map_or("", |i| i.help),
-        AppView::Utilities => app.utilities_menu.selected_item().map_or("", |i| i.help),
-        AppView::ManualInstaller => app.manual_install_menu.selected_item().map_or("", |i| i.help),
-        AppView::HelpManual => "This is the main help page. Use 'q' or 'Esc' to return to the previous menu.",
-        // FIX: ActionPopup is a popup, not a view with its own help. We show help for the view behind it.
-        AppView::ActionPopup => "", // Should not happen as help is disabled during action popups.
+    let help_text = match app.current_menu().and_then(|menu| menu.selected_item()) {
+        Some(item) => match item.unavailable_note(app) {
+            // The full name first: the menu may have cut it short.
+            None => format!("{} {}\n\n{}", item.icon, item.text, item.help),
+            Some(note) => format!("{} {}\n\n{}\n\n{}", item.icon, item.text, item.help, note),
+        },
+        None if app.current_view == AppView::HelpManual => "This is the main help page. Use 'q' or 'Esc' to return to the previous menu.".to_string(),
+        None => String::new(),
+    };
+    render_popup(f, "Context Help", &help_text, 60);
+}
+
+fn render_action_popup(f: &mut Frame, app: &mut App<'_>) {
+    let copy_hint = match (&app.task, app.popup_retry.is_some(), app.popup_path.is_some()) {
+        (Some(task), _, _) if task.follow => "Esc to stop | 'c' copy",
+        (Some(_), _, _) => "Esc to cancel | 'c' copy",
+        (None, true, _) => "'r' retry | 'c' copy",
+        (None, false, true) => "'c' copy | 'p' copy path",
+        (None, false, false) => "'c' copy",
+    };
+    let mut hint = if app.popup_wrap { format!(" {} | 's' save | 'w' unwrap ", copy_hint) } else { format!(" {} | 's' save | 'w' wrap | 'h'/'l' to pan ", copy_hint) };
+    let gauge_rows = if app.popup_progress.is_some() { 2 } else { 0 };
+    // Wide enough for the title and the footer, so short results are not cut off mid-hint.
+    let footer = hint.chars().count() + app.popup_notice.as_ref().map_or(0, |n| n.chars().count() + 3);
+    let min_width = Line::from(app.popup_title.as_str()).width().max(footer);
+    // A running action's checklist and per-item table lead, above whatever else it reported.
+    let mut text = app.popup_text.clone();
+    if let Some(items) = &app.popup_items {
+        text = format!("{}\n\n{}", items, text).trim_end().to_string();
+    }
+    if let Some(checklist) = &app.popup_checklist {
+        text = format!("{}\n\n{}", checklist, text).trim_end().to_string();
+    }
+    // Streamed lines arrive stripped; results may still carry a command's own colours.
+    let text = highlight::strip_ansi(&text);
+    let area = fitted_rect(&text, app.popup_wrap, min_width, gauge_rows, 90, f.size());
+    // Each line is coloured as a whole, so every row it wraps onto keeps its colour.
+    let mut lines = Vec::new();
+    for source in text.lines() {
+        let status = [(StepStatus::Done, Color::Green), (StepStatus::Running, Color::Yellow), (StepStatus::Failed, Color::Red)]
+            .into_iter()
+            .find(|(status, _)| source.starts_with(status.mark()))
+            .map(|(_, color)| color);
+        let color = status.or_else(|| highlight::classify(&app.highlight, source).map(output_color));
+        let mut rows = if app.popup_wrap { wrap_lines(source, area) } else { vec![Line::from(source.to_string())] };
+        if let Some(color) = color {
+            rows.iter_mut().for_each(|row| row.patch_style(Style::default().fg(color)));
+        }
+        lines.extend(rows);
+    }
+    if let (Some(path), Some(first)) = (&app.popup_path, lines.first_mut()) {
+        // Results that produced a file lead with its path; make it stand out.
+        if first.spans.iter().map(|s| s.content.as_ref()).collect::<String>() == path.display().to_string() {
+            first.patch_style(Style::default().fg(Theme::current().accent()).add_modifier(Modifier::BOLD | Modifier::UNDERLINED));
+        }
+    }
+    // Clamp here, where the real content size is known, so key handlers can scroll freely.
+    let visible = area.height.saturating_sub(2 + gauge_rows);
+    let max_scroll = (lines.len() as u16).saturating_sub(visible);
+    app.popup_scroll = app.popup_scroll.min(max_scroll);
+    app.popup_page = visible.max(1);
+    let widest = lines.iter().map(|l| l.width()).max().unwrap_or(0) as u16;
+    app.popup_hscroll = app.popup_hscroll.min(widest.saturating_sub(area.width.saturating_sub(2)));
+
+    if max_scroll > 0 {
+        hint = format!(" {}/{} | 'j'/'k' or PgUp/PgDn to scroll |{}", app.popup_scroll + visible.min(lines.len() as u16), lines.len(), hint);
+    }
+    let title = match &app.task {
+        Some(task) => format!("{} {}", spinner(task.started), app.popup_title),
+        None => app.popup_title.clone(),
+    };
+    let mut block = popup_block(&title).title(Title::from(hint).position(Position::Bottom).alignment(Alignment::Right));
+    if let Some(notice) = &app.popup_notice {
+        block = block.title(Title::from(format!(" {} ", notice)).position(Position::Bottom).alignment(Alignment::Left));
+    }
+    let paragraph = Paragraph::new(lines).scroll((app.popup_scroll, app.popup_hscroll));
+    let inner = block.inner(area);
+    f.render_widget(Clear, area);
+    f.render_widget(block, area);
+    if let Some(percent) = app.popup_progress {
+        let chunks = Layout::default().constraints([Constraint::Min(0), Constraint::Length(1), Constraint::Length(1)]).split(inner);
+        f.render_widget(paragraph, chunks[0]);
+        let mut gauge = Gauge::default().gauge_style(Style::default().fg(Theme::current().accent())).percent(percent as u16);
+        if let Some(label) = &app.popup_progress_label {
+            gauge = gauge.label(format!("{}%  {}", percent, label));
+        }
+        f.render_widget(gauge, chunks[2]);
+    } else {
+        f.render_widget(paragraph, inner);
+    }
+    render_scrollbar(f, area, app.popup_scroll, max_scroll);
+}
+
+fn output_color(kind: LineKind) -> Color {
+    match kind {
+        LineKind::Error => Color::Red,
+        LineKind::Warning => Color::Yellow,
+        LineKind::Heading => Color::Cyan,
+    }
+}
+
+fn render_report_popup(f: &mut Frame, app: &mut App<'_>) {
+    let Some(report) = &app.system_report else { return };
+    let area = centered_rect(80, 60, f.size());
+    let rows = report.rows();
+    let visible = area.height.saturating_sub(3);
+    let max_scroll = (rows.len() as u16).saturating_sub(visible);
+    app.popup_scroll = app.popup_scroll.min(max_scroll);
+    app.popup_page = visible.max(1);
+
+    let mut previous = "";
+    let table_rows: Vec<Row> = rows
+        .iter()
+        .skip(app.popup_scroll as usize)
+        .map(|(section, key, value)| {
+            // Only the first row of each section names it, so the sections read as groups.
+            let heading = if *section == previous { "" } else { section };
+            previous = section;
+            Row::new(vec![
+                Cell::from(heading.to_string()).style(Style::default().fg(Theme::current().accent()).add_modifier(Modifier::BOLD)),
+                Cell::from(key.clone()),
+                Cell::from(value.clone()),
+            ])
+        })
+        .collect();
+    let mut block = popup_block(&app.popup_title).title(Title::from(" 'c' copy | 's' save ").position(Position::Bottom).alignment(Alignment::Right));
+    if let Some(notice) = &app.popup_notice {
+        block = block.title(Title::from(format!(" {} ", notice)).position(Position::Bottom).alignment(Alignment::Left));
+    }
+    let table = Table::new(table_rows)
+        .header(Row::new(vec!["Section", "Property", "Value"]).style(Style::default().add_modifier(Modifier::BOLD)))
+        .widths(&[Constraint::Length(10), Constraint::Length(14), Constraint::Min(10)])
+        .block(block);
+    f.render_widget(Clear, area);
+    f.render_widget(table, area);
+}
+
+fn render_confirm_popup(f: &mut Frame, app: &App<'_>) {
+    // Room for the title and the button row below the text.
+    let area = fitted_rect(&app.popup_text, true, Line::from(app.popup_title.as_str()).width().max(26), 2, 70, f.size());
+    let mut lines = wrap_lines(&app.popup_text, area);
+    for line in lines.iter_mut().filter(|l| l.spans.first().is_some_and(|s| s.content.starts_with(MOUNTED_MARK))) {
+        line.patch_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD));
+    }
+    // The capital letter marks the default answer; the highlighted button is what Enter activates.
+    let button = |label: &'static str, focused: bool| {
+        let style = if focused { Style::default().bg(Theme::current().accent()).fg(Color::Black).add_modifier(Modifier::BOLD) } else { Style::default() };
+        Span::styled(label, style)
     };
-    render_popup(f, "Context Help", help_text, 60, 40);
+    let hint = if app.popup_confirm_default { "  [Y/n]" } else { "  [y/N]" };
+    lines.push(Line::from(""));
+    lines.push(Line::from(vec![button(" Yes ", app.popup_confirm_focus), Span::raw("   "), button(" No ", !app.popup_confirm_focus), Span::raw(hint)]));
+    f.render_widget(Clear, area);
+    f.render_widget(Paragraph::new(lines).block(popup_block(&app.popup_title)), area);
 }
 
-fn render_action_popup(f: &mut Frame, app: &App<'_>) { render_popup(f, &app.popup_title, &app.popup_text, 80, 50); }
-fn render_confirm_popup(f: &mut Frame, app: &App<'_>) { let text = format!("{}\n\n[Y] Yes / [N] No", app.popup_text); render_popup(f, &app.popup_title, &text, 60, 25); }
+/// A Confirm that wants the device typed instead of a yes.
+fn render_phrase_popup(f: &mut Frame, app: &App<'_>) {
+    let prompt = format!("Type {} to continue:", app.popup_phrase);
+    // Room for the title, the prompt, the typed line and the notice below the text.
+    let min_width = Line::from(app.popup_title.as_str()).width().max(prompt.chars().count() + 4).max(app.popup_notice.as_ref().map_or(0, |n| n.chars().count()));
+    let area = fitted_rect(&app.popup_text, true, min_width, 5, 70, f.size());
+    let mut lines = wrap_lines(&app.popup_text, area);
+    let typed = lines.len() as u16 + 3;
+    lines.push(Line::from(""));
+    lines.push(Line::styled(prompt, Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)));
+    lines.push(Line::from(format!("> {}", app.popup_input.value())));
+    if let Some(notice) = &app.popup_notice {
+        lines.push(Line::styled(notice.as_str(), Style::default().fg(Color::Yellow)));
+    }
+    f.render_widget(Clear, area);
+    f.render_widget(Paragraph::new(lines).block(popup_block(&app.popup_title)), area);
+    f.set_cursor(area.x + 3 + app.popup_input.visual_cursor() as u16, area.y + typed);
+}
 
 fn render_input_popup(f: &mut Frame, app: &App<'_>) {
-    let block = Block::default().title(app.popup_title.as_str()).borders(Borders::ALL).style(Style::default().bg(Color::Rgb(40, 40, 60)));
-    let area = centered_rect(60, 20, f.size());
-    let input = Paragraph::new(app.popup_input.value()).block(Block::default());
-    f.set_cursor(area.x + app.popup_input.visual_cursor() as u16 + 1, area.y + 1);
+    let mut block = Block::default().title(app.popup_title.as_str()).borders(Borders::ALL).style(Theme::current().surface());
+    // A partition size also shows the disk's allocation with it below a gap, and what is left.
+    let feedback = app.popup_size.as_ref().map(|field| field.feedback(app.popup_input.value()));
+    let mut min_width = feedback.as_ref().map_or(0, |(allocation, outcome)| allocation.chars().count().max(outcome.as_ref().map_or_else(|e| e.chars().count(), |o| o.chars().count())) + 2);
+    let mut extra_rows = if feedback.is_some() { 3 } else { 0 };
+    // A Checked popup says why its text was not accepted below a gap.
+    if let Some(invalid) = &app.popup_invalid {
+        min_width = min_width.max(invalid.chars().count() + 2);
+        extra_rows += 2;
+    }
+    let area = fitted_rect(app.popup_input.value(), false, Line::from(app.popup_title.as_str()).width().max(40).max(min_width), extra_rows, 80, f.size());
+    let (text, cursor) = if app.popup_masked {
+        ("•".repeat(app.popup_input.value().chars().count()), app.popup_input.cursor())
+    } else {
+        (app.popup_input.value().to_string(), app.popup_input.visual_cursor())
+    };
+    let mut lines = vec![Line::from(text)];
+    if let Some((allocation, outcome)) = feedback {
+        block = block.title(Title::from(" +/- step size | Enter continue ").position(Position::Bottom).alignment(Alignment::Right));
+        lines.push(Line::from(""));
+        lines.push(Line::styled(allocation, Style::default().fg(Theme::current().muted())));
+        lines.push(match outcome {
+            Ok(left) => Line::styled(left, Style::default().fg(Color::Green)),
+            Err(why) => Line::styled(why, Style::default().fg(Color::Red)),
+        });
+    }
+    if let Some(invalid) = &app.popup_invalid {
+        lines.push(Line::from(""));
+        lines.push(Line::styled(invalid.as_str(), Style::default().fg(Color::Red)));
+    }
+    let input = Paragraph::new(lines).block(Block::default());
+    f.set_cursor(area.x + cursor as u16 + 1, area.y + 1);
     f.render_widget(Clear, area);
     f.render_widget(block, area);
     let input_area = Layout::default().margin(1).constraints([Constraint::Min(0)]).split(area)[0];
     f.render_widget(input, input_area);
 }
 
+/// A Select or MultiSelect list's search: the one being typed, or what the last one found.
+fn list_search_title(app: &App<'_>) -> Title<'static> {
+    let status = match (app.popup_searching, app.popup_search.value()) {
+        (true, query) => format!(" /{}▏ ", query),
+        (false, "") if app.active_popup == Popup::Select => " '/' filter ".to_string(),
+        (false, "") => " '/' search ".to_string(),
+        (false, query) if app.active_popup == Popup::Select => format!(" '{}': {} of {} | Esc clears ", query, app.popup_list.items.len(), app.popup_unfiltered.len()),
+        (false, query) => match app.popup_list.items.iter().filter(|item| !crate::manual::match_ranges(item, query).is_empty()).count() {
+            0 => format!(" No match for '{}' ", query),
+            found => format!(" '{}': {} | 'n'/'N' next/previous ", query, found),
+        },
+    };
+    Title::from(status).position(Position::Bottom).alignment(Alignment::Left)
+}
+
 fn render_select_popup(f: &mut Frame, app: &mut App<'_>) {
-    let block = Block::default().title(app.popup_title.as_str()).borders(Borders::ALL).style(Style::default().bg(Color::Rgb(40, 40, 60)));
-    let area = centered_rect(80, 70, f.size());
+    let block = Block::default().title(app.popup_title.as_str()).title(list_search_title(app)).borders(Borders::ALL).style(Theme::current().surface());
+    // Measured with the highlight symbol in front.
+    let rows = app.popup_list.items.iter().map(|item| format!(">> {}", item)).collect::<Vec<_>>().join("\n");
+    // A disk choice also draws the highlighted disk's layout: a gap, the bar and its legend.
+    let (min_width, bar_rows) = if app.popup_disks.is_empty() { (0, 0) } else { (60, 4) };
+    let area = fitted_rect(&rows, false, Line::from(app.popup_title.as_str()).width().max(min_width), bar_rows, 80, f.size());
     let items: Vec<ListItem> = app.popup_list.items.iter().map(|i| ListItem::new(i.clone())).collect();
-    let list = List::new(items).highlight_style(Style::default().bg(Color::Rgb(60, 60, 90)).add_modifier(Modifier::BOLD)).highlight_symbol(">> ");
+    let list = List::new(items).highlight_style(Style::default().bg(Theme::current().selection()).add_modifier(Modifier::BOLD)).highlight_symbol(">> ");
+    f.render_widget(Clear, area);
+    f.render_widget(block, area);
+    let chunks = Layout::default().margin(1).constraints([Constraint::Min(0), Constraint::Length(bar_rows)]).split(area);
+    app.popup_page = chunks[0].height.max(1);
+    f.render_stateful_widget(list, chunks[0], &mut app.popup_list.state);
+    // Matched by label: a filtered list no longer lines up with the disks.
+    if let Some(disk) = app.popup_list.selected_item().and_then(|label| app.popup_disks.iter().find(|disk| disk.label() == *label)) {
+        render_layout_bar(f, disk, chunks[1]);
+    }
+}
+
+/// The colour a filesystem is drawn in on a layout bar.
+fn fs_color(fstype: Option<&str>) -> Color {
+    match fstype {
+        Some("vfat") => Color::Yellow,
+        Some("ext4" | "ext3" | "ext2") => Color::Blue,
+        Some("btrfs") => Color::Green,
+        Some("xfs") => Color::Cyan,
+        Some("swap") => Color::Red,
+        Some("crypto_LUKS") => Color::Magenta,
+        Some("ntfs") => Color::LightBlue,
+        Some(_) => Color::White,
+        None => Color::DarkGray,
+    }
+}
+
+/// `disk`'s partitions as a bar proportional to their sizes, with a legend below it.
+fn render_layout_bar(f: &mut Frame, disk: &BlockDevice, area: Rect) {
+    let segments = disk.layout_bar(area.width as usize);
+    // Unused space is shaded lighter than partitions.
+    let bar: Vec<Span> = segments.iter().map(|s| Span::styled(if s.name.is_some() { "█" } else { "░" }.repeat(s.width), Style::default().fg(fs_color(s.fstype.as_deref())))).collect();
+    let mut legend = Vec::new();
+    for s in &segments {
+        let name = s.name.as_deref().map_or("free", |name| name.trim_start_matches("/dev/"));
+        legend.push(Span::styled("■ ", Style::default().fg(fs_color(s.fstype.as_deref()))));
+        legend.push(Span::raw(format!("{} {} {}   ", name, s.fstype.as_deref().unwrap_or("-"), human_size(s.size))));
+    }
+    let lines = vec![Line::from(""), Line::from(bar), Line::from(legend)];
+    f.render_widget(Paragraph::new(lines).wrap(Wrap { trim: true }), area);
+}
+
+fn render_multi_select_popup(f: &mut Frame, app: &mut App<'_>) {
+    let diff = if app.popup_diffs.is_empty() { "" } else { " | d diff" };
+    let keys = format!(" Space tick/untick | a all{} | Enter continue ", diff);
+    let ticked = format!(" {} of {} ticked ", app.popup_checked.len(), app.popup_list.items.len());
+    let search = list_search_title(app);
+    // Room for the title beside the count, and for the search status beside the keys.
+    let min_width = (Line::from(app.popup_title.as_str()).width() + ticked.len()).max(search.content.width() + keys.len()) + 2;
+    let block = Block::default()
+        .title(app.popup_title.as_str())
+        .title(Title::from(ticked).alignment(Alignment::Right))
+        .title(search)
+        .borders(Borders::ALL)
+        .style(Theme::current().surface());
+    // Measured with the highlight symbol and the tick box in front.
+    let rows = app.popup_list.items.iter().map(|item| format!(">> [x] {}", item)).collect::<Vec<_>>().join("\n");
+    let area = fitted_rect(&rows, false, min_width, 0, 80, f.size());
+    let block = block.title(Title::from(keys).position(Position::Bottom).alignment(Alignment::Right));
+    let items: Vec<ListItem> = app.popup_list.items.iter().enumerate().map(|(i, item)| {
+        let mark = if app.popup_checked.contains(&i) { "[x]" } else { "[ ]" };
+        ListItem::new(format!("{} {}", mark, item))
+    }).collect();
+    let list = List::new(items).highlight_style(Style::default().bg(Theme::current().selection()).add_modifier(Modifier::BOLD)).highlight_symbol(">> ");
     f.render_widget(Clear, area);
     f.render_widget(block, area);
     let list_area = Layout::default().margin(1).constraints([Constraint::Min(0)]).split(area)[0];
+    app.popup_page = list_area.height.max(1);
     f.render_stateful_widget(list, list_area, &mut app.popup_list.state);
 }
 
-fn render_popup(f: &mut Frame, title: &str, text: &str, width_percent: u16, height_percent: u16) {
-    let block = Block::default().title(title).borders(Borders::ALL).style(Style::default().bg(Color::Rgb(40, 40, 60)));
-    let area = centered_rect(width_percent, height_percent, f.size());
-    let wrapped_text: Vec<Line> = wrap(text, (area.width - 4) as usize).iter().map(|s| Line::from(s.to_string())).collect();
-    let paragraph = Paragraph::new(wrapped_text).block(block);
+/// The highlighted Merge file's unified diff, over its list: removals red, additions green.
+fn render_diff_popup(f: &mut Frame, app: &mut App<'_>) {
+    let Some(diff) = app.popup_list.state.selected().and_then(|i| app.popup_diffs.get(i)) else { return };
+    let area = centered_rect(90, 80, f.size());
+    let lines: Vec<Line> = diff
+        .lines()
+        .map(|line| {
+            let style = match line.chars().next() {
+                _ if line.starts_with("---") || line.starts_with("+++") => Style::default().add_modifier(Modifier::BOLD),
+                Some('-') => Style::default().fg(Color::Red),
+                Some('+') => Style::default().fg(Color::Green),
+                Some('@') => Style::default().fg(Color::Cyan),
+                _ => Style::default(),
+            };
+            Line::styled(line.to_string(), style)
+        })
+        .collect();
+    let visible = area.height.saturating_sub(2);
+    app.popup_scroll = app.popup_scroll.min((lines.len() as u16).saturating_sub(visible));
+    app.popup_page = visible.max(1);
+    let title = format!("/etc/{}: - target, + snapshot", app.popup_list.selected_item().cloned().unwrap_or_default());
+    let block = popup_block(&title).title(Title::from(" 'j'/'k' or PgUp/PgDn to scroll | d/Esc back ").position(Position::Bottom).alignment(Alignment::Right));
+    f.render_widget(Clear, area);
+    f.render_widget(Paragraph::new(lines).block(block).scroll((app.popup_scroll, 0)), area);
+}
+
+fn render_results_popup(f: &mut Frame, app: &mut App<'_>) {
+    if app.results.is_empty() {
+        render_popup(f, &app.popup_title, "Nothing has finished in this session yet. Results appear here as actions complete.", 60);
+        return;
+    }
+    let block = popup_block(&app.popup_title).title(Title::from(" Enter reopens | Esc closes ").position(Position::Bottom).alignment(Alignment::Right));
+    let area = centered_rect(80, 70, f.size());
+    let items: Vec<ListItem> = app
+        .results
+        .iter()
+        .rev()
+        .zip(&app.popup_list.items)
+        .map(|(entry, line)| ListItem::new(line.clone()).style(Style::default().fg(if entry.ok { Theme::current().text() } else { Color::Red })))
+        .collect();
+    let list = List::new(items).highlight_style(Style::default().bg(Theme::current().selection()).add_modifier(Modifier::BOLD)).highlight_symbol(">> ");
+    f.render_widget(Clear, area);
+    f.render_widget(block, area);
+    let list_area = Layout::default().margin(1).constraints([Constraint::Min(0)]).split(area)[0];
+    app.popup_page = list_area.height.max(1);
+    f.render_stateful_widget(list, list_area, &mut app.popup_list.state);
+}
+
+fn render_services_popup(f: &mut Frame, app: &mut App<'_>) {
+    let hint = " '/' filter | s start  x stop  r restart  e enable  d disable | l log  f follow  p priority | Esc closes ";
+    let mut block = popup_block(&app.popup_title).title(Title::from(hint).position(Position::Bottom).alignment(Alignment::Right));
+    if let Some(notice) = &app.popup_notice {
+        block = block.title(Title::from(format!(" {} ", notice)).position(Position::Bottom).alignment(Alignment::Left));
+    }
+    let area = centered_rect(90, 80, f.size());
+    let inner = block.inner(area);
+    let chunks = Layout::default().constraints([Constraint::Length(1), Constraint::Min(0)]).split(inner);
+    let mut filter = match (app.service_filtering, app.service_filter.value()) {
+        (true, text) => Line::from(vec![Span::raw("Filter: "), Span::styled(format!("{}▏", text), Style::default().fg(Theme::current().accent()))]),
+        (false, "") => Line::from(Span::styled("Press '/' to filter by name, state or description", Style::default().fg(Color::DarkGray))),
+        (false, text) => Line::from(vec![Span::raw("Filter: "), Span::raw(text.to_string())]),
+    };
+    filter.spans.push(Span::styled(format!("   journal: {} and above", app.journal_priority()), Style::default().fg(Color::DarkGray)));
+    let items: Vec<ListItem> = app
+        .visible_services()
+        .iter()
+        .zip(&app.popup_list.items)
+        .map(|(unit, row)| ListItem::new(row.clone()).style(Style::default().fg(if unit.active == "failed" { Color::Red } else { Theme::current().text() })))
+        .collect();
+    let list = List::new(items).highlight_style(Style::default().bg(Theme::current().selection()).add_modifier(Modifier::BOLD)).highlight_symbol(">> ");
+    f.render_widget(Clear, area);
+    f.render_widget(block, area);
+    f.render_widget(Paragraph::new(filter), chunks[0]);
+    app.popup_page = chunks[1].height.max(1);
+    f.render_stateful_widget(list, chunks[1], &mut app.popup_list.state);
+}
+
+fn render_popup(f: &mut Frame, title: &str, text: &str, max_width_percent: u16) {
+    let area = fitted_rect(text, true, Line::from(title).width(), 0, max_width_percent, f.size());
+    let paragraph = Paragraph::new(wrap_lines(text, area)).block(popup_block(title));
     f.render_widget(Clear, area);
     f.render_widget(paragraph, area);
 }
 
+/// A braille spinner, one step per `SPINNER_STEP`, so it turns at the same speed whatever the frame rate.
+const SPINNER: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+const SPINNER_STEP: Duration = Duration::from_millis(80);
+
+fn spinner(started: Instant) -> &'static str {
+    SPINNER[(started.elapsed().as_millis() / SPINNER_STEP.as_millis()) as usize % SPINNER.len()]
+}
+
+fn popup_block(title: &str) -> Block<'_> {
+    Block::default().title(title).borders(Borders::ALL).style(Theme::current().surface())
+}
+
+fn wrap_lines(text: &str, area: Rect) -> Vec<Line<'static>> {
+    let width = area.width.saturating_sub(4).max(1) as usize;
+    wrap(text, width).iter().map(|s| Line::from(s.to_string())).collect()
+}
+
+/// `text` cut to `width` columns with a trailing '…', at the last space that leaves at least half
+/// of it, so words are not split unless a single word fills the line.
+fn truncate(text: &str, width: usize) -> String {
+    if Line::from(text).width() <= width {
+        return text.to_string();
+    } else if width == 0 {
+        return String::new();
+    }
+    let mut cut = String::new();
+    for c in text.chars() {
+        if Line::from(format!("{}{}…", cut, c)).width() > width {
+            break;
+        }
+        cut.push(c);
+    }
+    if let Some(space) = cut.trim_end().rfind(' ').filter(|&at| at > 0 && Line::from(&cut[..at]).width() * 2 >= width) {
+        cut.truncate(space);
+    }
+    format!("{}…", cut.trim_end())
+}
+
+/// Popups never get smaller than this, however little they show.
+const MIN_POPUP_WIDTH: u16 = 30;
+const MIN_POPUP_HEIGHT: u16 = 5;
+
+/// A centred popup sized to `text`: as wide as its longest line (or `min_width`, e.g. for the title)
+/// up to `max_width_percent` of the screen, and as tall as its lines, wrapped to that width when
+/// `wrap`, plus `extra_rows`, up to the whole screen.
+fn fitted_rect(text: &str, wrap_text: bool, min_width: usize, extra_rows: u16, max_width_percent: u16, screen: Rect) -> Rect {
+    let max_width = ((screen.width as u32 * max_width_percent as u32 / 100) as u16).max(MIN_POPUP_WIDTH).min(screen.width);
+    let longest = text.lines().map(|line| Line::from(line).width()).max().unwrap_or(0).max(min_width);
+    // Two columns of border and two of padding; `wrap_lines` wraps at the same width.
+    let width = (longest.min(u16::MAX as usize) as u16).saturating_add(4).max(MIN_POPUP_WIDTH).min(max_width);
+    let rows = if wrap_text { wrap(text, width.saturating_sub(4).max(1) as usize).len() } else { text.lines().count() };
+    let height = (rows.min(u16::MAX as usize) as u16).saturating_add(2 + extra_rows).max(MIN_POPUP_HEIGHT).min(screen.height);
+    Rect { x: screen.x + (screen.width - width) / 2, y: screen.y + (screen.height - height) / 2, width, height }
+}
+
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default().direction(Direction::Vertical).constraints([Constraint::Percentage((100 - percent_y) / 2), Constraint::Percentage(percent_y), Constraint::Percentage((100 - percent_y) / 2)]).split(r);
     Layout::default().direction(Direction::Horizontal).constraints([Constraint::Percentage((100 - percent_x) / 2), Constraint::Percentage(percent_x), Constraint::Percentage((100 - percent_x) / 2)]).split(popup_layout[1])[1]
 }
 
-const ASCII_ART: &str = r"
+pub const ASCII_ART: &str = r"
     █████╗  ██████╗  ██████╗██╗  ██╗     ███████╗██╗   ██╗██╗████████╗███████╗
    ██╔══██╗██╔════╝ ██╔════╝██║  ██║     ██╔════╝██║   ██║██║╚══██╔══╝██╔════╝
    ███████║██║  ███╗██║     ███████║     ███████╗██║   ██║██║   ██║   ███████╗
@@ -123,3 +861,34 @@ const ASCII_ART: &str = r"
    ██║  ██║╚██████╔╝╚██████╗██║  ██║     ███████║╚██████╔╝██║   ██║   ███████║
    ╚═╝  ╚═╝ ╚═════╝  ╚═════╝╚═╝  ╚═╝     ╚══════╝ ╚═════╝ ╚═╝   ╚═╝   ╚══════╝
 ";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn popups_fit_their_text_within_the_screen() {
+        let screen = Rect::new(0, 0, 100, 40);
+        let short = fitted_rect("Done.", true, 0, 0, 60, screen);
+        assert_eq!((short.width, short.height), (MIN_POPUP_WIDTH, MIN_POPUP_HEIGHT));
+        assert_eq!((short.x, short.y), ((100 - MIN_POPUP_WIDTH) / 2, (40 - MIN_POPUP_HEIGHT) / 2));
+
+        let line = "x".repeat(50);
+        let medium = fitted_rect(&[line.as_str(); 8].join("\n"), true, 0, 2, 60, screen);
+        assert_eq!((medium.width, medium.height), (54, 12));
+
+        // Too wide for 60%: wrapped twice as many rows; too long for the screen: all of it.
+        let wide = fitted_rect(&"y".repeat(112), true, 0, 0, 60, screen);
+        assert_eq!((wide.width, wide.height), (60, 5));
+        let long = fitted_rect(&["z"; 100].join("\n"), false, 0, 0, 60, screen);
+        assert_eq!((long.height, long.y), (40, 0));
+    }
+
+    #[test]
+    fn menu_text_is_cut_at_a_word_with_an_ellipsis() {
+        assert_eq!(truncate(" Replicator (Recommended)", 30), " Replicator (Recommended)");
+        assert_eq!(truncate(" Replicator (Recommended)", 20), " Replicator…");
+        assert_eq!(truncate(" Replicator", 8), " Replic…");
+        assert_eq!(truncate(" Replicator", 0), "");
+    }
+}
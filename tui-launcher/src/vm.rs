@@ -0,0 +1,72 @@
+// ===================================================================
+// Test Boot Module
+// ===================================================================
+// The QEMU command line "Test-Boot ISO in QEMU" starts: the ISO as a
+// CD-ROM, UEFI firmware from edk2-ovmf when it is installed, KVM when
+// /dev/kvm can be used, and a window of its own or, without a desktop,
+// the VGA console drawn with curses.
+
+use std::path::{Path, PathBuf};
+
+pub const QEMU: &str = "qemu-system-x86_64";
+/// What to install for a test boot: QEMU and its UEFI firmware.
+pub const PACKAGES: [&str; 2] = ["qemu-desktop", "edk2-ovmf"];
+
+/// Where edk2-ovmf has kept its firmware image over the years, newest first.
+const OVMF: [&str; 3] = ["/usr/share/edk2/x64/OVMF.4m.fd", "/usr/share/edk2-ovmf/x64/OVMF.fd", "/usr/share/ovmf/x64/OVMF.fd"];
+
+/// The UEFI firmware to boot with, if edk2-ovmf is installed.
+pub fn ovmf(exists: impl Fn(&Path) -> bool) -> Option<PathBuf> {
+    OVMF.iter().map(PathBuf::from).find(|path| exists(path))
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Display {
+    /// A window of its own on the desktop the suite runs in.
+    Window,
+    /// The VGA text console drawn in the terminal, for a tmux or screen session.
+    Curses,
+}
+
+/// The arguments for `QEMU` that boot `iso` read-only, so a test boot never changes it.
+pub fn qemu_args(iso: &Path, firmware: Option<&Path>, kvm: bool, display: Display) -> Vec<String> {
+    let name = iso.file_name().map_or(String::new(), |n| n.to_string_lossy().to_string());
+    let mut args = vec!["-name".to_string(), format!("arch-suite test boot: {}", name), "-m".to_string(), "2048".to_string()];
+    if kvm {
+        args.extend(["-enable-kvm", "-cpu", "host", "-smp", "2"].map(String::from));
+    }
+    if let Some(firmware) = firmware {
+        args.extend(["-bios".to_string(), firmware.display().to_string()]);
+    }
+    args.extend(["-drive".to_string(), format!("file={},media=cdrom,readonly=on", iso.display().to_string().replace(',', ",,")), "-boot".to_string(), "d".to_string()]);
+    args.extend(["-nic", "user,model=virtio-net-pci"].map(String::from));
+    if display == Display::Curses {
+        args.extend(["-display", "curses"].map(String::from));
+    }
+    args
+}
+
+/// Whether QEMU can open a window: the suite runs inside an X11 or Wayland session.
+pub fn has_desktop() -> bool {
+    ["DISPLAY", "WAYLAND_DISPLAY"].iter().any(|var| std::env::var_os(var).is_some_and(|value| !value.is_empty()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_iso_boots_read_only_with_the_firmware_found() {
+        let firmware = ovmf(|path| path == Path::new("/usr/share/edk2-ovmf/x64/OVMF.fd"));
+        assert_eq!(firmware.as_deref(), Some(Path::new("/usr/share/edk2-ovmf/x64/OVMF.fd")));
+        assert_eq!(ovmf(|_| false), None);
+
+        let args = qemu_args(Path::new("/isos/arch,test.iso"), firmware.as_deref(), true, Display::Curses);
+        assert_eq!(args[1], "arch-suite test boot: arch,test.iso");
+        assert!(args.windows(2).any(|pair| pair == ["-drive", "file=/isos/arch,,test.iso,media=cdrom,readonly=on"]));
+        assert!(args.windows(2).any(|pair| pair == ["-bios", "/usr/share/edk2-ovmf/x64/OVMF.fd"]));
+        assert!(args.ends_with(&["-display".to_string(), "curses".to_string()]));
+        let bios = qemu_args(Path::new("/a.iso"), None, false, Display::Window);
+        assert!(!bios.iter().any(|arg| arg == "-bios" || arg == "-enable-kvm" || arg == "-display"));
+    }
+}
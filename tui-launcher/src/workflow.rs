@@ -0,0 +1,346 @@
+// ===================================================================
+// Workflow Module
+// ===================================================================
+// An ordered list of named steps with a status each. The manual
+// installer is modelled as one: its first menu entries are the steps,
+// in order, and completed installer steps are matched back to them by
+// `StepKind::id`. A long action's sub-steps are tracked the same way,
+// as a `Checklist`, so its popup shows which one it is at. An action
+// working on several devices or hosts at once reports each in an
+// `ItemTable` row of its own.
+
+use crate::disk::human_size;
+use crate::install::InstallStep;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StepStatus {
+    Pending,
+    Running,
+    Done,
+    Failed,
+    /// Was done, but a step before it has been re-run since.
+    Stale,
+}
+
+impl StepStatus {
+    pub fn mark(self) -> &'static str {
+        match self {
+            StepStatus::Pending => "[ ]",
+            StepStatus::Running => "[…]",
+            StepStatus::Done => "[✓]",
+            StepStatus::Failed => "[✗]",
+            StepStatus::Stale => "[~]",
+        }
+    }
+}
+
+pub struct WorkflowStep {
+    /// Matches `StepKind::id` of the installer step this entry performs.
+    pub id: &'static str,
+    pub status: StepStatus,
+}
+
+pub struct Workflow {
+    pub steps: Vec<WorkflowStep>,
+    /// The step the user launched (or the plan reached), while it is being answered or run.
+    pub active: Option<usize>,
+    /// Launch the next pending step whenever one finishes, until a step fails or is cancelled.
+    pub run_all: bool,
+}
+
+impl Workflow {
+    pub fn new(ids: &[&'static str]) -> Self {
+        Workflow { steps: ids.iter().map(|&id| WorkflowStep { id, status: StepStatus::Pending }).collect(), active: None, run_all: false }
+    }
+
+    /// The manual installer, in menu order, with the steps already in the install history marked done.
+    pub fn manual_installer(history: &[InstallStep]) -> Self {
+        let mut workflow = Workflow::new(&["wipe", "partition", "format", "mount", "pacstrap", "fstab", "bootloader", "timezone", "locale", "keymap", "user", "passwords"]);
+        for step in history {
+            workflow.finished(step.kind.id(), false);
+        }
+        workflow
+    }
+
+    pub fn position(&self, id: &str) -> Option<usize> {
+        self.steps.iter().position(|s| s.id == id)
+    }
+
+    /// The first step that is not done yet; highlighted as the one to run next.
+    pub fn current(&self) -> Option<usize> {
+        self.steps.iter().position(|s| s.status != StepStatus::Done)
+    }
+
+    pub fn failed_step(&self) -> Option<usize> {
+        self.steps.iter().position(|s| s.status == StepStatus::Failed)
+    }
+
+    /// Makes step `index` active. Re-running a step that already has a result keeps the steps
+    /// before it as they are and marks the completed steps after it stale.
+    pub fn start(&mut self, index: usize) {
+        let Some(step) = self.steps.get(index) else { return };
+        if step.status != StepStatus::Pending {
+            for later in &mut self.steps[index + 1..] {
+                if later.status == StepStatus::Done {
+                    later.status = StepStatus::Stale;
+                }
+            }
+        }
+        self.active = Some(index);
+    }
+
+    /// The active step's command is now running.
+    pub fn running(&mut self) {
+        if let Some(step) = self.active.and_then(|i| self.steps.get_mut(i)) {
+            step.status = StepStatus::Running;
+        }
+    }
+
+    /// The active step stopped to ask something; it is not running until answered.
+    pub fn waiting(&mut self) {
+        if let Some(step) = self.active.and_then(|i| self.steps.get_mut(i)) {
+            if step.status == StepStatus::Running {
+                step.status = StepStatus::Pending;
+            }
+        }
+    }
+
+    /// Marks step `id` done. With `chained`, the step after it becomes active (an install plan
+    /// continues by itself); otherwise nothing is active any more.
+    pub fn finished(&mut self, id: &str, chained: bool) {
+        let Some(index) = self.position(id) else { return };
+        self.steps[index].status = StepStatus::Done;
+        self.active = if chained { Some(index + 1).filter(|&i| i < self.steps.len()) } else { None };
+    }
+
+    pub fn failed(&mut self) {
+        if let Some(step) = self.active.take().and_then(|i| self.steps.get_mut(i)) {
+            step.status = StepStatus::Failed;
+        }
+        self.run_all = false;
+    }
+
+    /// The user backed out of the active step (or it could not start); a sequence stops here.
+    pub fn stop(&mut self) {
+        self.waiting();
+        self.active = None;
+        self.run_all = false;
+    }
+}
+
+pub struct ChecklistItem {
+    pub label: String,
+    pub status: StepStatus,
+    pub started: Option<Instant>,
+    /// How long it ran, once it stopped.
+    pub took: Option<Duration>,
+}
+
+/// The sub-steps of one running action, from `Progress::Checklist`; at most one runs at a time.
+pub struct Checklist {
+    pub items: Vec<ChecklistItem>,
+}
+
+impl Checklist {
+    pub fn new(labels: Vec<String>) -> Self {
+        Checklist { items: labels.into_iter().map(|label| ChecklistItem { label, status: StepStatus::Pending, started: None, took: None }).collect() }
+    }
+
+    /// Item `index` started, so the one running before it is done.
+    pub fn start(&mut self, index: usize) {
+        self.finish(true);
+        if let Some(item) = self.items.get_mut(index) {
+            item.status = StepStatus::Running;
+            item.started = Some(Instant::now());
+        }
+    }
+
+    /// The action ended; the item still running is done with it, or is where it failed.
+    pub fn finish(&mut self, ok: bool) {
+        for item in self.items.iter_mut().filter(|item| item.status == StepStatus::Running) {
+            item.status = if ok { StepStatus::Done } else { StepStatus::Failed };
+            item.took = item.started.map(|started| started.elapsed());
+        }
+    }
+}
+
+/// `1.2s`, or `3m 05s` from a minute on.
+fn seconds(took: Duration) -> String {
+    match took.as_secs() {
+        0..=59 => format!("{:.1}s", took.as_secs_f32()),
+        secs => format!("{}m {:02}s", secs / 60, secs % 60),
+    }
+}
+
+impl fmt::Display for Checklist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines: Vec<String> = self
+            .items
+            .iter()
+            .map(|item| match item.took {
+                Some(took) => format!("{} {}  {}", item.status.mark(), item.label, seconds(took)),
+                None => format!("{} {}", item.status.mark(), item.label),
+            })
+            .collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+/// A change to one row of an `ItemTable`, keyed by device or host; `None` fields keep their value.
+#[derive(Clone, Debug)]
+pub struct ItemUpdate {
+    pub key: String,
+    pub status: StepStatus,
+    pub bytes: Option<u64>,
+    pub note: Option<String>,
+}
+
+pub struct ItemRow {
+    pub key: String,
+    pub status: StepStatus,
+    /// How much of it was processed so far.
+    pub bytes: u64,
+    /// What it is doing, or its outcome.
+    pub note: String,
+}
+
+/// The devices or hosts of one running action, from `Progress::Items`, each updated on its own so
+/// a failure leaves the others' results standing.
+pub struct ItemTable {
+    pub rows: Vec<ItemRow>,
+}
+
+impl ItemTable {
+    pub fn new(keys: Vec<String>) -> Self {
+        ItemTable { rows: keys.into_iter().map(|key| ItemRow { key, status: StepStatus::Pending, bytes: 0, note: String::new() }).collect() }
+    }
+
+    /// Applies `update`, adding a row for a key not announced up front.
+    pub fn update(&mut self, update: ItemUpdate) {
+        let i = match self.rows.iter().position(|row| row.key == update.key) {
+            Some(i) => i,
+            None => {
+                self.rows.push(ItemRow { key: update.key.clone(), status: StepStatus::Pending, bytes: 0, note: String::new() });
+                self.rows.len() - 1
+            }
+        };
+        let row = &mut self.rows[i];
+        row.status = update.status;
+        if let Some(bytes) = update.bytes {
+            row.bytes = bytes;
+        }
+        if let Some(note) = update.note {
+            row.note = note;
+        }
+    }
+
+    /// The action ended; rows it never finished failed with it, or did not get to run.
+    pub fn finish(&mut self, ok: bool) {
+        for row in self.rows.iter_mut().filter(|row| matches!(row.status, StepStatus::Pending | StepStatus::Running)) {
+            row.status = StepStatus::Failed;
+            row.note = if ok { "not reported".to_string() } else { "stopped with the action".to_string() };
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        let count = |status| self.rows.iter().filter(|row| row.status == status).count();
+        format!("{} of {} done, {} failed, {} running", count(StepStatus::Done), self.rows.len(), count(StepStatus::Failed), count(StepStatus::Running))
+    }
+}
+
+impl fmt::Display for ItemTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.rows.iter().map(|row| row.key.chars().count()).max().unwrap_or(0);
+        let mut lines = vec![self.summary()];
+        for row in &self.rows {
+            let bytes = if row.bytes > 0 { human_size(row.bytes) } else { "-".to_string() };
+            lines.push(format!("{} {:<width$}  {:>9}  {}", row.status.mark(), row.key, bytes, row.note, width = width).trim_end().to_string());
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_progress_and_failures_stop_a_sequence() {
+        let mut workflow = Workflow::new(&["a", "b", "c"]);
+        assert_eq!(workflow.current(), Some(0));
+        workflow.run_all = true;
+        workflow.start(0);
+        workflow.running();
+        assert_eq!(workflow.steps[0].status, StepStatus::Running);
+        workflow.finished("a", false);
+        assert_eq!((workflow.current(), workflow.active), (Some(1), None));
+
+        workflow.start(1);
+        workflow.running();
+        workflow.failed();
+        assert_eq!(workflow.steps[1].status, StepStatus::Failed);
+        assert!(!workflow.run_all);
+        assert_eq!(workflow.current(), Some(1));
+    }
+
+    #[test]
+    fn cancelling_a_prompt_leaves_the_step_pending() {
+        let mut workflow = Workflow::new(&["a"]);
+        workflow.start(0);
+        workflow.running();
+        workflow.waiting();
+        workflow.stop();
+        assert_eq!((workflow.steps[0].status, workflow.active), (StepStatus::Pending, None));
+    }
+
+    #[test]
+    fn rerunning_a_step_marks_the_later_completed_steps_stale() {
+        let mut workflow = Workflow::new(&["a", "b", "c", "d"]);
+        for id in ["a", "b", "d"] {
+            workflow.finished(id, false);
+        }
+        workflow.start(2);
+        workflow.running();
+        workflow.failed();
+        assert_eq!(workflow.failed_step(), Some(2));
+
+        workflow.start(2);
+        let statuses: Vec<StepStatus> = workflow.steps.iter().map(|s| s.status).collect();
+        assert_eq!(statuses, [StepStatus::Done, StepStatus::Done, StepStatus::Failed, StepStatus::Stale]);
+        workflow.running();
+        workflow.finished("c", false);
+        assert_eq!((workflow.failed_step(), workflow.current()), (None, Some(3)));
+    }
+
+    #[test]
+    fn a_checklist_marks_each_sub_step_as_the_next_one_starts() {
+        let mut checklist = Checklist::new(vec!["Archiving /etc".to_string(), "Bundling".to_string(), "Cleaning up".to_string()]);
+        checklist.start(0);
+        checklist.start(1);
+        let statuses: Vec<StepStatus> = checklist.items.iter().map(|item| item.status).collect();
+        assert_eq!(statuses, [StepStatus::Done, StepStatus::Running, StepStatus::Pending]);
+        checklist.finish(false);
+        assert_eq!(checklist.items[1].status, StepStatus::Failed);
+
+        checklist.items[0].took = Some(Duration::from_millis(1250));
+        checklist.items[1].took = Some(Duration::from_secs(185));
+        assert_eq!(checklist.to_string(), "[✓] Archiving /etc  1.2s\n[✗] Bundling  3m 05s\n[ ] Cleaning up");
+    }
+
+    #[test]
+    fn item_rows_update_on_their_own_and_survive_failures() {
+        let mut table = ItemTable::new(vec!["/dev/sdb".to_string(), "/dev/sdc".to_string()]);
+        let update = |key: &str, status, bytes, note: Option<&str>| ItemUpdate { key: key.to_string(), status, bytes, note: note.map(String::from) };
+        table.update(update("/dev/sdb", StepStatus::Running, Some(1024), Some("writing")));
+        table.update(update("/dev/sdc", StepStatus::Failed, None, Some("device busy")));
+        table.update(update("/dev/sdb", StepStatus::Done, Some(2048), None));
+        table.update(update("/dev/sdd", StepStatus::Running, None, None));
+        assert_eq!(table.summary(), "1 of 3 done, 1 failed, 1 running");
+        table.finish(false);
+        let notes: Vec<&str> = table.rows.iter().map(|row| row.note.as_str()).collect();
+        assert_eq!(notes, ["writing", "device busy", "stopped with the action"]);
+        assert_eq!(table.to_string().lines().nth(1), Some("[✓] /dev/sdb       2.0K  writing"));
+    }
+}